@@ -1,5 +1,6 @@
 /**
- * Syncs the version from package.json into src-tauri/Cargo.toml.
+ * Syncs the version from package.json into src-tauri/Cargo.toml and
+ * src-tauri/core/Cargo.toml.
  * Run automatically via `bun run version`.
  *
  * Always run from the project root: `bun run sync-version`
@@ -22,29 +23,36 @@ const pkg = JSON.parse(readFileSync(join(root, "package.json"), "utf8")) as {
 };
 const { version } = pkg;
 
-const cargoPath = join(root, "src-tauri", "Cargo.toml");
-const lines = readFileSync(cargoPath, "utf8").split("\n");
-
-let replaced = false;
-const updated = lines.map((line) => {
-  // Only replace the first bare `version = "..."` (the [package] field).
-  // Dependency version fields look like `foo = { version = "..." }` so they
-  // contain `{` on the same line — skip those.
-  if (!replaced && PACKAGE_VERSION_RE.test(line) && !line.includes("{")) {
-    replaced = true;
-    return `version = "${version}"`;
+// The app and the core library crate are released together.
+const cargoPaths = [
+  join(root, "src-tauri", "Cargo.toml"),
+  join(root, "src-tauri", "core", "Cargo.toml"),
+];
+
+for (const cargoPath of cargoPaths) {
+  const lines = readFileSync(cargoPath, "utf8").split("\n");
+
+  let replaced = false;
+  const updated = lines.map((line) => {
+    // Only replace the first bare `version = "..."` (the [package] field).
+    // Dependency version fields look like `foo = { version = "..." }` so they
+    // contain `{` on the same line — skip those.
+    if (!replaced && PACKAGE_VERSION_RE.test(line) && !line.includes("{")) {
+      replaced = true;
+      return `version = "${version}"`;
+    }
+    return line;
+  });
+
+  if (!replaced) {
+    console.error(`No version field found in ${cargoPath} — nothing updated.`);
+    process.exit(1);
   }
-  return line;
-});
 
-if (!replaced) {
-  console.error("No version field found in Cargo.toml — nothing updated.");
-  process.exit(1);
+  writeFileSync(cargoPath, updated.join("\n"), "utf8");
+  console.log(`Synced ${cargoPath} version → ${version}`);
 }
 
-writeFileSync(cargoPath, updated.join("\n"), "utf8");
-console.log(`Synced Cargo.toml version → ${version}`);
-
 const tauriConfPath = join(root, "src-tauri", "tauri.conf.json");
 const tauriConfLines = readFileSync(tauriConfPath, "utf8").split("\n");
 const updatedTauriConf = tauriConfLines.map((line) => {
@@ -0,0 +1,300 @@
+use crate::clock;
+use crate::credentials::CredentialManager;
+use crate::endpoint;
+use crate::failed_payloads;
+use crate::i18n::{ErrorCode, ParseError};
+use crate::models::{
+    AnthropicCostResult, AnthropicMessagesUsage, AnthropicReportPage, AnthropicUsageData, Provider,
+};
+use crate::rate_limit::{self, RateLimited};
+use crate::redact::Redacted;
+use crate::singleflight::SingleFlight;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, SecondsFormat, TimeZone, Utc};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+use crate::{debug_error, debug_net};
+
+const COST_REPORT_URL: &str = "https://api.anthropic.com/v1/organizations/cost_report";
+const USAGE_REPORT_URL: &str = "https://api.anthropic.com/v1/organizations/usage_report/messages";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Daily buckets per page; 31 covers a whole month in one request.
+const PAGE_LIMIT: &str = "31";
+/// Pages followed per report before using what was read, in case `next_page` never runs out.
+const MAX_PAGES: usize = 4;
+
+/// The cost report gives amounts in cents; divide by this to get dollars.
+const CENTS_TO_DOLLARS: f64 = 100.0;
+
+pub struct AnthropicService;
+
+impl AnthropicService {
+    /// Concurrent calls share one fetch of both reports.
+    pub async fn anthropic_fetch_usage(client: Arc<reqwest::Client>) -> Result<AnthropicUsageData> {
+        static FLIGHT: SingleFlight<AnthropicUsageData> = SingleFlight::new();
+        FLIGHT.run(|| Self::fetch_usage(client)).await
+    }
+
+    async fn fetch_usage(client: Arc<reqwest::Client>) -> Result<AnthropicUsageData> {
+        let api_key = CredentialManager::anthropic_read_admin_key_async().await?;
+        debug_net!("Using Anthropic Admin API key: {}", Redacted(&api_key));
+
+        let period_start =
+            month_start(clock::now_millis()).ok_or_else(|| anyhow!("System clock out of range"))?;
+        let starting_at = period_start.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+        let costs: Vec<AnthropicCostResult> =
+            Self::fetch_report(&client, COST_REPORT_URL, &api_key, &starting_at).await?;
+        let usage: Vec<AnthropicMessagesUsage> =
+            Self::fetch_report(&client, USAGE_REPORT_URL, &api_key, &starting_at).await?;
+        let resets_at =
+            next_month_start(period_start).ok_or_else(|| anyhow!("System clock out of range"))?;
+        summarize(
+            &costs,
+            &usage,
+            period_start.timestamp_millis(),
+            resets_at.timestamp_millis(),
+        )
+    }
+
+    fn request(
+        client: &reqwest::Client,
+        url: &'static str,
+        api_key: &str,
+    ) -> reqwest::RequestBuilder {
+        client
+            .get(endpoint::resolve(url).as_ref())
+            .timeout(endpoint::timeout(Provider::Anthropic))
+            .header("x-api-key", api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+    }
+
+    /// Every result of a daily report since `starting_at`, following `next_page`.
+    async fn fetch_report<T: DeserializeOwned>(
+        client: &reqwest::Client,
+        url: &'static str,
+        api_key: &str,
+        starting_at: &str,
+    ) -> Result<Vec<T>> {
+        let mut results = Vec::new();
+        let mut page: Option<String> = None;
+        for _ in 0..MAX_PAGES {
+            debug_net!("GET {url}");
+            let mut query = vec![
+                ("starting_at", starting_at),
+                ("bucket_width", "1d"),
+                ("limit", PAGE_LIMIT),
+            ];
+            if let Some(page) = &page {
+                query.push(("page", page));
+            }
+            let response = Self::request(client, url, api_key)
+                .query(&query)
+                .send()
+                .await?;
+            let text = Self::check_status(response)?.text().await?;
+
+            let report: AnthropicReportPage<T> = serde_json::from_str(&text).map_err(|e| {
+                failed_payloads::capture(Provider::Anthropic, &text, &e);
+                ParseError(format!("Failed to parse Anthropic report: {e}"))
+            })?;
+            results.extend(report.data.into_iter().flat_map(|bucket| bucket.results));
+            match report.next_page {
+                Some(next) if report.has_more => page = Some(next),
+                _ => return Ok(results),
+            }
+        }
+        debug_error!("Anthropic report has more than {MAX_PAGES} pages, using the first ones");
+        Ok(results)
+    }
+
+    fn check_status(response: reqwest::Response) -> Result<reqwest::Response> {
+        let status = response.status();
+        debug_net!("Response status: {status}");
+        rate_limit::record_quota(Provider::Anthropic, response.headers());
+
+        match status {
+            status if status.is_success() => Ok(response),
+            StatusCode::UNAUTHORIZED => {
+                debug_error!("Invalid Anthropic Admin API key");
+                Err(ErrorCode::InvalidApiKey.into())
+            }
+            StatusCode::FORBIDDEN => {
+                debug_error!("Access denied to Anthropic usage reports");
+                Err(ErrorCode::AccessDenied.into())
+            }
+            StatusCode::TOO_MANY_REQUESTS => {
+                debug_error!("Anthropic rate limit exceeded");
+                Err(RateLimited::from_headers(
+                    response.headers(),
+                    "Anthropic: Rate limited — please wait",
+                )
+                .into())
+            }
+            status if status.is_server_error() => {
+                debug_error!("Anthropic server error");
+                Err(ErrorCode::ServerError.into())
+            }
+            _ => {
+                debug_error!("Failed to fetch Anthropic usage report");
+                Err(ErrorCode::FetchFailed.into())
+            }
+        }
+    }
+
+    pub async fn anthropic_has_admin_key() -> bool {
+        CredentialManager::anthropic_has_admin_key_async().await
+    }
+
+    /// Checks that `api_key` is an Admin API key that may read the cost report.
+    pub async fn validate_admin_key(client: Arc<reqwest::Client>, api_key: &str) -> Result<()> {
+        let api_key = api_key.trim();
+        if api_key.is_empty() {
+            return Err(ErrorCode::ApiKeyEmpty.into());
+        }
+
+        let api_key_lower = api_key.to_lowercase();
+        if api_key_lower.starts_with("{env:") || api_key_lower.starts_with("$env:") {
+            return Ok(());
+        }
+        // Regular keys are rejected by the report endpoints with a bare 401
+        if api_key.starts_with("sk-ant-api") {
+            return Err(anyhow!(
+                "Usage reports need an Admin API key (sk-ant-admin...), not a regular API key"
+            ));
+        }
+
+        let api_key = CredentialManager::resolve_env_reference(api_key)?;
+        let starting_at = month_start(clock::now_millis())
+            .ok_or_else(|| anyhow!("System clock out of range"))?
+            .to_rfc3339_opts(SecondsFormat::Secs, true);
+
+        debug_net!("GET {COST_REPORT_URL} (validating key)");
+        let response = Self::request(&client, COST_REPORT_URL, &api_key)
+            .query(&[("starting_at", starting_at.as_str()), ("limit", "1")])
+            .send()
+            .await?;
+        Self::check_status(response).map(|_| ())
+    }
+}
+
+/// Midnight UTC on the first of the month `now_millis` falls in; reports are billed by UTC day.
+fn month_start(now_millis: i64) -> Option<DateTime<Utc>> {
+    let now = DateTime::<Utc>::from_timestamp_millis(now_millis)?;
+    Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+}
+
+fn next_month_start(start: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let (year, month) = match start.month() {
+        12 => (start.year() + 1, 1),
+        month => (start.year(), month + 1),
+    };
+    Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()
+}
+
+fn summarize(
+    costs: &[AnthropicCostResult],
+    usage: &[AnthropicMessagesUsage],
+    period_start: i64,
+    resets_at: i64,
+) -> Result<AnthropicUsageData> {
+    let mut cents = 0.0;
+    for cost in costs {
+        cents += cost.amount.trim().parse::<f64>().map_err(|e| {
+            ParseError(format!(
+                "Invalid Anthropic cost amount {:?}: {e}",
+                cost.amount
+            ))
+        })?;
+    }
+
+    let mut data = AnthropicUsageData {
+        month_cost: cents / CENTS_TO_DOLLARS,
+        currency: costs
+            .iter()
+            .find_map(|cost| cost.currency.clone())
+            .unwrap_or_else(|| "USD".to_string()),
+        input_tokens: 0,
+        cache_creation_input_tokens: 0,
+        cache_read_input_tokens: 0,
+        output_tokens: 0,
+        period_start,
+        resets_at,
+    };
+    for result in usage {
+        data.input_tokens += result.uncached_input_tokens;
+        data.cache_creation_input_tokens += result.cache_creation.ephemeral_1h_input_tokens
+            + result.cache_creation.ephemeral_5m_input_tokens;
+        data.cache_read_input_tokens += result.cache_read_input_tokens;
+        data.output_tokens += result.output_tokens;
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flatten<T>(page: AnthropicReportPage<T>) -> Vec<T> {
+        page.data.into_iter().flat_map(|b| b.results).collect()
+    }
+
+    #[test]
+    fn test_month_start() {
+        // 2025-03-15T12:34:56Z
+        let start = month_start(1_742_042_096_000).unwrap();
+        assert_eq!(
+            start.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "2025-03-01T00:00:00Z"
+        );
+        let december = month_start(1_765_000_000_000).unwrap();
+        assert_eq!(
+            next_month_start(december)
+                .unwrap()
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            "2026-01-01T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn test_summarize_reports() {
+        let costs: AnthropicReportPage<AnthropicCostResult> = serde_json::from_str(
+            r#"{"data": [
+                {"starting_at": "2025-03-01T00:00:00Z", "ending_at": "2025-03-02T00:00:00Z",
+                 "results": [{"currency": "USD", "amount": "1234.5", "model": null}]},
+                {"starting_at": "2025-03-02T00:00:00Z", "ending_at": "2025-03-03T00:00:00Z",
+                 "results": [{"currency": "USD", "amount": "65.5"}]}
+            ], "has_more": false, "next_page": null}"#,
+        )
+        .unwrap();
+        let usage: AnthropicReportPage<AnthropicMessagesUsage> = serde_json::from_str(
+            r#"{"data": [
+                {"starting_at": "2025-03-01T00:00:00Z", "ending_at": "2025-03-02T00:00:00Z",
+                 "results": [{"uncached_input_tokens": 100, "cache_read_input_tokens": 40,
+                              "cache_creation": {"ephemeral_1h_input_tokens": 5, "ephemeral_5m_input_tokens": 10},
+                              "output_tokens": 70, "server_tool_use": {"web_search_requests": 0}}]},
+                {"starting_at": "2025-03-02T00:00:00Z", "ending_at": "2025-03-03T00:00:00Z",
+                 "results": [{"uncached_input_tokens": 1, "output_tokens": 2}]}
+            ], "has_more": false, "next_page": null}"#,
+        )
+        .unwrap();
+
+        let data = summarize(&flatten(costs), &flatten(usage), 0, 1).unwrap();
+        assert_eq!(data.month_cost, 13.0);
+        assert_eq!(data.currency, "USD");
+        assert_eq!(data.input_tokens, 101);
+        assert_eq!(data.cache_creation_input_tokens, 15);
+        assert_eq!(data.cache_read_input_tokens, 40);
+        assert_eq!(data.output_tokens, 72);
+
+        let bad = [AnthropicCostResult {
+            amount: "n/a".to_string(),
+            currency: None,
+        }];
+        assert!(summarize(&bad, &[], 0, 1).is_err());
+    }
+}
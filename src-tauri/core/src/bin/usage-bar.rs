@@ -0,0 +1,292 @@
+//! `usage-bar [--json] [claude|codex|zai|amp|anthropic|gemini]...`: prints the usage the tray
+//! app shows and exits. With no providers named, every provider that has credentials is fetched.
+
+use serde_json::{json, Map, Value};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use usage_bar_core::amp_service::AmpService;
+use usage_bar_core::anthropic_service::AnthropicService;
+use usage_bar_core::claude_service::ClaudeService;
+use usage_bar_core::codex_service::CodexService;
+use usage_bar_core::credentials::CredentialManager;
+use usage_bar_core::gemini_service::GeminiService;
+use usage_bar_core::i18n::UsageError;
+use usage_bar_core::models::Provider;
+use usage_bar_core::zai_service::ZaiService;
+
+const USAGE: &str = "\
+Usage: usage-bar [--json] [claude|codex|zai|amp|anthropic|gemini]...
+
+Prints current usage for the named providers, or for every provider with stored
+credentials when none are named.
+
+Options:
+  --json      Print a JSON object keyed by provider instead of a one-line summary
+  -h, --help  Show this help";
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    json: bool,
+    /// Empty means every configured provider.
+    providers: Vec<Provider>,
+}
+
+fn parse_provider(name: &str) -> Option<Provider> {
+    match name.to_ascii_lowercase().as_str() {
+        "claude" => Some(Provider::Claude),
+        "codex" => Some(Provider::Codex),
+        "zai" | "z.ai" => Some(Provider::Zai),
+        "amp" => Some(Provider::Amp),
+        "anthropic" => Some(Provider::Anthropic),
+        "gemini" => Some(Provider::Gemini),
+        _ => None,
+    }
+}
+
+/// `Ok(None)` means help was requested.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut parsed = Args {
+        json: false,
+        providers: Vec::new(),
+    };
+    for arg in args {
+        match arg.as_str() {
+            "--json" => parsed.json = true,
+            "-h" | "--help" => return Ok(None),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {arg}")),
+            _ => {
+                let provider =
+                    parse_provider(&arg).ok_or_else(|| format!("Unknown provider {arg}"))?;
+                if !parsed.providers.contains(&provider) {
+                    parsed.providers.push(provider);
+                }
+            }
+        }
+    }
+    Ok(Some(parsed))
+}
+
+fn name(provider: Provider) -> &'static str {
+    match provider {
+        Provider::Claude => "Claude",
+        Provider::Codex => "Codex",
+        Provider::Zai => "Z.ai",
+        Provider::Amp => "Amp",
+        Provider::Anthropic => "Anthropic API",
+        Provider::Gemini => "Gemini API",
+    }
+}
+
+struct Clients {
+    http: Arc<reqwest::Client>,
+    amp: Arc<reqwest::Client>,
+}
+
+async fn has_credentials(provider: Provider) -> bool {
+    match provider {
+        Provider::Claude => CredentialManager::claude_read_credentials_async()
+            .await
+            .is_ok(),
+        Provider::Codex => CodexService::codex_has_auth(),
+        Provider::Zai => ZaiService::zai_has_api_key().await,
+        Provider::Amp => AmpService::amp_has_credentials().await,
+        Provider::Anthropic => AnthropicService::anthropic_has_admin_key().await,
+        Provider::Gemini => GeminiService::gemini_has_credentials().await,
+    }
+}
+
+/// One provider's usage as JSON and as a summary fragment like `Claude (Max) 5h 42% · 7d 13%`.
+async fn fetch(provider: Provider, clients: &Clients) -> Result<(Value, String)> {
+    let label = name(provider);
+    match provider {
+        Provider::Claude => {
+            ClaudeService::check_and_refresh_if_needed(clients.http.clone()).await?;
+            let (usage, tier) =
+                ClaudeService::claude_fetch_usage_and_tier(clients.http.clone()).await?;
+            let summary = format!(
+                "{label} ({}) 5h {:.0}% · 7d {:.0}%",
+                tier.plan_name, usage.five_hour_utilization, usage.seven_day_utilization
+            );
+            Ok((json!({ "usage": usage, "tier": tier }), summary))
+        }
+        Provider::Codex => {
+            let (usage, tier) =
+                CodexService::codex_fetch_usage_and_tier(clients.http.clone()).await?;
+            let mut summary = format!("{label} ({})", tier.plan_name);
+            for (window, reading) in [("5h", &usage.session_usage), ("7d", &usage.weekly_usage)] {
+                if let Some(reading) = reading {
+                    summary.push_str(&format!(" {window} {:.0}%", reading.percentage));
+                }
+            }
+            Ok((json!({ "usage": usage, "tier": tier }), summary))
+        }
+        Provider::Zai => {
+            let usage = ZaiService::zai_fetch_quota(clients.http.clone()).await?;
+            let mut summary = label.to_string();
+            if let Some(tier) = &usage.tier_name {
+                summary.push_str(&format!(" ({tier})"));
+            }
+            if let Some(tokens) = &usage.token_usage {
+                summary.push_str(&format!(" tokens {:.0}%", tokens.percentage));
+            }
+            if let Some(mcp) = &usage.mcp_usage {
+                summary.push_str(&format!(" · MCP {:.0}%", mcp.percentage));
+            }
+            Ok((json!({ "usage": usage }), summary))
+        }
+        Provider::Amp => {
+            let usage = AmpService::amp_fetch_usage(&clients.amp).await?;
+            let summary = format!(
+                "{label} {:.0}% (${:.2} of ${:.2})",
+                usage.used_percent, usage.used, usage.quota
+            );
+            Ok((json!({ "usage": usage }), summary))
+        }
+        Provider::Anthropic => {
+            let usage = AnthropicService::anthropic_fetch_usage(clients.http.clone()).await?;
+            let summary = format!(
+                "{label} {:.2} {} this month · {} in / {} out tokens",
+                usage.month_cost,
+                usage.currency,
+                usage.input_tokens
+                    + usage.cache_creation_input_tokens
+                    + usage.cache_read_input_tokens,
+                usage.output_tokens
+            );
+            Ok((json!({ "usage": usage }), summary))
+        }
+        Provider::Gemini => {
+            let usage = GeminiService::gemini_fetch_usage(clients.http.clone()).await?;
+            let mut summary = format!("{label} ({})", usage.project_id);
+            for (window, quota) in [("day", &usage.per_day), ("minute", &usage.per_minute)] {
+                if let Some(model) = &quota.model {
+                    summary.push_str(&format!(" {window} [{model}]"));
+                } else {
+                    summary.push_str(&format!(" {window}"));
+                }
+                match quota.limit {
+                    Some(limit) => {
+                        summary.push_str(&format!(" {}/{limit} requests", quota.requests))
+                    }
+                    None => summary.push_str(&format!(" {} requests", quota.requests)),
+                }
+            }
+            Ok((json!({ "usage": usage }), summary))
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<ExitCode> {
+    let clients = Clients {
+        http: Arc::new(
+            reqwest::Client::builder()
+                .timeout(Duration::from_secs(15))
+                .build()?,
+        ),
+        amp: Arc::new(AmpService::http_client()?),
+    };
+
+    let providers = if args.providers.is_empty() {
+        let mut configured = Vec::new();
+        for provider in Provider::ALL {
+            if has_credentials(provider).await {
+                configured.push(provider);
+            }
+        }
+        if configured.is_empty() {
+            eprintln!("No provider has credentials set up");
+            return Ok(ExitCode::FAILURE);
+        }
+        configured
+    } else {
+        args.providers
+    };
+
+    let mut failed = false;
+    let mut output = Map::new();
+    let mut parts = Vec::new();
+    for provider in providers {
+        match fetch(provider, &clients).await {
+            Ok((value, summary)) => {
+                output.insert(provider.key().to_string(), value);
+                parts.push(summary);
+            }
+            Err(e) => {
+                failed = true;
+                let error = UsageError::from(&e);
+                parts.push(format!("{}: {}", name(provider), error.message()));
+                output.insert(provider.key().to_string(), json!({ "error": error }));
+            }
+        }
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        println!("{}", parts.join(" | "));
+    }
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let runtime = match tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start runtime: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    runtime.block_on(run(args)).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        ExitCode::FAILURE
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<Option<Args>, String> {
+        parse_args(list.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            args(&["--json", "claude", "Z.ai", "claude"]),
+            Ok(Some(Args {
+                json: true,
+                providers: vec![Provider::Claude, Provider::Zai],
+            }))
+        );
+        assert_eq!(args(&["--help"]), Ok(None));
+        assert_eq!(
+            args(&["gemini"]).map(|a| a.map(|a| a.providers)),
+            Ok(Some(vec![Provider::Gemini]))
+        );
+        assert!(args(&["cursor"]).is_err());
+        assert!(args(&["--yaml"]).is_err());
+    }
+}
@@ -0,0 +1,382 @@
+//! Reads cookies straight from installed browsers' cookie stores, so a session cookie can
+//! be imported instead of copied out of devtools.
+//!
+//! Chromium browsers (Chrome, Edge, Brave) are read on Windows only: their cookies are
+//! AES-256-GCM encrypted under a key the `Local State` file keeps DPAPI-protected. macOS and
+//! Linux builds use AES-128-CBC, which `ring` doesn't offer. Firefox stores cookies in the
+//! clear and is read everywhere.
+
+use crate::credentials::CredentialManager;
+use crate::i18n::ErrorCode;
+use crate::vault;
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use rusqlite::{Connection, OpenFlags};
+use std::path::{Path, PathBuf};
+
+use crate::debug_cred;
+
+/// Chromium browsers by their `User Data` folder under `%LOCALAPPDATA%`.
+const CHROMIUM_BROWSERS: [(&str, &str); 3] = [
+    ("Chrome", r"Google\Chrome\User Data"),
+    ("Edge", r"Microsoft\Edge\User Data"),
+    ("Brave", r"BraveSoftware\Brave-Browser\User Data"),
+];
+
+/// `encrypted_key` in `Local State` is prefixed with this before the DPAPI blob.
+const DPAPI_PREFIX: &[u8] = b"DPAPI";
+
+/// From cookie database version 24, Chromium prepends the SHA-256 of the host to the value
+/// before encrypting it.
+const HOST_HASH_VERSION: i64 = 24;
+const HOST_HASH_LEN: usize = 32;
+
+/// A cookie found in a browser profile. Holds the value, so it is never logged.
+pub struct BrowserCookie {
+    pub browser: &'static str,
+    /// Profile folder name, e.g. `Default` or `Profile 1`.
+    pub profile: String,
+    pub value: String,
+}
+
+/// Every non-empty `name` cookie for `domain` (or `.domain`) across installed browsers,
+/// most recently used first within each browser. Stores that can't be read are skipped; their
+/// errors are logged, and `BrowserCookiesUnreadable` returned only if nothing was found.
+pub fn find_cookies(domain: &str, name: &str) -> Result<Vec<BrowserCookie>> {
+    let mut found = Vec::new();
+    let mut errors = Vec::new();
+
+    for store in cookie_stores() {
+        match read_store(&store, domain, name) {
+            Ok(values) => found.extend(values.into_iter().map(|value| BrowserCookie {
+                browser: store.browser,
+                profile: store.profile.clone(),
+                value,
+            })),
+            Err(e) => {
+                debug_cred!(
+                    "{} ({}) cookies unreadable: {e}",
+                    store.browser,
+                    store.profile
+                );
+                errors.push(format!("{}: {e}", store.browser));
+            }
+        }
+    }
+
+    if found.is_empty() && !errors.is_empty() {
+        errors.dedup();
+        debug_cred!("Could not read browser cookies ({})", errors.join("; "));
+        return Err(ErrorCode::BrowserCookiesUnreadable.into());
+    }
+    Ok(found)
+}
+
+enum StoreKind {
+    /// `Network/Cookies`, decrypted with the key in the `User Data` folder's `Local State`.
+    Chromium {
+        local_state: PathBuf,
+    },
+    Firefox,
+}
+
+struct CookieStore {
+    browser: &'static str,
+    profile: String,
+    database: PathBuf,
+    kind: StoreKind,
+}
+
+fn cookie_stores() -> Vec<CookieStore> {
+    let mut stores = Vec::new();
+
+    if cfg!(target_os = "windows") {
+        if let Some(local) = std::env::var_os("LOCALAPPDATA").filter(|v| !v.is_empty()) {
+            for (browser, folder) in CHROMIUM_BROWSERS {
+                let user_data = PathBuf::from(&local).join(folder);
+                for profile in subdirectories(&user_data) {
+                    let Some(database) = ["Network/Cookies", "Cookies"]
+                        .iter()
+                        .map(|file| profile.join(file))
+                        .find(|path| path.is_file())
+                    else {
+                        continue;
+                    };
+                    stores.push(CookieStore {
+                        browser,
+                        profile: folder_name(&profile),
+                        database,
+                        kind: StoreKind::Chromium {
+                            local_state: user_data.join("Local State"),
+                        },
+                    });
+                }
+            }
+        }
+    }
+
+    if let Some(profiles) = firefox_profiles_dir() {
+        for profile in subdirectories(&profiles) {
+            let database = profile.join("cookies.sqlite");
+            if database.is_file() {
+                stores.push(CookieStore {
+                    browser: "Firefox",
+                    profile: folder_name(&profile),
+                    database,
+                    kind: StoreKind::Firefox,
+                });
+            }
+        }
+    }
+
+    stores
+}
+
+fn firefox_profiles_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        let appdata = std::env::var_os("APPDATA").filter(|v| !v.is_empty())?;
+        return Some(PathBuf::from(appdata).join(r"Mozilla\Firefox\Profiles"));
+    }
+    let home = CredentialManager::home_dir().ok()?;
+    if cfg!(target_os = "macos") {
+        Some(home.join("Library/Application Support/Firefox/Profiles"))
+    } else {
+        Some(home.join(".mozilla/firefox"))
+    }
+}
+
+fn subdirectories(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut dirs: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+fn folder_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn read_store(store: &CookieStore, domain: &str, name: &str) -> Result<Vec<String>> {
+    let copy = TempCopy::of(&store.database).map_err(|e| {
+        anyhow!(
+            "{e}; close {} and try again if it keeps the file locked",
+            store.browser
+        )
+    })?;
+    let conn = Connection::open_with_flags(&copy.path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let dotted = format!(".{domain}");
+
+    match &store.kind {
+        StoreKind::Firefox => firefox_values(&conn, domain, &dotted, name),
+        StoreKind::Chromium { local_state } => {
+            let key = chromium_key(local_state)?;
+            let strip_host_hash = chromium_db_version(&conn) >= HOST_HASH_VERSION;
+            let mut stmt = conn.prepare(
+                "SELECT value, encrypted_value FROM cookies
+                 WHERE host_key IN (?1, ?2) AND name = ?3
+                 ORDER BY last_access_utc DESC",
+            )?;
+            let rows = stmt.query_map([domain, dotted.as_str(), name], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })?;
+            let mut values = Vec::new();
+            for row in rows {
+                let (plain, encrypted) = row?;
+                let value = if encrypted.is_empty() {
+                    plain
+                } else {
+                    decrypt_chromium(&key, &encrypted, strip_host_hash)?
+                };
+                if !value.is_empty() {
+                    values.push(value);
+                }
+            }
+            Ok(values)
+        }
+    }
+}
+
+fn firefox_values(
+    conn: &Connection,
+    domain: &str,
+    dotted: &str,
+    name: &str,
+) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT value FROM moz_cookies
+         WHERE host IN (?1, ?2) AND name = ?3
+         ORDER BY lastAccessed DESC",
+    )?;
+    let values = stmt
+        .query_map([domain, dotted, name], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(values
+        .into_iter()
+        .filter(|value| !value.is_empty())
+        .collect())
+}
+
+fn chromium_db_version(conn: &Connection) -> i64 {
+    conn.query_row("SELECT value FROM meta WHERE key = 'version'", [], |row| {
+        row.get::<_, String>(0)
+    })
+    .ok()
+    .and_then(|version| version.parse().ok())
+    .unwrap_or(0)
+}
+
+/// The AES key from `Local State`: base64 `os_crypt.encrypted_key`, `DPAPI` prefix,
+/// protected for the current Windows user.
+fn chromium_key(local_state: &Path) -> Result<Vec<u8>> {
+    let text = std::fs::read_to_string(local_state)
+        .map_err(|e| anyhow!("Failed to read Local State: {e}"))?;
+    let blob = encrypted_key(&text)?;
+    vault::protect::unprotect(&blob)
+}
+
+fn encrypted_key(local_state: &str) -> Result<Vec<u8>> {
+    let value: serde_json::Value =
+        serde_json::from_str(local_state).map_err(|e| anyhow!("Local State is not JSON: {e}"))?;
+    let encoded = value
+        .pointer("/os_crypt/encrypted_key")
+        .and_then(serde_json::Value::as_str)
+        .ok_or_else(|| anyhow!("Local State has no os_crypt.encrypted_key"))?;
+    let decoded = STANDARD
+        .decode(encoded)
+        .map_err(|e| anyhow!("os_crypt.encrypted_key is not base64: {e}"))?;
+    decoded
+        .strip_prefix(DPAPI_PREFIX)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| anyhow!("os_crypt.encrypted_key is not DPAPI-protected"))
+}
+
+/// `v10` values are `nonce || ciphertext || tag` under AES-256-GCM. `v20` (app-bound
+/// encryption, Chrome 127+) can only be decrypted by the browser itself.
+fn decrypt_chromium(key: &[u8], encrypted: &[u8], strip_host_hash: bool) -> Result<String> {
+    let Some(payload) = encrypted.strip_prefix(b"v10") else {
+        if encrypted.starts_with(b"v20") {
+            return Err(anyhow!(
+                "cookies use app-bound encryption, which only the browser can read"
+            ));
+        }
+        return Err(anyhow!("unsupported cookie encryption"));
+    };
+    if payload.len() < NONCE_LEN {
+        return Err(anyhow!("encrypted cookie is truncated"));
+    }
+    let (nonce, sealed) = payload.split_at(NONCE_LEN);
+    let key = LessSafeKey::new(
+        UnboundKey::new(&AES_256_GCM, key)
+            .map_err(|_| anyhow!("cookie key has the wrong length"))?,
+    );
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| anyhow!("bad cookie nonce"))?;
+    let mut buffer = sealed.to_vec();
+    let plain = key
+        .open_in_place(nonce, Aad::empty(), &mut buffer)
+        .map_err(|_| anyhow!("failed to decrypt cookie"))?;
+    let plain = if strip_host_hash && plain.len() >= HOST_HASH_LEN {
+        &plain[HOST_HASH_LEN..]
+    } else {
+        &plain[..]
+    };
+    String::from_utf8(plain.to_vec()).map_err(|_| anyhow!("decrypted cookie is not UTF-8"))
+}
+
+/// Browsers keep their cookie database open (and on Windows, locked against other
+/// writers), so it is read from a copy, along with Firefox's write-ahead log.
+struct TempCopy {
+    path: PathBuf,
+}
+
+impl TempCopy {
+    fn of(database: &Path) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!(
+            "usage-bar-cookies-{}-{}.sqlite",
+            std::process::id(),
+            crate::clock::now_millis()
+        ));
+        std::fs::copy(database, &path).map_err(|e| anyhow!("Failed to copy cookies: {e}"))?;
+        let copy = TempCopy { path };
+        let wal = database.with_extension("sqlite-wal");
+        if wal.is_file() {
+            // Best effort: without it the copy is only missing the newest writes
+            let _ = std::fs::copy(&wal, copy.wal());
+        }
+        Ok(copy)
+    }
+
+    fn wal(&self) -> PathBuf {
+        self.path.with_extension("sqlite-wal")
+    }
+}
+
+impl Drop for TempCopy {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        let _ = std::fs::remove_file(self.wal());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrypt_chromium_v10() {
+        let key = [7u8; 32];
+        let nonce = [1u8; NONCE_LEN];
+        let mut sealed = [[0u8; HOST_HASH_LEN].as_slice(), b"sess-123"].concat();
+        LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).unwrap())
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut sealed,
+            )
+            .unwrap();
+        let encrypted = [b"v10".as_slice(), &nonce, &sealed].concat();
+
+        assert_eq!(
+            decrypt_chromium(&key, &encrypted, true).unwrap(),
+            "sess-123"
+        );
+        assert!(decrypt_chromium(&[8u8; 32], &encrypted, true).is_err());
+        assert!(decrypt_chromium(&key, b"v20abc", true)
+            .unwrap_err()
+            .to_string()
+            .contains("app-bound"));
+    }
+
+    #[test]
+    fn test_encrypted_key_strips_dpapi_prefix() {
+        // base64("DPAPIkey")
+        let local_state = r#"{"os_crypt":{"encrypted_key":"RFBBUElrZXk="}}"#;
+        assert_eq!(encrypted_key(local_state).unwrap(), b"key");
+        assert!(encrypted_key(r#"{"os_crypt":{"encrypted_key":"a2V5"}}"#).is_err());
+        assert!(encrypted_key("{}").is_err());
+    }
+
+    #[test]
+    fn test_firefox_values() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE moz_cookies (host TEXT, name TEXT, value TEXT, lastAccessed INTEGER);
+             INSERT INTO moz_cookies VALUES ('.ampcode.com', 'session', 'old', 1);
+             INSERT INTO moz_cookies VALUES ('ampcode.com', 'session', 'new', 2);
+             INSERT INTO moz_cookies VALUES ('ampcode.com', 'other', 'x', 3);
+             INSERT INTO moz_cookies VALUES ('evil.com', 'session', 'y', 4);",
+        )
+        .unwrap();
+        let values = firefox_values(&conn, "ampcode.com", ".ampcode.com", "session").unwrap();
+        assert_eq!(values, ["new", "old"]);
+    }
+}
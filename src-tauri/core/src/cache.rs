@@ -0,0 +1,600 @@
+use anyhow::Result;
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::clock;
+use crate::i18n::UsageError;
+use crate::models::{
+    AmpUsageData, AnthropicUsageData, ClaudeTierData, CodexTierData, CodexUsageData,
+    GeminiUsageData, Provider, UsageData, ZaiAccountUsage, ZaiBalanceData, ZaiTierData,
+    ZaiUsageData,
+};
+use crate::rate_limit::RateLimited;
+use crate::{debug_cache, debug_error};
+
+pub struct CacheEntry<T> {
+    /// Shared so a hit is a pointer copy, not a deep clone of the payload.
+    data: Arc<T>,
+    expires_at: Instant,
+    fetched_at: Instant,
+}
+
+/// Do-not-fetch window started by a 429 from the provider.
+struct Backoff {
+    until: Instant,
+    /// Same deadline as `until`, as epoch millis for the frontend.
+    until_epoch_ms: i64,
+}
+
+/// What `ResponseCache::get_or_fetch` served.
+pub enum Cached<T> {
+    /// A fresh entry, or a value fetched (and stored) just now.
+    Fresh(Arc<T>),
+    /// The provider is backing off after a 429; `data` is the last stored value.
+    RateLimited { data: Arc<T>, until: i64 },
+    /// The fetch failed; `data` is the last stored value, fetched at `fetched_at` (epoch
+    /// millis).
+    Stale {
+        data: Arc<T>,
+        fetched_at: i64,
+        error: anyhow::Error,
+    },
+}
+
+pub struct ResponseCache<T> {
+    entry: Arc<Mutex<Option<CacheEntry<T>>>>,
+    backoff: Arc<Mutex<Option<Backoff>>>,
+    /// Seconds; atomic so settings can change it while the cache is shared.
+    ttl_seconds: AtomicU64,
+}
+
+fn lock_recovering<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        debug_error!("Cache mutex poisoned, recovering...");
+        poisoned.into_inner()
+    })
+}
+
+impl<T> ResponseCache<T> {
+    pub fn new(ttl_seconds: u64) -> Self {
+        Self {
+            entry: Arc::new(Mutex::new(None)),
+            backoff: Arc::new(Mutex::new(None)),
+            ttl_seconds: AtomicU64::new(ttl_seconds),
+        }
+    }
+
+    /// Applies to values stored from now on; the current entry keeps its expiry.
+    pub fn set_ttl(&self, ttl_seconds: u64) {
+        self.ttl_seconds.store(ttl_seconds, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> Option<Arc<T>> {
+        let guard = lock_recovering(&self.entry);
+
+        guard.as_ref().and_then(|entry| {
+            if Instant::now() < entry.expires_at {
+                debug_cache!("Hit: Returning cached data");
+                Some(Arc::clone(&entry.data))
+            } else {
+                debug_cache!("Miss: Cache entry expired");
+                None
+            }
+        })
+    }
+
+    /// Returns the last stored value even if its TTL has passed.
+    /// Only meant for fallbacks (e.g. rate limiting) — normal reads go through `get()`.
+    pub fn get_stale(&self) -> Option<Arc<T>> {
+        let guard = lock_recovering(&self.entry);
+        guard.as_ref().map(|entry| Arc::clone(&entry.data))
+    }
+
+    pub fn set(&self, data: impl Into<Arc<T>>) {
+        let mut guard = lock_recovering(&self.entry);
+
+        let now = Instant::now();
+        let ttl_seconds = self.ttl_seconds.load(Ordering::Relaxed);
+        *guard = Some(CacheEntry {
+            data: data.into(),
+            expires_at: now + Duration::from_secs(ttl_seconds),
+            fetched_at: now,
+        });
+        debug_cache!("Set: Cached data (TTL: {ttl_seconds}s)");
+    }
+
+    /// Stores a value fetched `age` ago (by an earlier run), already expired: `get_stale()`
+    /// and `age()` see it, but the next `get()` still misses and fetches.
+    pub fn seed(&self, data: impl Into<Arc<T>>, age: Duration) {
+        let mut guard = lock_recovering(&self.entry);
+
+        let now = Instant::now();
+        *guard = Some(CacheEntry {
+            data: data.into(),
+            expires_at: now,
+            fetched_at: now.checked_sub(age).unwrap_or(now),
+        });
+        let age_seconds = age.as_secs();
+        debug_cache!("Seed: Stale data from {age_seconds}s ago");
+    }
+
+    /// Time since the stored value was fetched, whether or not it has expired.
+    pub fn age(&self) -> Option<Duration> {
+        let guard = lock_recovering(&self.entry);
+        guard.as_ref().map(|entry| entry.fetched_at.elapsed())
+    }
+
+    /// Expires the current entry so the next `get()` misses.
+    /// The value itself is kept for `get_stale()` so a rate-limited refresh still has data to show.
+    pub fn clear(&self) {
+        let mut guard = lock_recovering(&self.entry);
+
+        if let Some(entry) = guard.as_mut() {
+            entry.expires_at = Instant::now();
+        }
+        debug_cache!("Clear: Cache invalidated");
+    }
+
+    /// Serves a fresh entry, or runs `fetch` and stores its result. `force` skips the fresh
+    /// entry but never the backoff window: while rate limited, or when `fetch` hits a 429,
+    /// the last stored value is served instead. Any other failed fetch serves the last
+    /// stored value as `Stale`, unless the provider is no longer configured. With nothing
+    /// stored the error is returned for the caller's own fallback.
+    ///
+    /// No lock is held while `fetch` runs.
+    pub async fn get_or_fetch<F, Fut>(&self, force: bool, fetch: F) -> Result<Cached<T>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        if !force {
+            if let Some(data) = self.get() {
+                return Ok(Cached::Fresh(data));
+            }
+        }
+
+        if let Some(until) = self.rate_limited_until() {
+            debug_cache!("Backoff: Rate limited until {until}, skipping fetch");
+            return match self.get_stale() {
+                Some(data) => Ok(Cached::RateLimited { data, until }),
+                // Still typed, so the caller learns how long is left of the window.
+                None => Err(RateLimited::new(
+                    Duration::from_millis((until - clock::now_millis()).max(0) as u64),
+                    "Rate limited — please wait and try again",
+                )
+                .into()),
+            };
+        }
+
+        match fetch().await {
+            Ok(data) => {
+                let data = Arc::new(data);
+                self.set(Arc::clone(&data));
+                Ok(Cached::Fresh(data))
+            }
+            Err(e) => {
+                debug_cache!("Fetch failed: {e}");
+                let Some(rate_limited) = e.downcast_ref::<RateLimited>() else {
+                    return self.stale_or(e);
+                };
+                self.set_rate_limited(rate_limited.retry_after);
+                match (self.rate_limited_until(), self.get_stale()) {
+                    (Some(until), Some(data)) => Ok(Cached::RateLimited { data, until }),
+                    _ => Err(e),
+                }
+            }
+        }
+    }
+
+    fn stale_or(&self, error: anyhow::Error) -> Result<Cached<T>> {
+        // Missing credentials mean the user signed out; old data would be misleading.
+        if matches!(UsageError::from(&error), UsageError::NotConfigured { .. }) {
+            return Err(error);
+        }
+        let guard = lock_recovering(&self.entry);
+        let Some(entry) = guard.as_ref() else {
+            return Err(error);
+        };
+        debug_cache!("Stale: Serving last stored value after failed fetch");
+        Ok(Cached::Stale {
+            data: Arc::clone(&entry.data),
+            fetched_at: clock::now_millis() - entry.fetched_at.elapsed().as_millis() as i64,
+            error,
+        })
+    }
+
+    /// Starts a do-not-fetch window for this provider.
+    pub fn set_rate_limited(&self, retry_after: Duration) {
+        let mut guard = lock_recovering(&self.backoff);
+
+        let now_ms = clock::now_millis();
+        *guard = Some(Backoff {
+            until: Instant::now() + retry_after,
+            until_epoch_ms: now_ms + retry_after.as_millis() as i64,
+        });
+        let retry_after_seconds = retry_after.as_secs();
+        debug_cache!("Backoff: Provider rate limited for {retry_after_seconds}s");
+    }
+
+    /// Epoch millis at which the current rate-limit window ends, or `None` if fetching is allowed.
+    pub fn rate_limited_until(&self) -> Option<i64> {
+        let mut guard = lock_recovering(&self.backoff);
+
+        match guard.as_ref() {
+            Some(backoff) if Instant::now() < backoff.until => Some(backoff.until_epoch_ms),
+            Some(_) => {
+                debug_cache!("Backoff: Rate-limit window elapsed");
+                *guard = None;
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+/// What a provider response is cached as. Backoff windows live on `Usage`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Usage,
+    Tier,
+    /// Z.ai's pay-as-you-go balance, fetched apart from the quota.
+    Balance,
+    /// Quotas of Z.ai's named keys, fetched after the default key's.
+    Accounts,
+}
+
+impl Endpoint {
+    const ALL: [Endpoint; 4] = [
+        Endpoint::Usage,
+        Endpoint::Tier,
+        Endpoint::Balance,
+        Endpoint::Accounts,
+    ];
+}
+
+/// A `CacheMap` slot and the type stored in it.
+pub struct CacheKey<T> {
+    pub provider: Provider,
+    pub endpoint: Endpoint,
+    data: PhantomData<fn() -> T>,
+}
+
+impl<T> CacheKey<T> {
+    pub const fn new(provider: Provider, endpoint: Endpoint) -> Self {
+        Self {
+            provider,
+            endpoint,
+            data: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for CacheKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for CacheKey<T> {}
+
+pub const CLAUDE_USAGE: CacheKey<UsageData> = CacheKey::new(Provider::Claude, Endpoint::Usage);
+pub const CLAUDE_TIER: CacheKey<ClaudeTierData> = CacheKey::new(Provider::Claude, Endpoint::Tier);
+pub const CODEX_USAGE: CacheKey<CodexUsageData> = CacheKey::new(Provider::Codex, Endpoint::Usage);
+pub const CODEX_TIER: CacheKey<CodexTierData> = CacheKey::new(Provider::Codex, Endpoint::Tier);
+pub const ZAI_USAGE: CacheKey<ZaiUsageData> = CacheKey::new(Provider::Zai, Endpoint::Usage);
+pub const ZAI_TIER: CacheKey<ZaiTierData> = CacheKey::new(Provider::Zai, Endpoint::Tier);
+pub const ZAI_BALANCE: CacheKey<ZaiBalanceData> = CacheKey::new(Provider::Zai, Endpoint::Balance);
+pub const ZAI_ACCOUNTS: CacheKey<BTreeMap<String, ZaiAccountUsage>> =
+    CacheKey::new(Provider::Zai, Endpoint::Accounts);
+pub const AMP_USAGE: CacheKey<AmpUsageData> = CacheKey::new(Provider::Amp, Endpoint::Usage);
+pub const ANTHROPIC_USAGE: CacheKey<AnthropicUsageData> =
+    CacheKey::new(Provider::Anthropic, Endpoint::Usage);
+pub const GEMINI_USAGE: CacheKey<GeminiUsageData> =
+    CacheKey::new(Provider::Gemini, Endpoint::Usage);
+
+/// The parts of a `ResponseCache` that don't depend on its type, so `CacheMap` can act on
+/// a provider without knowing what it caches.
+trait Slot: Send + Sync {
+    fn age(&self) -> Option<Duration>;
+    fn clear(&self);
+    fn set_ttl(&self, ttl_seconds: u64);
+    fn rate_limited_until(&self) -> Option<i64>;
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+}
+
+impl<T: Send + Sync + 'static> Slot for ResponseCache<T> {
+    fn age(&self) -> Option<Duration> {
+        ResponseCache::age(self)
+    }
+
+    fn clear(&self) {
+        ResponseCache::clear(self)
+    }
+
+    fn set_ttl(&self, ttl_seconds: u64) {
+        ResponseCache::set_ttl(self, ttl_seconds)
+    }
+
+    fn rate_limited_until(&self) -> Option<i64> {
+        ResponseCache::rate_limited_until(self)
+    }
+
+    fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+        self
+    }
+}
+
+/// (provider, scope name)
+type ScopeId = (Provider, String);
+/// A provider's slots, taken out of `CacheMap::slots` while their scope is inactive.
+type ParkedSlots = Vec<(Endpoint, Arc<dyn Slot>)>;
+
+/// Every provider's response caches, keyed by `(Provider, Endpoint)`. A slot is created on
+/// first use with the TTL configured for it, so a new provider needs keys, not new state.
+/// A provider can switch between named scopes (Claude profiles), each with its own slots.
+pub struct CacheMap {
+    default_ttl_seconds: u64,
+    ttls: Mutex<HashMap<(Provider, Endpoint), u64>>,
+    slots: Mutex<HashMap<(Provider, Endpoint), Arc<dyn Slot>>>,
+    /// Active scope per provider; absent means the unnamed default scope `""`.
+    scopes: Mutex<HashMap<Provider, String>>,
+    /// Slots of the scopes that are not active, kept so switching back serves them again.
+    parked: Mutex<HashMap<ScopeId, ParkedSlots>>,
+}
+
+impl CacheMap {
+    pub fn new(default_ttl_seconds: u64) -> Self {
+        Self {
+            default_ttl_seconds,
+            ttls: Mutex::new(HashMap::new()),
+            slots: Mutex::new(HashMap::new()),
+            scopes: Mutex::new(HashMap::new()),
+            parked: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Overrides the TTL for one slot; call before the slot is first used.
+    pub fn with_ttl(self, provider: Provider, endpoint: Endpoint, ttl_seconds: u64) -> Self {
+        lock_recovering(&self.ttls).insert((provider, endpoint), ttl_seconds);
+        self
+    }
+
+    /// Sets the TTL of every slot of `provider`, including ones already in use. Stored
+    /// values keep their expiry; the new TTL applies from the next fetch.
+    pub fn set_provider_ttl(&self, provider: Provider, ttl_seconds: u64) {
+        let mut ttls = lock_recovering(&self.ttls);
+        for endpoint in Endpoint::ALL {
+            ttls.insert((provider, endpoint), ttl_seconds);
+            if let Some(slot) = self.slot(provider, endpoint) {
+                slot.set_ttl(ttl_seconds);
+            }
+        }
+        debug_cache!("TTL for {provider:?} set to {ttl_seconds}s");
+    }
+
+    fn slot(&self, provider: Provider, endpoint: Endpoint) -> Option<Arc<dyn Slot>> {
+        lock_recovering(&self.slots)
+            .get(&(provider, endpoint))
+            .cloned()
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self, key: CacheKey<T>) -> Arc<ResponseCache<T>> {
+        let id = (key.provider, key.endpoint);
+        let ttl = lock_recovering(&self.ttls)
+            .get(&id)
+            .copied()
+            .unwrap_or(self.default_ttl_seconds);
+        let mut slots = lock_recovering(&self.slots);
+        let slot = slots
+            .entry(id)
+            .or_insert_with(|| Arc::new(ResponseCache::<T>::new(ttl)));
+        Arc::clone(slot).into_any().downcast().unwrap_or_else(|_| {
+            // Only possible if two keys name the same slot with different types.
+            debug_error!("Cache slot {id:?} holds another type, replacing it");
+            let cache = Arc::new(ResponseCache::new(ttl));
+            *slot = Arc::clone(&cache) as Arc<dyn Slot>;
+            cache
+        })
+    }
+
+    /// Makes `scope` the provider's active scope: its current slots are set aside under the
+    /// old scope and the ones last used under `scope` (none, the first time) take their place.
+    pub fn switch_scope(&self, provider: Provider, scope: &str) {
+        let mut scopes = lock_recovering(&self.scopes);
+        let current = scopes.get(&provider).cloned().unwrap_or_default();
+        if current == scope {
+            return;
+        }
+        // Read before taking `slots`; `set_provider_ttl` locks them the other way round.
+        let ttls: Vec<(Endpoint, u64)> = lock_recovering(&self.ttls)
+            .iter()
+            .filter(|((p, _), _)| *p == provider)
+            .map(|(&(_, endpoint), &ttl)| (endpoint, ttl))
+            .collect();
+
+        let mut slots = lock_recovering(&self.slots);
+        let mut parked = lock_recovering(&self.parked);
+        let outgoing: ParkedSlots = Endpoint::ALL
+            .into_iter()
+            .filter_map(|endpoint| Some((endpoint, slots.remove(&(provider, endpoint))?)))
+            .collect();
+        parked.insert((provider, current), outgoing);
+        for (endpoint, slot) in parked
+            .remove(&(provider, scope.to_string()))
+            .unwrap_or_default()
+        {
+            // The TTL may have changed while the slot was parked.
+            if let Some(&(_, ttl)) = ttls.iter().find(|(e, _)| *e == endpoint) {
+                slot.set_ttl(ttl);
+            }
+            slots.insert((provider, endpoint), slot);
+        }
+        scopes.insert(provider, scope.to_string());
+        debug_cache!("{provider:?} caches switched to scope {scope:?}");
+    }
+
+    /// Age of the provider's cached usage; `None` if nothing has been fetched yet.
+    pub fn usage_age(&self, provider: Provider) -> Option<Duration> {
+        self.slot(provider, Endpoint::Usage)?.age()
+    }
+
+    /// Epoch millis at which the provider's rate-limit window ends, if it is in one.
+    pub fn rate_limited_until(&self, provider: Provider) -> Option<i64> {
+        self.slot(provider, Endpoint::Usage)?.rate_limited_until()
+    }
+
+    /// Expires every slot of `provider`; see `ResponseCache::clear`.
+    pub fn clear(&self, provider: Provider) {
+        let slots: Vec<Arc<dyn Slot>> = lock_recovering(&self.slots)
+            .iter()
+            .filter(|((p, _), _)| *p == provider)
+            .map(|(_, slot)| Arc::clone(slot))
+            .collect();
+        for slot in slots {
+            slot.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::ErrorCode;
+
+    /// (whether the value is rate limited, the value)
+    fn served(result: Result<Cached<u32>>) -> (bool, u32) {
+        match result.unwrap() {
+            Cached::Fresh(data) => (false, *data),
+            Cached::RateLimited { data, .. } | Cached::Stale { data, .. } => (true, *data),
+        }
+    }
+
+    #[test]
+    fn test_get_or_fetch_caches_forces_and_backs_off() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let cache = ResponseCache::new(60);
+        let fetched = |value: u32| move || async move { Ok(value) };
+
+        runtime.block_on(async {
+            assert_eq!(
+                served(cache.get_or_fetch(false, fetched(1)).await),
+                (false, 1)
+            );
+            assert_eq!(
+                served(cache.get_or_fetch(false, fetched(2)).await),
+                (false, 1)
+            );
+            assert_eq!(
+                served(cache.get_or_fetch(true, fetched(3)).await),
+                (false, 3)
+            );
+
+            let limited = cache
+                .get_or_fetch(true, || async {
+                    Err(RateLimited::from_headers(&reqwest::header::HeaderMap::new(), "429").into())
+                })
+                .await;
+            assert_eq!(served(limited), (true, 3));
+            // The backoff holds even for a forced refresh.
+            assert_eq!(
+                served(cache.get_or_fetch(true, fetched(4)).await),
+                (true, 3)
+            );
+        });
+
+        // With nothing stored, the backoff error still says how long is left.
+        let empty: ResponseCache<u32> = ResponseCache::new(60);
+        empty.set_rate_limited(Duration::from_secs(120));
+        let error = runtime
+            .block_on(empty.get_or_fetch(true, fetched(6)))
+            .err()
+            .unwrap();
+        let wait = error.downcast_ref::<RateLimited>().unwrap().retry_after;
+        assert!(wait > Duration::from_secs(110) && wait <= Duration::from_secs(120));
+
+        // A failed fetch serves the stored value, unless the provider was signed out.
+        let failing = ResponseCache::new(60);
+        failing.set(5);
+        runtime.block_on(async {
+            assert_eq!(
+                served(
+                    failing
+                        .get_or_fetch(true, || async { Err(ErrorCode::FetchFailed.into()) })
+                        .await
+                ),
+                (true, 5)
+            );
+            assert!(failing
+                .get_or_fetch(true, || async { Err(ErrorCode::NotConfigured.into()) })
+                .await
+                .is_err());
+        });
+
+        // Hits share the stored value instead of cloning it.
+        assert!(Arc::ptr_eq(
+            &cache.get().unwrap(),
+            &cache.get_stale().unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_cache_map_slots_by_key() {
+        let tier = || CodexTierData {
+            plan_name: "Plus".into(),
+        };
+        let caches = CacheMap::new(60).with_ttl(Provider::Zai, Endpoint::Tier, 0);
+        caches.get(CODEX_TIER).set(tier());
+        assert!(Arc::ptr_eq(
+            &caches.get(CODEX_TIER),
+            &caches.get(CODEX_TIER)
+        ));
+        assert!(caches.get(CODEX_TIER).get().is_some());
+        assert!(caches.usage_age(Provider::Codex).is_none());
+
+        caches.clear(Provider::Codex);
+        assert!(caches.get(CODEX_TIER).get().is_none());
+        assert!(caches.get(CODEX_TIER).get_stale().is_some());
+
+        // A TTL change reaches slots already in use.
+        caches.set_provider_ttl(Provider::Codex, 0);
+        caches.get(CODEX_TIER).set(tier());
+        assert!(caches.get(CODEX_TIER).get().is_none());
+
+        let seeded = ResponseCache::new(60);
+        seeded.seed(7, Duration::from_secs(120));
+        assert!(seeded.get().is_none());
+        assert_eq!(seeded.get_stale().as_deref(), Some(&7));
+        assert!(seeded
+            .age()
+            .is_some_and(|age| age >= Duration::from_secs(120)));
+
+        // A zero TTL expires at once.
+        caches.get(ZAI_TIER).set(ZaiTierData {
+            plan_name: "Pro".into(),
+        });
+        assert!(caches.get(ZAI_TIER).get().is_none());
+    }
+
+    #[test]
+    fn test_switch_scope_keeps_each_scope_cached() {
+        let caches = CacheMap::new(30);
+        let key = CacheKey::<u32>::new(Provider::Claude, Endpoint::Usage);
+        caches.get(key).set(1);
+
+        caches.switch_scope(Provider::Claude, "work");
+        assert!(caches.get(key).get().is_none());
+        caches.get(key).set(2);
+
+        caches.switch_scope(Provider::Claude, "");
+        assert_eq!(caches.get(key).get().as_deref(), Some(&1));
+        caches.switch_scope(Provider::Claude, "work");
+        assert_eq!(caches.get(key).get().as_deref(), Some(&2));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::claude_service::{OAUTH_CLIENT_ID, TOKEN_REFRESH_URL};
+use crate::clock;
+use crate::credentials::CredentialManager;
+use crate::endpoint;
+use crate::models::{ClaudeOAuth, ClaudeOAuthCredentials, Provider, TokenRefreshResponse};
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use reqwest::Url;
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::{debug_claude, debug_error, debug_net};
+
+const AUTHORIZE_URL: &str = "https://claude.ai/oauth/authorize";
+/// Anthropic's paste-code page: after sign-in it shows `code#state` for the user to copy
+/// back into the app, so no local listener or custom URL scheme is needed.
+const REDIRECT_URI: &str = "https://console.anthropic.com/oauth/code/callback";
+const SCOPES: &str = "org:create_api_key user:profile user:inference";
+
+/// Random bytes in the PKCE verifier and the state; 32 gives the 43-character verifier.
+const RANDOM_BYTES: usize = 32;
+
+/// A login started in the browser and not yet finished. Holds the PKCE verifier, so it
+/// stays in memory and is never logged.
+pub struct PendingLogin {
+    verifier: String,
+    state: String,
+}
+
+/// Signs in to Claude without Claude Code: OAuth authorization code with PKCE, using
+/// Claude Code's client ID so the tokens work with the same endpoints.
+pub struct ClaudeLogin;
+
+impl ClaudeLogin {
+    /// A new login and the authorize URL to open in the browser.
+    pub fn start() -> Result<(PendingLogin, String)> {
+        let rng = SystemRandom::new();
+        let pending = PendingLogin {
+            verifier: random_token(&rng)?,
+            state: random_token(&rng)?,
+        };
+        let url = authorize_url(&pending)?;
+        debug_claude!("Claude login started");
+        Ok((pending, url))
+    }
+
+    /// Exchanges the `code#state` pasted from the callback page for tokens and saves them
+    /// where `claude_read_credentials` looks, the active profile's folder if one is selected.
+    pub async fn complete(
+        client: &reqwest::Client,
+        pending: PendingLogin,
+        pasted: &str,
+    ) -> Result<()> {
+        let code = parse_pasted_code(pasted, &pending.state)?;
+
+        debug_net!("POST {TOKEN_REFRESH_URL} (authorization code)");
+        let response = client
+            .post(endpoint::resolve_for(Provider::Claude, TOKEN_REFRESH_URL).as_ref())
+            .timeout(endpoint::timeout(Provider::Claude))
+            .json(&serde_json::json!({
+                "grant_type": "authorization_code",
+                "code": code,
+                "state": pending.state,
+                "client_id": OAUTH_CLIENT_ID,
+                "redirect_uri": REDIRECT_URI,
+                "code_verifier": pending.verifier,
+            }))
+            .send()
+            .await?;
+
+        let status = response.status();
+        debug_net!("Response status: {status}");
+        if !status.is_success() {
+            let error_text = response.text().await?;
+            debug_error!("Claude login failed: {error_text}");
+            return Err(anyhow!(
+                "Login failed ({status}); the code may have expired, start again"
+            ));
+        }
+
+        let tokens: TokenRefreshResponse = response.json().await?;
+        let credentials = ClaudeOAuthCredentials {
+            claude_ai_oauth: ClaudeOAuth {
+                access_token: tokens.access_token,
+                refresh_token: tokens.refresh_token,
+                expires_at: Some(clock::now_millis() + tokens.expires_in * 1000),
+                subscription_type: None,
+                rate_limit_tier: None,
+            },
+        };
+        tokio::task::spawn_blocking(move || {
+            CredentialManager::claude_write_credentials(&credentials)
+        })
+        .await
+        .map_err(|e| anyhow!("Credential task failed: {e}"))??;
+        debug_claude!("Claude login complete, credentials saved");
+        Ok(())
+    }
+}
+
+fn random_token(rng: &SystemRandom) -> Result<String> {
+    let mut bytes = [0u8; RANDOM_BYTES];
+    rng.fill(&mut bytes)
+        .map_err(|_| anyhow!("Failed to generate random login state"))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// RFC 7636 S256: the unpadded base64url SHA-256 of the verifier.
+fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(digest(&SHA256, verifier.as_bytes()))
+}
+
+fn authorize_url(pending: &PendingLogin) -> Result<String> {
+    let url = Url::parse_with_params(
+        AUTHORIZE_URL,
+        &[
+            ("code", "true"),
+            ("client_id", OAUTH_CLIENT_ID),
+            ("response_type", "code"),
+            ("redirect_uri", REDIRECT_URI),
+            ("scope", SCOPES),
+            ("code_challenge", &code_challenge(&pending.verifier)),
+            ("code_challenge_method", "S256"),
+            ("state", &pending.state),
+        ],
+    )?;
+    Ok(url.into())
+}
+
+/// The callback page shows `code#state`; a bare code is accepted too. A different state
+/// means the code belongs to another login attempt.
+fn parse_pasted_code<'a>(pasted: &'a str, state: &str) -> Result<&'a str> {
+    let pasted = pasted.trim();
+    let (code, pasted_state) = match pasted.split_once('#') {
+        Some((code, pasted_state)) => (code, Some(pasted_state)),
+        None => (pasted, None),
+    };
+    if code.is_empty() {
+        return Err(anyhow!("Paste the code shown after signing in"));
+    }
+    if pasted_state.is_some_and(|pasted_state| pasted_state != state) {
+        return Err(anyhow!(
+            "This code is from a different login attempt; start again"
+        ));
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_challenge_rfc7636() {
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn test_parse_pasted_code() {
+        assert_eq!(parse_pasted_code(" abc#s1 \n", "s1").unwrap(), "abc");
+        assert_eq!(parse_pasted_code("abc", "s1").unwrap(), "abc");
+        assert!(parse_pasted_code("abc#s2", "s1").is_err());
+        assert!(parse_pasted_code("#s1", "s1").is_err());
+    }
+
+    #[test]
+    fn test_authorize_url() {
+        let pending = PendingLogin {
+            verifier: "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string(),
+            state: "s1".to_string(),
+        };
+        let url = Url::parse(&authorize_url(&pending).unwrap()).unwrap();
+        let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert!(query.contains(&(
+            "code_challenge".to_string(),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".to_string()
+        )));
+        assert!(query.contains(&("state".to_string(), "s1".to_string())));
+        assert!(query.contains(&("redirect_uri".to_string(), REDIRECT_URI.to_string())));
+    }
+}
@@ -0,0 +1,764 @@
+use crate::clock::{Clock, SystemClock};
+use crate::credentials::CredentialManager;
+use crate::endpoint;
+use crate::failed_payloads;
+use crate::i18n::{ErrorCode, ParseError, UsageError};
+use crate::models::{
+    AccountUsage, AggregateUsage, ClaudeOAuth, ClaudeOAuthCredentials, ClaudeProfile,
+    ClaudeProfileResponse, ClaudeTierData, CombinedUsage, Provider, TokenRefreshResponse,
+    UsageData, UsageResponse,
+};
+use crate::rate_limit::{self, RateLimited};
+use crate::singleflight::SingleFlight;
+use anyhow::{anyhow, Result};
+use reqwest::StatusCode;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{debug_claude, debug_error, debug_net};
+
+/// Claude Code's OAuth client ID registered with Anthropic.
+/// Used in token refresh requests to console.anthropic.com/v1/oauth/token.
+/// Claude Code's OAuth client ID registered with Anthropic.
+/// Used in token refresh requests to console.anthropic.com/v1/oauth/token.
+pub(crate) const OAUTH_CLIENT_ID: &str = "9d1c250a-e61b-44d9-88ed-5944d1962f5e";
+/// Origins `claude_api_base_url` and `claude_oauth_base_url` stand in for.
+pub const API_ORIGIN: &str = "https://api.anthropic.com";
+pub const OAUTH_ORIGIN: &str = "https://console.anthropic.com";
+const USAGE_API_URL: &str = "https://api.anthropic.com/api/oauth/usage";
+const PROFILE_API_URL: &str = "https://api.anthropic.com/api/oauth/profile";
+pub(crate) const TOKEN_REFRESH_URL: &str = "https://console.anthropic.com/v1/oauth/token";
+
+/// Treat tokens as expired this many milliseconds before actual expiry,
+/// to prevent using a token that expires mid-request.
+const TOKEN_EXPIRY_BUFFER_MS: i64 = 60 * 1_000;
+
+/// The scheduled refresh runs this long before expiry, well ahead of
+/// `TOKEN_EXPIRY_BUFFER_MS`, so a fetch never finds the token expired.
+const SCHEDULED_REFRESH_LEAD_MS: i64 = 5 * 60 * 1_000;
+/// Longest the scheduler sleeps between checks, so a credentials file replaced meanwhile
+/// (a new login in Claude Code) or time spent suspended is noticed.
+const MAX_REFRESH_WAIT: Duration = Duration::from_secs(30 * 60);
+/// Wait after a failed scheduled refresh or while no credentials can be read.
+const REFRESH_RETRY_WAIT: Duration = Duration::from_secs(5 * 60);
+
+pub struct ClaudeService;
+
+impl ClaudeService {
+    fn now_millis() -> Result<i64> {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .map_err(|e| anyhow!("System clock error: {e}"))
+    }
+
+    async fn handle_combined_response(
+        response: reqwest::Response,
+        credentials: ClaudeOAuthCredentials,
+    ) -> Result<(UsageData, ClaudeTierData)> {
+        let response_text = response.text().await?;
+
+        let usage_response: UsageResponse = serde_json::from_str(&response_text).map_err(|e| {
+            failed_payloads::capture(Provider::Claude, &response_text, &e);
+            ParseError(format!("Failed to parse usage response: {e}"))
+        })?;
+
+        let usage_data = Self::usage_data(&usage_response);
+
+        let plan_name = Self::infer_plan_name(&credentials.claude_ai_oauth, &usage_response);
+        let raw_tier = credentials
+            .claude_ai_oauth
+            .rate_limit_tier
+            .clone()
+            .unwrap_or_else(|| usage_response.rate_limit_tier.clone().unwrap_or_default());
+
+        let tier_data = ClaudeTierData {
+            plan_name,
+            rate_limit_tier: raw_tier,
+            organization_name: CredentialManager::claude_active_organization_name_async().await,
+        };
+
+        Ok((usage_data, tier_data))
+    }
+
+    fn usage_data(usage_response: &UsageResponse) -> UsageData {
+        let extra_usage = usage_response.extra_usage.as_ref();
+        UsageData {
+            five_hour_utilization: usage_response
+                .five_hour
+                .as_ref()
+                .map(|p| p.utilization)
+                .unwrap_or(0.0),
+            five_hour_resets_at: usage_response.five_hour.as_ref().and_then(|p| p.resets_at),
+            seven_day_utilization: usage_response
+                .seven_day
+                .as_ref()
+                .map(|p| p.utilization)
+                .unwrap_or(0.0),
+            seven_day_resets_at: usage_response.seven_day.as_ref().and_then(|p| p.resets_at),
+            seven_day_opus_utilization: usage_response
+                .seven_day_opus
+                .as_ref()
+                .map(|p| p.utilization),
+            seven_day_opus_resets_at: usage_response
+                .seven_day_opus
+                .as_ref()
+                .and_then(|p| p.resets_at),
+            extra_usage_enabled: extra_usage.map(|e| e.is_enabled).unwrap_or(false),
+            extra_usage_monthly_limit: extra_usage.and_then(|e| e.monthly_limit),
+            extra_usage_used_credits: extra_usage.and_then(|e| e.used_credits),
+            extra_usage_utilization: extra_usage.and_then(|e| e.utilization),
+            extra_usage_resets_at: extra_usage.and_then(|e| e.resets_at),
+            extra_usage_budget_utilization: budget_utilization(
+                extra_usage.and_then(|e| e.used_credits),
+                Self::extra_usage_budget(),
+            ),
+        }
+    }
+
+    /// Monthly extra usage budget in dollars from settings, for `extra_usage_budget_utilization`.
+    pub fn claude_set_extra_usage_budget(budget: Option<f64>) {
+        *lock_budget() = budget;
+    }
+
+    /// Gateway base URLs from settings for the usage and profile API (`api`) and for token
+    /// refresh and login (`oauth`); `None` uses Anthropic's own hosts.
+    pub fn claude_set_base_urls(api: Option<String>, oauth: Option<String>) {
+        endpoint::set_base_url(Provider::Claude, API_ORIGIN, api);
+        endpoint::set_base_url(Provider::Claude, OAUTH_ORIGIN, oauth);
+    }
+
+    pub fn extra_usage_budget() -> Option<f64> {
+        *lock_budget()
+    }
+
+    /// Usage for one login's token. Unlike `claude_fetch_usage_and_tier` this never refreshes:
+    /// refreshed tokens are written back to the active login only, so another login whose
+    /// token has expired reports `AuthFailed` until Claude Code refreshes it.
+    async fn claude_fetch_account_usage(
+        client: &reqwest::Client,
+        credentials: &ClaudeOAuthCredentials,
+    ) -> Result<UsageData> {
+        debug_net!("GET {USAGE_API_URL} (account view)");
+        let response = client
+            .get(endpoint::resolve_for(Provider::Claude, USAGE_API_URL).as_ref())
+            .timeout(endpoint::timeout(Provider::Claude))
+            .header(
+                "Authorization",
+                format!("Bearer {}", credentials.claude_ai_oauth.access_token),
+            )
+            .header("anthropic-beta", "oauth-2025-04-20")
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => {
+                let response_text = response.text().await?;
+                let usage_response: UsageResponse =
+                    serde_json::from_str(&response_text).map_err(|e| {
+                        failed_payloads::capture(Provider::Claude, &response_text, &e);
+                        ParseError(format!("Failed to parse usage response: {e}"))
+                    })?;
+                Ok(Self::usage_data(&usage_response))
+            }
+            StatusCode::UNAUTHORIZED => Err(ErrorCode::AuthFailed.into()),
+            StatusCode::FORBIDDEN => Err(ErrorCode::AccessDenied.into()),
+            StatusCode::TOO_MANY_REQUESTS => Err(RateLimited::from_headers(
+                response.headers(),
+                "Rate limited — please wait and try again",
+            )
+            .into()),
+            status if status.is_server_error() => Err(ErrorCode::ServerError.into()),
+            _ => Err(ErrorCode::FetchFailed.into()),
+        }
+    }
+
+    /// Email, organization and plan of the active login. Refreshes an expired token first,
+    /// like the usage fetch.
+    pub async fn claude_fetch_profile(client: Arc<reqwest::Client>) -> Result<ClaudeProfile> {
+        Self::check_and_refresh_if_needed(client.clone()).await?;
+        let credentials = CredentialManager::claude_read_credentials_async().await?;
+
+        debug_net!("GET {PROFILE_API_URL}");
+        let response = client
+            .get(endpoint::resolve_for(Provider::Claude, PROFILE_API_URL).as_ref())
+            .timeout(endpoint::timeout(Provider::Claude))
+            .header(
+                "Authorization",
+                format!("Bearer {}", credentials.claude_ai_oauth.access_token),
+            )
+            .header("anthropic-beta", "oauth-2025-04-20")
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => {
+                let response_text = response.text().await?;
+                let profile: ClaudeProfileResponse =
+                    serde_json::from_str(&response_text).map_err(|e| {
+                        failed_payloads::capture(Provider::Claude, &response_text, &e);
+                        ParseError(format!("Failed to parse profile response: {e}"))
+                    })?;
+                Ok(ClaudeProfile::from_response(
+                    profile,
+                    credentials.claude_ai_oauth.subscription_type,
+                ))
+            }
+            StatusCode::UNAUTHORIZED => Err(ErrorCode::AuthFailed.into()),
+            StatusCode::FORBIDDEN => Err(ErrorCode::AccessDenied.into()),
+            StatusCode::TOO_MANY_REQUESTS => Err(RateLimited::from_headers(
+                response.headers(),
+                "Rate limited — please wait and try again",
+            )
+            .into()),
+            status if status.is_server_error() => Err(ErrorCode::ServerError.into()),
+            _ => Err(ErrorCode::FetchFailed.into()),
+        }
+    }
+
+    /// Usage for every Claude login at once, fetched in parallel, with combined utilization.
+    pub async fn claude_fetch_accounts_usage(client: Arc<reqwest::Client>) -> AggregateUsage {
+        // The active login's token is the only one we may refresh; do it first so its
+        // request below doesn't fail on an expired token.
+        if let Err(e) = Self::check_and_refresh_if_needed(client.clone()).await {
+            debug_claude!("Account view: active token refresh failed: {e}");
+        }
+
+        let accounts = CredentialManager::claude_accounts_async().await;
+        let tasks: Vec<_> = accounts
+            .into_iter()
+            .map(|(account, credentials)| {
+                let client = client.clone();
+                tokio::spawn(async move {
+                    let result = match credentials {
+                        Ok(credentials) => {
+                            Self::claude_fetch_account_usage(&client, &credentials).await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    match result {
+                        Ok(usage) => AccountUsage {
+                            account,
+                            usage: Some(usage),
+                            error: None,
+                        },
+                        Err(e) => {
+                            debug_claude!("Account view: {:?} failed: {e}", account.source);
+                            AccountUsage {
+                                account,
+                                usage: None,
+                                error: Some(UsageError::from(&e)),
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut accounts = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            match task.await {
+                Ok(account) => accounts.push(account),
+                Err(e) => {
+                    debug_error!("Account view task failed: {e}");
+                }
+            }
+        }
+
+        let usages: Vec<&UsageData> = accounts.iter().filter_map(|a| a.usage.as_ref()).collect();
+        AggregateUsage {
+            combined: Self::combine_usage(&usages),
+            accounts,
+        }
+    }
+
+    fn combine_usage(usages: &[&UsageData]) -> Option<CombinedUsage> {
+        if usages.is_empty() {
+            return None;
+        }
+        let count = usages.len() as f64;
+        let fold = |pick: fn(&UsageData) -> f64| {
+            let values = usages.iter().map(|u| pick(u));
+            (
+                values.clone().sum::<f64>() / count,
+                values.fold(0.0, f64::max),
+            )
+        };
+        let (five_hour_average, five_hour_max) = fold(|u| u.five_hour_utilization);
+        let (seven_day_average, seven_day_max) = fold(|u| u.seven_day_utilization);
+        Some(CombinedUsage {
+            accounts: usages.len(),
+            five_hour_average,
+            five_hour_max,
+            seven_day_average,
+            seven_day_max,
+        })
+    }
+
+    /// Fetches both usage and tier data from a single API call.
+    /// This is more efficient than calling fetch_usage and fetch_tier separately
+    /// since they both hit the same endpoint. Concurrent calls share one request.
+    pub async fn claude_fetch_usage_and_tier(
+        client: Arc<reqwest::Client>,
+    ) -> Result<(UsageData, ClaudeTierData)> {
+        static FLIGHT: SingleFlight<(UsageData, ClaudeTierData)> = SingleFlight::new();
+        FLIGHT.run(|| Self::fetch_usage_and_tier(client)).await
+    }
+
+    async fn fetch_usage_and_tier(
+        client: Arc<reqwest::Client>,
+    ) -> Result<(UsageData, ClaudeTierData)> {
+        debug_claude!("claude_fetch_usage_and_tier: Starting request");
+        debug_net!("GET {USAGE_API_URL}");
+
+        let credentials = CredentialManager::claude_read_credentials_async().await?;
+        let token = credentials.claude_ai_oauth.access_token.clone();
+        debug_claude!("Using access token (expires_at: N/A)");
+
+        let response = client
+            .get(endpoint::resolve_for(Provider::Claude, USAGE_API_URL).as_ref())
+            .timeout(endpoint::timeout(Provider::Claude))
+            .header("Authorization", format!("Bearer {token}"))
+            .header("anthropic-beta", "oauth-2025-04-20")
+            .send()
+            .await?;
+
+        let status = response.status();
+        debug_net!("Response status: {status}");
+        rate_limit::record_quota(Provider::Claude, response.headers());
+
+        match response.status() {
+            StatusCode::UNAUTHORIZED => {
+                debug_claude!("Unauthorized: Attempting token refresh");
+                Self::refresh_token(client.clone()).await?;
+                let refreshed_creds = CredentialManager::claude_read_credentials_async().await?;
+                let token = refreshed_creds.claude_ai_oauth.access_token.clone();
+                let retry_response = client
+                    .get(endpoint::resolve_for(Provider::Claude, USAGE_API_URL).as_ref())
+                    .timeout(endpoint::timeout(Provider::Claude))
+                    .header("Authorization", format!("Bearer {token}"))
+                    .header("anthropic-beta", "oauth-2025-04-20")
+                    .send()
+                    .await?;
+
+                let retry_status = retry_response.status();
+                debug_net!("Retry response status: {retry_status}");
+                rate_limit::record_quota(Provider::Claude, retry_response.headers());
+
+                match retry_response.status() {
+                    status if status.is_success() => {
+                        debug_claude!("Successfully fetched usage+tier data after retry");
+                        Self::handle_combined_response(retry_response, refreshed_creds).await
+                    }
+                    StatusCode::UNAUTHORIZED => {
+                        debug_error!("Still unauthorized after token refresh");
+                        Err(ErrorCode::AuthFailed.into())
+                    }
+                    StatusCode::FORBIDDEN => {
+                        debug_error!("Access denied after token refresh");
+                        Err(ErrorCode::AccessDenied.into())
+                    }
+                    StatusCode::TOO_MANY_REQUESTS => {
+                        debug_error!("Rate limited after token refresh");
+                        Err(RateLimited::from_headers(
+                            retry_response.headers(),
+                            "Rate limited — please wait and try again",
+                        )
+                        .into())
+                    }
+                    status if status.is_server_error() => {
+                        debug_error!("Server error after token refresh");
+                        Err(ErrorCode::ServerError.into())
+                    }
+                    _ => {
+                        debug_error!("Failed to fetch usage+tier data after token refresh");
+                        Err(ErrorCode::FetchFailed.into())
+                    }
+                }
+            }
+            status if status.is_success() => {
+                debug_claude!("Successfully fetched usage+tier data");
+                Self::handle_combined_response(response, credentials).await
+            }
+            StatusCode::FORBIDDEN => {
+                debug_error!("Access denied — check your permissions");
+                Err(ErrorCode::AccessDenied.into())
+            }
+            StatusCode::TOO_MANY_REQUESTS => {
+                debug_error!("Rate limited — please wait and try again");
+                Err(RateLimited::from_headers(
+                    response.headers(),
+                    "Rate limited — please wait and try again",
+                )
+                .into())
+            }
+            status if status.is_server_error() => {
+                debug_error!("Server error — try again later");
+                Err(ErrorCode::ServerError.into())
+            }
+            _ => {
+                debug_error!("Failed to fetch usage+tier data");
+                Err(ErrorCode::FetchFailed.into())
+            }
+        }
+    }
+
+    /// Concurrent calls share one refresh: the refresh token is single-use, so a second
+    /// request with it would fail and could sign the user out.
+    pub async fn refresh_token(client: Arc<reqwest::Client>) -> Result<()> {
+        static FLIGHT: SingleFlight<()> = SingleFlight::new();
+        FLIGHT.run(|| Self::refresh_token_once(client)).await
+    }
+
+    async fn refresh_token_once(client: Arc<reqwest::Client>) -> Result<()> {
+        debug_claude!("refresh_token: Starting token refresh");
+        debug_net!("POST {TOKEN_REFRESH_URL}");
+
+        let credentials = CredentialManager::claude_read_credentials_async().await?;
+        if credentials.claude_ai_oauth.refresh_token.is_empty() {
+            debug_claude!("No refresh token (CLAUDE_CODE_OAUTH_TOKEN?), cannot refresh");
+            return Err(ErrorCode::AuthFailed.into());
+        }
+
+        let params = [
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &credentials.claude_ai_oauth.refresh_token),
+            ("client_id", OAUTH_CLIENT_ID),
+        ];
+
+        let response = client
+            .post(endpoint::resolve_for(Provider::Claude, TOKEN_REFRESH_URL).as_ref())
+            .timeout(endpoint::timeout(Provider::Claude))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .form(&params)
+            .send()
+            .await?;
+
+        let status = response.status();
+        debug_net!("Response status: {status}");
+
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            debug_error!("Token refresh rate limited");
+            return Err(RateLimited::from_headers(
+                response.headers(),
+                "Rate limited — please wait and try again",
+            )
+            .into());
+        }
+        if status == StatusCode::BAD_REQUEST || status == StatusCode::UNAUTHORIZED {
+            // Revoked, expired or already-used refresh token (`invalid_grant`): retrying
+            // won't help, only a new login will.
+            let error_text = response.text().await?;
+            debug_error!("Token refresh rejected: {error_text}");
+            return Err(ErrorCode::LoginRequired.into());
+        }
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            debug_error!("Token refresh failed: {error_text}");
+            return Err(anyhow!("Token refresh failed: {error_text}"));
+        }
+
+        let refresh_response: TokenRefreshResponse = response.json().await?;
+
+        let now = Self::now_millis()?;
+        let expires_at = now + (refresh_response.expires_in * 1000);
+
+        let expiry_ms = refresh_response.expires_in * 1000;
+        debug_claude!("Token refresh successful (new expiry in {expiry_ms}ms)");
+
+        CredentialManager::claude_update_token_async(
+            refresh_response.access_token,
+            refresh_response.refresh_token,
+            expires_at,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn is_token_expired() -> bool {
+        match CredentialManager::claude_read_credentials_async().await {
+            // A long-lived token cannot be refreshed; the API reports when it stops working
+            Ok(credentials) if credentials.claude_ai_oauth.refresh_token.is_empty() => false,
+            Ok(credentials) => {
+                Self::token_expired_at(credentials.claude_ai_oauth.expires_at, &SystemClock)
+            }
+            Err(_) => {
+                debug_claude!("Failed to read credentials, treating as expired");
+                true
+            }
+        }
+    }
+
+    fn token_expired_at(expires_at: Option<i64>, clock: &impl Clock) -> bool {
+        let Some(expires_at) = expires_at else {
+            debug_claude!("Token has no expiry date, treating as expired");
+            return true;
+        };
+        let now = clock.now_millis();
+        let expired = now + TOKEN_EXPIRY_BUFFER_MS >= expires_at;
+        debug_claude!("Token expiry check: now={now}, expires_at={expires_at}, expired={expired}");
+        expired
+    }
+
+    /// One step of the background refresh: refreshes the token if it expires within
+    /// `SCHEDULED_REFRESH_LEAD_MS`, then returns how long to wait before the next step.
+    pub async fn claude_scheduled_refresh(client: Arc<reqwest::Client>) -> Duration {
+        let oauth = match CredentialManager::claude_read_credentials_async().await {
+            Ok(credentials) => credentials.claude_ai_oauth,
+            Err(_) => return REFRESH_RETRY_WAIT,
+        };
+        // A long-lived token cannot be refreshed
+        if oauth.refresh_token.is_empty() {
+            return MAX_REFRESH_WAIT;
+        }
+        let delay = scheduled_refresh_delay(oauth.expires_at, SystemClock.now_millis());
+        if !delay.is_zero() {
+            return delay;
+        }
+
+        debug_claude!("Token expires soon, refreshing ahead of the next fetch");
+        match Self::refresh_token(client).await {
+            // The next step schedules from the new expiry; waiting first keeps a token
+            // issued with a very short lifetime from being refreshed in a tight loop.
+            Ok(()) => REFRESH_RETRY_WAIT,
+            Err(e) => {
+                debug_error!("Scheduled token refresh failed: {e}");
+                REFRESH_RETRY_WAIT
+            }
+        }
+    }
+
+    pub async fn check_and_refresh_if_needed(client: Arc<reqwest::Client>) -> Result<()> {
+        if Self::is_token_expired().await {
+            debug_claude!("Token expired or expiring soon, refreshing");
+            Self::refresh_token(client).await?;
+        } else {
+            debug_claude!("Token is still valid, skipping refresh");
+        }
+        Ok(())
+    }
+
+    /// Tier inference precedence, first recognized value wins:
+    /// 1. subscription_type from the credential file (most reliable, modern accounts)
+    /// 2. rate_limit_tier from the API response
+    /// 3. rate_limit_tier from the credential file, when the response omits tier info
+    /// 4. billing_type from the API response: Stripe-billed means at least Pro
+    ///
+    /// Anything else is Free.
+    fn infer_plan_name(oauth: &ClaudeOAuth, response: &UsageResponse) -> String {
+        let lower = |value: &Option<String>| value.as_deref().unwrap_or_default().to_lowercase();
+        let subscription = lower(&oauth.subscription_type);
+        let response_tier = lower(&response.rate_limit_tier);
+        let credential_tier = lower(&oauth.rate_limit_tier);
+        let billing = lower(&response.billing_type);
+
+        Self::plan_from_subscription(&subscription)
+            .or_else(|| Self::plan_from_rate_limit_tier(&response_tier))
+            .or_else(|| Self::plan_from_rate_limit_tier(&credential_tier))
+            .or_else(|| billing.contains("stripe").then_some("Pro"))
+            .unwrap_or_else(|| {
+                debug_claude!(
+                    "Unknown subscription {subscription:?}, rate_limit_tier {response_tier:?} / {credential_tier:?} with billing {billing:?}; defaulting to Free"
+                );
+                "Free"
+            })
+            .to_string()
+    }
+
+    fn plan_from_subscription(subscription_type: &str) -> Option<&'static str> {
+        if subscription_type.contains("max") {
+            Some("Max")
+        } else if subscription_type.contains("pro") {
+            Some("Pro")
+        } else if subscription_type.contains("team") {
+            Some("Team")
+        } else if subscription_type.contains("enterprise") {
+            Some("Enterprise")
+        } else {
+            None
+        }
+    }
+
+    /// NOTE: the numbered mappings are speculative, based on observed patterns:
+    /// - "tier_2"/"tier_3" → "Pro" (assumed)
+    /// - "tier_4"/"tier_5" → "Team" (assumed)
+    /// - "tier_1_5x"/"tier_free_5x" etc. (actual API values) not yet mapped
+    ///
+    /// Credential files use names like `default_claude_max_20x`.
+    fn plan_from_rate_limit_tier(tier: &str) -> Option<&'static str> {
+        if tier.contains("max") || tier == "tier_2_5x" || tier == "tier_3_5x" {
+            Some("Max")
+        } else if tier.contains("team") || tier == "tier_4" || tier == "tier_5" {
+            // tier_4/tier_5 assumed to map to Team; revisit if Anthropic introduces new tier names
+            Some("Team")
+        } else if tier.contains("enterprise") {
+            Some("Enterprise")
+        } else if tier == "tier_2" || tier == "tier_3" || tier.contains("pro") {
+            Some("Pro")
+        } else {
+            None
+        }
+    }
+}
+
+static EXTRA_USAGE_BUDGET: Mutex<Option<f64>> = Mutex::new(None);
+
+fn lock_budget() -> MutexGuard<'static, Option<f64>> {
+    EXTRA_USAGE_BUDGET.lock().unwrap_or_else(|poisoned| {
+        debug_error!("Extra usage budget mutex poisoned, recovering...");
+        poisoned.into_inner()
+    })
+}
+
+/// Time until `SCHEDULED_REFRESH_LEAD_MS` before `expires_at`, capped at
+/// `MAX_REFRESH_WAIT`; zero when that point has passed. Tokens without an expiry are
+/// refreshed by the next fetch instead.
+fn scheduled_refresh_delay(expires_at: Option<i64>, now: i64) -> Duration {
+    let Some(expires_at) = expires_at else {
+        return MAX_REFRESH_WAIT;
+    };
+    let millis = (expires_at - SCHEDULED_REFRESH_LEAD_MS - now).max(0) as u64;
+    Duration::from_millis(millis).min(MAX_REFRESH_WAIT)
+}
+
+/// Percent of `budget` (dollars) spent, from extra usage credits in cents.
+fn budget_utilization(used_credits: Option<f64>, budget: Option<f64>) -> Option<f64> {
+    let budget = budget.filter(|b| *b > 0.0)?;
+    Some(used_credits.unwrap_or(0.0) / 100.0 / budget * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+
+    #[test]
+    fn test_token_expired_within_buffer() {
+        let expires_at = 1_700_000_000_000;
+        let before_buffer = FixedClock(expires_at - TOKEN_EXPIRY_BUFFER_MS - 1);
+        let inside_buffer = FixedClock(expires_at - TOKEN_EXPIRY_BUFFER_MS);
+        assert!(!ClaudeService::token_expired_at(
+            Some(expires_at),
+            &before_buffer
+        ));
+        assert!(ClaudeService::token_expired_at(
+            Some(expires_at),
+            &inside_buffer
+        ));
+        assert!(ClaudeService::token_expired_at(None, &before_buffer));
+    }
+
+    fn usage(five_hour: f64, seven_day: f64) -> UsageData {
+        UsageData {
+            five_hour_utilization: five_hour,
+            five_hour_resets_at: None,
+            seven_day_utilization: seven_day,
+            seven_day_resets_at: None,
+            seven_day_opus_utilization: None,
+            seven_day_opus_resets_at: None,
+            extra_usage_enabled: false,
+            extra_usage_monthly_limit: None,
+            extra_usage_used_credits: None,
+            extra_usage_utilization: None,
+            extra_usage_resets_at: None,
+            extra_usage_budget_utilization: None,
+        }
+    }
+
+    #[test]
+    fn test_usage_data_reads_seven_day_opus() {
+        let response: UsageResponse = serde_json::from_str(
+            r#"{
+                "five_hour": {"utilization": 12.0, "resets_at": null},
+                "seven_day": {"utilization": 30.0, "resets_at": null},
+                "seven_day_opus": {"utilization": 64.0, "resets_at": "2025-01-01T00:00:00Z"}
+            }"#,
+        )
+        .unwrap();
+        let usage = ClaudeService::usage_data(&response);
+        assert_eq!(usage.seven_day_opus_utilization, Some(64.0));
+        assert!(usage.seven_day_opus_resets_at.is_some());
+
+        let response: UsageResponse =
+            serde_json::from_str(r#"{"five_hour": null, "seven_day": null}"#).unwrap();
+        assert_eq!(
+            ClaudeService::usage_data(&response).seven_day_opus_utilization,
+            None
+        );
+    }
+
+    #[test]
+    fn test_infer_plan_name_falls_back_to_credential_tier() {
+        let oauth = |subscription: Option<&str>, tier: Option<&str>| ClaudeOAuth {
+            access_token: String::new(),
+            refresh_token: String::new(),
+            expires_at: None,
+            subscription_type: subscription.map(String::from),
+            rate_limit_tier: tier.map(String::from),
+        };
+        let bare: UsageResponse = serde_json::from_str("{}").unwrap();
+        let tiered: UsageResponse =
+            serde_json::from_str(r#"{"rate_limit_tier": "tier_2", "billing_type": "stripe"}"#)
+                .unwrap();
+
+        let plan = |oauth: &ClaudeOAuth, response: &UsageResponse| {
+            ClaudeService::infer_plan_name(oauth, response)
+        };
+        assert_eq!(plan(&oauth(Some("max"), None), &tiered), "Max");
+        assert_eq!(
+            plan(&oauth(None, Some("default_claude_max_20x")), &bare),
+            "Max"
+        );
+        assert_eq!(
+            plan(
+                &oauth(Some("unknown"), Some("default_claude_max_5x")),
+                &bare
+            ),
+            "Max"
+        );
+        assert_eq!(
+            plan(&oauth(None, Some("default_claude_max_20x")), &tiered),
+            "Pro"
+        );
+        assert_eq!(plan(&oauth(None, None), &bare), "Free");
+    }
+
+    #[test]
+    fn test_scheduled_refresh_delay() {
+        let now = 1_000_000_000;
+        let minute = 60_000;
+        assert_eq!(
+            scheduled_refresh_delay(Some(now + 8 * minute), now),
+            Duration::from_secs(3 * 60)
+        );
+        assert_eq!(
+            scheduled_refresh_delay(Some(now + 2 * minute), now),
+            Duration::ZERO
+        );
+        assert_eq!(
+            scheduled_refresh_delay(Some(now + 8 * 60 * minute), now),
+            MAX_REFRESH_WAIT
+        );
+        assert_eq!(scheduled_refresh_delay(None, now), MAX_REFRESH_WAIT);
+    }
+
+    #[test]
+    fn test_budget_utilization() {
+        assert_eq!(budget_utilization(Some(1500.0), Some(20.0)), Some(75.0));
+        assert_eq!(budget_utilization(None, Some(20.0)), Some(0.0));
+        assert_eq!(budget_utilization(Some(1500.0), None), None);
+    }
+
+    #[test]
+    fn test_combine_usage_averages_and_max() {
+        let (a, b) = (usage(20.0, 50.0), usage(80.0, 10.0));
+        let combined = ClaudeService::combine_usage(&[&a, &b]).unwrap();
+        assert_eq!(combined.accounts, 2);
+        assert_eq!(combined.five_hour_average, 50.0);
+        assert_eq!(combined.five_hour_max, 80.0);
+        assert_eq!(combined.seven_day_average, 30.0);
+        assert_eq!(combined.seven_day_max, 50.0);
+        assert_eq!(ClaudeService::combine_usage(&[]), None);
+    }
+}
@@ -0,0 +1,52 @@
+use chrono::{DateTime, Local, TimeZone};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Shift applied to `SystemClock`, set by the `debug_set_clock_offset` command.
+static OFFSET_MS: AtomicI64 = AtomicI64::new(0);
+
+/// Source of "now" for reset, expiry and countdown math, so it can be pinned in tests
+/// and shifted at runtime to check edge cases such as DST transitions.
+pub trait Clock {
+    /// Epoch milliseconds.
+    fn now_millis(&self) -> i64;
+
+    fn now_local(&self) -> DateTime<Local> {
+        Local
+            .timestamp_millis_opt(self.now_millis())
+            .single()
+            .unwrap_or_else(Local::now)
+    }
+}
+
+/// Wall clock plus the debug offset (zero unless set).
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> i64 {
+        let wall = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or_default();
+        wall.saturating_add(OFFSET_MS.load(Ordering::Relaxed))
+    }
+}
+
+/// A clock stopped at the given epoch millis.
+#[cfg(test)]
+pub struct FixedClock(pub i64);
+
+#[cfg(test)]
+impl Clock for FixedClock {
+    fn now_millis(&self) -> i64 {
+        self.0
+    }
+}
+
+pub fn now_millis() -> i64 {
+    SystemClock.now_millis()
+}
+
+pub fn set_offset_millis(offset: i64) {
+    OFFSET_MS.store(offset, Ordering::Relaxed);
+}
@@ -1,7 +1,13 @@
+use crate::credentials::CredentialManager;
+use crate::endpoint;
+use crate::failed_payloads;
+use crate::i18n::{ErrorCode, ParseError};
 use crate::models::{
     CodexAuthFile, CodexCredits, CodexRefreshResponse, CodexTierData, CodexUsageData,
-    CodexUsageResponse, CodexUsageWindow, CodexWindowUsage,
+    CodexUsageResponse, CodexUsageWindow, CodexWindowUsage, Provider,
 };
+use crate::rate_limit::{self, RateLimited};
+use crate::singleflight::SingleFlight;
 use anyhow::{anyhow, Result};
 use reqwest::StatusCode;
 use std::fs;
@@ -33,8 +39,16 @@ impl CodexService {
             })
     }
 
+    /// Concurrent calls share one request (and one token refresh if it is needed).
     pub async fn codex_fetch_usage_and_tier(
         client: Arc<reqwest::Client>,
+    ) -> Result<(CodexUsageData, CodexTierData)> {
+        static FLIGHT: SingleFlight<(CodexUsageData, CodexTierData)> = SingleFlight::new();
+        FLIGHT.run(|| Self::fetch_usage_and_tier(client)).await
+    }
+
+    async fn fetch_usage_and_tier(
+        client: Arc<reqwest::Client>,
     ) -> Result<(CodexUsageData, CodexTierData)> {
         let mut auth = Self::read_auth_async().await?;
         let mut response = Self::fetch_usage(client.clone(), &auth).await;
@@ -65,9 +79,7 @@ impl CodexService {
             }
         }
 
-        let home = std::env::var_os("USERPROFILE")
-            .map(PathBuf::from)
-            .ok_or_else(|| anyhow!("USERPROFILE environment variable not set"))?;
+        let home = CredentialManager::home_dir()?;
         Ok(home.join(".codex").join("auth.json"))
     }
 
@@ -75,25 +87,38 @@ impl CodexService {
         let path = Self::auth_path()?;
         let path_display = path.display();
         let json = fs::read_to_string(&path).map_err(|e| {
-            anyhow!(
-                "Codex auth not found: failed to read {path_display}. {e}. Run `codex` to sign in."
-            )
+            debug_error!("Codex auth not found: failed to read {path_display}. {e}");
+            anyhow::Error::from(ErrorCode::CodexNotSignedIn)
         })?;
-        serde_json::from_str(&json).map_err(|e| anyhow!("Failed to parse Codex auth.json: {e}"))
+        Self::parse_auth(&json)
     }
 
     async fn read_auth_async() -> Result<CodexAuthFile> {
         let path = Self::auth_path()?;
         let path_display = path.display();
         let json = async_fs::read_to_string(&path).await.map_err(|e| {
-            anyhow!(
-                "Codex auth not found: failed to read {path_display}. {e}. Run `codex` to sign in."
-            )
+            debug_error!("Codex auth not found: failed to read {path_display}. {e}");
+            anyhow::Error::from(ErrorCode::CodexNotSignedIn)
         })?;
-        serde_json::from_str(&json).map_err(|e| anyhow!("Failed to parse Codex auth.json: {e}"))
+        Self::parse_auth(&json)
     }
 
+    fn parse_auth(json: &str) -> Result<CodexAuthFile> {
+        serde_json::from_str(json).map_err(|e| {
+            debug_error!("Failed to parse Codex auth.json: {e}");
+            ErrorCode::ReauthRequired.into()
+        })
+    }
+
+    /// Writes refreshed tokens back; the details of a failure only go to the log.
     async fn write_auth_async(auth: &CodexAuthFile) -> Result<()> {
+        Self::write_auth_inner(auth).await.map_err(|e| {
+            debug_error!("{e}");
+            ErrorCode::CredentialSaveFailed.into()
+        })
+    }
+
+    async fn write_auth_inner(auth: &CodexAuthFile) -> Result<()> {
         let path = Self::auth_path()?;
         let existing = async_fs::read_to_string(&path)
             .await
@@ -134,6 +159,7 @@ impl CodexService {
 
         let mut request = client
             .get(url)
+            .timeout(endpoint::timeout(Provider::Codex))
             .bearer_auth(token)
             .header("Accept", "application/json")
             .header("User-Agent", "codex-cli");
@@ -148,19 +174,37 @@ impl CodexService {
             .map_err(|e| CodexFetchError::Other(e.into()))?;
         let status = response.status();
         debug_net!("Codex response status: {status}");
+        rate_limit::record_quota(Provider::Codex, response.headers());
 
         match status {
             StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(CodexFetchError::Unauthorized),
+            StatusCode::TOO_MANY_REQUESTS => Err(CodexFetchError::Other(
+                RateLimited::from_headers(
+                    response.headers(),
+                    "Codex: Rate limited — please wait and try again",
+                )
+                .into(),
+            )),
             status if status.is_success() => {
-                response.json::<CodexUsageResponse>().await.map_err(|e| {
-                    CodexFetchError::Other(anyhow!("Invalid response from Codex usage API: {e}"))
+                let body = response
+                    .text()
+                    .await
+                    .map_err(|e| CodexFetchError::Other(e.into()))?;
+                serde_json::from_str::<CodexUsageResponse>(&body).map_err(|e| {
+                    failed_payloads::capture(Provider::Codex, &body, &e);
+                    CodexFetchError::Other(
+                        ParseError(format!("Invalid response from Codex usage API: {e}")).into(),
+                    )
                 })
             }
             status => {
                 let body = response.text().await.unwrap_or_default();
-                Err(CodexFetchError::Other(anyhow!(
-                    "Codex API error {status}: {body}"
-                )))
+                debug_error!("Codex API error {status}: {body}");
+                Err(CodexFetchError::Other(if status.is_server_error() {
+                    ErrorCode::ServerError.into()
+                } else {
+                    ErrorCode::FetchFailed.into()
+                }))
             }
         }
     }
@@ -169,17 +213,16 @@ impl CodexService {
         client: Arc<reqwest::Client>,
         mut auth: CodexAuthFile,
     ) -> Result<CodexAuthFile> {
-        let tokens = auth.tokens.as_mut().ok_or_else(|| {
-            anyhow!("Codex auth.json contains no OAuth tokens. Run `codex` to sign in.")
-        })?;
+        let tokens = auth.tokens.as_mut().ok_or(ErrorCode::ReauthRequired)?;
         let refresh_token = tokens
             .refresh_token
             .as_deref()
-            .ok_or_else(|| anyhow!("Codex refresh token missing. Run `codex` to sign in again."))?;
+            .ok_or(ErrorCode::ReauthRequired)?;
 
         debug_net!("POST {CODEX_REFRESH_URL}");
         let response = client
-            .post(CODEX_REFRESH_URL)
+            .post(endpoint::resolve(CODEX_REFRESH_URL).as_ref())
+            .timeout(endpoint::timeout(Provider::Codex))
             .json(&serde_json::json!({
                 "client_id": CODEX_CLIENT_ID,
                 "grant_type": "refresh_token",
@@ -191,19 +234,22 @@ impl CodexService {
 
         let status = response.status();
         if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
-            return Err(anyhow!(
-                "Codex refresh token expired or invalid. Run `codex` to sign in again."
-            ));
+            return Err(ErrorCode::ReauthRequired.into());
+        }
+        if status.is_server_error() {
+            debug_error!("Codex token refresh failed ({status})");
+            return Err(ErrorCode::ServerError.into());
         }
         if !status.is_success() {
-            let body = response.text().await.unwrap_or_default();
-            return Err(anyhow!("Codex token refresh failed ({status}): {body}"));
+            // A rejected refresh token (`invalid_grant`) needs a new sign-in
+            debug_error!("Codex token refresh failed ({status})");
+            return Err(ErrorCode::ReauthRequired.into());
         }
 
-        let refreshed = response
-            .json::<CodexRefreshResponse>()
-            .await
-            .map_err(|e| anyhow!("Invalid Codex token refresh response: {e}"))?;
+        let refreshed = response.json::<CodexRefreshResponse>().await.map_err(|e| {
+            debug_error!("Invalid Codex token refresh response: {e}");
+            anyhow::Error::from(ErrorCode::UnexpectedResponse)
+        })?;
 
         tokens.access_token = refreshed.access_token;
         if let Some(refresh_token) = refreshed.refresh_token {
@@ -227,9 +273,7 @@ impl CodexService {
             .as_ref()
             .map(|tokens| tokens.access_token.as_str())
             .filter(|token| !token.is_empty())
-            .ok_or_else(|| {
-                anyhow!("Codex auth.json contains no access token. Run `codex` to sign in.")
-            })
+            .ok_or_else(|| ErrorCode::ReauthRequired.into())
     }
 
     async fn usage_url_async() -> String {
@@ -296,9 +340,7 @@ enum CodexFetchError {
 impl CodexFetchError {
     fn into_anyhow(self) -> anyhow::Error {
         match self {
-            Self::Unauthorized => {
-                anyhow!("Codex OAuth token expired or invalid. Run `codex` to re-authenticate.")
-            }
+            Self::Unauthorized => ErrorCode::ReauthRequired.into(),
             Self::Other(error) => error,
         }
     }
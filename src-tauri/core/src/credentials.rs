@@ -0,0 +1,1993 @@
+use crate::clock;
+use crate::models::{
+    ClaudeAccount, ClaudeCredentialSource, ClaudeGlobalConfig, ClaudeOAuth, ClaudeOAuthCredentials,
+    ClaudeOrganization, CredentialMigration, CredentialSourceStatus, GoogleAuthorizedUser,
+    Provider, ZaiKeySource,
+};
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::debug_cred;
+use crate::i18n::{ErrorCode, UsageError};
+use crate::redact::Redacted;
+use crate::vault::{OsStore, SecretStore};
+
+/// Environment variables checked for a Z.ai key when none is stored, in order.
+const ZAI_API_KEY_VARS: [&str; 2] = ["ZAI_API_KEY", "Z_AI_API_KEY"];
+
+/// Environment variables checked for a Gemini API key when none is stored, in order; the
+/// same ones the Gemini SDKs read.
+const GEMINI_API_KEY_VARS: [&str; 2] = ["GEMINI_API_KEY", "GOOGLE_API_KEY"];
+
+/// Hosts of Z.ai's Anthropic-compatible endpoint, as set in `ANTHROPIC_BASE_URL`.
+const ZAI_ANTHROPIC_HOSTS: [&str; 2] = ["api.z.ai", "open.bigmodel.cn"];
+
+/// `env.ANTHROPIC_AUTH_TOKEN` of a Claude Code `settings.json` whose `ANTHROPIC_BASE_URL`
+/// points at Z.ai.
+fn zai_key_from_claude_settings(json: &str) -> Option<String> {
+    let settings: serde_json::Value = serde_json::from_str(json).ok()?;
+    let env = settings.get("env")?;
+    let base_url = env.get("ANTHROPIC_BASE_URL")?.as_str()?;
+    let host = reqwest::Url::parse(base_url).ok()?.host_str()?.to_string();
+    if !ZAI_ANTHROPIC_HOSTS.contains(&host.as_str()) {
+        return None;
+    }
+    ["ANTHROPIC_AUTH_TOKEN", "ANTHROPIC_API_KEY"]
+        .iter()
+        .find_map(|name| env.get(name)?.as_str())
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+}
+
+/// The API key of opencode's `zai-coding-plan` (or `zai`) provider in its `auth.json`.
+fn zai_key_from_opencode_auth(json: &str) -> Option<String> {
+    let auth: serde_json::Value = serde_json::from_str(json).ok()?;
+    ["zai-coding-plan", "zai"]
+        .iter()
+        .find_map(|provider| auth.get(provider)?.get("key")?.as_str())
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+}
+
+/// The ampcode.com API key in the Amp CLI's `secrets.json`, stored as
+/// `"apiKey@https://ampcode.com/": "..."`; any other `apiKey@` entry is taken if none is.
+fn amp_key_from_cli_secrets(json: &str) -> Option<String> {
+    let secrets: serde_json::Value = serde_json::from_str(json).ok()?;
+    let secrets = secrets.as_object()?;
+    secrets
+        .iter()
+        .filter(|(name, _)| name.starts_with("apiKey"))
+        .min_by_key(|(name, _)| !name.contains("ampcode.com"))
+        .and_then(|(_, value)| value.as_str())
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+}
+
+/// A resolved Z.ai key and where it was found.
+type ZaiKey = (String, ZaiKeySource);
+
+/// Short-lived credential cache to avoid repeated file/secret store reads within a single operation batch.
+/// TTL is intentionally short (5 seconds) since credentials can change externally. Failures
+/// are kept as `UsageError` so a cached miss reports the same code as the original.
+struct CredentialCache {
+    claude_credentials: Option<(Instant, ClaudeOAuthCredentials)>,
+    zai_api_key: Option<(Instant, Result<ZaiKey, UsageError>)>,
+    amp_session: Option<(Instant, Result<String, UsageError>)>,
+    amp_api_key: Option<(Instant, Result<String, UsageError>)>,
+    anthropic_admin_key: Option<(Instant, Result<String, UsageError>)>,
+    gemini_api_key: Option<(Instant, Result<String, UsageError>)>,
+    gemini_oauth: Option<(Instant, Result<GoogleAuthorizedUser, UsageError>)>,
+}
+
+impl CredentialCache {
+    const TTL: Duration = Duration::from_secs(5);
+
+    fn new() -> Self {
+        Self {
+            claude_credentials: None,
+            zai_api_key: None,
+            amp_session: None,
+            amp_api_key: None,
+            anthropic_admin_key: None,
+            gemini_api_key: None,
+            gemini_oauth: None,
+        }
+    }
+
+    fn claude_get(&self) -> Option<ClaudeOAuthCredentials> {
+        self.claude_credentials
+            .as_ref()
+            .and_then(|(instant, creds)| {
+                if instant.elapsed() < Self::TTL {
+                    Some(creds.clone())
+                } else {
+                    None
+                }
+            })
+    }
+
+    fn claude_set(&mut self, creds: ClaudeOAuthCredentials) {
+        self.claude_credentials = Some((Instant::now(), creds));
+    }
+
+    fn claude_invalidate(&mut self) {
+        self.claude_credentials = None;
+    }
+
+    fn zai_get(&self) -> Option<Result<ZaiKey, UsageError>> {
+        self.zai_api_key.as_ref().and_then(|(instant, result)| {
+            if instant.elapsed() < Self::TTL {
+                Some(result.clone())
+            } else {
+                None
+            }
+        })
+    }
+
+    fn zai_set(&mut self, result: Result<ZaiKey, UsageError>) {
+        self.zai_api_key = Some((Instant::now(), result));
+    }
+
+    fn zai_invalidate(&mut self) {
+        self.zai_api_key = None;
+    }
+
+    fn amp_get(&self) -> Option<Result<String, UsageError>> {
+        self.amp_session.as_ref().and_then(|(instant, result)| {
+            if instant.elapsed() < Self::TTL {
+                Some(result.clone())
+            } else {
+                None
+            }
+        })
+    }
+
+    fn amp_set(&mut self, result: Result<String, UsageError>) {
+        self.amp_session = Some((Instant::now(), result));
+    }
+
+    fn amp_invalidate(&mut self) {
+        self.amp_session = None;
+    }
+
+    fn amp_api_key_get(&self) -> Option<Result<String, UsageError>> {
+        self.amp_api_key.as_ref().and_then(|(instant, result)| {
+            if instant.elapsed() < Self::TTL {
+                Some(result.clone())
+            } else {
+                None
+            }
+        })
+    }
+
+    fn amp_api_key_set(&mut self, result: Result<String, UsageError>) {
+        self.amp_api_key = Some((Instant::now(), result));
+    }
+
+    fn amp_api_key_invalidate(&mut self) {
+        self.amp_api_key = None;
+    }
+
+    fn anthropic_get(&self) -> Option<Result<String, UsageError>> {
+        self.anthropic_admin_key
+            .as_ref()
+            .and_then(|(instant, result)| {
+                if instant.elapsed() < Self::TTL {
+                    Some(result.clone())
+                } else {
+                    None
+                }
+            })
+    }
+
+    fn anthropic_set(&mut self, result: Result<String, UsageError>) {
+        self.anthropic_admin_key = Some((Instant::now(), result));
+    }
+
+    fn anthropic_invalidate(&mut self) {
+        self.anthropic_admin_key = None;
+    }
+
+    fn gemini_api_key_get(&self) -> Option<Result<String, UsageError>> {
+        self.gemini_api_key.as_ref().and_then(|(instant, result)| {
+            if instant.elapsed() < Self::TTL {
+                Some(result.clone())
+            } else {
+                None
+            }
+        })
+    }
+
+    fn gemini_api_key_set(&mut self, result: Result<String, UsageError>) {
+        self.gemini_api_key = Some((Instant::now(), result));
+    }
+
+    fn gemini_oauth_get(&self) -> Option<Result<GoogleAuthorizedUser, UsageError>> {
+        self.gemini_oauth.as_ref().and_then(|(instant, result)| {
+            if instant.elapsed() < Self::TTL {
+                Some(result.clone())
+            } else {
+                None
+            }
+        })
+    }
+
+    fn gemini_oauth_set(&mut self, result: Result<GoogleAuthorizedUser, UsageError>) {
+        self.gemini_oauth = Some((Instant::now(), result));
+    }
+
+    fn gemini_invalidate(&mut self) {
+        self.gemini_api_key = None;
+        self.gemini_oauth = None;
+    }
+}
+
+static CACHE: Mutex<Option<CredentialCache>> = Mutex::new(None);
+
+/// Empty until settings are loaded, meaning `ClaudeCredentialSource::DEFAULT_PRIORITY`.
+static CLAUDE_SOURCE_PRIORITY: Mutex<Vec<ClaudeCredentialSource>> = Mutex::new(Vec::new());
+
+/// Config directory of the selected Claude profile; `None` means the sources decide.
+static CLAUDE_PROFILE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// WSL distros the `Wsl` source searches, in order; empty means every running distro.
+static CLAUDE_WSL_DISTROS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Organization UUID selected in settings; `None` means priority order alone decides.
+static CLAUDE_ORGANIZATION: Mutex<Option<String>> = Mutex::new(None);
+
+/// Long-lived token (from `claude setup-token`) Claude Code accepts instead of a login.
+const CLAUDE_OAUTH_TOKEN_VAR: &str = "CLAUDE_CODE_OAUTH_TOKEN";
+
+/// Whether `CLAUDE_OAUTH_TOKEN_VAR` is used when no source has a credentials file.
+static CLAUDE_OAUTH_TOKEN_ENV: AtomicBool = AtomicBool::new(true);
+
+/// The login Keychain item Claude Code keeps its OAuth tokens in on macOS instead of
+/// `~/.claude/.credentials.json`.
+#[cfg(target_os = "macos")]
+const CLAUDE_KEYCHAIN_SERVICE: &str = "Claude Code-credentials";
+
+/// Cleared by `install_test_vault` so E2E runs on a Mac never see the real login.
+#[cfg(target_os = "macos")]
+static CLAUDE_KEYCHAIN_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Where Z.ai and Amp secrets live. `None` means `OsStore`.
+static SECRET_STORE: RwLock<Option<Arc<dyn SecretStore>>> = RwLock::new(None);
+
+/// IMPORTANT: The credential cache mutex is held for the entire duration of `f`.
+/// `f` must not perform I/O, blocking calls, or acquire other locks — only cache lookups.
+fn with_cache<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut CredentialCache) -> R,
+{
+    let mut guard = CACHE.lock().expect("credential cache mutex poisoned");
+    if guard.is_none() {
+        *guard = Some(CredentialCache::new());
+    }
+    f(guard.as_mut().unwrap())
+}
+
+/// Advisory lock on a file shared with another app: a `<file>.lock` directory, since
+/// `mkdir` is atomic on every filesystem the credentials can live on, WSL shares included.
+/// Removed on drop.
+struct FileLock {
+    path: PathBuf,
+}
+
+impl FileLock {
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    /// A lock older than this was left behind by a writer that crashed.
+    const STALE_AFTER: Duration = Duration::from_secs(30);
+
+    fn acquire(target: &Path) -> Result<Self> {
+        let mut name = target.as_os_str().to_owned();
+        name.push(".lock");
+        let path = PathBuf::from(name);
+        let deadline = Instant::now() + Self::TIMEOUT;
+        loop {
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let stale = fs::metadata(&path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .is_some_and(|age| age > Self::STALE_AFTER);
+                    if stale {
+                        debug_cred!("Removing stale lock {}", path.display());
+                        let _ = fs::remove_dir(&path);
+                        continue;
+                    }
+                    if Instant::now() >= deadline {
+                        return Err(anyhow!("Timed out waiting for {}", path.display()));
+                    }
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                Err(e) => return Err(anyhow!("Failed to lock credentials file: {e}")),
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir(&self.path) {
+            debug_cred!("Failed to remove lock {}: {e}", self.path.display());
+        }
+    }
+}
+
+pub struct CredentialManager;
+
+impl CredentialManager {
+    const ZAI_TARGET: &'static str = "usage-bar-zai-credentials";
+    /// Labels of the named Z.ai keys, each saved at `ZAI_TARGET:<label>`; the secret stores
+    /// can't list their entries, so the labels are kept here.
+    const ZAI_LABELS_TARGET: &'static str = "usage-bar-zai-credentials-labels";
+    /// Label of the Z.ai key saved at `ZAI_TARGET` itself.
+    pub const ZAI_DEFAULT_LABEL: &'static str = "default";
+    const ZAI_LABEL_MAX_LEN: usize = 32;
+    const AMP_TARGET: &'static str = "usage-bar-amp-credentials";
+    /// Amp API key, used instead of the session cookie when `amp_auth` is `api_key`.
+    const AMP_API_KEY_TARGET: &'static str = "usage-bar-amp-api-key";
+    /// Epoch millis at which the session cookie was saved, for the expiry warning.
+    const AMP_SESSION_SAVED_AT_TARGET: &'static str = "usage-bar-amp-session-saved-at";
+    const ANTHROPIC_TARGET: &'static str = "usage-bar-anthropic-credentials";
+    /// Gemini API key; only used to validate it and to find its project.
+    const GEMINI_API_KEY_TARGET: &'static str = "usage-bar-gemini-api-key";
+    /// Google OAuth `authorized_user` JSON, which Cloud Monitoring quota reads need.
+    const GEMINI_OAUTH_TARGET: &'static str = "usage-bar-gemini-oauth";
+
+    /// Target names used by earlier builds, checked at startup by `migrate_legacy_credentials`.
+    const LEGACY_TARGETS: &'static [(Provider, &'static str)] = &[
+        (Provider::Zai, "usage-bar-zai-api-key"),
+        (Provider::Zai, "usage-bar-windows-zai"),
+        (Provider::Amp, "usage-bar-amp-session-cookie"),
+        (Provider::Amp, "usage-bar-windows-amp"),
+    ];
+
+    /// Resolve {env:varname} or $ENV:varname syntax to environment variable value
+    /// Returns the input string unchanged if it doesn't match the pattern
+    pub fn resolve_env_reference(input: &str) -> Result<String> {
+        let input_lower = input.to_lowercase();
+
+        // Check for {env:varname} or {ENV:varname} syntax
+        if let Some(_rest) = input_lower.strip_prefix("{env:") {
+            if input_lower.ends_with('}') {
+                // Strip "{env:" prefix and "}" suffix from original input to preserve casing
+                let original_var_name = input
+                    .strip_prefix("{env:")
+                    .or_else(|| input.strip_prefix("{ENV:"))
+                    .and_then(|s| s.strip_suffix('}'))
+                    .unwrap_or("");
+                debug_cred!("Resolving env variable: {original_var_name}");
+                return std::env::var(original_var_name)
+                    .inspect(|value| {
+                        debug_cred!(
+                            "Resolved env variable {original_var_name}: {}",
+                            Redacted(value)
+                        );
+                    })
+                    .map_err(|_| {
+                        debug_cred!("Failed to resolve env variable: {original_var_name}");
+                        anyhow::Error::from(ErrorCode::EnvVarMissing)
+                    });
+            }
+        }
+
+        // Check for $ENV:varname or $env:varname syntax
+        if let Some(_rest) = input_lower.strip_prefix("$env:") {
+            // Get the original casing version from the original input
+            let prefix_end = input.find('$').unwrap_or(0);
+            let prefix_end_char = input[prefix_end..]
+                .char_indices()
+                .nth(5)
+                .map(|(i, _)| prefix_end + i)
+                .unwrap_or(input.len());
+            let original_var_name = &input[prefix_end_char..]; // Skip prefix, keep everything after
+            debug_cred!("Resolving env variable: {original_var_name}");
+            return std::env::var(original_var_name)
+                .inspect(|value| {
+                    debug_cred!(
+                        "Resolved env variable {original_var_name}: {}",
+                        Redacted(value)
+                    );
+                })
+                .map_err(|_| {
+                    debug_cred!("Failed to resolve env variable: {original_var_name}");
+                    anyhow::Error::from(ErrorCode::EnvVarMissing)
+                });
+        }
+
+        Ok(input.to_string())
+    }
+
+    // ── Claude credentials (file-based, sources tried in `claude_credential_sources` order) ──
+
+    /// Sets the order in which Claude credential sources are tried. Takes effect on the next read.
+    pub fn claude_set_source_priority(priority: Vec<ClaudeCredentialSource>) {
+        let mut guard = CLAUDE_SOURCE_PRIORITY
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if *guard != priority {
+            debug_cred!("Claude credential source priority: {priority:?}");
+            *guard = priority;
+            with_cache(|c| c.claude_invalidate());
+        }
+    }
+
+    /// Prefers the source logged in to `organization`. Takes effect on the next read.
+    pub fn claude_set_organization(organization: Option<String>) {
+        let mut guard = CLAUDE_ORGANIZATION
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if *guard != organization {
+            debug_cred!("Claude organization: {organization:?}");
+            *guard = organization;
+            with_cache(|c| c.claude_invalidate());
+        }
+    }
+
+    /// Reads Claude credentials from `dir` alone, bypassing sources and organization, while
+    /// a profile is selected. Takes effect on the next read.
+    pub fn claude_set_profile_dir(dir: Option<PathBuf>) {
+        let mut guard = CLAUDE_PROFILE_DIR
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if *guard != dir {
+            debug_cred!("Claude profile dir: {dir:?}");
+            *guard = dir;
+            with_cache(|c| c.claude_invalidate());
+        }
+    }
+
+    /// Lets `claude_read_credentials` fall back to `CLAUDE_CODE_OAUTH_TOKEN`. Takes effect on
+    /// the next read.
+    pub fn claude_set_oauth_token_env(enabled: bool) {
+        if CLAUDE_OAUTH_TOKEN_ENV.swap(enabled, Ordering::Relaxed) != enabled {
+            debug_cred!("Claude OAuth token env var enabled: {enabled}");
+            with_cache(|c| c.claude_invalidate());
+        }
+    }
+
+    fn claude_profile_dir() -> Option<PathBuf> {
+        CLAUDE_PROFILE_DIR
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    fn claude_selected_organization() -> Option<String> {
+        CLAUDE_ORGANIZATION
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    fn claude_source_priority() -> Vec<ClaudeCredentialSource> {
+        let guard = CLAUDE_SOURCE_PRIORITY
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if guard.is_empty() {
+            ClaudeCredentialSource::DEFAULT_PRIORITY.to_vec()
+        } else {
+            guard.clone()
+        }
+    }
+
+    /// `%USERPROFILE%` on Windows, `$HOME` elsewhere.
+    pub fn home_dir() -> Result<PathBuf> {
+        let var = if cfg!(target_os = "windows") {
+            "USERPROFILE"
+        } else {
+            "HOME"
+        };
+        std::env::var_os(var)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+            .ok_or_else(|| anyhow!("{var} environment variable not set"))
+    }
+
+    /// Where the `Home` source looks: `~/.claude`, and outside Windows also
+    /// `$XDG_CONFIG_HOME/claude` (`~/.config/claude` when unset), which newer Claude Code
+    /// builds use on Linux.
+    fn claude_home_dirs(home: &Path, xdg_config_home: Option<PathBuf>) -> Vec<PathBuf> {
+        let mut dirs = vec![home.join(".claude")];
+        if !cfg!(target_os = "windows") {
+            let config = xdg_config_home
+                .filter(|dir| dir.is_absolute())
+                .unwrap_or_else(|| home.join(".config"));
+            dirs.push(config.join("claude"));
+        }
+        dirs
+    }
+
+    /// Checks both possible filenames — .credentials.json (dot prefix) and credentials.json
+    fn claude_find_in_dir(claude_dir: &Path) -> Option<PathBuf> {
+        [".credentials.json", "credentials.json"]
+            .into_iter()
+            .map(|name| claude_dir.join(name))
+            .find(|path| path.exists())
+    }
+
+    /// Limits the `Wsl` source to `distros`, searched in order; empty searches every running
+    /// distro. Takes effect on the next read.
+    pub fn claude_set_wsl_distros(distros: Vec<String>) {
+        let mut guard = CLAUDE_WSL_DISTROS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if *guard != distros {
+            debug_cred!("Claude WSL distros: {distros:?}");
+            *guard = distros;
+            with_cache(|c| c.claude_invalidate());
+        }
+    }
+
+    /// `\\wsl.localhost\<distro>\home\<user>\.claude`, first match across the distros set with
+    /// `claude_set_wsl_distros`, or across every running distro.
+    fn claude_find_in_wsl() -> Option<PathBuf> {
+        let root = [r"\\wsl.localhost\", r"\\wsl$\"]
+            .into_iter()
+            .map(PathBuf::from)
+            .find(|root| root.is_dir())?;
+        let distros = CLAUDE_WSL_DISTROS
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        Self::claude_find_in_wsl_root(&root, &distros)
+    }
+
+    /// Searches `distros` under `root`, or every distro there when none are named.
+    fn claude_find_in_wsl_root(root: &Path, distros: &[String]) -> Option<PathBuf> {
+        let distro_dirs: Vec<PathBuf> = if distros.is_empty() {
+            fs::read_dir(root)
+                .ok()?
+                .flatten()
+                .map(|e| e.path())
+                .collect()
+        } else {
+            distros.iter().map(|distro| root.join(distro)).collect()
+        };
+
+        distro_dirs
+            .into_iter()
+            .flat_map(|distro| {
+                let users = fs::read_dir(distro.join("home"))
+                    .map(|entries| entries.flatten().map(|e| e.path()).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                users.into_iter().chain([distro.join("root")])
+            })
+            .find_map(|user_home| Self::claude_find_in_dir(&user_home.join(".claude")))
+    }
+
+    /// Locates the credentials file for one source, or explains why it has none.
+    fn claude_locate(source: ClaudeCredentialSource) -> std::result::Result<PathBuf, String> {
+        match source {
+            ClaudeCredentialSource::ConfigDir => {
+                let dir = std::env::var_os("CLAUDE_CONFIG_DIR")
+                    .map(PathBuf::from)
+                    .ok_or_else(|| "CLAUDE_CONFIG_DIR is not set".to_string())?;
+                Self::claude_find_in_dir(&dir)
+                    .ok_or_else(|| format!("No credentials file in {}", dir.display()))
+            }
+            ClaudeCredentialSource::Home => {
+                let home = Self::home_dir().map_err(|e| e.to_string())?;
+                let dirs = Self::claude_home_dirs(
+                    &home,
+                    std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from),
+                );
+                dirs.iter()
+                    .find_map(|dir| Self::claude_find_in_dir(dir))
+                    .ok_or_else(|| {
+                        let searched: Vec<String> =
+                            dirs.iter().map(|dir| dir.display().to_string()).collect();
+                        format!("No credentials file in {}", searched.join(" or "))
+                    })
+            }
+            ClaudeCredentialSource::Wsl => Self::claude_find_in_wsl()
+                .ok_or_else(|| "No WSL distro with Claude credentials found".to_string()),
+        }
+    }
+
+    /// `(uuid, name)` from the `.claude.json` that Claude Code keeps next to a credentials
+    /// file: inside the config dir for `CLAUDE_CONFIG_DIR`, one level up for `~/.claude`.
+    fn claude_read_organization(credentials_path: &Path) -> Option<(String, String)> {
+        let claude_dir = credentials_path.parent()?;
+        let config = [Some(claude_dir), claude_dir.parent()]
+            .into_iter()
+            .flatten()
+            .map(|dir| dir.join(".claude.json"))
+            .find_map(|path| fs::read_to_string(path).ok())?;
+        let account = serde_json::from_str::<ClaudeGlobalConfig>(&config)
+            .ok()?
+            .oauth_account?;
+        let uuid = account.organization_uuid?;
+        let name = account.organization_name.unwrap_or_else(|| uuid.clone());
+        Some((uuid, name))
+    }
+
+    /// First source logged in to the selected organization, if one is selected and found.
+    fn claude_organization_path() -> Option<(ClaudeCredentialSource, PathBuf)> {
+        let selected = Self::claude_selected_organization()?;
+        let found = Self::claude_source_priority()
+            .into_iter()
+            .filter_map(|source| Self::claude_locate(source).ok().map(|path| (source, path)))
+            .find(|(_, path)| {
+                Self::claude_read_organization(path).is_some_and(|(uuid, _)| uuid == selected)
+            });
+        if found.is_none() {
+            debug_cred!("No credential source is logged in to organization {selected}");
+        }
+        found
+    }
+
+    fn claude_credentials_path() -> Result<PathBuf> {
+        debug_cred!("claude_credentials_path called");
+
+        if let Some(dir) = Self::claude_profile_dir() {
+            // Default to .credentials.json when nothing exists (for error messages)
+            return Ok(
+                Self::claude_find_in_dir(&dir).unwrap_or_else(|| dir.join(".credentials.json"))
+            );
+        }
+
+        if let Some((source, path)) = Self::claude_organization_path() {
+            debug_cred!("Using {source:?} credentials for selected organization: {path:?}");
+            return Ok(path);
+        }
+
+        for source in Self::claude_source_priority() {
+            match Self::claude_locate(source) {
+                Ok(path) => {
+                    debug_cred!("Using {source:?} credentials: {path:?}");
+                    return Ok(path);
+                }
+                Err(reason) => {
+                    debug_cred!("Skipping {source:?}: {reason}");
+                }
+            }
+        }
+
+        debug_cred!("No source has credentials, defaulting to ~/.claude/.credentials.json");
+        // Default to .credentials.json when nothing exists (for error messages)
+        Ok(Self::home_dir()?.join(".claude").join(".credentials.json"))
+    }
+
+    /// Claude Code's config folder for the login in use, where its `projects` transcripts are.
+    pub fn claude_config_dir() -> Result<PathBuf> {
+        let path = Self::claude_credentials_path()?;
+        path.parent()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| anyhow!("Credentials path has no parent folder: {path:?}"))
+    }
+
+    /// Every configured source in priority order, marking which one `claude_read_credentials` uses.
+    pub fn claude_credential_sources() -> Vec<CredentialSourceStatus> {
+        let profile_selected = Self::claude_profile_dir().is_some();
+        let mut active: Option<ClaudeCredentialSource> =
+            Self::claude_organization_path().map(|(source, _)| source);
+
+        Self::claude_source_priority()
+            .into_iter()
+            .map(|source| {
+                let located = Self::claude_locate(source);
+                let path = located.as_ref().ok().map(|p| p.display().to_string());
+                let found = located.is_ok();
+                let (is_active, reason) = match (located, active) {
+                    (Ok(_), _) if profile_selected => {
+                        (false, "Found, but a Claude profile is selected".to_string())
+                    }
+                    (Ok(_), Some(winner)) if winner == source => {
+                        (true, "Logged in to the selected organization".to_string())
+                    }
+                    (Ok(_), None) => {
+                        active = Some(source);
+                        (
+                            true,
+                            "First source in priority order with a credentials file".to_string(),
+                        )
+                    }
+                    (Ok(_), Some(winner)) => {
+                        (false, format!("Found, but {winner:?} has higher priority"))
+                    }
+                    (Err(reason), _) => (false, reason),
+                };
+                CredentialSourceStatus {
+                    source,
+                    path,
+                    found,
+                    active: is_active,
+                    reason,
+                }
+            })
+            .collect()
+    }
+
+    /// Organizations the configured sources are logged in to, one entry per organization.
+    pub fn claude_organizations() -> Vec<ClaudeOrganization> {
+        let active_path = Self::claude_credentials_path().ok();
+        let mut organizations: Vec<ClaudeOrganization> = Vec::new();
+        for source in Self::claude_source_priority() {
+            let Ok(path) = Self::claude_locate(source) else {
+                continue;
+            };
+            let Some((uuid, name)) = Self::claude_read_organization(&path) else {
+                continue;
+            };
+            let active = active_path.as_ref() == Some(&path);
+            match organizations.iter_mut().find(|org| org.uuid == uuid) {
+                Some(existing) => existing.active |= active,
+                None => organizations.push(ClaudeOrganization {
+                    uuid,
+                    name,
+                    source,
+                    active,
+                }),
+            }
+        }
+        organizations
+    }
+
+    /// Name of the organization whose login `claude_read_credentials` uses.
+    pub fn claude_active_organization_name() -> Option<String> {
+        let path = Self::claude_credentials_path().ok()?;
+        Self::claude_read_organization(&path).map(|(_, name)| name)
+    }
+
+    /// Distinct logins across the configured sources in priority order, each with the
+    /// credentials read from its own file (never the cache, which holds only the active one).
+    pub fn claude_accounts() -> Vec<(ClaudeAccount, Result<ClaudeOAuthCredentials>)> {
+        let active_path = Self::claude_credentials_path().ok();
+        let mut paths: Vec<(ClaudeCredentialSource, PathBuf)> = Vec::new();
+        for source in Self::claude_source_priority() {
+            if let Ok(path) = Self::claude_locate(source) {
+                if !paths.iter().any(|(_, seen)| *seen == path) {
+                    paths.push((source, path));
+                }
+            }
+        }
+
+        paths
+            .into_iter()
+            .map(|(source, path)| {
+                let organization = Self::claude_read_organization(&path);
+                let account = ClaudeAccount {
+                    source,
+                    active: active_path.as_ref() == Some(&path),
+                    organization_uuid: organization.as_ref().map(|(uuid, _)| uuid.clone()),
+                    organization_name: organization.map(|(_, name)| name),
+                };
+                (account, Self::claude_read_credentials_at(&path))
+            })
+            .collect()
+    }
+
+    fn claude_read_credentials_at(path: &Path) -> Result<ClaudeOAuthCredentials> {
+        debug_cred!("Reading credentials from: {path:?}");
+
+        let json_str = fs::read_to_string(path).map_err(|e| {
+            debug_cred!("Failed to read {}: {e}", path.display());
+            anyhow::Error::from(ErrorCode::CredentialsNotFound)
+        })?;
+        let json_len = json_str.len();
+        debug_cred!("Read {json_len} bytes from credentials file");
+
+        let credentials: ClaudeOAuthCredentials = serde_json::from_str(&json_str).map_err(|e| {
+            debug_cred!("Failed to parse JSON: {e}");
+            anyhow::Error::from(ErrorCode::CredentialsUnreadable)
+        })?;
+        debug_cred!("Successfully parsed credentials");
+        Ok(credentials)
+    }
+
+    pub fn claude_read_credentials() -> Result<ClaudeOAuthCredentials> {
+        debug_cred!("claude_read_credentials called");
+
+        // Check cache first
+        if let Some(cached) = with_cache(|c| c.claude_get()) {
+            debug_cred!("Returning cached Claude credentials");
+            return Ok(cached);
+        }
+
+        let path = Self::claude_credentials_path()?;
+        let credentials = match Self::claude_read_credentials_at(&path) {
+            Err(e) if !path.exists() => Self::claude_read_without_file().ok_or(e)?,
+            result => result?,
+        };
+
+        // Cache the result
+        with_cache(|c| c.claude_set(credentials.clone()));
+
+        Ok(credentials)
+    }
+
+    /// Where credentials come from when no source has a file: the Keychain, where Claude
+    /// Code on macOS keeps the login, then `CLAUDE_CODE_OAUTH_TOKEN`. A selected profile
+    /// never falls back to the env var, which belongs to whichever login created it.
+    fn claude_read_without_file() -> Option<ClaudeOAuthCredentials> {
+        #[cfg(target_os = "macos")]
+        if let Ok(credentials) = Self::claude_read_keychain() {
+            return Some(credentials);
+        }
+        if Self::claude_profile_dir().is_some() || !CLAUDE_OAUTH_TOKEN_ENV.load(Ordering::Relaxed) {
+            return None;
+        }
+        let credentials = Self::claude_credentials_from_token(
+            &std::env::var(CLAUDE_OAUTH_TOKEN_VAR).unwrap_or_default(),
+        )?;
+        debug_cred!("Using Claude token from {CLAUDE_OAUTH_TOKEN_VAR}");
+        Some(credentials)
+    }
+
+    /// A bare access token has no refresh token or known expiry, so `ClaudeService` never
+    /// tries to refresh it.
+    fn claude_credentials_from_token(token: &str) -> Option<ClaudeOAuthCredentials> {
+        let token = token.trim();
+        if token.is_empty() {
+            return None;
+        }
+        Some(ClaudeOAuthCredentials {
+            claude_ai_oauth: ClaudeOAuth {
+                access_token: token.to_string(),
+                refresh_token: String::new(),
+                expires_at: None,
+                subscription_type: None,
+                rate_limit_tier: None,
+            },
+        })
+    }
+
+    #[cfg(target_os = "macos")]
+    fn claude_keychain_raw() -> Option<String> {
+        if !CLAUDE_KEYCHAIN_ENABLED.load(Ordering::Relaxed) {
+            return None;
+        }
+        let blob = crate::vault::keychain_read(CLAUDE_KEYCHAIN_SERVICE).ok()?;
+        String::from_utf8(blob).ok()
+    }
+
+    #[cfg(target_os = "macos")]
+    fn claude_read_keychain() -> Result<ClaudeOAuthCredentials> {
+        let json_str = Self::claude_keychain_raw()
+            .ok_or_else(|| anyhow::Error::from(ErrorCode::CredentialsNotFound))?;
+        debug_cred!(
+            "Read {} bytes from the Claude Code Keychain item",
+            json_str.len()
+        );
+        serde_json::from_str(&json_str).map_err(|e| {
+            debug_cred!("Failed to parse JSON: {e}");
+            anyhow::Error::from(ErrorCode::CredentialsUnreadable)
+        })
+    }
+
+    /// Refreshed tokens go back to the Keychain item they were read from, so Claude Code
+    /// keeps working with the rotated refresh token.
+    #[cfg(target_os = "macos")]
+    fn claude_write_keychain(existing: &str, credentials: &ClaudeOAuthCredentials) -> Result<()> {
+        let result = match Self::claude_merge_credentials(Some(existing), credentials)? {
+            Some(json_str) => crate::vault::keychain_write(CLAUDE_KEYCHAIN_SERVICE, &json_str),
+            None => {
+                debug_cred!("Keychain item holds a newer token, keeping it");
+                Ok(())
+            }
+        };
+        with_cache(|c| c.claude_invalidate());
+        result
+    }
+
+    /// Times the file may change under us between reading and replacing it before giving up.
+    const CLAUDE_WRITE_ATTEMPTS: usize = 3;
+
+    /// The file's new contents with `credentials` merged into `existing`, or `None` when the
+    /// token already on disk expires later (Claude Code refreshed it after we did).
+    fn claude_merge_credentials(
+        existing: Option<&str>,
+        credentials: &ClaudeOAuthCredentials,
+    ) -> Result<Option<String>> {
+        // Preserve fields we don't model (file belongs to Claude Code)
+        let mut root: serde_json::Value = match existing {
+            Some(existing) => serde_json::from_str(existing)
+                .map_err(|e| anyhow!("Failed to parse credentials file (may be corrupted): {e}"))?,
+            None => serde_json::json!({}),
+        };
+
+        let on_disk_expiry = root["claudeAiOauth"]["expiresAt"].as_i64();
+        if let (Some(on_disk), Some(ours)) =
+            (on_disk_expiry, credentials.claude_ai_oauth.expires_at)
+        {
+            if on_disk > ours {
+                return Ok(None);
+            }
+        }
+
+        // Update only the claudeAiOauth subtree
+        root["claudeAiOauth"] = serde_json::to_value(&credentials.claude_ai_oauth)
+            .map_err(|e| anyhow!("Failed to serialize credentials: {e}"))?;
+        serde_json::to_string_pretty(&root)
+            .map(Some)
+            .map_err(|e| anyhow!("Failed to serialize credentials: {e}"))
+    }
+
+    fn claude_read_raw(path: &Path) -> Result<Option<String>> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow!("Failed to read credentials file: {e}")),
+        }
+    }
+
+    /// Claude Code rewrites the same file when it refreshes, so the write holds a
+    /// `FileLock` and re-reads the file right before the rename; if another writer that
+    /// ignores the lock changed it meanwhile, the merge starts over from its contents.
+    pub fn claude_write_credentials(credentials: &ClaudeOAuthCredentials) -> Result<()> {
+        let path = Self::claude_credentials_path()?;
+
+        #[cfg(target_os = "macos")]
+        if !path.exists() {
+            if let Some(existing) = Self::claude_keychain_raw() {
+                return Self::claude_write_keychain(&existing, credentials);
+            }
+        }
+
+        let result = Self::claude_write_file(&path, credentials);
+
+        // Invalidate cache after writing new credentials (or finding newer ones on disk)
+        with_cache(|c| c.claude_invalidate());
+
+        result.map_err(|e| {
+            debug_cred!("{e}");
+            ErrorCode::CredentialSaveFailed.into()
+        })
+    }
+
+    fn claude_write_file(path: &Path, credentials: &ClaudeOAuthCredentials) -> Result<()> {
+        // Ensure directory exists
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| anyhow!("Failed to create .claude directory: {e}"))?;
+        }
+        let _lock = FileLock::acquire(path)?;
+        Self::claude_write_locked(path, credentials)
+    }
+
+    fn claude_write_locked(path: &Path, credentials: &ClaudeOAuthCredentials) -> Result<()> {
+        let temp_path = path.with_extension("json.tmp");
+        for attempt in 1..=Self::CLAUDE_WRITE_ATTEMPTS {
+            let existing = Self::claude_read_raw(path)?;
+            let Some(json_str) = Self::claude_merge_credentials(existing.as_deref(), credentials)?
+            else {
+                debug_cred!("Credentials file holds a newer token, keeping it");
+                return Ok(());
+            };
+
+            // Atomic write: temp file + rename
+            fs::write(&temp_path, &json_str)
+                .map_err(|e| anyhow!("Failed to write credentials: {e}"))?;
+            if Self::claude_read_raw(path)? != existing {
+                debug_cred!("Credentials file changed while saving (attempt {attempt}), retrying");
+                let _ = fs::remove_file(&temp_path);
+                continue;
+            }
+            return fs::rename(&temp_path, path).map_err(|e| {
+                let _ = fs::remove_file(&temp_path);
+                anyhow!("Failed to save credentials: {e}")
+            });
+        }
+        Err(anyhow!(
+            "Credentials file kept changing while saving; try again"
+        ))
+    }
+
+    /// Drops the cached Claude credentials so the next read goes back to the sources, e.g.
+    /// after Claude Code signed in again.
+    pub fn claude_forget_cached() {
+        with_cache(|c| c.claude_invalidate());
+    }
+
+    pub fn claude_update_token(
+        access_token: &str,
+        refresh_token: &str,
+        expires_at: i64,
+    ) -> Result<()> {
+        let mut credentials = Self::claude_read_credentials()?;
+        credentials.claude_ai_oauth.access_token = access_token.to_string();
+        credentials.claude_ai_oauth.refresh_token = refresh_token.to_string();
+        credentials.claude_ai_oauth.expires_at = Some(expires_at);
+        Self::claude_write_credentials(&credentials)
+    }
+
+    pub fn zai_read_api_key() -> Result<String> {
+        Self::zai_find_api_key().map(|(key, _)| key)
+    }
+
+    /// The Z.ai key and where it was found: the stored key, else `ZAI_API_KEY_VARS`, else the
+    /// CLI configs of `zai_discover_api_key`.
+    pub fn zai_find_api_key() -> Result<ZaiKey> {
+        // Check cache first - cache stores the resolved API key result
+        if let Some(cached) = with_cache(|c| c.zai_get()) {
+            debug_cred!("Returning cached Z.ai API key");
+            return cached.map_err(anyhow::Error::from);
+        }
+
+        let found = match Self::read_credential(Self::ZAI_TARGET) {
+            Ok(blob) => {
+                let (key_str, _) =
+                    Self::decode_blob(&blob).ok_or(ErrorCode::CredentialsUnreadable)?;
+                // Resolve environment variable if using {env:varname} syntax
+                (Self::resolve_env_reference(&key_str)?, ZaiKeySource::Stored)
+            }
+            Err(e) => Self::zai_discover_api_key().ok_or(e)?,
+        };
+
+        // Cache the resolved value (not the raw env var reference)
+        // This avoids repeated resolution and log spam
+        with_cache(|c| c.zai_set(Ok(found.clone())));
+
+        Ok(found)
+    }
+
+    /// A key set up for other tools, for when none is stored.
+    fn zai_discover_api_key() -> Option<ZaiKey> {
+        let from_env = ZAI_API_KEY_VARS.iter().find_map(|var| {
+            let key = std::env::var(var).ok()?;
+            Some(key.trim().to_string()).filter(|key| !key.is_empty())
+        });
+        if let Some(key) = from_env {
+            debug_cred!("Using Z.ai API key from the environment");
+            return Some((key, ZaiKeySource::Environment));
+        }
+
+        let read = |path: PathBuf| fs::read_to_string(path).ok();
+        // Claude Code pointed at Z.ai's Anthropic-compatible endpoint (Z.ai's setup guide).
+        let claude_settings = Self::claude_config_dir()
+            .ok()
+            .and_then(|dir| read(dir.join("settings.json")));
+        if let Some(key) = claude_settings
+            .as_deref()
+            .and_then(zai_key_from_claude_settings)
+        {
+            debug_cred!("Using Z.ai API key from Claude Code settings");
+            return Some((key, ZaiKeySource::ClaudeSettings));
+        }
+
+        let home = Self::home_dir().ok()?;
+        let opencode_auth = read(home.join(".local/share/opencode/auth.json"));
+        if let Some(key) = opencode_auth
+            .as_deref()
+            .and_then(zai_key_from_opencode_auth)
+        {
+            debug_cred!("Using Z.ai API key from opencode");
+            return Some((key, ZaiKeySource::OpenCode));
+        }
+        None
+    }
+
+    /// Where the Z.ai key in use comes from; `None` when there is none.
+    pub fn zai_api_key_source() -> Option<ZaiKeySource> {
+        match Self::zai_find_api_key() {
+            Ok((_, source)) => Some(source),
+            Err(e) => {
+                with_cache(|c| c.zai_set(Err(UsageError::from(&e))));
+                None
+            }
+        }
+    }
+
+    pub fn zai_write_api_key(api_key: &str) -> Result<()> {
+        Self::write_credential(Self::ZAI_TARGET, api_key)?;
+        // Invalidate cache after writing
+        with_cache(|c| c.zai_invalidate());
+        Ok(())
+    }
+
+    pub fn zai_delete_api_key() -> Result<()> {
+        Self::delete_credential(Self::ZAI_TARGET)?;
+        // Invalidate cache after deleting
+        with_cache(|c| c.zai_invalidate());
+        Ok(())
+    }
+
+    /// Trims `label` and checks it can name a Z.ai key: 1 to 32 letters, digits, `-` or `_`.
+    pub fn zai_check_label(label: &str) -> Result<&str> {
+        let label = label.trim();
+        if label.is_empty()
+            || label.len() > Self::ZAI_LABEL_MAX_LEN
+            || !label
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(ErrorCode::InvalidKeyLabel.into());
+        }
+        Ok(label)
+    }
+
+    /// Labels of the saved named Z.ai keys, without `ZAI_DEFAULT_LABEL`.
+    pub fn zai_labels() -> Vec<String> {
+        let Ok(blob) = Self::read_credential(Self::ZAI_LABELS_TARGET) else {
+            return Vec::new();
+        };
+        Self::decode_blob(&blob)
+            .and_then(|(json, _)| serde_json::from_str(&json).ok())
+            .unwrap_or_else(|| {
+                debug_cred!("Z.ai key labels unreadable, ignoring them");
+                Vec::new()
+            })
+    }
+
+    fn zai_write_labels(labels: &[String]) -> Result<()> {
+        if labels.is_empty() {
+            return Self::delete_credential(Self::ZAI_LABELS_TARGET).or(Ok(()));
+        }
+        Self::write_credential(Self::ZAI_LABELS_TARGET, &serde_json::to_string(labels)?)
+    }
+
+    fn zai_named_target(label: &str) -> String {
+        format!("{}:{label}", Self::ZAI_TARGET)
+    }
+
+    /// The key saved under `label`; `ZAI_DEFAULT_LABEL` is the one `zai_read_api_key` reads.
+    pub fn zai_read_named_api_key(label: &str) -> Result<String> {
+        if label == Self::ZAI_DEFAULT_LABEL {
+            return Self::zai_read_api_key();
+        }
+        let blob = Self::read_credential(&Self::zai_named_target(Self::zai_check_label(label)?))?;
+        let (key_str, _) = Self::decode_blob(&blob).ok_or(ErrorCode::CredentialsUnreadable)?;
+        Self::resolve_env_reference(&key_str)
+    }
+
+    pub fn zai_write_named_api_key(label: &str, api_key: &str) -> Result<()> {
+        if label == Self::ZAI_DEFAULT_LABEL {
+            return Self::zai_write_api_key(api_key);
+        }
+        let label = Self::zai_check_label(label)?;
+        Self::write_credential(&Self::zai_named_target(label), api_key)?;
+        let mut labels = Self::zai_labels();
+        if !labels.iter().any(|l| l == label) {
+            labels.push(label.to_string());
+            Self::zai_write_labels(&labels)?;
+        }
+        debug_cred!("Saved Z.ai key {label:?}");
+        Ok(())
+    }
+
+    pub fn zai_delete_named_api_key(label: &str) -> Result<()> {
+        if label == Self::ZAI_DEFAULT_LABEL {
+            return Self::zai_delete_api_key();
+        }
+        let label = Self::zai_check_label(label)?;
+        let deleted = Self::delete_credential(&Self::zai_named_target(label));
+        let mut labels = Self::zai_labels();
+        labels.retain(|l| l != label);
+        Self::zai_write_labels(&labels)?;
+        deleted
+    }
+
+    pub fn amp_read_session_cookie() -> Result<String> {
+        if let Some(cached) = with_cache(|c| c.amp_get()) {
+            debug_cred!("Returning cached Amp session cookie");
+            return cached.map_err(anyhow::Error::from);
+        }
+
+        let blob = Self::read_credential(Self::AMP_TARGET)?;
+
+        let (cookie_str, _) = Self::decode_blob(&blob).ok_or(ErrorCode::CredentialsUnreadable)?;
+
+        with_cache(|c| c.amp_set(Ok(cookie_str.clone())));
+
+        Ok(cookie_str)
+    }
+
+    pub fn amp_write_session_cookie(cookie: &str) -> Result<()> {
+        Self::write_credential(Self::AMP_TARGET, cookie)?;
+        with_cache(|c| c.amp_invalidate());
+        // Only feeds the expiry warning, so the cookie stays saved if this fails
+        let saved_at = clock::now_millis().to_string();
+        if let Err(e) = Self::write_credential(Self::AMP_SESSION_SAVED_AT_TARGET, &saved_at) {
+            debug_cred!("Failed to record when the Amp session was saved: {e}");
+        }
+        Ok(())
+    }
+
+    pub fn amp_delete_session_cookie() -> Result<()> {
+        Self::delete_credential(Self::AMP_TARGET)?;
+        with_cache(|c| c.amp_invalidate());
+        let _ = Self::delete_credential(Self::AMP_SESSION_SAVED_AT_TARGET);
+        Ok(())
+    }
+
+    /// When the session cookie was saved; `None` for cookies saved by older builds.
+    pub fn amp_session_saved_at() -> Option<i64> {
+        let blob = Self::read_credential(Self::AMP_SESSION_SAVED_AT_TARGET).ok()?;
+        let (saved_at, _) = Self::decode_blob(&blob)?;
+        saved_at.trim().parse().ok()
+    }
+
+    pub fn amp_has_session_cookie() -> bool {
+        if let Some(cached) = with_cache(|c| c.amp_get()) {
+            debug_cred!("Returning cached Amp session cookie for has_session_cookie check");
+            return cached.is_ok();
+        }
+
+        match Self::amp_read_session_cookie() {
+            Ok(_) => true,
+            Err(e) => {
+                with_cache(|c| c.amp_set(Err(UsageError::from(&e))));
+                false
+            }
+        }
+    }
+
+    pub fn amp_read_api_key() -> Result<String> {
+        if let Some(cached) = with_cache(|c| c.amp_api_key_get()) {
+            debug_cred!("Returning cached Amp API key");
+            return cached.map_err(anyhow::Error::from);
+        }
+
+        let key = match Self::read_credential(Self::AMP_API_KEY_TARGET) {
+            Ok(blob) => {
+                let (key_str, _) =
+                    Self::decode_blob(&blob).ok_or(ErrorCode::CredentialsUnreadable)?;
+                // Same `{env:varname}` syntax as the Z.ai key
+                Self::resolve_env_reference(&key_str)?
+            }
+            Err(e) => Self::amp_discover_api_key().ok_or(e)?,
+        };
+
+        with_cache(|c| c.amp_api_key_set(Ok(key.clone())));
+
+        Ok(key)
+    }
+
+    /// The key the Amp CLI uses, for when none is stored: `AMP_API_KEY`, else the CLI's
+    /// `secrets.json` under `$XDG_DATA_HOME/amp` (`~/.local/share/amp`, on Windows too).
+    fn amp_discover_api_key() -> Option<String> {
+        if let Some(key) = std::env::var("AMP_API_KEY")
+            .ok()
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty())
+        {
+            debug_cred!("Using Amp API key from the environment");
+            return Some(key);
+        }
+
+        let data_dir = std::env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| Self::home_dir().ok().map(|home| home.join(".local/share")))?;
+        let secrets = fs::read_to_string(data_dir.join("amp/secrets.json")).ok()?;
+        let key = amp_key_from_cli_secrets(&secrets)?;
+        debug_cred!("Using Amp API key from the Amp CLI");
+        Some(key)
+    }
+
+    pub fn amp_write_api_key(api_key: &str) -> Result<()> {
+        Self::write_credential(Self::AMP_API_KEY_TARGET, api_key)?;
+        with_cache(|c| c.amp_api_key_invalidate());
+        Ok(())
+    }
+
+    pub fn amp_delete_api_key() -> Result<()> {
+        Self::delete_credential(Self::AMP_API_KEY_TARGET)?;
+        with_cache(|c| c.amp_api_key_invalidate());
+        Ok(())
+    }
+
+    pub fn amp_has_api_key() -> bool {
+        if let Some(cached) = with_cache(|c| c.amp_api_key_get()) {
+            debug_cred!("Returning cached Amp API key for has_api_key check");
+            return cached.is_ok();
+        }
+
+        match Self::amp_read_api_key() {
+            Ok(_) => true,
+            Err(e) => {
+                with_cache(|c| c.amp_api_key_set(Err(UsageError::from(&e))));
+                false
+            }
+        }
+    }
+
+    pub fn gemini_read_api_key() -> Result<String> {
+        if let Some(cached) = with_cache(|c| c.gemini_api_key_get()) {
+            debug_cred!("Returning cached Gemini API key");
+            return cached.map_err(anyhow::Error::from);
+        }
+
+        let key = match Self::read_credential(Self::GEMINI_API_KEY_TARGET) {
+            Ok(blob) => {
+                let (key_str, _) =
+                    Self::decode_blob(&blob).ok_or(ErrorCode::CredentialsUnreadable)?;
+                // Same `{env:varname}` syntax as the Z.ai key
+                Self::resolve_env_reference(&key_str)?
+            }
+            Err(e) => GEMINI_API_KEY_VARS
+                .iter()
+                .find_map(|var| {
+                    std::env::var(var)
+                        .ok()
+                        .map(|key| key.trim().to_string())
+                        .filter(|key| !key.is_empty())
+                        .inspect(|_| {
+                            debug_cred!("Using Gemini API key from {var}");
+                        })
+                })
+                .ok_or(e)?,
+        };
+
+        with_cache(|c| c.gemini_api_key_set(Ok(key.clone())));
+
+        Ok(key)
+    }
+
+    pub fn gemini_write_api_key(api_key: &str) -> Result<()> {
+        Self::write_credential(Self::GEMINI_API_KEY_TARGET, api_key)?;
+        with_cache(|c| c.gemini_invalidate());
+        Ok(())
+    }
+
+    pub fn gemini_delete_api_key() -> Result<()> {
+        Self::delete_credential(Self::GEMINI_API_KEY_TARGET)?;
+        with_cache(|c| c.gemini_invalidate());
+        Ok(())
+    }
+
+    pub fn gemini_has_api_key() -> bool {
+        if let Some(cached) = with_cache(|c| c.gemini_api_key_get()) {
+            debug_cred!("Returning cached Gemini API key for has_api_key check");
+            return cached.is_ok();
+        }
+
+        match Self::gemini_read_api_key() {
+            Ok(_) => true,
+            Err(e) => {
+                with_cache(|c| c.gemini_api_key_set(Err(UsageError::from(&e))));
+                false
+            }
+        }
+    }
+
+    /// The stored OAuth credential, else gcloud's Application Default Credentials.
+    pub fn gemini_read_oauth_credentials() -> Result<GoogleAuthorizedUser> {
+        if let Some(cached) = with_cache(|c| c.gemini_oauth_get()) {
+            debug_cred!("Returning cached Gemini OAuth credentials");
+            return cached.map_err(anyhow::Error::from);
+        }
+
+        let json = match Self::read_credential(Self::GEMINI_OAUTH_TARGET) {
+            Ok(blob) => {
+                Self::decode_blob(&blob)
+                    .ok_or(ErrorCode::CredentialsUnreadable)?
+                    .0
+            }
+            Err(e) => {
+                let path = Self::gcloud_adc_path().ok_or(e)?;
+                debug_cred!("Reading Application Default Credentials from {path:?}");
+                fs::read_to_string(&path)
+                    .map_err(|_| anyhow::Error::from(ErrorCode::CredentialsNotFound))?
+            }
+        };
+        let credentials = Self::gemini_parse_oauth_credentials(&json)?;
+
+        with_cache(|c| c.gemini_oauth_set(Ok(credentials.clone())));
+
+        Ok(credentials)
+    }
+
+    /// Only `authorized_user` credentials carry a refresh token; service account keys don't.
+    fn gemini_parse_oauth_credentials(json: &str) -> Result<GoogleAuthorizedUser> {
+        let credentials: GoogleAuthorizedUser = serde_json::from_str(json.trim())
+            .map_err(|_| anyhow::Error::from(ErrorCode::OauthCredentialInvalid))?;
+        if credentials.refresh_token.trim().is_empty() {
+            return Err(ErrorCode::OauthCredentialInvalid.into());
+        }
+        Ok(credentials)
+    }
+
+    /// `GOOGLE_APPLICATION_CREDENTIALS`, else `application_default_credentials.json` in the
+    /// gcloud config directory (`CLOUDSDK_CONFIG`, `%APPDATA%\gcloud` or `~/.config/gcloud`).
+    fn gcloud_adc_path() -> Option<PathBuf> {
+        let non_empty = |var: &str| std::env::var_os(var).filter(|value| !value.is_empty());
+        if let Some(path) = non_empty("GOOGLE_APPLICATION_CREDENTIALS") {
+            return Some(PathBuf::from(path));
+        }
+        let config_dir = match non_empty("CLOUDSDK_CONFIG") {
+            Some(dir) => PathBuf::from(dir),
+            None if cfg!(target_os = "windows") => {
+                PathBuf::from(non_empty("APPDATA")?).join("gcloud")
+            }
+            None => Self::home_dir().ok()?.join(".config").join("gcloud"),
+        };
+        Some(config_dir.join("application_default_credentials.json"))
+    }
+
+    /// Checks `json` parses as an `authorized_user` credential before storing it.
+    pub fn gemini_write_oauth_credentials(json: &str) -> Result<()> {
+        let credentials = Self::gemini_parse_oauth_credentials(json)?;
+        Self::write_credential(
+            Self::GEMINI_OAUTH_TARGET,
+            &serde_json::to_string(&credentials)?,
+        )?;
+        with_cache(|c| c.gemini_invalidate());
+        Ok(())
+    }
+
+    pub fn gemini_delete_oauth_credentials() -> Result<()> {
+        Self::delete_credential(Self::GEMINI_OAUTH_TARGET)?;
+        with_cache(|c| c.gemini_invalidate());
+        Ok(())
+    }
+
+    pub fn gemini_has_oauth_credentials() -> bool {
+        if let Some(cached) = with_cache(|c| c.gemini_oauth_get()) {
+            debug_cred!("Returning cached Gemini OAuth credentials for has_credentials check");
+            return cached.is_ok();
+        }
+
+        match Self::gemini_read_oauth_credentials() {
+            Ok(_) => true,
+            Err(e) => {
+                with_cache(|c| c.gemini_oauth_set(Err(UsageError::from(&e))));
+                false
+            }
+        }
+    }
+
+    pub fn zai_has_api_key() -> bool {
+        // Check cache first to avoid double reading
+        // Cache stores the resolved API key result
+        if let Some(cached) = with_cache(|c| c.zai_get()) {
+            debug_cred!("Returning cached Z.ai API key for has_api_key check");
+            return cached.is_ok();
+        }
+
+        // Cache miss - read and validate credential (this will cache the result)
+        Self::zai_api_key_source().is_some()
+    }
+
+    pub fn anthropic_read_admin_key() -> Result<String> {
+        if let Some(cached) = with_cache(|c| c.anthropic_get()) {
+            debug_cred!("Returning cached Anthropic Admin API key");
+            return cached.map_err(anyhow::Error::from);
+        }
+
+        let blob = Self::read_credential(Self::ANTHROPIC_TARGET)?;
+
+        let (key_str, _) = Self::decode_blob(&blob).ok_or(ErrorCode::CredentialsUnreadable)?;
+
+        // Same `{env:varname}` syntax as the Z.ai key
+        let key = Self::resolve_env_reference(&key_str)?;
+
+        with_cache(|c| c.anthropic_set(Ok(key.clone())));
+
+        Ok(key)
+    }
+
+    pub fn anthropic_write_admin_key(api_key: &str) -> Result<()> {
+        Self::write_credential(Self::ANTHROPIC_TARGET, api_key)?;
+        with_cache(|c| c.anthropic_invalidate());
+        Ok(())
+    }
+
+    pub fn anthropic_delete_admin_key() -> Result<()> {
+        Self::delete_credential(Self::ANTHROPIC_TARGET)?;
+        with_cache(|c| c.anthropic_invalidate());
+        Ok(())
+    }
+
+    pub fn anthropic_has_admin_key() -> bool {
+        if let Some(cached) = with_cache(|c| c.anthropic_get()) {
+            debug_cred!("Returning cached Anthropic Admin API key for has_admin_key check");
+            return cached.is_ok();
+        }
+
+        match Self::anthropic_read_admin_key() {
+            Ok(_) => true,
+            Err(e) => {
+                with_cache(|c| c.anthropic_set(Err(UsageError::from(&e))));
+                false
+            }
+        }
+    }
+
+    /// Decodes a credential blob. Ours are UTF-8; blobs written by `cmdkey` or PowerShell's
+    /// `CredentialManager` module are UTF-16LE. Returns the text and whether it was UTF-16.
+    fn decode_blob(blob: &[u8]) -> Option<(String, bool)> {
+        if blob.len() >= 2 && blob.len().is_multiple_of(2) && blob.contains(&0) {
+            let wide: Vec<u16> = blob
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            return String::from_utf16(&wide)
+                .ok()
+                .map(|text| (text.trim_end_matches('\0').to_string(), true));
+        }
+        String::from_utf8(blob.to_vec())
+            .ok()
+            .map(|text| (text, false))
+    }
+
+    /// Moves credentials stored under a legacy target name to the current one, and rewrites
+    /// UTF-16 blobs as UTF-8, so an upgrade never looks like a lost key. A current credential
+    /// always wins over a legacy one; legacy entries are deleted only after a successful copy.
+    pub fn migrate_legacy_credentials() -> Vec<CredentialMigration> {
+        let mut migrations = Vec::new();
+
+        for (provider, target) in [
+            (Provider::Zai, Self::ZAI_TARGET),
+            (Provider::Amp, Self::AMP_TARGET),
+            (Provider::Anthropic, Self::ANTHROPIC_TARGET),
+            (Provider::Gemini, Self::GEMINI_API_KEY_TARGET),
+        ] {
+            let migration = |from: &str, reencoded, error| CredentialMigration {
+                provider,
+                from: from.to_string(),
+                to: target.to_string(),
+                reencoded,
+                error,
+            };
+
+            if let Ok(blob) = Self::read_credential(target) {
+                if let Some((text, true)) = Self::decode_blob(&blob) {
+                    let error = Self::write_credential(target, &text)
+                        .err()
+                        .map(|e| e.to_string());
+                    migrations.push(migration(target, true, error));
+                }
+                continue;
+            }
+
+            let legacy = Self::LEGACY_TARGETS
+                .iter()
+                .filter(|(p, _)| *p == provider)
+                .find_map(|(_, name)| Self::read_credential(name).ok().map(|blob| (*name, blob)));
+            let Some((from, blob)) = legacy else {
+                continue;
+            };
+            let Some((text, reencoded)) = Self::decode_blob(&blob) else {
+                migrations.push(migration(
+                    from,
+                    false,
+                    Some("Stored value is not valid UTF-8 or UTF-16".to_string()),
+                ));
+                continue;
+            };
+            match Self::write_credential(target, &text) {
+                Ok(()) => {
+                    if Self::delete_credential(from).is_err() {
+                        debug_cred!("Migrated {from} but could not delete it");
+                    }
+                    migrations.push(migration(from, reencoded, None));
+                }
+                Err(e) => migrations.push(migration(from, reencoded, Some(e.to_string()))),
+            }
+        }
+
+        if !migrations.is_empty() {
+            with_cache(|c| {
+                c.zai_invalidate();
+                c.amp_invalidate();
+                c.amp_api_key_invalidate();
+                c.anthropic_invalidate();
+                c.gemini_invalidate();
+            });
+        }
+        for m in &migrations {
+            if let Some(e) = &m.error {
+                debug_cred!(
+                    "Failed to migrate {:?} credential {}: {e}",
+                    m.provider,
+                    m.from
+                );
+            } else {
+                debug_cred!(
+                    "Migrated {:?} credential {} -> {}",
+                    m.provider,
+                    m.from,
+                    m.to
+                );
+            }
+        }
+        migrations
+    }
+
+    /// Routes every secret store read, write and delete through `store` from now on.
+    pub fn set_secret_store(store: Arc<dyn SecretStore>) {
+        *SECRET_STORE
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(store);
+        with_cache(|c| {
+            c.zai_invalidate();
+            c.amp_invalidate();
+            c.amp_api_key_invalidate();
+            c.anthropic_invalidate();
+            c.gemini_invalidate();
+        });
+    }
+
+    fn secret_store() -> Arc<dyn SecretStore> {
+        SECRET_STORE
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+            .unwrap_or_else(|| Arc::new(OsStore))
+    }
+
+    /// Swaps the OS secret store for an empty in-memory vault and resets cached
+    /// credentials and Claude source settings, so each E2E scenario starts clean.
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn install_test_vault() {
+        #[cfg(target_os = "macos")]
+        CLAUDE_KEYCHAIN_ENABLED.store(false, Ordering::Relaxed);
+        Self::set_secret_store(Arc::new(crate::vault::MemoryStore::default()));
+        *CACHE
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+        Self::claude_set_source_priority(vec![ClaudeCredentialSource::ConfigDir]);
+        Self::claude_set_organization(None);
+    }
+
+    fn read_credential(target_name: &str) -> Result<Vec<u8>> {
+        Self::secret_store().read(target_name)
+    }
+
+    fn write_credential(target_name: &str, data: &str) -> Result<()> {
+        Self::secret_store().write(target_name, data)
+    }
+
+    fn delete_credential(target_name: &str) -> Result<()> {
+        Self::secret_store().delete(target_name)
+    }
+}
+
+/// Async facade for commands and services. `CredReadW` and the credentials file can block
+/// for a long time (network-backed credential stores, slow WSL mounts), so these run the
+/// calls above on the blocking pool instead of stalling a tokio worker.
+impl CredentialManager {
+    async fn blocking<R, F>(f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce() -> Result<R> + Send + 'static,
+    {
+        tokio::task::spawn_blocking(f)
+            .await
+            .map_err(|e| anyhow!("Credential task failed: {e}"))?
+    }
+
+    pub async fn claude_read_credentials_async() -> Result<ClaudeOAuthCredentials> {
+        Self::blocking(Self::claude_read_credentials).await
+    }
+
+    pub async fn claude_update_token_async(
+        access_token: String,
+        refresh_token: String,
+        expires_at: i64,
+    ) -> Result<()> {
+        Self::blocking(move || Self::claude_update_token(&access_token, &refresh_token, expires_at))
+            .await
+    }
+
+    pub async fn claude_accounts_async() -> Vec<(ClaudeAccount, Result<ClaudeOAuthCredentials>)> {
+        Self::blocking(|| Ok(Self::claude_accounts()))
+            .await
+            .unwrap_or_default()
+    }
+
+    pub async fn claude_credential_sources_async() -> Vec<CredentialSourceStatus> {
+        Self::blocking(|| Ok(Self::claude_credential_sources()))
+            .await
+            .unwrap_or_default()
+    }
+
+    pub async fn claude_organizations_async() -> Vec<ClaudeOrganization> {
+        Self::blocking(|| Ok(Self::claude_organizations()))
+            .await
+            .unwrap_or_default()
+    }
+
+    pub async fn claude_active_organization_name_async() -> Option<String> {
+        Self::blocking(|| Ok(Self::claude_active_organization_name()))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    pub async fn zai_read_api_key_async() -> Result<String> {
+        Self::blocking(Self::zai_read_api_key).await
+    }
+
+    pub async fn zai_write_api_key_async(api_key: String) -> Result<()> {
+        Self::blocking(move || Self::zai_write_api_key(&api_key)).await
+    }
+
+    pub async fn zai_delete_api_key_async() -> Result<()> {
+        Self::blocking(Self::zai_delete_api_key).await
+    }
+
+    pub async fn zai_has_api_key_async() -> bool {
+        Self::blocking(|| Ok(Self::zai_has_api_key()))
+            .await
+            .unwrap_or(false)
+    }
+
+    pub async fn zai_api_key_source_async() -> Option<ZaiKeySource> {
+        Self::blocking(|| Ok(Self::zai_api_key_source()))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    pub async fn zai_labels_async() -> Vec<String> {
+        Self::blocking(|| Ok(Self::zai_labels()))
+            .await
+            .unwrap_or_default()
+    }
+
+    pub async fn zai_read_named_api_key_async(label: String) -> Result<String> {
+        Self::blocking(move || Self::zai_read_named_api_key(&label)).await
+    }
+
+    pub async fn zai_write_named_api_key_async(label: String, api_key: String) -> Result<()> {
+        Self::blocking(move || Self::zai_write_named_api_key(&label, &api_key)).await
+    }
+
+    pub async fn zai_delete_named_api_key_async(label: String) -> Result<()> {
+        Self::blocking(move || Self::zai_delete_named_api_key(&label)).await
+    }
+
+    pub async fn amp_read_session_cookie_async() -> Result<String> {
+        Self::blocking(Self::amp_read_session_cookie).await
+    }
+
+    pub async fn amp_write_session_cookie_async(cookie: String) -> Result<()> {
+        Self::blocking(move || Self::amp_write_session_cookie(&cookie)).await
+    }
+
+    pub async fn amp_delete_session_cookie_async() -> Result<()> {
+        Self::blocking(Self::amp_delete_session_cookie).await
+    }
+
+    pub async fn amp_has_session_cookie_async() -> bool {
+        Self::blocking(|| Ok(Self::amp_has_session_cookie()))
+            .await
+            .unwrap_or(false)
+    }
+
+    pub async fn amp_session_saved_at_async() -> Option<i64> {
+        Self::blocking(|| Ok(Self::amp_session_saved_at()))
+            .await
+            .ok()
+            .flatten()
+    }
+
+    pub async fn amp_read_api_key_async() -> Result<String> {
+        Self::blocking(Self::amp_read_api_key).await
+    }
+
+    pub async fn amp_write_api_key_async(api_key: String) -> Result<()> {
+        Self::blocking(move || Self::amp_write_api_key(&api_key)).await
+    }
+
+    pub async fn amp_delete_api_key_async() -> Result<()> {
+        Self::blocking(Self::amp_delete_api_key).await
+    }
+
+    pub async fn amp_has_api_key_async() -> bool {
+        Self::blocking(|| Ok(Self::amp_has_api_key()))
+            .await
+            .unwrap_or(false)
+    }
+
+    pub async fn anthropic_read_admin_key_async() -> Result<String> {
+        Self::blocking(Self::anthropic_read_admin_key).await
+    }
+
+    pub async fn anthropic_write_admin_key_async(api_key: String) -> Result<()> {
+        Self::blocking(move || Self::anthropic_write_admin_key(&api_key)).await
+    }
+
+    pub async fn anthropic_delete_admin_key_async() -> Result<()> {
+        Self::blocking(Self::anthropic_delete_admin_key).await
+    }
+
+    pub async fn anthropic_has_admin_key_async() -> bool {
+        Self::blocking(|| Ok(Self::anthropic_has_admin_key()))
+            .await
+            .unwrap_or(false)
+    }
+
+    pub async fn gemini_read_api_key_async() -> Result<String> {
+        Self::blocking(Self::gemini_read_api_key).await
+    }
+
+    pub async fn gemini_write_api_key_async(api_key: String) -> Result<()> {
+        Self::blocking(move || Self::gemini_write_api_key(&api_key)).await
+    }
+
+    pub async fn gemini_delete_api_key_async() -> Result<()> {
+        Self::blocking(Self::gemini_delete_api_key).await
+    }
+
+    pub async fn gemini_has_api_key_async() -> bool {
+        Self::blocking(|| Ok(Self::gemini_has_api_key()))
+            .await
+            .unwrap_or(false)
+    }
+
+    pub async fn gemini_read_oauth_credentials_async() -> Result<GoogleAuthorizedUser> {
+        Self::blocking(Self::gemini_read_oauth_credentials).await
+    }
+
+    pub async fn gemini_write_oauth_credentials_async(json: String) -> Result<()> {
+        Self::blocking(move || Self::gemini_write_oauth_credentials(&json)).await
+    }
+
+    pub async fn gemini_delete_oauth_credentials_async() -> Result<()> {
+        Self::blocking(Self::gemini_delete_oauth_credentials).await
+    }
+
+    pub async fn gemini_has_oauth_credentials_async() -> bool {
+        Self::blocking(|| Ok(Self::gemini_has_oauth_credentials()))
+            .await
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claude_home_dirs() {
+        let home = Path::new("/home/jane");
+        let dirs = CredentialManager::claude_home_dirs(home, None);
+        assert_eq!(dirs[0], home.join(".claude"));
+        if cfg!(target_os = "windows") {
+            assert_eq!(dirs.len(), 1);
+        } else {
+            assert_eq!(dirs[1], home.join(".config").join("claude"));
+            let xdg = CredentialManager::claude_home_dirs(home, Some("/xdg".into()));
+            assert_eq!(xdg[1], Path::new("/xdg/claude"));
+            // Relative values are invalid per the XDG spec and ignored.
+            let relative = CredentialManager::claude_home_dirs(home, Some("cfg".into()));
+            assert_eq!(relative[1], home.join(".config").join("claude"));
+        }
+    }
+
+    #[test]
+    fn test_claude_find_in_wsl_root() {
+        let root = std::env::temp_dir().join(format!("usage-bar-wsl-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (distro, user) in [("Ubuntu", "jane"), ("Debian", "joe")] {
+            let dir = root.join(distro).join("home").join(user).join(".claude");
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(".credentials.json"), "{}").unwrap();
+        }
+
+        let found = |distros: &[&str]| {
+            let distros: Vec<String> = distros.iter().map(|d| d.to_string()).collect();
+            CredentialManager::claude_find_in_wsl_root(&root, &distros)
+        };
+        assert!(found(&[]).is_some());
+        assert!(found(&["Debian", "Ubuntu"])
+            .unwrap()
+            .starts_with(root.join("Debian")));
+        assert!(found(&["Arch"]).is_none());
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_claude_credentials_from_token() {
+        let credentials =
+            CredentialManager::claude_credentials_from_token(" sk-ant-oat01-x\n").expect("token");
+        assert_eq!(credentials.claude_ai_oauth.access_token, "sk-ant-oat01-x");
+        assert!(credentials.claude_ai_oauth.refresh_token.is_empty());
+        assert!(CredentialManager::claude_credentials_from_token("  ").is_none());
+    }
+
+    #[test]
+    fn test_zai_key_from_claude_settings() {
+        let settings = |base_url: &str| {
+            format!(
+                r#"{{"env": {{"ANTHROPIC_BASE_URL": "{base_url}", "ANTHROPIC_AUTH_TOKEN": " k.1 "}}}}"#
+            )
+        };
+        assert_eq!(
+            zai_key_from_claude_settings(&settings("https://api.z.ai/api/anthropic")).as_deref(),
+            Some("k.1")
+        );
+        assert_eq!(
+            zai_key_from_claude_settings(&settings("https://api.anthropic.com")),
+            None
+        );
+        assert_eq!(zai_key_from_claude_settings(r#"{"model": "opus"}"#), None);
+    }
+
+    #[test]
+    fn test_zai_key_from_opencode_auth() {
+        assert_eq!(
+            zai_key_from_opencode_auth(
+                r#"{"anthropic": {"type": "oauth"}, "zai-coding-plan": {"type": "api", "key": "k.2"}}"#
+            )
+            .as_deref(),
+            Some("k.2")
+        );
+        assert_eq!(
+            zai_key_from_opencode_auth(r#"{"zai": {"type": "api"}}"#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_amp_key_from_cli_secrets() {
+        assert_eq!(
+            amp_key_from_cli_secrets(
+                r#"{"apiKey@https://example.com/": "other", "apiKey@https://ampcode.com/": " sgamp_1 "}"#
+            )
+            .as_deref(),
+            Some("sgamp_1")
+        );
+        assert_eq!(
+            amp_key_from_cli_secrets(r#"{"apiKey@https://example.com/": "other"}"#).as_deref(),
+            Some("other")
+        );
+        assert_eq!(amp_key_from_cli_secrets(r#"{"token": "x"}"#), None);
+    }
+
+    #[test]
+    fn test_zai_check_label() {
+        assert_eq!(
+            CredentialManager::zai_check_label(" team-1 ").unwrap(),
+            "team-1"
+        );
+        assert!(CredentialManager::zai_check_label("").is_err());
+        assert!(CredentialManager::zai_check_label("a:b").is_err());
+        assert!(CredentialManager::zai_check_label(&"x".repeat(33)).is_err());
+    }
+
+    #[test]
+    fn test_decode_blob_utf8() {
+        assert_eq!(
+            CredentialManager::decode_blob(b"{env:ZAI_KEY}"),
+            Some(("{env:ZAI_KEY}".to_string(), false))
+        );
+    }
+
+    #[test]
+    fn test_merge_credentials_keeps_newer_token_on_disk() {
+        let ours: ClaudeOAuthCredentials = serde_json::from_str(
+            r#"{"claudeAiOauth": {"accessToken": "new", "refreshToken": "r", "expiresAt": 2000}}"#,
+        )
+        .unwrap();
+
+        let older = r#"{"claudeAiOauth": {"accessToken": "old", "expiresAt": 1000}, "mcp": {}}"#;
+        let merged = CredentialManager::claude_merge_credentials(Some(older), &ours)
+            .unwrap()
+            .unwrap();
+        let merged: serde_json::Value = serde_json::from_str(&merged).unwrap();
+        assert_eq!(merged["claudeAiOauth"]["accessToken"], "new");
+        assert!(merged.get("mcp").is_some());
+
+        let newer = r#"{"claudeAiOauth": {"accessToken": "theirs", "expiresAt": 3000}}"#;
+        assert!(
+            CredentialManager::claude_merge_credentials(Some(newer), &ours)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_decode_blob_utf16() {
+        let blob: Vec<u8> = "abc\0"
+            .encode_utf16()
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect();
+        assert_eq!(
+            CredentialManager::decode_blob(&blob),
+            Some(("abc".to_string(), true))
+        );
+    }
+}
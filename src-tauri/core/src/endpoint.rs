@@ -0,0 +1,114 @@
+use std::borrow::Cow;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::models::Provider;
+
+/// Request timeout for a provider with none configured; also the shared clients' own.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Per-provider request timeouts from settings, applied to each request so a change takes
+/// effect without rebuilding the clients.
+static TIMEOUTS: Mutex<Vec<(Provider, Duration)>> = Mutex::new(Vec::new());
+
+/// Base URLs from settings that stand in for an origin of one provider's requests, e.g. an
+/// LLM gateway: (provider, origin, base).
+static BASE_URLS: Mutex<Vec<(Provider, &'static str, String)>> = Mutex::new(Vec::new());
+
+/// Base URL of the E2E mock server; see the app's `e2e.rs`.
+#[cfg(any(test, feature = "test-support"))]
+static MOCK_BASE: std::sync::Mutex<Option<String>> = std::sync::Mutex::new(None);
+
+/// Points every provider request at `base` (e.g. `http://127.0.0.1:4100`), or back at the
+/// real APIs with `None`.
+#[cfg(any(test, feature = "test-support"))]
+pub fn set_mock_base(base: Option<String>) {
+    *MOCK_BASE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = base;
+}
+
+/// The URL a provider request should go to. Outside tests this is `url` itself; under the
+/// E2E harness the scheme and host are swapped for the mock server's, keeping the path.
+pub fn resolve(url: &'static str) -> Cow<'static, str> {
+    #[cfg(any(test, feature = "test-support"))]
+    {
+        let base = MOCK_BASE
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        if let (Some(base), Ok(parsed)) = (base, reqwest::Url::parse(url)) {
+            return Cow::Owned(format!("{base}{}", parsed.path()));
+        }
+    }
+    Cow::Borrowed(url)
+}
+
+/// Sends `provider`'s requests for `origin` (e.g. `https://api.anthropic.com`) to `base`
+/// instead, with the request path appended; `None` sends them to `origin` again.
+pub fn set_base_url(provider: Provider, origin: &'static str, base: Option<String>) {
+    let mut bases = BASE_URLS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    bases.retain(|(p, o, _)| !(*p == provider && *o == origin));
+    if let Some(base) = base {
+        bases.push((provider, origin, base.trim_end_matches('/').to_string()));
+    }
+}
+
+/// `resolve` for a provider whose base URL settings can override: a configured base
+/// replaces the origin of `url`.
+pub fn resolve_for(provider: Provider, url: &'static str) -> Cow<'static, str> {
+    let bases = BASE_URLS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let replaced = bases.iter().find_map(|(p, origin, base)| {
+        let path = url.strip_prefix(origin).filter(|_| *p == provider)?;
+        Some(format!("{base}{path}"))
+    });
+    match replaced {
+        Some(url) => Cow::Owned(url),
+        None => resolve(url),
+    }
+}
+
+pub fn set_timeout(provider: Provider, timeout: Duration) {
+    let mut timeouts = TIMEOUTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    timeouts.retain(|(p, _)| *p != provider);
+    timeouts.push((provider, timeout));
+}
+
+/// How long a request to `provider` may take before it fails with a timeout.
+pub fn timeout(provider: Provider) -> Duration {
+    TIMEOUTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .iter()
+        .find(|(p, _)| *p == provider)
+        .map_or(DEFAULT_TIMEOUT, |(_, timeout)| *timeout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_for_base_url() {
+        const URL: &str = "https://gateway-test.invalid/v1/usage";
+        set_base_url(
+            Provider::Amp,
+            "https://gateway-test.invalid",
+            Some("https://llm.example.com/proxy/".to_string()),
+        );
+        assert_eq!(
+            resolve_for(Provider::Amp, URL),
+            "https://llm.example.com/proxy/v1/usage"
+        );
+        assert_eq!(resolve_for(Provider::Zai, URL), URL);
+
+        set_base_url(Provider::Amp, "https://gateway-test.invalid", None);
+        assert_eq!(resolve_for(Provider::Amp, URL), URL);
+    }
+}
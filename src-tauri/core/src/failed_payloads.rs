@@ -0,0 +1,180 @@
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::models::Provider;
+use crate::redact;
+use crate::{debug_app, debug_error};
+
+/// Larger payloads are cut here; the start of a response is enough to see a format change.
+const MAX_PAYLOAD_BYTES: usize = 64 * 1024;
+/// Oldest captures are deleted past this many.
+const MAX_FILES: usize = 20;
+
+/// Set by the app at startup. Until then (and in tests) nothing is written.
+static DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+fn dir_guard() -> MutexGuard<'static, Option<PathBuf>> {
+    DIR.lock().unwrap_or_else(|poisoned| {
+        debug_error!("Failed payloads mutex poisoned, recovering...");
+        poisoned.into_inner()
+    })
+}
+
+pub fn set_dir(dir: Option<PathBuf>) {
+    *dir_guard() = dir;
+}
+
+pub fn dir() -> Option<PathBuf> {
+    dir_guard().clone()
+}
+
+/// One file in the failed payloads folder, attached to bug reports about provider format
+/// changes.
+#[derive(Debug, Serialize, Deserialize)]
+struct FailedPayload {
+    provider: Provider,
+    captured_at: String,
+    error: String,
+    truncated: bool,
+    payload: String,
+}
+
+/// A capture as listed by `list_failed_payloads`, without the payload itself.
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedPayloadEntry {
+    pub file_name: String,
+    pub provider: Provider,
+    pub captured_at: String,
+    pub error: String,
+    pub truncated: bool,
+}
+
+/// Cuts `payload` to at most `max` bytes on a character boundary.
+fn truncate(payload: &str, max: usize) -> (&str, bool) {
+    if payload.len() <= max {
+        return (payload, false);
+    }
+    let mut end = max;
+    while !payload.is_char_boundary(end) {
+        end -= 1;
+    }
+    (&payload[..end], true)
+}
+
+fn sanitize(payload: &str, error: &str) -> (String, String, bool) {
+    let (payload, truncated) = truncate(payload, MAX_PAYLOAD_BYTES);
+    (
+        redact::scrub_payload(payload),
+        redact::scrub_payload(error),
+        truncated,
+    )
+}
+
+/// Capture files sorted oldest first; names start with a sortable timestamp.
+fn capture_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+fn write(dir: &Path, provider: Provider, payload: &str, error: &str) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let now = Utc::now();
+    let (payload, error, truncated) = sanitize(payload, error);
+    let record = FailedPayload {
+        provider,
+        captured_at: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+        error,
+        truncated,
+        payload,
+    };
+    let path = dir.join(format!(
+        "{}-{}.json",
+        now.format("%Y%m%dT%H%M%S%3f"),
+        provider.key()
+    ));
+    fs::write(&path, serde_json::to_string_pretty(&record)?)?;
+
+    let files = capture_files(dir);
+    for old in files.iter().take(files.len().saturating_sub(MAX_FILES)) {
+        if let Err(e) = fs::remove_file(old) {
+            debug_error!("Failed to prune {}: {e}", old.display());
+        }
+    }
+    Ok(path)
+}
+
+/// Saves a sanitized, size-capped copy of a response that failed to parse. Best effort: a
+/// failure here is logged and never masks the parse error being reported.
+pub fn capture(provider: Provider, payload: &str, error: &dyn std::fmt::Display) {
+    let Some(dir) = dir() else {
+        return;
+    };
+    match write(&dir, provider, payload, &error.to_string()) {
+        Ok(path) => {
+            debug_app!(
+                "Saved unparseable {} payload to {}",
+                provider.key(),
+                path.display()
+            );
+        }
+        Err(e) => {
+            debug_error!("Failed to save unparseable {} payload: {e}", provider.key());
+        }
+    }
+}
+
+/// Captures newest first.
+pub fn list() -> Vec<FailedPayloadEntry> {
+    let Some(dir) = dir() else {
+        return Vec::new();
+    };
+    capture_files(&dir)
+        .into_iter()
+        .rev()
+        .filter_map(|path| {
+            let record: FailedPayload =
+                serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+            Some(FailedPayloadEntry {
+                file_name: path.file_name()?.to_string_lossy().into_owned(),
+                provider: record.provider,
+                captured_at: record.captured_at,
+                error: record.error,
+                truncated: record.truncated,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_caps_size_and_strips_secrets() {
+        let payload = format!(
+            r#"{{"email": "jane@example.com", "org": "3f0c1a2b-1234-4abc-9def-0123456789ab", "access_token": "abc123"}}{}"#,
+            "é".repeat(MAX_PAYLOAD_BYTES)
+        );
+        let (sanitized, error, truncated) = sanitize(&payload, "bad token=abc123");
+        assert!(truncated);
+        assert!(sanitized.len() < payload.len());
+        for secret in ["jane@example.com", "3f0c1a2b", "abc123"] {
+            assert!(!sanitized.contains(secret), "{secret} leaked");
+        }
+        assert!(!error.contains("abc123"));
+
+        assert_eq!(truncate("short", 10), ("short", false));
+    }
+}
@@ -0,0 +1,644 @@
+use crate::clock;
+use crate::credentials::CredentialManager;
+use crate::endpoint;
+use crate::failed_payloads;
+use crate::i18n::{ErrorCode, ParseError};
+use crate::models::{
+    GeminiQuotaWindow, GeminiUsageData, GoogleAuthorizedUser, GoogleKeyLookup, GoogleTokenResponse,
+    MonitoringTimeSeries, MonitoringTimeSeriesList, MonitoringValue, Provider,
+};
+use crate::rate_limit::{self, RateLimited};
+use crate::redact::Redacted;
+use crate::singleflight::SingleFlight;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, SecondsFormat, TimeZone, Utc, Weekday};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{debug_error, debug_net};
+
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const MODELS_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+const LOOKUP_KEY_URL: &str = "https://apikeys.googleapis.com/v2/keys:lookupKey";
+const MONITORING_URL: &str = "https://monitoring.googleapis.com/v3/projects";
+
+/// Usage of every quota of the Gemini API, counted per minute by Service Control.
+const USAGE_FILTER: &str = r#"metric.type="serviceruntime.googleapis.com/quota/rate/net_usage" AND resource.type="consumer_quota" AND resource.label.service="generativelanguage.googleapis.com""#;
+/// The limits of those quotas, as configured for the project.
+const LIMIT_FILTER: &str = r#"metric.type="serviceruntime.googleapis.com/quota/limit" AND resource.type="consumer_quota" AND resource.label.service="generativelanguage.googleapis.com""#;
+
+/// Limits are only written when they change and about once a day, so look back far enough
+/// to always find one.
+const LIMIT_LOOKBACK_SECS: i64 = 2 * 24 * 60 * 60;
+/// Access tokens are refreshed this long before Google says they expire.
+const TOKEN_EXPIRY_MARGIN_MS: i64 = 60_000;
+
+struct AccessToken {
+    /// The refresh token it was issued for, so new credentials get a new token.
+    refresh_token: String,
+    token: String,
+    expires_at: i64,
+}
+
+static TOKEN: Mutex<Option<AccessToken>> = Mutex::new(None);
+
+/// Project from the `gemini_project_id` setting; `None` finds it from the credentials.
+static PROJECT: Mutex<Option<String>> = Mutex::new(None);
+
+/// Project the stored API key belongs to, keyed by the key, so the lookup runs once.
+static KEY_PROJECT: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+pub struct GeminiService;
+
+impl GeminiService {
+    pub fn gemini_set_project(project: Option<String>) {
+        *PROJECT
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = project;
+    }
+
+    /// Forgets the cached access token and key project, after credentials are saved or
+    /// deleted.
+    pub fn gemini_reset_session() {
+        *TOKEN
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+        *KEY_PROJECT
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    }
+
+    /// Concurrent calls share one set of Cloud Monitoring queries.
+    pub async fn gemini_fetch_usage(client: Arc<reqwest::Client>) -> Result<GeminiUsageData> {
+        static FLIGHT: SingleFlight<GeminiUsageData> = SingleFlight::new();
+        FLIGHT.run(|| Self::fetch_usage(client)).await
+    }
+
+    async fn fetch_usage(client: Arc<reqwest::Client>) -> Result<GeminiUsageData> {
+        let credentials = CredentialManager::gemini_read_oauth_credentials_async().await?;
+        let access_token = Self::access_token(&client, &credentials).await?;
+        let project_id = Self::project_id(&client, &access_token, &credentials).await?;
+
+        let now_ms = clock::now_millis();
+        let now = DateTime::<Utc>::from_timestamp_millis(now_ms).ok_or_else(clock_out_of_range)?;
+        let day_start = pacific_day_start(now).ok_or_else(clock_out_of_range)?;
+        let resets_at = next_pacific_day_start(day_start).ok_or_else(clock_out_of_range)?;
+
+        let requests_today = Self::query(
+            &client,
+            &access_token,
+            &project_id,
+            USAGE_FILTER,
+            "ALIGN_SUM",
+            day_start,
+            now,
+        )
+        .await?;
+        let requests_last_minute = Self::query(
+            &client,
+            &access_token,
+            &project_id,
+            USAGE_FILTER,
+            "ALIGN_SUM",
+            now - Duration::minutes(1),
+            now,
+        )
+        .await?;
+        let limits = Self::query(
+            &client,
+            &access_token,
+            &project_id,
+            LIMIT_FILTER,
+            "ALIGN_MAX",
+            now - Duration::seconds(LIMIT_LOOKBACK_SECS),
+            now,
+        )
+        .await?;
+
+        Ok(GeminiUsageData {
+            project_id,
+            per_day: busiest_window(&requests_today, &limits, "PerDay")?,
+            per_minute: busiest_window(&requests_last_minute, &limits, "PerMinute")?,
+            period_start: day_start.timestamp_millis(),
+            resets_at: resets_at.timestamp_millis(),
+        })
+    }
+
+    /// A cached access token for `credentials`, else a new one from the refresh token.
+    async fn access_token(
+        client: &reqwest::Client,
+        credentials: &GoogleAuthorizedUser,
+    ) -> Result<String> {
+        let now = clock::now_millis();
+        if let Some(cached) = TOKEN
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .as_ref()
+            .filter(|t| t.refresh_token == credentials.refresh_token && t.expires_at > now)
+        {
+            return Ok(cached.token.clone());
+        }
+
+        debug_net!("POST {TOKEN_URL}");
+        let response = client
+            .post(endpoint::resolve(TOKEN_URL).as_ref())
+            .timeout(endpoint::timeout(Provider::Gemini))
+            .form(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", credentials.client_id.as_str()),
+                ("client_secret", credentials.client_secret.as_str()),
+                ("refresh_token", credentials.refresh_token.as_str()),
+            ])
+            .send()
+            .await?;
+        let status = response.status();
+        debug_net!("Response status: {status}");
+        // An expired or revoked refresh token is `400 invalid_grant`
+        if status == StatusCode::BAD_REQUEST || status == StatusCode::UNAUTHORIZED {
+            debug_error!("Google rejected the Gemini OAuth refresh token");
+            return Err(ErrorCode::AuthFailed.into());
+        }
+        let token: GoogleTokenResponse = Self::parse(Self::check_status(response)?).await?;
+        debug_net!("Got Google access token: {}", Redacted(&token.access_token));
+
+        let expires_in_ms = token.expires_in.unwrap_or(3600) * 1000;
+        *TOKEN
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(AccessToken {
+            refresh_token: credentials.refresh_token.clone(),
+            token: token.access_token.clone(),
+            expires_at: now + expires_in_ms - TOKEN_EXPIRY_MARGIN_MS,
+        });
+        Ok(token.access_token)
+    }
+
+    /// The `gemini_project_id` setting, else the project of the stored API key, else the
+    /// credentials' quota project.
+    async fn project_id(
+        client: &reqwest::Client,
+        access_token: &str,
+        credentials: &GoogleAuthorizedUser,
+    ) -> Result<String> {
+        let configured = PROJECT
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        if let Some(project) = configured {
+            return Ok(project);
+        }
+        if let Ok(api_key) = CredentialManager::gemini_read_api_key_async().await {
+            match Self::key_project(client, access_token, &api_key).await {
+                Ok(project) => return Ok(project),
+                Err(e) => {
+                    debug_error!("Could not look up the Gemini API key's project: {e}");
+                }
+            }
+        }
+        credentials
+            .quota_project_id
+            .clone()
+            .filter(|project| !project.trim().is_empty())
+            .ok_or_else(|| {
+                anyhow!("Set gemini_project_id or save the Gemini API key to know which project to read")
+            })
+    }
+
+    async fn key_project(
+        client: &reqwest::Client,
+        access_token: &str,
+        api_key: &str,
+    ) -> Result<String> {
+        let cached = KEY_PROJECT
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .as_ref()
+            .filter(|(key, _)| key == api_key)
+            .map(|(_, project)| project.clone());
+        if let Some(project) = cached {
+            return Ok(project);
+        }
+
+        // The key is a query parameter here, so the URL is never logged or kept in errors
+        debug_net!("GET {LOOKUP_KEY_URL}");
+        let response = client
+            .get(endpoint::resolve(LOOKUP_KEY_URL).as_ref())
+            .timeout(endpoint::timeout(Provider::Gemini))
+            .bearer_auth(access_token)
+            .query(&[("keyString", api_key)])
+            .send()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        let lookup: GoogleKeyLookup = Self::parse(
+            Self::check_status(response).map_err(|e| anyhow!("API key lookup failed: {e}"))?,
+        )
+        .await?;
+        let project = project_of_key_parent(&lookup.parent)
+            .ok_or_else(|| ParseError(format!("Unexpected API key parent {:?}", lookup.parent)))?;
+
+        *KEY_PROJECT
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) =
+            Some((api_key.to_string(), project.clone()));
+        Ok(project)
+    }
+
+    /// Every series matching `filter` in `[start, end]`, one per quota and model, each
+    /// aligned into a single point.
+    async fn query(
+        client: &reqwest::Client,
+        access_token: &str,
+        project_id: &str,
+        filter: &str,
+        aligner: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<MonitoringTimeSeriesList> {
+        let url = format!(
+            "{}/{project_id}/timeSeries",
+            endpoint::resolve(MONITORING_URL)
+        );
+        let alignment_period = format!("{}s", (end - start).num_seconds().max(60));
+        debug_net!("GET {url}");
+        let response = client
+            .get(&url)
+            .timeout(endpoint::timeout(Provider::Gemini))
+            .bearer_auth(access_token)
+            .query(&[
+                ("filter", filter),
+                (
+                    "interval.startTime",
+                    &start.to_rfc3339_opts(SecondsFormat::Secs, true),
+                ),
+                (
+                    "interval.endTime",
+                    &end.to_rfc3339_opts(SecondsFormat::Secs, true),
+                ),
+                ("aggregation.alignmentPeriod", &alignment_period),
+                ("aggregation.perSeriesAligner", aligner),
+            ])
+            .send()
+            .await?;
+        Self::parse(Self::check_status(response)?).await
+    }
+
+    async fn parse<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+        let text = response.text().await?;
+        serde_json::from_str(&text).map_err(|e| {
+            failed_payloads::capture(Provider::Gemini, &text, &e);
+            ParseError(format!("Failed to parse Google response: {e}")).into()
+        })
+    }
+
+    fn check_status(response: reqwest::Response) -> Result<reqwest::Response> {
+        let status = response.status();
+        debug_net!("Response status: {status}");
+        rate_limit::record_quota(Provider::Gemini, response.headers());
+
+        match status {
+            status if status.is_success() => Ok(response),
+            StatusCode::UNAUTHORIZED => {
+                debug_error!("Google rejected the Gemini access token");
+                Err(ErrorCode::AuthFailed.into())
+            }
+            // Also when the Cloud Monitoring API isn't enabled for the project
+            StatusCode::FORBIDDEN => {
+                debug_error!("Access denied to Gemini quota metrics");
+                Err(ErrorCode::AccessDenied.into())
+            }
+            StatusCode::TOO_MANY_REQUESTS => {
+                debug_error!("Google rate limit exceeded");
+                Err(RateLimited::from_headers(
+                    response.headers(),
+                    "Gemini: Rate limited — please wait",
+                )
+                .into())
+            }
+            status if status.is_server_error() => {
+                debug_error!("Google server error");
+                Err(ErrorCode::ServerError.into())
+            }
+            _ => {
+                debug_error!("Failed to fetch Gemini quota metrics");
+                Err(ErrorCode::FetchFailed.into())
+            }
+        }
+    }
+
+    pub async fn gemini_has_credentials() -> bool {
+        CredentialManager::gemini_has_oauth_credentials_async().await
+    }
+
+    /// Checks that the OAuth credentials can get an access token and read the project's
+    /// quota metrics.
+    pub async fn validate_credentials(client: Arc<reqwest::Client>) -> Result<()> {
+        Self::fetch_usage(client).await.map(|_| ())
+    }
+
+    /// Checks that `api_key` is accepted by the Gemini API.
+    pub async fn validate_api_key(client: Arc<reqwest::Client>, api_key: &str) -> Result<()> {
+        let api_key = api_key.trim();
+        if api_key.is_empty() {
+            return Err(ErrorCode::ApiKeyEmpty.into());
+        }
+
+        let api_key_lower = api_key.to_lowercase();
+        if api_key_lower.starts_with("{env:") || api_key_lower.starts_with("$env:") {
+            return Ok(());
+        }
+
+        debug_net!("GET {MODELS_URL} (validating key)");
+        let response = client
+            .get(endpoint::resolve(MODELS_URL).as_ref())
+            .timeout(endpoint::timeout(Provider::Gemini))
+            .header("x-goog-api-key", api_key)
+            .query(&[("pageSize", "1")])
+            .send()
+            .await?;
+        // An unknown key is `400 API_KEY_INVALID`, a restricted one `403`
+        match response.status() {
+            StatusCode::BAD_REQUEST | StatusCode::FORBIDDEN => {
+                debug_error!("Invalid Gemini API key");
+                Err(ErrorCode::InvalidApiKey.into())
+            }
+            _ => Self::check_status(response).map(|_| ()),
+        }
+    }
+}
+
+fn clock_out_of_range() -> anyhow::Error {
+    anyhow!("System clock out of range")
+}
+
+/// `projects/123/locations/global` → `123`; Cloud Monitoring takes the number as well as the ID.
+fn project_of_key_parent(parent: &str) -> Option<String> {
+    let mut parts = parent.split('/');
+    match (parts.next(), parts.next()) {
+        (Some("projects"), Some(project)) if !project.is_empty() => Some(project.to_string()),
+        _ => None,
+    }
+}
+
+/// Request quotas; token quotas (`generate_content_input_tokens`) are counted separately.
+fn is_request_quota(quota_metric: &str) -> bool {
+    quota_metric.to_ascii_lowercase().contains("request")
+}
+
+fn point_value(value: &MonitoringValue) -> Result<Option<i64>> {
+    if let Some(int) = &value.int64_value {
+        return int.parse::<i64>().map(Some).map_err(|e| {
+            ParseError(format!("Invalid Cloud Monitoring value {int:?}: {e}")).into()
+        });
+    }
+    Ok(value.double_value.map(|double| double.round() as i64))
+}
+
+/// A request quota of one model: `(quota_metric, model)`. Quotas without a `model`
+/// dimension have an empty model.
+type QuotaGroup = (String, String);
+
+fn quota_group(series: &MonitoringTimeSeries) -> Option<QuotaGroup> {
+    let labels = &series.metric.labels;
+    let quota_metric = labels
+        .get("quota_metric")
+        .filter(|metric| is_request_quota(metric))?;
+    let model = labels.get("model").cloned().unwrap_or_default();
+    Some((quota_metric.clone(), model))
+}
+
+/// Requests counted in `usage` per request quota and model.
+fn request_counts(usage: &MonitoringTimeSeriesList) -> Result<BTreeMap<QuotaGroup, u64>> {
+    let mut counts = BTreeMap::new();
+    for series in &usage.time_series {
+        let Some(group) = quota_group(series) else {
+            continue;
+        };
+        let count = counts.entry(group).or_insert(0u64);
+        for point in &series.points {
+            *count += point_value(&point.value)?.unwrap_or(0).max(0) as u64;
+        }
+    }
+    Ok(counts)
+}
+
+/// The smallest limit per request quota and model whose `limit_name` contains `window`
+/// (`PerDay`, `PerMinute`). Negative limits mean unlimited and are skipped.
+fn limits_for(
+    limits: &MonitoringTimeSeriesList,
+    window: &str,
+) -> Result<BTreeMap<QuotaGroup, u64>> {
+    let mut smallest = BTreeMap::new();
+    for series in &limits.time_series {
+        let matches = series
+            .metric
+            .labels
+            .get("limit_name")
+            .is_some_and(|name| name.contains(window));
+        let Some(group) = quota_group(series).filter(|_| matches) else {
+            continue;
+        };
+        let Some(point) = series.points.first() else {
+            continue;
+        };
+        if let Some(limit) = point_value(&point.value)?.filter(|limit| *limit >= 0) {
+            let limit = limit as u64;
+            smallest
+                .entry(group)
+                .and_modify(|s: &mut u64| *s = (*s).min(limit))
+                .or_insert(limit);
+        }
+    }
+    Ok(smallest)
+}
+
+/// The quota and model closest to its own `window` limit. When no limit is known, the one
+/// with the most requests, without a percentage.
+fn busiest_window(
+    usage: &MonitoringTimeSeriesList,
+    limits: &MonitoringTimeSeriesList,
+    window: &str,
+) -> Result<GeminiQuotaWindow> {
+    let counts = request_counts(usage)?;
+    let limits = limits_for(limits, window)?;
+
+    let limited = limits
+        .iter()
+        .filter(|(_, limit)| **limit > 0)
+        .map(|(group, &limit)| {
+            let requests = counts.get(group).copied().unwrap_or(0);
+            (
+                group,
+                requests,
+                requests as f64 / limit as f64 * 100.0,
+                limit,
+            )
+        })
+        .max_by(|a, b| a.2.total_cmp(&b.2));
+    if let Some(((_, model), requests, percent, limit)) = limited {
+        return Ok(GeminiQuotaWindow {
+            requests,
+            limit: Some(limit),
+            used_percent: Some(percent),
+            model: Some(model.clone()).filter(|m| !m.is_empty()),
+        });
+    }
+
+    let busiest = counts.iter().max_by_key(|(_, requests)| **requests);
+    Ok(GeminiQuotaWindow {
+        requests: busiest.map_or(0, |(_, requests)| *requests),
+        limit: None,
+        used_percent: None,
+        model: busiest
+            .map(|((_, model), _)| model.clone())
+            .filter(|m| !m.is_empty()),
+    })
+}
+
+/// The `n`th Sunday of `month`, for the US daylight saving rules.
+fn nth_sunday(year: i32, month: u32, n: u32) -> Option<NaiveDate> {
+    NaiveDate::from_weekday_of_month_opt(year, month, Weekday::Sun, n as u8)
+}
+
+/// Hours Pacific time is behind UTC at `at`: PDT from 2am on the second Sunday of March to
+/// 2am on the first Sunday of November, PST otherwise.
+fn pacific_offset_hours(at: DateTime<Utc>) -> Option<i64> {
+    let year = at.year();
+    let dst_start = Utc.from_utc_datetime(&nth_sunday(year, 3, 2)?.and_hms_opt(10, 0, 0)?);
+    let dst_end = Utc.from_utc_datetime(&nth_sunday(year, 11, 1)?.and_hms_opt(9, 0, 0)?);
+    Some(if at >= dst_start && at < dst_end {
+        7
+    } else {
+        8
+    })
+}
+
+/// The last midnight Pacific time, when Gemini API daily quotas reset.
+fn pacific_day_start(now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let local = now - Duration::hours(pacific_offset_hours(now)?);
+    let midnight = Utc.from_utc_datetime(&local.date_naive().and_hms_opt(0, 0, 0)?);
+    // Midnight may be across a daylight saving change from `now`
+    let approx = midnight + Duration::hours(pacific_offset_hours(now)?);
+    Some(midnight + Duration::hours(pacific_offset_hours(approx)?))
+}
+
+/// The midnight Pacific time after `day_start`; 23 or 25 hours later on daylight saving changes.
+fn next_pacific_day_start(day_start: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    pacific_day_start(day_start + Duration::hours(26))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).unwrap().into()
+    }
+
+    fn format(at: DateTime<Utc>) -> String {
+        at.to_rfc3339_opts(SecondsFormat::Secs, true)
+    }
+
+    #[test]
+    fn test_pacific_day_start() {
+        // Summer: PDT, midnight is 07:00 UTC
+        let start = pacific_day_start(utc("2025-07-15T06:59:00Z")).unwrap();
+        assert_eq!(format(start), "2025-07-14T07:00:00Z");
+        assert_eq!(
+            format(next_pacific_day_start(start).unwrap()),
+            "2025-07-15T07:00:00Z"
+        );
+        // Winter: PST, midnight is 08:00 UTC
+        assert_eq!(
+            format(pacific_day_start(utc("2025-01-10T08:00:00Z")).unwrap()),
+            "2025-01-10T08:00:00Z"
+        );
+        // 2025-03-09 starts in PST and ends in PDT, so it lasts 23 hours
+        let spring = pacific_day_start(utc("2025-03-09T12:00:00Z")).unwrap();
+        assert_eq!(format(spring), "2025-03-09T08:00:00Z");
+        assert_eq!(
+            format(next_pacific_day_start(spring).unwrap()),
+            "2025-03-10T07:00:00Z"
+        );
+        // 2025-11-02 lasts 25 hours
+        let autumn = pacific_day_start(utc("2025-11-02T20:00:00Z")).unwrap();
+        assert_eq!(format(autumn), "2025-11-02T07:00:00Z");
+        assert_eq!(
+            format(next_pacific_day_start(autumn).unwrap()),
+            "2025-11-03T08:00:00Z"
+        );
+    }
+
+    fn series(json: &str) -> MonitoringTimeSeriesList {
+        serde_json::from_str(json).unwrap()
+    }
+
+    const REQUESTS: &str = "generativelanguage.googleapis.com/generate_content_requests";
+
+    #[test]
+    fn test_busiest_window_per_model() {
+        let usage = series(&format!(
+            r#"{{"timeSeries": [
+                {{"metric": {{"labels": {{"quota_metric": "{REQUESTS}", "model": "gemini-2.5-flash"}}}},
+                 "points": [{{"value": {{"int64Value": "600"}}}}, {{"value": {{"int64Value": "300"}}}}]}},
+                {{"metric": {{"labels": {{"quota_metric": "{REQUESTS}", "model": "gemini-2.5-pro"}}}},
+                 "points": [{{"value": {{"int64Value": "40"}}}}]}},
+                {{"metric": {{"labels": {{"quota_metric": "generativelanguage.googleapis.com/generate_content_input_tokens",
+                                         "model": "gemini-2.5-pro"}}}},
+                 "points": [{{"value": {{"int64Value": "99999"}}}}]}}
+            ]}}"#
+        ));
+        let limits = series(&format!(
+            r#"{{"timeSeries": [
+                {{"metric": {{"labels": {{"quota_metric": "{REQUESTS}", "model": "gemini-2.5-flash",
+                                         "limit_name": "GenerateContentRequestsPerDayPerProjectPerModel"}}}},
+                 "points": [{{"value": {{"int64Value": "10000"}}}}]}},
+                {{"metric": {{"labels": {{"quota_metric": "{REQUESTS}", "model": "gemini-2.5-pro",
+                                         "limit_name": "GenerateContentRequestsPerDayPerProjectPerModel"}}}},
+                 "points": [{{"value": {{"int64Value": "100"}}}}]}},
+                {{"metric": {{"labels": {{"quota_metric": "{REQUESTS}", "model": "gemini-2.5-pro",
+                                         "limit_name": "GenerateRequestsPerDayPerProjectPerModel-FreeTier"}}}},
+                 "points": [{{"value": {{"int64Value": "-1"}}}}]}},
+                {{"metric": {{"labels": {{"quota_metric": "{REQUESTS}", "model": "gemini-2.5-flash",
+                                         "limit_name": "GenerateContentRequestsPerMinutePerProjectPerModel"}}}},
+                 "points": [{{"value": {{"int64Value": "1000"}}}}]}}
+            ]}}"#
+        ));
+
+        // Summed across models this would be 940 of 100 (940%); pro alone is 40 of 100
+        let day = busiest_window(&usage, &limits, "PerDay").unwrap();
+        assert_eq!(day.model.as_deref(), Some("gemini-2.5-pro"));
+        assert_eq!((day.requests, day.limit), (40, Some(100)));
+        assert_eq!(day.used_percent, Some(40.0));
+
+        let minute = busiest_window(&usage, &limits, "PerMinute").unwrap();
+        assert_eq!(minute.model.as_deref(), Some("gemini-2.5-flash"));
+        assert_eq!(minute.used_percent, Some(90.0));
+
+        // No limits reported: the busiest model, without a percentage
+        let unknown =
+            busiest_window(&usage, &MonitoringTimeSeriesList::default(), "PerDay").unwrap();
+        assert_eq!(unknown.model.as_deref(), Some("gemini-2.5-flash"));
+        assert_eq!((unknown.requests, unknown.used_percent), (900, None));
+
+        let empty = MonitoringTimeSeriesList::default();
+        let idle = busiest_window(&empty, &empty, "PerDay").unwrap();
+        assert_eq!((idle.requests, idle.limit, idle.model), (0, None, None));
+
+        let bad = series(
+            r#"{"timeSeries": [{"metric": {"labels": {"quota_metric": "requests"}},
+                                "points": [{"value": {"int64Value": "n/a"}}]}]}"#,
+        );
+        assert!(busiest_window(&bad, &empty, "PerDay").is_err());
+    }
+
+    #[test]
+    fn test_project_of_key_parent() {
+        assert_eq!(
+            project_of_key_parent("projects/123456/locations/global").as_deref(),
+            Some("123456")
+        );
+        assert_eq!(project_of_key_parent("organizations/1"), None);
+        assert_eq!(project_of_key_parent("projects/"), None);
+    }
+}
@@ -0,0 +1,463 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{LazyLock, RwLock};
+
+use crate::rate_limit::RateLimited;
+use crate::{debug_error, redact};
+
+/// Message catalogs, one JSON object per locale mapping `ErrorCode` keys to text.
+/// Add a locale by dropping `locales/<code>.json` next to `en.json` and listing it here;
+/// keys it leaves out fall back to English.
+const CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.json")),
+    ("de", include_str!("../locales/de.json")),
+];
+
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// Locale used for messages, set from the `language` setting.
+static LANGUAGE: RwLock<String> = RwLock::new(String::new());
+
+static MESSAGES: LazyLock<HashMap<&'static str, HashMap<String, String>>> = LazyLock::new(|| {
+    CATALOGS
+        .iter()
+        .filter_map(|(locale, json)| match serde_json::from_str(json) {
+            Ok(messages) => Some((*locale, messages)),
+            Err(e) => {
+                debug_error!("Invalid message catalog {locale}: {e}");
+                None
+            }
+        })
+        .collect()
+});
+
+/// Stable identifier for a user-facing error. Sent to the popup next to the localized
+/// message so it can branch on the code rather than the wording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    NotConfigured,
+    CredentialsNotFound,
+    AuthFailed,
+    AccessDenied,
+    RateLimited,
+    ServerError,
+    FetchFailed,
+    InvalidApiKey,
+    ApiKeyEmpty,
+    ApiKeyTooShort,
+    SessionExpired,
+    SessionInvalid,
+    SessionExpiring,
+    ReauthRequired,
+    LoginRequired,
+    QuotaExhausted,
+    RegionRestricted,
+    NetworkTimeout,
+    NetworkUnreachable,
+    UnexpectedResponse,
+    CodexNotSignedIn,
+    CredentialsUnreadable,
+    CredentialSaveFailed,
+    EnvVarMissing,
+    OauthCredentialInvalid,
+    InvalidKeyLabel,
+    BrowserCookiesUnreadable,
+    NoBrowserSession,
+}
+
+impl ErrorCode {
+    pub fn key(self) -> &'static str {
+        match self {
+            Self::NotConfigured => "not_configured",
+            Self::CredentialsNotFound => "credentials_not_found",
+            Self::AuthFailed => "auth_failed",
+            Self::AccessDenied => "access_denied",
+            Self::RateLimited => "rate_limited",
+            Self::ServerError => "server_error",
+            Self::FetchFailed => "fetch_failed",
+            Self::InvalidApiKey => "invalid_api_key",
+            Self::ApiKeyEmpty => "api_key_empty",
+            Self::ApiKeyTooShort => "api_key_too_short",
+            Self::SessionExpired => "session_expired",
+            Self::SessionInvalid => "session_invalid",
+            Self::SessionExpiring => "session_expiring",
+            Self::ReauthRequired => "reauth_required",
+            Self::LoginRequired => "login_required",
+            Self::QuotaExhausted => "quota_exhausted",
+            Self::RegionRestricted => "region_restricted",
+            Self::NetworkTimeout => "network_timeout",
+            Self::NetworkUnreachable => "network_unreachable",
+            Self::UnexpectedResponse => "unexpected_response",
+            Self::CodexNotSignedIn => "codex_not_signed_in",
+            Self::CredentialsUnreadable => "credentials_unreadable",
+            Self::CredentialSaveFailed => "credential_save_failed",
+            Self::EnvVarMissing => "env_var_missing",
+            Self::OauthCredentialInvalid => "oauth_credential_invalid",
+            Self::InvalidKeyLabel => "invalid_key_label",
+            Self::BrowserCookiesUnreadable => "browser_cookies_unreadable",
+            Self::NoBrowserSession => "no_browser_session",
+        }
+    }
+
+    /// Text for this code in `locale`, falling back to English and then to the key.
+    fn message_in(self, locale: &str) -> String {
+        [locale, DEFAULT_LANGUAGE]
+            .iter()
+            .find_map(|l| MESSAGES.get(l).and_then(|m| m.get(self.key())))
+            .cloned()
+            .unwrap_or_else(|| self.key().to_string())
+    }
+
+    /// Text for this code in the configured language.
+    pub fn message(self) -> String {
+        self.message_in(&language())
+    }
+}
+
+/// Error carrying an `ErrorCode`; services return it (via `anyhow`) for failures the user
+/// should see. `Display` is the English text so logs stay readable.
+#[derive(Debug)]
+pub struct UserError(pub ErrorCode);
+
+impl fmt::Display for UserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.message_in(DEFAULT_LANGUAGE))
+    }
+}
+
+impl std::error::Error for UserError {}
+
+impl From<ErrorCode> for anyhow::Error {
+    fn from(code: ErrorCode) -> Self {
+        UserError(code).into()
+    }
+}
+
+/// A provider response that did not have the expected shape (usually a format change on the
+/// provider's side). Commands report it as `UsageError::Parse`.
+#[derive(Debug)]
+pub struct ParseError(pub String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Error returned by commands, by kind so the popup can offer the right recovery (sign in
+/// again, wait, check the connection). Every kind carries the message in the configured
+/// language, or the raw error text for failures without a code.
+///
+/// Serialized flat as `{ kind, code, message, retry_after? }`.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum UsageError {
+    /// The stored credential was rejected or has expired.
+    #[error("{message}")]
+    AuthExpired { code: ErrorCode, message: String },
+    /// The provider asked us to back off; `retry_after` is in seconds when it said how long.
+    #[error("{message}")]
+    RateLimited {
+        retry_after: Option<u64>,
+        message: String,
+    },
+    /// The provider could not be reached.
+    #[error("{message}")]
+    Network {
+        code: Option<ErrorCode>,
+        message: String,
+    },
+    /// The provider answered with something we could not read.
+    #[error("{message}")]
+    Parse { message: String },
+    /// No credential is stored for the provider.
+    #[error("{message}")]
+    NotConfigured { code: ErrorCode, message: String },
+    /// Anything else: server errors, invalid input, local I/O.
+    #[error("{message}")]
+    Other {
+        code: Option<ErrorCode>,
+        message: String,
+    },
+}
+
+impl UsageError {
+    /// Wraps a plain message (bad input, a local failure) as `Other`.
+    pub fn other(message: impl Into<String>) -> Self {
+        Self::Other {
+            code: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::AuthExpired { .. } => "auth_expired",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::Network { .. } => "network",
+            Self::Parse { .. } => "parse",
+            Self::NotConfigured { .. } => "not_configured",
+            Self::Other { .. } => "other",
+        }
+    }
+
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            Self::AuthExpired { code, .. } | Self::NotConfigured { code, .. } => Some(*code),
+            Self::RateLimited { .. } => Some(ErrorCode::RateLimited),
+            Self::Network { code, .. } | Self::Other { code, .. } => *code,
+            Self::Parse { .. } => Some(ErrorCode::UnexpectedResponse),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::AuthExpired { message, .. }
+            | Self::RateLimited { message, .. }
+            | Self::Network { message, .. }
+            | Self::Parse { message }
+            | Self::NotConfigured { message, .. }
+            | Self::Other { message, .. } => message,
+        }
+    }
+
+    fn rate_limited(retry_after: Option<u64>) -> Self {
+        Self::RateLimited {
+            retry_after,
+            message: ErrorCode::RateLimited.message(),
+        }
+    }
+}
+
+impl Serialize for UsageError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Wire<'a> {
+            kind: &'static str,
+            code: Option<ErrorCode>,
+            message: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            retry_after: Option<u64>,
+        }
+
+        let retry_after = match self {
+            Self::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        };
+        Wire {
+            kind: self.kind(),
+            code: self.code(),
+            message: self.message(),
+            retry_after,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl From<ErrorCode> for UsageError {
+    fn from(code: ErrorCode) -> Self {
+        let message = code.message();
+        match code {
+            ErrorCode::NotConfigured
+            | ErrorCode::CredentialsNotFound
+            | ErrorCode::CodexNotSignedIn => Self::NotConfigured { code, message },
+            ErrorCode::AuthFailed
+            | ErrorCode::AccessDenied
+            | ErrorCode::InvalidApiKey
+            | ErrorCode::SessionExpired
+            | ErrorCode::SessionInvalid
+            | ErrorCode::ReauthRequired
+            | ErrorCode::LoginRequired
+            | ErrorCode::CredentialsUnreadable => Self::AuthExpired { code, message },
+            ErrorCode::RateLimited => Self::rate_limited(None),
+            ErrorCode::NetworkTimeout | ErrorCode::NetworkUnreachable => Self::Network {
+                code: Some(code),
+                message,
+            },
+            ErrorCode::UnexpectedResponse => Self::Parse { message },
+            ErrorCode::ServerError
+            | ErrorCode::FetchFailed
+            | ErrorCode::ApiKeyEmpty
+            | ErrorCode::ApiKeyTooShort
+            | ErrorCode::SessionExpiring
+            | ErrorCode::QuotaExhausted
+            | ErrorCode::RegionRestricted
+            | ErrorCode::CredentialSaveFailed
+            | ErrorCode::EnvVarMissing
+            | ErrorCode::OauthCredentialInvalid
+            | ErrorCode::InvalidKeyLabel
+            | ErrorCode::BrowserCookiesUnreadable
+            | ErrorCode::NoBrowserSession => Self::Other {
+                code: Some(code),
+                message,
+            },
+        }
+    }
+}
+
+impl From<&anyhow::Error> for UsageError {
+    fn from(error: &anyhow::Error) -> Self {
+        if let Some(e) = error.downcast_ref::<UsageError>() {
+            return e.clone();
+        }
+        if let Some(e) = error.downcast_ref::<UserError>() {
+            return e.0.into();
+        }
+        if let Some(e) = error.downcast_ref::<RateLimited>() {
+            return Self::rate_limited(Some(e.retry_after.as_secs()));
+        }
+        // The parse detail is for logs and failed payloads; the user gets the catalog text
+        if error.is::<ParseError>() {
+            return ErrorCode::UnexpectedResponse.into();
+        }
+        let message = redact::scrub(&error.to_string()).into_owned();
+        match error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        {
+            Some(e) if e.is_timeout() => ErrorCode::NetworkTimeout.into(),
+            Some(e) if e.is_connect() => ErrorCode::NetworkUnreachable.into(),
+            Some(e) if e.is_request() => Self::Network {
+                code: None,
+                message,
+            },
+            _ => Self::Other {
+                code: None,
+                message,
+            },
+        }
+    }
+}
+
+impl From<anyhow::Error> for UsageError {
+    fn from(error: anyhow::Error) -> Self {
+        (&error).into()
+    }
+}
+
+impl From<String> for UsageError {
+    fn from(message: String) -> Self {
+        Self::other(message)
+    }
+}
+
+pub fn is_supported(locale: &str) -> bool {
+    CATALOGS.iter().any(|(l, _)| *l == locale)
+}
+
+pub fn supported_languages() -> Vec<&'static str> {
+    CATALOGS.iter().map(|(l, _)| *l).collect()
+}
+
+pub fn set_language(locale: &str) {
+    let mut guard = LANGUAGE.write().unwrap_or_else(|poisoned| {
+        debug_error!("Language lock poisoned, recovering...");
+        poisoned.into_inner()
+    });
+    *guard = locale.to_string();
+}
+
+pub fn language() -> String {
+    let guard = LANGUAGE
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if guard.is_empty() {
+        DEFAULT_LANGUAGE.to_string()
+    } else {
+        guard.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_CODES: [ErrorCode; 28] = [
+        ErrorCode::NotConfigured,
+        ErrorCode::CredentialsNotFound,
+        ErrorCode::AuthFailed,
+        ErrorCode::AccessDenied,
+        ErrorCode::RateLimited,
+        ErrorCode::ServerError,
+        ErrorCode::FetchFailed,
+        ErrorCode::InvalidApiKey,
+        ErrorCode::ApiKeyEmpty,
+        ErrorCode::ApiKeyTooShort,
+        ErrorCode::SessionExpired,
+        ErrorCode::SessionInvalid,
+        ErrorCode::SessionExpiring,
+        ErrorCode::ReauthRequired,
+        ErrorCode::LoginRequired,
+        ErrorCode::QuotaExhausted,
+        ErrorCode::RegionRestricted,
+        ErrorCode::NetworkTimeout,
+        ErrorCode::NetworkUnreachable,
+        ErrorCode::UnexpectedResponse,
+        ErrorCode::CodexNotSignedIn,
+        ErrorCode::CredentialsUnreadable,
+        ErrorCode::CredentialSaveFailed,
+        ErrorCode::EnvVarMissing,
+        ErrorCode::OauthCredentialInvalid,
+        ErrorCode::InvalidKeyLabel,
+        ErrorCode::BrowserCookiesUnreadable,
+        ErrorCode::NoBrowserSession,
+    ];
+
+    #[test]
+    fn test_english_catalog_covers_every_code() {
+        let english = &MESSAGES[DEFAULT_LANGUAGE];
+        for code in ALL_CODES {
+            assert!(english.contains_key(code.key()), "missing {}", code.key());
+        }
+    }
+
+    #[test]
+    fn test_catalogs_only_use_known_keys() {
+        for (locale, messages) in MESSAGES.iter() {
+            for key in messages.keys() {
+                assert!(
+                    ALL_CODES.iter().any(|c| c.key() == key),
+                    "{locale} has unknown key {key}"
+                );
+            }
+        }
+        assert_eq!(MESSAGES.len(), CATALOGS.len());
+    }
+
+    #[test]
+    fn test_message_falls_back_to_english() {
+        assert_eq!(
+            ErrorCode::AuthFailed.message_in("xx"),
+            ErrorCode::AuthFailed.message_in(DEFAULT_LANGUAGE)
+        );
+        assert_ne!(
+            ErrorCode::AuthFailed.message_in("de"),
+            ErrorCode::AuthFailed.message_in(DEFAULT_LANGUAGE)
+        );
+    }
+
+    #[test]
+    fn test_usage_error_kinds() {
+        let error: anyhow::Error = ErrorCode::SessionExpired.into();
+        let usage_error = UsageError::from(&error);
+        assert_eq!(usage_error.kind(), "auth_expired");
+        assert_eq!(usage_error.code(), Some(ErrorCode::SessionExpired));
+
+        let plain = UsageError::from(anyhow::anyhow!("Token refresh failed"));
+        assert_eq!(plain.kind(), "other");
+        assert_eq!(plain.code(), None);
+        assert_eq!(plain.message(), "Token refresh failed");
+
+        let parse = UsageError::from(anyhow::Error::new(ParseError("bad json".into())));
+        assert_eq!(parse.kind(), "parse");
+        assert_eq!(parse.message(), ErrorCode::UnexpectedResponse.message());
+
+        let json = serde_json::to_value(UsageError::from(ErrorCode::NotConfigured)).unwrap();
+        assert_eq!(json["kind"], "not_configured");
+        assert_eq!(json["code"], "not_configured");
+        assert!(json.get("retry_after").is_none());
+    }
+}
@@ -0,0 +1,31 @@
+//! Provider API clients, response models, credential storage and caching for Usage Bar.
+//! Nothing here depends on Tauri, so a CLI or test harness can fetch usage directly.
+
+pub mod amp_service;
+pub mod anthropic_service;
+pub mod browser_cookies;
+pub mod cache;
+pub mod claude_login;
+pub mod claude_service;
+pub mod clock;
+pub mod codex_service;
+pub mod credentials;
+pub mod endpoint;
+pub mod failed_payloads;
+pub mod gemini_service;
+pub mod i18n;
+pub mod local_usage;
+pub mod logging;
+pub mod models;
+pub mod money;
+pub mod rate_limit;
+pub mod redact;
+pub mod singleflight;
+pub mod vault;
+pub mod zai_service;
+
+// Re-export logging constants so macros can find them via $crate
+pub use logging::{
+    COLOR_BLUE, COLOR_BRIGHT_CYAN, COLOR_BRIGHT_RED, COLOR_CYAN, COLOR_GRAY, COLOR_GREEN,
+    COLOR_MAGENTA, COLOR_RED, COLOR_RESET, COLOR_YELLOW,
+};
@@ -0,0 +1,513 @@
+use crate::clock;
+use crate::credentials::CredentialManager;
+use crate::models::{
+    DateRange, LocalUsage, ModelUsage, ProjectSort, ProjectUsage, ProjectUsageReport,
+    TranscriptLine, TranscriptUsage,
+};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, Utc};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::{debug_claude, debug_error};
+
+/// Folders below `projects/` searched for transcripts: project, session, `subagents`.
+const MAX_DEPTH: usize = 3;
+
+/// Dollars per million tokens: input, output, cache write (5 minute), cache read.
+struct ModelPrice {
+    input: f64,
+    output: f64,
+    cache_write: f64,
+    cache_read: f64,
+}
+
+/// API list prices by model ID prefix; the first match wins, so more specific prefixes
+/// come first. Models not listed (new releases, `<synthetic>`) get no cost.
+const PRICES: [(&str, ModelPrice); 11] = [
+    ("claude-opus-4-5", price(5.0, 25.0, 6.25, 0.50)),
+    ("claude-opus-4-1", price(15.0, 75.0, 18.75, 1.50)),
+    ("claude-opus-4-20", price(15.0, 75.0, 18.75, 1.50)),
+    ("claude-sonnet-4-5", price(3.0, 15.0, 3.75, 0.30)),
+    ("claude-sonnet-4-20", price(3.0, 15.0, 3.75, 0.30)),
+    ("claude-3-7-sonnet", price(3.0, 15.0, 3.75, 0.30)),
+    ("claude-3-5-sonnet", price(3.0, 15.0, 3.75, 0.30)),
+    ("claude-haiku-4-5", price(1.0, 5.0, 1.25, 0.10)),
+    ("claude-3-5-haiku", price(0.80, 4.0, 1.0, 0.08)),
+    ("claude-3-opus", price(15.0, 75.0, 18.75, 1.50)),
+    ("claude-3-haiku", price(0.25, 1.25, 0.30, 0.03)),
+];
+
+const fn price(input: f64, output: f64, cache_write: f64, cache_read: f64) -> ModelPrice {
+    ModelPrice {
+        input,
+        output,
+        cache_write,
+        cache_read,
+    }
+}
+
+/// One assistant response from a transcript.
+#[derive(Debug, Clone)]
+struct Entry {
+    /// The session's working directory.
+    project: String,
+    model: String,
+    tokens: TranscriptUsage,
+    cost: Option<f64>,
+}
+
+/// The model's tier, for comparing e.g. Opus against Sonnet across versions.
+fn family(model: &str) -> Option<&'static str> {
+    ["opus", "sonnet", "haiku"]
+        .into_iter()
+        .find(|family| model.contains(family))
+}
+
+/// Estimated cost of `tokens` on `model` at list prices.
+fn estimate_cost(model: &str, tokens: &TranscriptUsage) -> Option<f64> {
+    let (_, price) = PRICES
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))?;
+    let per_token = |count: u64, dollars_per_million: f64| count as f64 * dollars_per_million;
+    Some(
+        (per_token(tokens.input_tokens, price.input)
+            + per_token(tokens.output_tokens, price.output)
+            + per_token(tokens.cache_creation_input_tokens, price.cache_write)
+            + per_token(tokens.cache_read_input_tokens, price.cache_read))
+            / 1_000_000.0,
+    )
+}
+
+/// A `DateRange` with its defaults filled in. Also bounds `claude_get_history`.
+#[derive(Debug, Clone, Copy)]
+pub struct Bounds {
+    pub since: i64,
+    pub until: Option<i64>,
+}
+
+impl Bounds {
+    pub fn resolve(range: DateRange) -> Result<Self> {
+        let since = match range.from {
+            Some(from) => from,
+            None => local_day_start(clock::now_millis())
+                .ok_or_else(|| anyhow!("System clock out of range"))?,
+        };
+        if range.to.is_some_and(|to| to < since) {
+            return Err(anyhow!("The end of the range is before its start"));
+        }
+        Ok(Self {
+            since,
+            until: range.to,
+        })
+    }
+
+    pub fn contains(&self, timestamp: i64) -> bool {
+        timestamp >= self.since && self.until.is_none_or(|until| timestamp < until)
+    }
+}
+
+/// An entry for a transcript line with usage inside `bounds`, or `None` for any other
+/// line. `project_dir` names the project when the line has no `cwd`. `seen` drops repeats:
+/// Claude Code writes a response again when a session is resumed or continued, with the
+/// same message and request IDs.
+fn parse_line(
+    line: &str,
+    bounds: Bounds,
+    project_dir: &str,
+    seen: &mut HashSet<String>,
+) -> Option<Entry> {
+    // Most lines are prompts, tool results and metadata; skip them before parsing JSON.
+    if !line.contains("\"usage\"") {
+        return None;
+    }
+    let parsed: TranscriptLine = serde_json::from_str(line).ok()?;
+    let message = parsed.message?;
+    let tokens = message.usage?;
+    let timestamp = DateTime::parse_from_rfc3339(parsed.timestamp.as_deref()?)
+        .ok()?
+        .timestamp_millis();
+    if !bounds.contains(timestamp) {
+        return None;
+    }
+    if let (Some(id), Some(request_id)) = (&message.id, &parsed.request_id) {
+        if !seen.insert(format!("{id}:{request_id}")) {
+            return None;
+        }
+    }
+
+    let model = message.model.unwrap_or_else(|| "unknown".to_string());
+    let cost = parsed.cost_usd.or_else(|| estimate_cost(&model, &tokens));
+    Some(Entry {
+        project: parsed.cwd.unwrap_or_else(|| project_dir.to_string()),
+        model,
+        tokens,
+        cost,
+    })
+}
+
+/// Every `.jsonl` file below `dir` modified at or after `since`; older files can't hold
+/// newer responses.
+fn transcript_files(dir: &Path, since: i64, depth: usize, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            if depth < MAX_DEPTH {
+                transcript_files(&path, since, depth + 1, files);
+            }
+            continue;
+        }
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or(Duration::MAX);
+        if path.extension().is_some_and(|ext| ext == "jsonl")
+            && modified.as_millis() >= since.max(0) as u128
+        {
+            files.push(path);
+        }
+    }
+}
+
+/// Responses in the transcripts under `projects_dir` inside `bounds`.
+fn read_entries(projects_dir: &Path, bounds: Bounds) -> Vec<Entry> {
+    let mut files = Vec::new();
+    transcript_files(projects_dir, bounds.since, 1, &mut files);
+    debug_claude!(
+        "Reading {} Claude Code transcripts in {projects_dir:?}",
+        files.len()
+    );
+
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+    for path in files {
+        // Claude Code names each project's folder after its path, with `/` replaced by `-`.
+        let project_dir = path
+            .strip_prefix(projects_dir)
+            .ok()
+            .and_then(|relative| relative.components().next())
+            .map(|dir| dir.as_os_str().to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                debug_error!("Skipping transcript {path:?}: {e}");
+                continue;
+            }
+        };
+        // A line that isn't UTF-8 (a write cut off mid-character) ends the file early.
+        for line in BufReader::new(file).lines().map_while(|line| line.ok()) {
+            if let Some(entry) = parse_line(&line, bounds, &project_dir, &mut seen) {
+                entries.push(entry);
+            }
+        }
+    }
+    entries
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    tokens: TranscriptUsage,
+    /// `None` until an entry with a cost is added.
+    cost: Option<f64>,
+    requests: u64,
+}
+
+impl Totals {
+    fn add(&mut self, entry: &Entry) {
+        self.tokens.add(&entry.tokens);
+        if let Some(cost) = entry.cost {
+            self.cost = Some(self.cost.unwrap_or(0.0) + cost);
+        }
+        self.requests += 1;
+    }
+}
+
+/// Totals per distinct `key`, in order of first appearance.
+fn group(entries: &[Entry], key: fn(&Entry) -> &str) -> Vec<(String, Totals)> {
+    let mut groups: Vec<(String, Totals)> = Vec::new();
+    for entry in entries {
+        let index = match groups.iter().position(|(name, _)| name == key(entry)) {
+            Some(index) => index,
+            None => {
+                groups.push((key(entry).to_string(), Totals::default()));
+                groups.len() - 1
+            }
+        };
+        groups[index].1.add(entry);
+    }
+    groups
+}
+
+/// Most expensive first, unpriced last; ties by name.
+fn by_cost(a: (&str, Option<f64>), b: (&str, Option<f64>)) -> std::cmp::Ordering {
+    b.1.unwrap_or(0.0)
+        .total_cmp(&a.1.unwrap_or(0.0))
+        .then_with(|| a.0.cmp(b.0))
+}
+
+/// Totals per model, most expensive first.
+fn by_model(entries: &[Entry]) -> Vec<ModelUsage> {
+    let mut models: Vec<ModelUsage> = group(entries, |entry| &entry.model)
+        .into_iter()
+        .map(|(model, totals)| ModelUsage {
+            family: family(&model),
+            model,
+            tokens: totals.tokens,
+            cost: totals.cost,
+            requests: totals.requests,
+        })
+        .collect();
+    models.sort_by(|a, b| by_cost((&a.model, a.cost), (&b.model, b.cost)));
+    models
+}
+
+fn by_project(entries: &[Entry], sort: ProjectSort) -> Vec<ProjectUsage> {
+    let mut projects: Vec<ProjectUsage> = group(entries, |entry| &entry.project)
+        .into_iter()
+        .map(|(project, totals)| ProjectUsage {
+            project,
+            tokens: totals.tokens,
+            cost: totals.cost,
+            requests: totals.requests,
+        })
+        .collect();
+    match sort {
+        ProjectSort::Cost => {
+            projects.sort_by(|a, b| by_cost((&a.project, a.cost), (&b.project, b.cost)))
+        }
+        ProjectSort::Tokens => projects.sort_by(|a, b| {
+            b.tokens
+                .total()
+                .cmp(&a.tokens.total())
+                .then_with(|| a.project.cmp(&b.project))
+        }),
+        ProjectSort::Name => projects.sort_by(|a, b| a.project.cmp(&b.project)),
+    }
+    projects
+}
+
+fn summarize(entries: &[Entry], bounds: Bounds) -> LocalUsage {
+    let models = by_model(entries);
+    let mut tokens = TranscriptUsage::default();
+    for model in &models {
+        tokens.add(&model.tokens);
+    }
+    LocalUsage {
+        since: bounds.since,
+        until: bounds.until,
+        tokens,
+        cost: models.iter().filter_map(|m| m.cost).sum(),
+        models,
+    }
+}
+
+/// Epoch millis of the most recent local midnight at or before `now_millis`.
+fn local_day_start(now_millis: i64) -> Option<i64> {
+    let now = DateTime::<Utc>::from_timestamp_millis(now_millis)?.with_timezone(&Local);
+    let midnight = now.date_naive().and_hms_opt(0, 0, 0)?;
+    Some(
+        midnight
+            .and_local_timezone(Local)
+            .earliest()?
+            .timestamp_millis(),
+    )
+}
+
+pub struct LocalUsageReader;
+
+impl LocalUsageReader {
+    /// Today's Claude Code usage on this machine, from the transcripts of the login in use.
+    pub fn claude_local_usage() -> Result<LocalUsage> {
+        Self::claude_model_breakdown(DateRange::default())
+    }
+
+    /// Usage per model inside `range`.
+    pub fn claude_model_breakdown(range: DateRange) -> Result<LocalUsage> {
+        let bounds = Bounds::resolve(range)?;
+        Ok(summarize(&Self::entries(bounds)?, bounds))
+    }
+
+    /// Usage per project inside `range`, sorted by `sort`.
+    pub fn claude_project_usage(range: DateRange, sort: ProjectSort) -> Result<ProjectUsageReport> {
+        let bounds = Bounds::resolve(range)?;
+        Ok(ProjectUsageReport {
+            since: bounds.since,
+            until: bounds.until,
+            projects: by_project(&Self::entries(bounds)?, sort),
+        })
+    }
+
+    fn entries(bounds: Bounds) -> Result<Vec<Entry>> {
+        let projects_dir = CredentialManager::claude_config_dir()?.join("projects");
+        Ok(read_entries(&projects_dir, bounds))
+    }
+
+    pub async fn claude_local_usage_async() -> Result<LocalUsage> {
+        blocking(Self::claude_local_usage).await
+    }
+
+    pub async fn claude_model_breakdown_async(range: DateRange) -> Result<LocalUsage> {
+        blocking(move || Self::claude_model_breakdown(range)).await
+    }
+
+    pub async fn claude_project_usage_async(
+        range: DateRange,
+        sort: ProjectSort,
+    ) -> Result<ProjectUsageReport> {
+        blocking(move || Self::claude_project_usage(range, sort)).await
+    }
+}
+
+/// Transcripts can run to hundreds of megabytes, so they are read on the blocking pool.
+async fn blocking<T: Send + 'static>(
+    read: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    tokio::task::spawn_blocking(read)
+        .await
+        .map_err(|e| anyhow!("Local usage task failed: {e}"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(id: &str, model: &str, timestamp: &str, input: u64, output: u64) -> String {
+        serde_json::json!({
+            "type": "assistant",
+            "timestamp": timestamp,
+            "cwd": "/home/jane/repo",
+            "requestId": format!("req_{id}"),
+            "message": {
+                "id": format!("msg_{id}"),
+                "model": model,
+                "usage": {"input_tokens": input, "output_tokens": output,
+                          "cache_read_input_tokens": 1_000_000}
+            }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_parse_lines_dedupes_and_prices() {
+        // 2025-03-01T00:00:00Z
+        let since = 1_740_787_200_000;
+        let bounds = Bounds { since, until: None };
+        let mut seen = HashSet::new();
+        let lines = [
+            line(
+                "a",
+                "claude-sonnet-4-5-20250929",
+                "2025-03-01T10:00:00Z",
+                1_000_000,
+                0,
+            ),
+            line(
+                "a",
+                "claude-sonnet-4-5-20250929",
+                "2025-03-01T10:00:00Z",
+                1_000_000,
+                0,
+            ),
+            line(
+                "b",
+                "claude-opus-4-1-20250805",
+                "2025-03-01T11:00:00Z",
+                0,
+                1_000_000,
+            ),
+            line(
+                "c",
+                "claude-sonnet-4-5-20250929",
+                "2025-02-28T23:59:59Z",
+                5,
+                5,
+            ),
+            line("d", "<synthetic>", "2025-03-01T12:00:00Z", 0, 0),
+            r#"{"type":"user","message":{"role":"user","content":"hi"}}"#.to_string(),
+        ];
+        let entries: Vec<Entry> = lines
+            .iter()
+            .filter_map(|l| parse_line(l, bounds, "-home-jane-repo", &mut seen))
+            .collect();
+        assert_eq!(entries.len(), 3);
+
+        let usage = summarize(&entries, bounds);
+        assert_eq!(usage.tokens.input_tokens, 1_000_000);
+        assert_eq!(usage.tokens.cache_read_input_tokens, 3_000_000);
+        let models: Vec<&str> = usage.models.iter().map(|m| m.model.as_str()).collect();
+        assert_eq!(
+            models,
+            [
+                "claude-opus-4-1-20250805",
+                "claude-sonnet-4-5-20250929",
+                "<synthetic>"
+            ]
+        );
+        let close =
+            |cost: Option<f64>, expected: f64| cost.is_some_and(|c| (c - expected).abs() < 1e-9);
+        assert!(close(usage.models[0].cost, 76.5));
+        assert!(close(usage.models[1].cost, 3.3));
+        assert_eq!(usage.models[2].cost, None);
+        let families: Vec<Option<&str>> = usage.models.iter().map(|m| m.family).collect();
+        assert_eq!(families, [Some("opus"), Some("sonnet"), None]);
+        assert_eq!(usage.models[1].requests, 1);
+        assert!(close(Some(usage.cost), 79.8));
+    }
+
+    #[test]
+    fn test_project_usage_range_and_sort() {
+        let entry = |project: &str, output_tokens: u64, cost: Option<f64>| Entry {
+            project: project.to_string(),
+            model: "claude-sonnet-4-5".to_string(),
+            tokens: TranscriptUsage {
+                output_tokens,
+                ..Default::default()
+            },
+            cost,
+        };
+        let bounds = Bounds {
+            since: 100,
+            until: Some(200),
+        };
+        assert!(bounds.contains(100) && bounds.contains(199));
+        assert!(!bounds.contains(99) && !bounds.contains(200));
+        assert!(Bounds::resolve(DateRange {
+            from: Some(200),
+            to: Some(100)
+        })
+        .is_err());
+
+        let entries = [
+            entry("/b", 10, Some(1.0)),
+            entry("/a", 500, Some(0.5)),
+            entry("/b", 10, Some(1.0)),
+            entry("/c", 5, None),
+        ];
+        let names = |sort| -> Vec<String> {
+            by_project(&entries, sort)
+                .into_iter()
+                .map(|p| p.project)
+                .collect()
+        };
+        assert_eq!(names(ProjectSort::Cost), ["/b", "/a", "/c"]);
+        assert_eq!(names(ProjectSort::Tokens), ["/a", "/b", "/c"]);
+        assert_eq!(names(ProjectSort::Name), ["/a", "/b", "/c"]);
+
+        let b = &by_project(&entries, ProjectSort::Cost)[0];
+        assert_eq!(
+            (b.tokens.output_tokens, b.cost, b.requests),
+            (20, Some(2.0), 2)
+        );
+    }
+}
@@ -12,17 +12,19 @@ pub const COLOR_GRAY: &str = "\x1b[90m"; // Timestamps
 // ============================================================================
 // CATEGORY-SPECIFIC MACROS (Debug builds only)
 // ============================================================================
+// Every message passes through `redact::scrub`; wrap known secrets in `Redacted`.
+// Output goes to stderr so the CLI's stdout stays machine-readable.
 
 // [APP] - Cyan - Application lifecycle, startup, tray events
 #[macro_export]
 #[cfg(debug_assertions)]
 macro_rules! debug_app {
     ($($arg:tt)*) => {
-        println!(
+        eprintln!(
             "{color}[APP]{reset} {message}",
             color = $crate::COLOR_CYAN,
             reset = $crate::COLOR_RESET,
-            message = format!($($arg)*)
+            message = $crate::redact::scrub(&format!($($arg)*))
         );
     };
 }
@@ -38,11 +40,11 @@ macro_rules! debug_app {
 #[cfg(debug_assertions)]
 macro_rules! debug_claude {
     ($($arg:tt)*) => {
-        println!(
+        eprintln!(
             "{color}[CLAUDE]{reset} {message}",
             color = $crate::COLOR_GREEN,
             reset = $crate::COLOR_RESET,
-            message = format!($($arg)*)
+            message = $crate::redact::scrub(&format!($($arg)*))
         );
     };
 }
@@ -58,11 +60,11 @@ macro_rules! debug_claude {
 #[cfg(debug_assertions)]
 macro_rules! debug_zai {
     ($($arg:tt)*) => {
-        println!(
+        eprintln!(
             "{color}[ZAI]{reset} {message}",
             color = $crate::COLOR_YELLOW,
             reset = $crate::COLOR_RESET,
-            message = format!($($arg)*)
+            message = $crate::redact::scrub(&format!($($arg)*))
         );
     };
 }
@@ -78,11 +80,11 @@ macro_rules! debug_zai {
 #[cfg(debug_assertions)]
 macro_rules! debug_cred {
     ($($arg:tt)*) => {
-        println!(
+        eprintln!(
             "{color}[CRED]{reset} {message}",
             color = $crate::COLOR_MAGENTA,
             reset = $crate::COLOR_RESET,
-            message = format!($($arg)*)
+            message = $crate::redact::scrub(&format!($($arg)*))
         );
     };
 }
@@ -98,11 +100,11 @@ macro_rules! debug_cred {
 #[cfg(debug_assertions)]
 macro_rules! debug_cache {
     ($($arg:tt)*) => {
-        println!(
+        eprintln!(
             "{color}[CACHE]{reset} {message}",
             color = $crate::COLOR_BLUE,
             reset = $crate::COLOR_RESET,
-            message = format!($($arg)*)
+            message = $crate::redact::scrub(&format!($($arg)*))
         );
     };
 }
@@ -118,11 +120,11 @@ macro_rules! debug_cache {
 #[cfg(debug_assertions)]
 macro_rules! debug_net {
     ($($arg:tt)*) => {
-        println!(
+        eprintln!(
             "{color}[NET]{reset} {message}",
             color = $crate::COLOR_BRIGHT_RED,
             reset = $crate::COLOR_RESET,
-            message = format!($($arg)*)
+            message = $crate::redact::scrub(&format!($($arg)*))
         );
     };
 }
@@ -140,11 +142,11 @@ pub const COLOR_BRIGHT_CYAN: &str = "\x1b[96m";
 #[cfg(debug_assertions)]
 macro_rules! debug_amp {
     ($($arg:tt)*) => {
-        println!(
+        eprintln!(
             "{color}[AMP]{reset} {message}",
             color = $crate::COLOR_BRIGHT_CYAN,
             reset = $crate::COLOR_RESET,
-            message = format!($($arg)*)
+            message = $crate::redact::scrub(&format!($($arg)*))
         );
     };
 }
@@ -160,11 +162,11 @@ macro_rules! debug_amp {
 #[cfg(debug_assertions)]
 macro_rules! debug_error {
     ($($arg:tt)*) => {
-        println!(
+        eprintln!(
             "{color}[ERROR]{reset} {message}",
             color = $crate::COLOR_RED,
             reset = $crate::COLOR_RESET,
-            message = format!($($arg)*)
+            message = $crate::redact::scrub(&format!($($arg)*))
         );
     };
 }
@@ -182,7 +184,7 @@ macro_rules! debug_error {
 #[macro_export]
 #[cfg(debug_assertions)]
 macro_rules! debug_log {
-    ($($arg:tt)*) => { println!($($arg)*); };
+    ($($arg:tt)*) => { eprintln!("{}", $crate::redact::scrub(&format!($($arg)*))); };
 }
 
 #[macro_export]
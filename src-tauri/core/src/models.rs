@@ -0,0 +1,1794 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::debug_error;
+use crate::i18n::{ErrorCode, UsageError};
+use crate::rate_limit::RequestQuota;
+
+/// Provider identifier used in settings and events; serialized lowercase (`"zai"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Claude,
+    Codex,
+    Zai,
+    Amp,
+    /// Pay-as-you-go Anthropic API spend, read with an Admin API key.
+    Anthropic,
+    /// Gemini API request quotas of a Google Cloud project, read from Cloud Monitoring.
+    Gemini,
+}
+
+impl Provider {
+    pub const ALL: [Provider; 6] = [
+        Provider::Claude,
+        Provider::Codex,
+        Provider::Zai,
+        Provider::Amp,
+        Provider::Anthropic,
+        Provider::Gemini,
+    ];
+
+    pub fn key(self) -> &'static str {
+        match self {
+            Provider::Claude => "claude",
+            Provider::Codex => "codex",
+            Provider::Zai => "zai",
+            Provider::Amp => "amp",
+            Provider::Anthropic => "anthropic",
+            Provider::Gemini => "gemini",
+        }
+    }
+}
+
+/// Provider data as returned by commands, with freshness annotations flattened alongside it
+/// so the frontend reads the same fields it always has.
+#[derive(Debug, Clone, Serialize)]
+pub struct Envelope<T> {
+    #[serde(flatten)]
+    pub data: T,
+    /// Epoch millis at which the provider's 429 backoff ends. When set, `data` is the last
+    /// cached value and no request was made.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limited_until: Option<i64>,
+    /// Set when this fetch failed and `data` is the last good value (from the cache or
+    /// history) instead.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub is_stale: bool,
+    /// Why this fetch failed, when `is_stale`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Epoch millis at which the stale `data` was fetched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stale_since: Option<i64>,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            data,
+            rate_limited_until: None,
+            is_stale: false,
+            error: None,
+            stale_since: None,
+        }
+    }
+
+    pub fn rate_limited(data: T, until: i64) -> Self {
+        Self {
+            rate_limited_until: Some(until),
+            ..Self::new(data)
+        }
+    }
+
+    pub fn last_good(data: T, error: String, stale_since: i64) -> Self {
+        Self {
+            is_stale: true,
+            error: Some(error),
+            stale_since: Some(stale_since),
+            ..Self::new(data)
+        }
+    }
+
+    /// The same envelope around `f(data)`.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Envelope<U> {
+        Envelope {
+            data: f(self.data),
+            rate_limited_until: self.rate_limited_until,
+            is_stale: self.is_stale,
+            error: self.error,
+            stale_since: self.stale_since,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageResponse {
+    pub five_hour: Option<UsagePeriod>,
+    pub seven_day: Option<UsagePeriod>,
+    /// Separate weekly limit on Opus models, sent for Max plans only.
+    #[serde(default)]
+    pub seven_day_opus: Option<UsagePeriod>,
+    pub extra_usage: Option<ExtraUsageResponse>,
+    // Tier info also comes from the same /usage endpoint
+    #[serde(default)]
+    pub rate_limit_tier: Option<String>,
+    #[serde(default)]
+    pub billing_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtraUsageResponse {
+    pub is_enabled: bool,
+    pub monthly_limit: Option<f64>,
+    pub used_credits: Option<f64>,
+    pub utilization: Option<f64>,
+    /// When the monthly extra usage period ends. Not every account gets it.
+    #[serde(default, deserialize_with = "ResetTime::deserialize_lenient")]
+    pub resets_at: Option<ResetTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsagePeriod {
+    pub utilization: f64,
+    /// Malformed values are logged and dropped rather than failing the whole response.
+    #[serde(default, deserialize_with = "ResetTime::deserialize_lenient")]
+    pub resets_at: Option<ResetTime>,
+}
+
+/// A window reset instant, parsed and validated once when the API response is read.
+/// Serializes as epoch milliseconds like every other provider's reset; RFC 3339 strings
+/// (Claude's API, and history written by older versions) are still read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResetTime(DateTime<Utc>);
+
+impl ResetTime {
+    /// Resets outside this range are a parsing or API error, not a real window.
+    const MIN_YEAR: i32 = 2020;
+    const MAX_YEAR: i32 = 2100;
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let parsed = DateTime::parse_from_rfc3339(value.trim())
+            .map_err(|e| format!("invalid reset time {value:?}: {e}"))?
+            .with_timezone(&Utc);
+        Self::check_year(parsed, value)
+    }
+
+    pub fn from_epoch_millis(millis: i64) -> Result<Self, String> {
+        let parsed = DateTime::<Utc>::from_timestamp_millis(millis)
+            .ok_or_else(|| format!("reset time {millis} is out of range"))?;
+        Self::check_year(parsed, &millis.to_string())
+    }
+
+    fn check_year(parsed: DateTime<Utc>, value: &str) -> Result<Self, String> {
+        let year = chrono::Datelike::year(&parsed);
+        if !(Self::MIN_YEAR..=Self::MAX_YEAR).contains(&year) {
+            return Err(format!("reset time {value:?} is out of range"));
+        }
+        Ok(Self(parsed))
+    }
+
+    pub fn epoch_millis(self) -> i64 {
+        self.0.timestamp_millis()
+    }
+
+    /// Like `Option<ResetTime>`'s `Deserialize`, but a malformed value becomes `None`.
+    fn deserialize_lenient<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Self>, D::Error> {
+        let Some(raw) = Option::<RawResetTime>::deserialize(deserializer)? else {
+            return Ok(None);
+        };
+        Ok(raw
+            .parse()
+            .inspect_err(|e| {
+                debug_error!("Ignoring {e}");
+            })
+            .ok())
+    }
+}
+
+impl fmt::Display for ResetTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.to_rfc3339_opts(SecondsFormat::Millis, true))
+    }
+}
+
+impl Serialize for ResetTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(self.epoch_millis())
+    }
+}
+
+impl<'de> Deserialize<'de> for ResetTime {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        RawResetTime::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawResetTime {
+    Millis(i64),
+    Text(String),
+}
+
+impl RawResetTime {
+    fn parse(self) -> Result<ResetTime, String> {
+        match self {
+            RawResetTime::Millis(millis) => ResetTime::from_epoch_millis(millis),
+            RawResetTime::Text(text) => ResetTime::parse(&text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageData {
+    pub five_hour_utilization: f64,
+    #[serde(default, deserialize_with = "ResetTime::deserialize_lenient")]
+    pub five_hour_resets_at: Option<ResetTime>,
+    pub seven_day_utilization: f64,
+    #[serde(default, deserialize_with = "ResetTime::deserialize_lenient")]
+    pub seven_day_resets_at: Option<ResetTime>,
+    /// Weekly Opus limit; `None` on plans without one.
+    #[serde(default)]
+    pub seven_day_opus_utilization: Option<f64>,
+    #[serde(default, deserialize_with = "ResetTime::deserialize_lenient")]
+    pub seven_day_opus_resets_at: Option<ResetTime>,
+    pub extra_usage_enabled: bool,
+    pub extra_usage_monthly_limit: Option<f64>,
+    pub extra_usage_used_credits: Option<f64>,
+    pub extra_usage_utilization: Option<f64>,
+    #[serde(default, deserialize_with = "ResetTime::deserialize_lenient")]
+    pub extra_usage_resets_at: Option<ResetTime>,
+    /// Percent of the `claude_extra_usage_budget` setting spent, worked out at fetch time;
+    /// `None` without a budget.
+    #[serde(default)]
+    pub extra_usage_budget_utilization: Option<f64>,
+}
+
+/// Status fields Z.ai puts in every body, including error bodies sent with HTTP 200:
+/// `{"code": 1001, "msg": "...", "success": false}`, or an `error` object on its
+/// Anthropic-compatible routes.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ZaiStatusBody {
+    pub code: Option<ZaiStatusCode>,
+    pub msg: Option<String>,
+    pub success: Option<bool>,
+    pub error: Option<ZaiErrorObject>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ZaiErrorObject {
+    pub code: Option<ZaiStatusCode>,
+    pub message: Option<String>,
+}
+
+/// Sent as a number or as a numeric string.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ZaiStatusCode {
+    Number(i64),
+    Text(String),
+}
+
+impl ZaiStatusCode {
+    pub fn value(&self) -> Option<i64> {
+        match self {
+            Self::Number(n) => Some(*n),
+            Self::Text(s) => s.trim().parse().ok(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZaiQuotaResponse {
+    pub data: ZaiQuotaData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZaiQuotaData {
+    pub limits: Vec<ZaiQuotaLimit>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZaiQuotaLimit {
+    #[serde(rename = "type")]
+    pub limit_type: String,
+    pub percentage: f64,
+    #[serde(rename = "nextResetTime")]
+    pub next_reset_time: Option<i64>,
+    #[serde(rename = "currentValue")]
+    pub current_value: Option<i32>,
+    pub usage: Option<i32>,
+    /// Every other field of the entry, as sent.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZaiUsageData {
+    pub token_usage: Option<TokenUsage>,
+    pub mcp_usage: Option<McpUsage>,
+    pub tier_name: Option<String>,
+    /// Every entry Z.ai reported, including types without a field above.
+    #[serde(default)]
+    pub limits: Vec<ZaiLimit>,
+}
+
+/// Where the Z.ai key in use was found, returned by `zai_check_api_key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZaiKeySource {
+    /// Saved in the app (the secret store).
+    Stored,
+    /// `ZAI_API_KEY` or `Z_AI_API_KEY`.
+    Environment,
+    /// Claude Code's `settings.json`, set up to use Z.ai's Anthropic-compatible endpoint.
+    ClaudeSettings,
+    /// opencode's `auth.json`.
+    OpenCode,
+}
+
+/// One saved Z.ai key's quota in `ZaiAccountsUsage`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ZaiAccountUsage {
+    pub usage: Option<ZaiUsageData>,
+    pub error: Option<UsageError>,
+}
+
+/// Quota of every saved Z.ai key, returned by `zai_get_all` after the default key's usage.
+#[derive(Debug, Clone, Serialize)]
+pub struct ZaiAccountsUsage {
+    /// Keyed by label; `default` is the key saved without one.
+    pub accounts: BTreeMap<String, ZaiAccountUsage>,
+    /// Mean token-window percentage of the keys that reported one; `None` with fewer than two.
+    pub aggregate_percentage: Option<f64>,
+}
+
+impl ZaiAccountsUsage {
+    pub fn new(accounts: BTreeMap<String, ZaiAccountUsage>) -> Self {
+        let percentages: Vec<f64> = accounts
+            .values()
+            .filter_map(|account| Some(account.usage.as_ref()?.token_usage.as_ref()?.percentage))
+            .collect();
+        let aggregate_percentage = (percentages.len() >= 2)
+            .then(|| percentages.iter().sum::<f64>() / percentages.len() as f64);
+        Self {
+            accounts,
+            aggregate_percentage,
+        }
+    }
+}
+
+/// One of Z.ai's `limits`, whatever its type, so limits Z.ai adds later still show up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ZaiLimit {
+    /// `TOKENS_LIMIT`, `TIME_LIMIT`, ...
+    pub limit_type: String,
+    pub percentage: f64,
+    pub current: Option<i32>,
+    pub total: Option<i32>,
+    pub resets_at: Option<i64>,
+    /// The entry's other fields untouched (`unit`, `number`, `usageDetails`, ...), for the
+    /// popup to show details the fields above don't cover.
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub details: serde_json::Map<String, serde_json::Value>,
+}
+
+impl From<ZaiQuotaLimit> for ZaiLimit {
+    fn from(limit: ZaiQuotaLimit) -> Self {
+        Self {
+            limit_type: limit.limit_type,
+            percentage: limit.percentage,
+            current: limit.current_value,
+            total: limit.usage,
+            resets_at: limit.next_reset_time,
+            details: limit.extra,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZaiTierData {
+    pub plan_name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ZaiSubscriptionResponse {
+    #[serde(default)]
+    pub data: Vec<ZaiSubscription>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZaiSubscription {
+    /// `GLM Coding Pro`, ...
+    #[serde(default, alias = "planName")]
+    pub product_name: Option<String>,
+    /// `VALID`, `EXPIRED`, ...; a subscription without one counts as active.
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+impl ZaiSubscription {
+    pub fn is_active(&self) -> bool {
+        self.status.as_deref().is_none_or(|status| {
+            ["valid", "active", "effective"].contains(&status.to_ascii_lowercase().as_str())
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ZaiModelUsageResponse {
+    pub data: ZaiModelUsageRows,
+}
+
+/// The model-usage rows, bare or wrapped in `list`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ZaiModelUsageRows {
+    Rows(Vec<ZaiModelUsageRow>),
+    Wrapped { list: Vec<ZaiModelUsageRow> },
+}
+
+impl ZaiModelUsageRows {
+    pub fn into_rows(self) -> Vec<ZaiModelUsageRow> {
+        match self {
+            Self::Rows(rows) | Self::Wrapped { list: rows } => rows,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ZaiModelUsageRow {
+    #[serde(alias = "modelName", alias = "modelCode")]
+    pub model: String,
+    #[serde(default, alias = "modelCallCount", alias = "callCount")]
+    pub calls: u64,
+    #[serde(default, alias = "tokensUsage", alias = "totalTokens")]
+    pub tokens: u64,
+}
+
+/// One model of `zai_get_model_usage`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ZaiModelUsage {
+    /// `glm-4.6`, `glm-4.5v`, ...
+    pub model: String,
+    pub calls: u64,
+    pub tokens: u64,
+    /// Percent of all tokens in the range.
+    pub token_share: f64,
+}
+
+/// Returned by `zai_get_model_usage`; `since`/`until` are the epoch millis asked for.
+#[derive(Debug, Clone, Serialize)]
+pub struct ZaiModelUsageReport {
+    pub since: i64,
+    pub until: i64,
+    /// Most tokens first.
+    pub models: Vec<ZaiModelUsage>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ZaiBalanceResponse {
+    pub data: ZaiBalanceReport,
+}
+
+/// Amounts come as numbers or numeric strings.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZaiBalanceReport {
+    #[serde(deserialize_with = "deserialize_f64_from_number_or_string")]
+    pub balance: f64,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_f64_from_number_or_string"
+    )]
+    pub recharge_amount: Option<f64>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_f64_from_number_or_string"
+    )]
+    pub give_amount: Option<f64>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_optional_f64_from_number_or_string"
+    )]
+    pub total_spend_amount: Option<f64>,
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+/// Z.ai pay-as-you-go account, returned by `zai_get_balance`; API calls outside the coding
+/// plan are paid from it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ZaiBalanceData {
+    /// Money left, in `currency`.
+    pub balance: f64,
+    /// Total topped up.
+    pub recharged: Option<f64>,
+    /// Credit granted by Z.ai (vouchers, promotions).
+    pub granted: Option<f64>,
+    /// Total spent since the account was opened.
+    pub spent: Option<f64>,
+    /// `USD` unless the report names another.
+    pub currency: String,
+}
+
+impl From<ZaiBalanceReport> for ZaiBalanceData {
+    fn from(report: ZaiBalanceReport) -> Self {
+        Self {
+            balance: report.balance,
+            recharged: report.recharge_amount,
+            granted: report.give_amount,
+            spent: report.total_spend_amount,
+            currency: report
+                .currency
+                .filter(|currency| !currency.is_empty())
+                .unwrap_or_else(|| "USD".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub percentage: f64,
+    pub resets_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpUsage {
+    pub percentage: f64,
+    pub used: i32,
+    pub total: i32,
+}
+
+/// The Amp account a session cookie signs in to, returned by `amp_validate_session_cookie`.
+/// Either field is `None` when the settings data doesn't show it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AmpAccount {
+    pub email: Option<String>,
+    /// The team workspace the session is in, if any.
+    pub team: Option<String>,
+}
+
+/// Where `amp_import_cookie_from_browser` found the session cookie it saved.
+#[derive(Debug, Clone, Serialize)]
+pub struct AmpCookieImport {
+    pub browser: String,
+    /// Browser profile folder, e.g. `Default` or `Profile 1`.
+    pub profile: String,
+    pub account: AmpAccount,
+}
+
+/// How Amp requests authenticate, the `amp_auth` setting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmpAuth {
+    /// The `session` cookie copied from the browser.
+    #[default]
+    Cookie,
+    /// An API key from ampcode.com settings (what the Amp CLI uses).
+    ApiKey,
+}
+
+/// Whether the saved Amp session cookie still works, from recent fetches and its age.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmpSessionState {
+    #[default]
+    Ok,
+    /// Close to the assumed session lifetime, or rejected once.
+    Expiring,
+    /// Rejected by consecutive fetches.
+    Expired,
+}
+
+/// Returned by `amp_get_session_status` and emitted as `amp-session-changed` when `state`
+/// changes. Cookie auth only; with an API key the state stays `ok`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AmpSessionStatus {
+    pub state: AmpSessionState,
+    /// Epoch millis at which the cookie was saved; `None` if saved by an older build.
+    pub saved_at: Option<i64>,
+    /// When the cookie is expected to stop working, `saved_at` plus the assumed lifetime.
+    pub expires_at: Option<i64>,
+    pub consecutive_auth_failures: u32,
+    /// `session_expiring` or `session_expired`, for the tray to prompt with.
+    pub error: Option<UsageError>,
+}
+
+/// Places Claude Code may keep its OAuth credentials, in the order
+/// `settings.json` lists them under `claude_credential_sources`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaudeCredentialSource {
+    /// `%CLAUDE_CONFIG_DIR%`, when Claude Code is pointed at a custom config directory.
+    ConfigDir,
+    /// `%USERPROFILE%\.claude`, the default Claude Code location.
+    Home,
+    /// `~/.claude` inside a WSL distro, for Claude Code installed under Linux.
+    Wsl,
+}
+
+impl ClaudeCredentialSource {
+    pub const DEFAULT_PRIORITY: [ClaudeCredentialSource; 3] = [
+        ClaudeCredentialSource::ConfigDir,
+        ClaudeCredentialSource::Home,
+        ClaudeCredentialSource::Wsl,
+    ];
+}
+
+/// What a planned run is expected to consume, for `can_i_run`.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunEstimate {
+    /// Percentage points of every usage window.
+    Percent(f64),
+    /// Dollars, checked against windows with a spend budget (Amp quota, Claude extra usage).
+    Cost(f64),
+}
+
+/// Whether one provider has enough headroom for a `RunEstimate`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderHeadroom {
+    pub provider: Provider,
+    pub allowed: bool,
+    /// The window with the least headroom, if the provider has any usage data.
+    pub limiting_window: Option<String>,
+    /// Headroom left in `limiting_window`, in the estimate's unit.
+    pub remaining: Option<f64>,
+    pub reason: String,
+}
+
+/// Where the Claude 5-hour window is heading at the current pace. Returned by
+/// `claude_get_forecast`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BurnForecast {
+    /// Latest 5-hour utilization; `None` with no recent samples.
+    pub utilization: Option<f64>,
+    /// Percentage points per hour over this window's samples; `None` until they span a few
+    /// minutes.
+    pub burn_rate_per_hour: Option<f64>,
+    /// Epoch millis at which the window reaches 100% at this rate; `None` if usage isn't rising.
+    pub limit_at: Option<i64>,
+    /// Epoch millis.
+    pub resets_at: Option<i64>,
+    /// Utilization at the reset if the rate holds.
+    pub projected_at_reset: Option<f64>,
+    /// True if the limit comes before the reset.
+    pub will_hit_limit: bool,
+    /// Samples in this window the rate is based on.
+    pub samples: usize,
+}
+
+/// Where the Z.ai prompt allowance (`TIME_LIMIT`) is heading at the current pace. Returned
+/// by `zai_get_forecast`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ZaiPromptForecast {
+    /// Latest prompt count and allowance; `None` with no recent samples.
+    pub used: Option<i32>,
+    pub total: Option<i32>,
+    /// Prompts per hour over this window's samples; `None` until they span a few minutes.
+    pub prompts_per_hour: Option<f64>,
+    /// Epoch millis at which the allowance runs out at this rate; `None` if usage isn't rising.
+    pub limit_at: Option<i64>,
+    /// Epoch millis.
+    pub resets_at: Option<i64>,
+    /// Prompts used by the reset if the rate holds.
+    pub projected_at_reset: Option<f64>,
+    /// True if the allowance runs out before the reset.
+    pub will_hit_limit: bool,
+    /// Samples in this window the rate is based on.
+    pub samples: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HeadroomCheck {
+    /// True if at least one of the checked providers has enough headroom.
+    pub allowed: bool,
+    pub providers: Vec<ProviderHeadroom>,
+}
+
+/// One Claude login found across the credential sources.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudeAccount {
+    pub source: ClaudeCredentialSource,
+    /// The login `claude_get_usage` and the popup use.
+    pub active: bool,
+    pub organization_uuid: Option<String>,
+    pub organization_name: Option<String>,
+}
+
+/// One login's share of `claude_get_accounts_usage`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountUsage {
+    #[serde(flatten)]
+    pub account: ClaudeAccount,
+    pub usage: Option<UsageData>,
+    pub error: Option<UsageError>,
+}
+
+/// Utilization across every login that returned usage.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CombinedUsage {
+    pub accounts: usize,
+    pub five_hour_average: f64,
+    pub five_hour_max: f64,
+    pub seven_day_average: f64,
+    pub seven_day_max: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AggregateUsage {
+    /// `None` when no login returned usage.
+    pub combined: Option<CombinedUsage>,
+    pub accounts: Vec<AccountUsage>,
+}
+
+/// Extra usage numbers returned by `claude_get_extra_usage`, in dollars, so the popup can
+/// confirm before `claude_open_billing` leaves the app.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtraUsageContext {
+    pub enabled: bool,
+    pub used: Option<f64>,
+    pub monthly_limit: Option<f64>,
+    pub utilization: Option<f64>,
+    pub resets_at: Option<i64>,
+    /// The `claude_extra_usage_budget` setting and the percent of it spent.
+    pub budget: Option<f64>,
+    pub budget_utilization: Option<f64>,
+    /// Page `claude_open_billing` opens.
+    pub billing_url: &'static str,
+}
+
+impl ExtraUsageContext {
+    pub const BILLING_URL: &'static str = "https://claude.ai/settings/billing";
+
+    pub fn from_usage(usage: &UsageData, budget: Option<f64>) -> Self {
+        // The API reports extra usage amounts in cents.
+        Self {
+            enabled: usage.extra_usage_enabled,
+            used: usage.extra_usage_used_credits.map(|cents| cents / 100.0),
+            monthly_limit: usage.extra_usage_monthly_limit.map(|cents| cents / 100.0),
+            utilization: usage.extra_usage_utilization,
+            resets_at: usage.extra_usage_resets_at.map(ResetTime::epoch_millis),
+            budget,
+            budget_utilization: usage.extra_usage_budget_utilization,
+            billing_url: Self::BILLING_URL,
+        }
+    }
+}
+
+/// One entry of `get_provider_health`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderHealth {
+    pub provider: Provider,
+    /// Seconds since the cached usage was fetched; `None` before the first fetch.
+    pub fetched_secs_ago: Option<u64>,
+    pub rate_limited_until: Option<i64>,
+    /// Requests left per the provider's rate-limit headers, for providers that send them.
+    pub request_quota: Option<RequestQuota>,
+}
+
+/// One entry of `get_credential_sources`: where a source points and whether it is in use.
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialSourceStatus {
+    pub source: ClaudeCredentialSource,
+    pub path: Option<String>,
+    pub found: bool,
+    pub active: bool,
+    pub reason: String,
+}
+
+/// A stored credential moved to its current target name or rewritten in the current format
+/// at startup.
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialMigration {
+    pub provider: Provider,
+    /// Target name the credential was read from.
+    pub from: String,
+    /// Target name it now lives under.
+    pub to: String,
+    /// The blob was UTF-16 (as written by `cmdkey` or PowerShell) and was re-encoded as UTF-8.
+    pub reencoded: bool,
+    /// Why the migration failed; the old credential is left untouched when set.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaudeOAuthCredentials {
+    #[serde(rename = "claudeAiOauth")]
+    pub claude_ai_oauth: ClaudeOAuth,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaudeOAuth {
+    #[serde(rename = "accessToken")]
+    pub access_token: String,
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: String,
+    #[serde(rename = "expiresAt")]
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_expires_at")]
+    pub expires_at: Option<i64>,
+    #[serde(rename = "subscriptionType", default)]
+    pub subscription_type: Option<String>,
+    #[serde(rename = "rateLimitTier", default)]
+    pub rate_limit_tier: Option<String>,
+}
+
+fn deserialize_expires_at<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::{self, Deserialize};
+    use serde_json::Value;
+
+    let value = Value::deserialize(deserializer)?;
+    match value {
+        Value::Null => Ok(None),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Ok(Some(i))
+            } else if let Some(f) = n.as_f64() {
+                Ok(Some(f as i64))
+            } else {
+                Err(de::Error::custom("invalid number for expires_at"))
+            }
+        }
+        _ => Err(de::Error::custom("expected number or null for expires_at")),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenRefreshResponse {
+    #[serde(rename = "access_token")]
+    pub access_token: String,
+    #[serde(rename = "refresh_token")]
+    pub refresh_token: String,
+    #[serde(rename = "expires_in")]
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaudeTierData {
+    pub plan_name: String,
+    pub rate_limit_tier: String,
+    /// Organization of the Claude Code login in use, when its `.claude.json` names one.
+    #[serde(default)]
+    pub organization_name: Option<String>,
+}
+
+/// Response of the OAuth profile endpoint; only the fields `ClaudeProfile` needs are read.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClaudeProfileResponse {
+    #[serde(default)]
+    pub account: Option<ClaudeProfileAccount>,
+    #[serde(default)]
+    pub organization: Option<ClaudeProfileOrganization>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClaudeProfileAccount {
+    #[serde(default, alias = "email_address")]
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClaudeProfileOrganization {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// `claude_max`, `claude_pro`, `claude_team`, ...
+    #[serde(default)]
+    pub organization_type: Option<String>,
+}
+
+/// Which account the Claude usage belongs to, returned by `claude_get_profile`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClaudeProfile {
+    pub email: Option<String>,
+    pub organization_name: Option<String>,
+    /// `max`, `pro`, `team`, ...: the organization's type, or the credentials file's
+    /// `subscriptionType` when the profile has none.
+    pub subscription_type: Option<String>,
+}
+
+impl ClaudeProfile {
+    pub fn from_response(
+        response: ClaudeProfileResponse,
+        credentials_subscription: Option<String>,
+    ) -> Self {
+        let organization = response.organization;
+        let organization_type = organization
+            .as_ref()
+            .and_then(|org| org.organization_type.as_deref())
+            .map(|kind| kind.strip_prefix("claude_").unwrap_or(kind).to_string());
+        Self {
+            email: response.account.and_then(|account| account.email),
+            organization_name: organization.and_then(|org| org.name),
+            subscription_type: organization_type
+                .or(credentials_subscription)
+                .filter(|kind| !kind.is_empty()),
+        }
+    }
+}
+
+/// Result of `claude_reauth`: whether the Claude login works, and if not the login started
+/// to fix it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClaudeReauth {
+    pub status: ClaudeAuthStatus,
+    /// Why signing in again is needed: `credentials_not_found`, `auth_failed`,
+    /// `login_required`, ...
+    pub code: Option<ErrorCode>,
+    /// Authorize URL of the login waiting for its code; finish it with `claude_complete_login`.
+    pub login_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClaudeAuthStatus {
+    Ok,
+    NeedsLogin,
+}
+
+/// `oauthAccount` from Claude Code's `.claude.json`; only the organization fields are read.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClaudeGlobalConfig {
+    #[serde(rename = "oauthAccount")]
+    pub oauth_account: Option<ClaudeOAuthAccount>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaudeOAuthAccount {
+    pub organization_uuid: Option<String>,
+    pub organization_name: Option<String>,
+}
+
+/// One entry of `claude_get_organizations`: an organization some credential source is logged in to.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaudeOrganization {
+    pub uuid: String,
+    pub name: String,
+    pub source: ClaudeCredentialSource,
+    /// Whether usage is currently fetched with this organization's login.
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmpUsageData {
+    pub quota: f64,
+    pub used: f64,
+    /// Clamped to [0.0, 100.0]. If quota is 0, division yields infinity → clamped to 100.0.
+    pub used_percent: f64,
+    pub hourly_replenishment: f64,
+    /// Duration of the usage window in hours. Stored as f64 because the Amp JS object
+    /// may theoretically use fractional hours; use `as u32` when integer precision suffices.
+    pub window_hours: Option<f64>,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_expires_at")]
+    pub resets_at: Option<i64>,
+    /// `resets_at` was guessed from `window_hours` (aligned to the Unix epoch) because the
+    /// settings data had no reset time; rolling windows can reset earlier or later.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub resets_at_estimated: bool,
+    /// Paid credits, when the settings data has them (not from the HTML regex fallback).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<AmpBalance>,
+    /// The team workspace's shared credits, when the session is in one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<AmpTeamUsageData>,
+    /// When and from what this was read; `None` in samples written by older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<AmpFetchMeta>,
+    /// The dollar values above as `Money`, formatted for `currency_locale` when parsed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub money: Option<AmpMoney>,
+}
+
+/// `AmpUsageData`'s dollar values for display. Fields are `None` where the raw value is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AmpMoney {
+    pub quota: Money,
+    pub used: Money,
+    /// `quota - used`, at least zero.
+    pub remaining: Money,
+    pub hourly_replenishment: Money,
+    pub balance: Option<Money>,
+    pub recent_spend: Option<Money>,
+    pub team_quota: Option<Money>,
+    pub team_used: Option<Money>,
+    pub team_balance: Option<Money>,
+}
+
+/// An amount in integer minor units (cents for USD) with its ISO 4217 code, and the same
+/// amount formatted for the `currency_locale` setting, e.g. `1.234,56 US$`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Money {
+    pub currency: String,
+    pub minor_units: i64,
+    pub formatted: String,
+}
+
+/// How `Money::formatted` names the currency, the `currency_display` setting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CurrencyDisplay {
+    /// `$` in `en-US`, `US$` elsewhere so it isn't read as a local dollar.
+    #[default]
+    Symbol,
+    /// `USD`.
+    Code,
+}
+
+/// Where Amp usage came from. Amp has no public usage API, so this tells a wrong number
+/// from a parser that no longer matches the page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AmpFetchMeta {
+    /// Epoch millis at which the response was parsed.
+    pub fetched_at: i64,
+    pub source: AmpSource,
+    /// `AmpService::PARSER_VERSION` of the build that parsed it.
+    pub parser_version: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmpSource {
+    /// `/settings/__data.json`, the SvelteKit loader data.
+    LoaderData,
+    /// JSON embedded in the settings page.
+    EmbeddedJson,
+    /// The `freeTierUsage` JS object regex-scraped from the page.
+    HtmlScrape,
+    /// The internal API, with an API key.
+    ApiKey,
+}
+
+/// Team-level Amp credits, in dollars, returned by `amp_get_team_usage`. Fields the settings
+/// data doesn't show are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AmpTeamUsageData {
+    pub name: Option<String>,
+    pub quota: Option<f64>,
+    pub used: Option<f64>,
+    /// `used` of `quota`, clamped to [0.0, 100.0]; `None` without both.
+    pub used_percent: Option<f64>,
+    pub balance: Option<f64>,
+    pub recent_spend: Option<f64>,
+    pub members: Option<u32>,
+}
+
+/// Amp's paid (non-free-tier) credits, in dollars.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AmpBalance {
+    pub balance: f64,
+    /// Paid credits spent recently, over whatever period Amp reports.
+    pub recent_spend: Option<f64>,
+    pub auto_top_up: Option<AmpAutoTopUp>,
+}
+
+/// Buys `amount` of credits whenever the balance falls below `threshold`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AmpAutoTopUp {
+    pub enabled: bool,
+    pub threshold: Option<f64>,
+    pub amount: Option<f64>,
+}
+
+/// One page of an Admin API report (`cost_report`, `usage_report/messages`): daily buckets,
+/// each with one result per group. Reports are requested ungrouped, so one per bucket.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnthropicReportPage<T> {
+    pub data: Vec<AnthropicReportBucket<T>>,
+    #[serde(default)]
+    pub has_more: bool,
+    #[serde(default)]
+    pub next_page: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnthropicReportBucket<T> {
+    pub results: Vec<T>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnthropicCostResult {
+    /// Decimal string in the currency's lowest unit (cents for USD), e.g. `"1234.5"`.
+    pub amount: String,
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AnthropicMessagesUsage {
+    pub uncached_input_tokens: u64,
+    pub cache_creation: AnthropicCacheCreation,
+    pub cache_read_input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AnthropicCacheCreation {
+    pub ephemeral_1h_input_tokens: u64,
+    pub ephemeral_5m_input_tokens: u64,
+}
+
+/// Month-to-date Anthropic API usage for the organization the Admin API key belongs to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnthropicUsageData {
+    /// Spend since `period_start` in `currency` (dollars, not cents).
+    pub month_cost: f64,
+    pub currency: String,
+    /// Input tokens that were neither written to nor read from the prompt cache.
+    pub input_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+    pub output_tokens: u64,
+    /// Epoch millis of the start of the current month (UTC).
+    pub period_start: i64,
+    /// Epoch millis at which the next month, and new totals, begin.
+    pub resets_at: i64,
+}
+
+/// A Google OAuth `authorized_user` credential, as written by
+/// `gcloud auth application-default login`. Exchanged for an access token on each fetch.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GoogleAuthorizedUser {
+    pub client_id: String,
+    pub client_secret: String,
+    pub refresh_token: String,
+    /// Project billed for quota; used when neither `gemini_project_id` nor the API key
+    /// names one.
+    #[serde(default)]
+    pub quota_project_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GoogleTokenResponse {
+    pub access_token: String,
+    /// Seconds until `access_token` expires.
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+}
+
+/// Response of the API Keys `keys:lookupKey` method; `parent` is `projects/<number>/locations/global`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GoogleKeyLookup {
+    pub parent: String,
+}
+
+/// One page of Cloud Monitoring `projects.timeSeries.list`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MonitoringTimeSeriesList {
+    pub time_series: Vec<MonitoringTimeSeries>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MonitoringTimeSeries {
+    pub metric: MonitoringMetric,
+    /// Newest first.
+    pub points: Vec<MonitoringPoint>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MonitoringMetric {
+    /// E.g. `quota_metric` and, for limits, `limit_name`.
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MonitoringPoint {
+    pub value: MonitoringValue,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MonitoringValue {
+    /// Int64 values are JSON strings, e.g. `"1500"`.
+    pub int64_value: Option<String>,
+    pub double_value: Option<f64>,
+}
+
+/// Requests of the model closest to its limit in one Gemini API rate-limit window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeminiQuotaWindow {
+    pub requests: u64,
+    /// That model's request limit for this window; `None` when Cloud Monitoring reported
+    /// none, e.g. on a project that never sent a request.
+    pub limit: Option<u64>,
+    pub used_percent: Option<f64>,
+    /// E.g. `gemini-2.5-pro`; `None` for quotas that aren't per model.
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Gemini API request quota consumption of one Google Cloud project. Each model is
+/// measured against its own limits; each window reports the model closest to its limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeminiUsageData {
+    pub project_id: String,
+    /// Requests since midnight Pacific time, when daily quotas reset.
+    pub per_day: GeminiQuotaWindow,
+    /// Requests in the last minute. Cloud Monitoring lags a few minutes behind.
+    pub per_minute: GeminiQuotaWindow,
+    /// Epoch millis of the last midnight Pacific time.
+    pub period_start: i64,
+    /// Epoch millis of the next midnight Pacific time.
+    pub resets_at: i64,
+}
+
+/// One line of a Claude Code transcript (`~/.claude/projects/<project>/<session>.jsonl`).
+/// Only assistant messages carry `message.usage`; every other line is skipped.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TranscriptLine {
+    pub timestamp: Option<String>,
+    /// Working directory of the session, i.e. the project.
+    #[serde(default)]
+    pub cwd: Option<String>,
+    #[serde(rename = "requestId", default)]
+    pub request_id: Option<String>,
+    /// Cost Claude Code worked out itself; only some versions write it.
+    #[serde(rename = "costUSD", default)]
+    pub cost_usd: Option<f64>,
+    pub message: Option<TranscriptMessage>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TranscriptMessage {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    pub usage: Option<TranscriptUsage>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TranscriptUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+}
+
+impl TranscriptUsage {
+    pub fn add(&mut self, other: &TranscriptUsage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.input_tokens
+            + self.output_tokens
+            + self.cache_creation_input_tokens
+            + self.cache_read_input_tokens
+    }
+}
+
+/// Time range for the local usage commands and `zai_get_model_usage`, in epoch millis.
+/// `from` defaults to local midnight today and `to` to no end.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct DateRange {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+/// Order of `claude_get_project_usage`'s projects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectSort {
+    /// Most expensive first.
+    #[default]
+    Cost,
+    /// Most tokens first.
+    Tokens,
+    /// Project path, A to Z.
+    Name,
+}
+
+/// Tokens and estimated cost of one project (session working directory) in Claude Code's
+/// local transcripts.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectUsage {
+    pub project: String,
+    #[serde(flatten)]
+    pub tokens: TranscriptUsage,
+    /// Dollars for the priced models; `None` if none of the project's models has a price.
+    pub cost: Option<f64>,
+    /// Responses counted.
+    pub requests: u64,
+}
+
+/// Returned by `claude_get_project_usage`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectUsageReport {
+    pub since: i64,
+    pub until: Option<i64>,
+    pub projects: Vec<ProjectUsage>,
+}
+
+/// Tokens and estimated cost of one model in Claude Code's local transcripts.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelUsage {
+    pub model: String,
+    /// `opus`, `sonnet` or `haiku`; `None` for other models.
+    pub family: Option<&'static str>,
+    #[serde(flatten)]
+    pub tokens: TranscriptUsage,
+    /// Dollars at API list prices; `None` for a model without a known price.
+    pub cost: Option<f64>,
+    /// Responses counted.
+    pub requests: u64,
+}
+
+/// Claude Code usage on this machine from `since` to `until`, read from its transcripts.
+/// Returned by `claude_get_local_usage` (today) and `claude_get_model_breakdown`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalUsage {
+    /// Epoch millis; local midnight today unless a range was given.
+    pub since: i64,
+    pub until: Option<i64>,
+    #[serde(flatten)]
+    pub tokens: TranscriptUsage,
+    /// Sum of the priced models' costs.
+    pub cost: f64,
+    /// Most expensive first.
+    pub models: Vec<ModelUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodexUsageData {
+    pub session_usage: Option<CodexWindowUsage>,
+    pub weekly_usage: Option<CodexWindowUsage>,
+    pub credits: Option<CodexCredits>,
+    pub tier_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodexTierData {
+    pub plan_name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodexWindowUsage {
+    pub percentage: f64,
+    /// Epoch milliseconds.
+    pub resets_at: Option<i64>,
+    pub window_seconds: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodexCredits {
+    pub has_credits: bool,
+    pub unlimited: bool,
+    pub balance: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodexAuthFile {
+    #[serde(rename = "OPENAI_API_KEY", default)]
+    pub openai_api_key: Option<String>,
+    #[serde(default)]
+    pub tokens: Option<CodexAuthTokens>,
+    #[serde(default)]
+    pub last_refresh: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodexAuthTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub id_token: Option<String>,
+    #[serde(default)]
+    pub account_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodexUsageResponse {
+    #[serde(default)]
+    pub plan_type: Option<String>,
+    #[serde(default)]
+    pub rate_limit: Option<CodexRateLimitDetails>,
+    #[serde(default)]
+    pub credits: Option<CodexUsageCredits>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodexRateLimitDetails {
+    #[serde(default)]
+    pub primary_window: Option<CodexUsageWindow>,
+    #[serde(default)]
+    pub secondary_window: Option<CodexUsageWindow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodexUsageWindow {
+    #[serde(deserialize_with = "deserialize_f64_from_number_or_string")]
+    pub used_percent: f64,
+    #[serde(deserialize_with = "deserialize_i64_from_number_or_string")]
+    pub reset_at: i64,
+    #[serde(deserialize_with = "deserialize_i64_from_number_or_string")]
+    pub limit_window_seconds: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodexUsageCredits {
+    #[serde(default)]
+    pub has_credits: bool,
+    #[serde(default)]
+    pub unlimited: bool,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_optional_f64_from_number_or_string")]
+    pub balance: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodexRefreshResponse {
+    pub access_token: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub id_token: Option<String>,
+}
+
+/// One usage window of a `UsageSnapshot`, named like the alert windows (`seven_day`, `mcp`).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SnapshotWindow {
+    pub name: &'static str,
+    pub percentage: f64,
+    /// Epoch millis.
+    pub resets_at: Option<i64>,
+}
+
+/// The same view of every provider's usage, so the popup can render any provider without
+/// knowing its response shape. Returned by `get_all_snapshots`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageSnapshot {
+    pub provider: Provider,
+    /// The window that runs out first in practice: Claude and Codex 5-hour, Z.ai tokens,
+    /// Amp credits, Gemini requests per day.
+    pub primary_percentage: f64,
+    /// When the primary window resets, in epoch millis.
+    pub resets_at: Option<i64>,
+    pub secondary_windows: Vec<SnapshotWindow>,
+    pub plan_name: Option<String>,
+    /// Epoch millis at which the data was fetched from the provider.
+    pub fetched_at: i64,
+}
+
+impl UsageSnapshot {
+    pub fn claude(usage: &UsageData, tier: Option<&ClaudeTierData>, fetched_at: i64) -> Self {
+        let mut secondary_windows = vec![SnapshotWindow {
+            name: "seven_day",
+            percentage: usage.seven_day_utilization,
+            resets_at: usage.seven_day_resets_at.map(ResetTime::epoch_millis),
+        }];
+        if let Some(opus) = usage.seven_day_opus_utilization {
+            secondary_windows.push(SnapshotWindow {
+                name: "seven_day_opus",
+                percentage: opus,
+                resets_at: usage.seven_day_opus_resets_at.map(ResetTime::epoch_millis),
+            });
+        }
+        if let Some(extra) = usage
+            .extra_usage_utilization
+            .filter(|_| usage.extra_usage_enabled)
+        {
+            secondary_windows.push(SnapshotWindow {
+                name: "extra_usage",
+                percentage: extra,
+                resets_at: None,
+            });
+        }
+        Self {
+            provider: Provider::Claude,
+            primary_percentage: usage.five_hour_utilization,
+            resets_at: usage.five_hour_resets_at.map(ResetTime::epoch_millis),
+            secondary_windows,
+            plan_name: tier.map(|t| t.plan_name.clone()),
+            fetched_at,
+        }
+    }
+
+    /// `None` when Codex reported neither window.
+    pub fn codex(usage: &CodexUsageData, fetched_at: i64) -> Option<Self> {
+        let mut windows = [
+            ("session", usage.session_usage.as_ref()),
+            ("weekly", usage.weekly_usage.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, window)| {
+            window.map(|w| SnapshotWindow {
+                name,
+                percentage: w.percentage,
+                resets_at: w.resets_at,
+            })
+        });
+        let primary = windows.next()?;
+        Some(Self {
+            provider: Provider::Codex,
+            primary_percentage: primary.percentage,
+            resets_at: primary.resets_at,
+            secondary_windows: windows.collect(),
+            plan_name: usage.tier_name.clone(),
+            fetched_at,
+        })
+    }
+
+    /// `None` when Z.ai reported no token window.
+    pub fn zai(usage: &ZaiUsageData, fetched_at: i64) -> Option<Self> {
+        let tokens = usage.token_usage.as_ref()?;
+        Some(Self {
+            provider: Provider::Zai,
+            primary_percentage: tokens.percentage,
+            resets_at: tokens.resets_at,
+            secondary_windows: usage
+                .mcp_usage
+                .iter()
+                .map(|mcp| SnapshotWindow {
+                    name: "mcp",
+                    percentage: mcp.percentage,
+                    resets_at: None,
+                })
+                .collect(),
+            plan_name: usage.tier_name.clone(),
+            fetched_at,
+        })
+    }
+
+    /// `None` until Cloud Monitoring reports a daily request limit.
+    pub fn gemini(usage: &GeminiUsageData, fetched_at: i64) -> Option<Self> {
+        let primary_percentage = usage.per_day.used_percent?;
+        Some(Self {
+            provider: Provider::Gemini,
+            primary_percentage,
+            resets_at: Some(usage.resets_at),
+            secondary_windows: usage
+                .per_minute
+                .used_percent
+                .iter()
+                .map(|&percentage| SnapshotWindow {
+                    name: "minute",
+                    percentage,
+                    resets_at: None,
+                })
+                .collect(),
+            plan_name: None,
+            fetched_at,
+        })
+    }
+
+    pub fn amp(usage: &AmpUsageData, fetched_at: i64) -> Self {
+        Self {
+            provider: Provider::Amp,
+            primary_percentage: usage.used_percent,
+            resets_at: usage.resets_at,
+            secondary_windows: Vec::new(),
+            plan_name: None,
+            fetched_at,
+        }
+    }
+}
+
+fn deserialize_f64_from_number_or_string<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::{self, Deserialize};
+    use serde_json::Value;
+
+    match Value::deserialize(deserializer)? {
+        Value::Number(number) => number
+            .as_f64()
+            .ok_or_else(|| de::Error::custom("invalid number for f64")),
+        Value::String(value) => value
+            .parse::<f64>()
+            .map_err(|_| de::Error::custom("invalid string for f64")),
+        _ => Err(de::Error::custom("expected number or numeric string")),
+    }
+}
+
+fn deserialize_i64_from_number_or_string<'de, D>(deserializer: D) -> Result<i64, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::{self, Deserialize};
+    use serde_json::Value;
+
+    match Value::deserialize(deserializer)? {
+        Value::Number(number) => number
+            .as_i64()
+            .or_else(|| number.as_f64().map(|value| value as i64))
+            .ok_or_else(|| de::Error::custom("invalid number for i64")),
+        Value::String(value) => value
+            .parse::<i64>()
+            .or_else(|_| value.parse::<f64>().map(|value| value as i64))
+            .map_err(|_| de::Error::custom("invalid string for i64")),
+        _ => Err(de::Error::custom("expected number or numeric string")),
+    }
+}
+
+fn deserialize_optional_f64_from_number_or_string<'de, D>(
+    deserializer: D,
+) -> Result<Option<f64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::{self, Deserialize};
+    use serde_json::Value;
+
+    match Value::deserialize(deserializer)? {
+        Value::Null => Ok(None),
+        Value::Number(number) => number
+            .as_f64()
+            .map(Some)
+            .ok_or_else(|| de::Error::custom("invalid number for optional f64")),
+        Value::String(value) if value.trim().is_empty() => Ok(None),
+        Value::String(value) => value
+            .parse::<f64>()
+            .map(Some)
+            .map_err(|_| de::Error::custom("invalid string for optional f64")),
+        _ => Err(de::Error::custom(
+            "expected number, numeric string, or null",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reset_time_parses_to_utc() {
+        let reset = ResetTime::parse("2025-01-01T02:00:00.000+02:00").unwrap();
+        assert_eq!(reset.epoch_millis(), 1_735_689_600_000);
+        assert_eq!(reset.to_string(), "2025-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn test_reset_time_rejects_malformed_values() {
+        assert!(ResetTime::parse("not a date").is_err());
+        assert!(ResetTime::parse("1970-01-01T00:00:00Z").is_err());
+    }
+
+    #[test]
+    fn test_usage_period_drops_malformed_reset() {
+        let period: UsagePeriod =
+            serde_json::from_str(r#"{"utilization": 12.5, "resets_at": "soon"}"#).unwrap();
+        assert_eq!(period.resets_at, None);
+
+        let period: UsagePeriod = serde_json::from_str(
+            r#"{"utilization": 12.5, "resets_at": "2025-01-01T00:00:00+00:00"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            period.resets_at.map(ResetTime::epoch_millis),
+            Some(1_735_689_600_000)
+        );
+    }
+
+    #[test]
+    fn test_reset_time_serializes_as_epoch_millis() {
+        let reset = ResetTime::parse("2025-01-01T00:00:00Z").unwrap();
+        assert_eq!(serde_json::to_string(&reset).unwrap(), "1735689600000");
+        // History written before the switch holds strings.
+        for stored in [r#""2025-01-01T00:00:00.000Z""#, "1735689600000"] {
+            assert_eq!(serde_json::from_str::<ResetTime>(stored).unwrap(), reset);
+        }
+        assert!(serde_json::from_str::<ResetTime>("0").is_err());
+    }
+
+    #[test]
+    fn test_claude_profile_from_response() {
+        let response: ClaudeProfileResponse = serde_json::from_str(
+            r#"{"account": {"email": "jane@example.com", "full_name": "Jane"},
+                "organization": {"name": "Acme", "organization_type": "claude_max"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            ClaudeProfile::from_response(response, Some("pro".into())),
+            ClaudeProfile {
+                email: Some("jane@example.com".into()),
+                organization_name: Some("Acme".into()),
+                subscription_type: Some("max".into()),
+            }
+        );
+
+        let response: ClaudeProfileResponse = serde_json::from_str("{}").unwrap();
+        let profile = ClaudeProfile::from_response(response, Some("pro".into()));
+        assert_eq!(profile.email, None);
+        assert_eq!(profile.subscription_type.as_deref(), Some("pro"));
+    }
+
+    #[test]
+    fn test_extra_usage_context_converts_cents() {
+        let usage = UsageData {
+            five_hour_utilization: 0.0,
+            five_hour_resets_at: None,
+            seven_day_utilization: 0.0,
+            seven_day_resets_at: None,
+            seven_day_opus_utilization: None,
+            seven_day_opus_resets_at: None,
+            extra_usage_enabled: true,
+            extra_usage_monthly_limit: Some(2500.0),
+            extra_usage_used_credits: Some(1840.0),
+            extra_usage_utilization: Some(73.6),
+            extra_usage_resets_at: None,
+            extra_usage_budget_utilization: Some(92.0),
+        };
+        let context = ExtraUsageContext::from_usage(&usage, Some(20.0));
+        assert_eq!(context.used, Some(18.4));
+        assert_eq!(context.monthly_limit, Some(25.0));
+        assert_eq!(context.budget, Some(20.0));
+        assert_eq!(context.budget_utilization, Some(92.0));
+    }
+
+    #[test]
+    fn test_snapshots_pick_primary_window() {
+        let codex = CodexUsageData {
+            session_usage: None,
+            weekly_usage: Some(CodexWindowUsage {
+                percentage: 40.0,
+                resets_at: Some(1_000),
+                window_seconds: Some(604_800),
+            }),
+            credits: None,
+            tier_name: Some("Plus".into()),
+        };
+        let snapshot = UsageSnapshot::codex(&codex, 5).unwrap();
+        assert_eq!(snapshot.primary_percentage, 40.0);
+        assert_eq!(snapshot.resets_at, Some(1_000));
+        assert!(snapshot.secondary_windows.is_empty());
+
+        let zai = ZaiUsageData {
+            token_usage: None,
+            mcp_usage: None,
+            tier_name: None,
+            limits: Vec::new(),
+        };
+        assert!(UsageSnapshot::zai(&zai, 5).is_none());
+    }
+
+    #[test]
+    fn test_zai_accounts_aggregate() {
+        let account = |percentage: Option<f64>| ZaiAccountUsage {
+            usage: Some(ZaiUsageData {
+                token_usage: percentage.map(|percentage| TokenUsage {
+                    percentage,
+                    resets_at: None,
+                }),
+                mcp_usage: None,
+                tier_name: None,
+                limits: Vec::new(),
+            }),
+            error: None,
+        };
+        let single =
+            ZaiAccountsUsage::new(BTreeMap::from([("default".into(), account(Some(40.0)))]));
+        assert_eq!(single.aggregate_percentage, None);
+
+        let accounts = ZaiAccountsUsage::new(BTreeMap::from([
+            ("default".into(), account(Some(40.0))),
+            ("team".into(), account(Some(80.0))),
+            ("empty".into(), account(None)),
+        ]));
+        assert_eq!(accounts.aggregate_percentage, Some(60.0));
+    }
+}
@@ -0,0 +1,205 @@
+//! Money amounts formatted for display. Providers report plain dollar floats; this turns
+//! them into `Money` (currency code, integer minor units and a display string) using the
+//! `currency_locale` and `currency_display` settings. Only the separators and symbol
+//! placement of common locales are known; anything else uses `en-US` separators.
+
+use crate::i18n;
+use crate::models::{CurrencyDisplay, Money};
+use std::sync::RwLock;
+
+/// Minor units per unit for the currencies providers bill in (all USD so far).
+const MINOR_PER_UNIT: f64 = 100.0;
+
+struct Format {
+    /// `None` follows the `language` setting.
+    locale: Option<String>,
+    display: CurrencyDisplay,
+}
+
+static FORMAT: RwLock<Format> = RwLock::new(Format {
+    locale: None,
+    display: CurrencyDisplay::Symbol,
+});
+
+/// How a locale writes `1234.5`: separators, and whether the currency goes first.
+struct LocaleStyle {
+    decimal: char,
+    group: &'static str,
+    currency_first: bool,
+    /// Space between the currency and the number.
+    spaced: bool,
+}
+
+const EN: LocaleStyle = LocaleStyle {
+    decimal: '.',
+    group: ",",
+    currency_first: true,
+    spaced: false,
+};
+const DE: LocaleStyle = LocaleStyle {
+    decimal: ',',
+    group: ".",
+    currency_first: false,
+    spaced: true,
+};
+const NL: LocaleStyle = LocaleStyle {
+    decimal: ',',
+    group: ".",
+    currency_first: true,
+    spaced: true,
+};
+const CH: LocaleStyle = LocaleStyle {
+    decimal: '.',
+    group: "\u{2019}",
+    currency_first: true,
+    spaced: true,
+};
+/// French groups with a narrow no-break space.
+const FR: LocaleStyle = LocaleStyle {
+    decimal: ',',
+    group: "\u{202f}",
+    currency_first: false,
+    spaced: true,
+};
+/// Nordic and most Slavic locales group with a no-break space.
+const SPACE_GROUPED: LocaleStyle = LocaleStyle {
+    decimal: ',',
+    group: "\u{a0}",
+    currency_first: false,
+    spaced: true,
+};
+
+pub fn set_format(locale: Option<String>, display: CurrencyDisplay) {
+    *FORMAT
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Format { locale, display };
+}
+
+/// `ll` or `ll-RR` (e.g. `de`, `en-GB`); checked by settings validation.
+pub fn is_valid_locale(locale: &str) -> bool {
+    let mut parts = locale.split('-');
+    let language = parts.next().unwrap_or_default();
+    let region = parts.next();
+    parts.next().is_none()
+        && (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && region.is_none_or(|r| {
+            (r.len() == 2 && r.chars().all(|c| c.is_ascii_alphabetic()))
+                || (r.len() == 3 && r.chars().all(|c| c.is_ascii_digit()))
+        })
+}
+
+/// `dollars` as `Money`, formatted with the configured locale.
+pub fn usd(dollars: f64) -> Money {
+    let minor_units = (dollars * MINOR_PER_UNIT).round() as i64;
+    let format = FORMAT
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let locale = match &format.locale {
+        Some(locale) => locale.clone(),
+        None => default_locale(&i18n::language()).to_string(),
+    };
+    Money {
+        currency: "USD".to_string(),
+        minor_units,
+        formatted: format_usd(minor_units, &locale, format.display),
+    }
+}
+
+/// The locale `currency_locale` defaults to for each `language`.
+fn default_locale(language: &str) -> &'static str {
+    match language {
+        "de" => "de-DE",
+        _ => "en-US",
+    }
+}
+
+fn style(locale: &str) -> &'static LocaleStyle {
+    let lower = locale.to_ascii_lowercase();
+    let (language, region) = lower.split_once('-').unwrap_or((&lower, ""));
+    match (language, region) {
+        ("de" | "it" | "fr" | "rm", "ch") | ("de", "li") => &CH,
+        ("nl", _) | ("pt", "br") => &NL,
+        ("de" | "es" | "it" | "pt" | "da" | "id" | "tr" | "el" | "ro" | "hr", _) => &DE,
+        ("fr", _) => &FR,
+        ("sv" | "nb" | "no" | "fi" | "pl" | "cs" | "sk" | "ru" | "uk" | "hu", _) => &SPACE_GROUPED,
+        _ => &EN,
+    }
+}
+
+/// `$` for US English, where it can't be taken for another dollar; `US$` elsewhere.
+fn usd_symbol(locale: &str) -> &'static str {
+    if locale.eq_ignore_ascii_case("en-US") || locale.eq_ignore_ascii_case("en") {
+        "$"
+    } else {
+        "US$"
+    }
+}
+
+fn format_usd(minor_units: i64, locale: &str, display: CurrencyDisplay) -> String {
+    let style = style(locale);
+    let (currency, spaced) = match display {
+        CurrencyDisplay::Symbol => (usd_symbol(locale), style.spaced),
+        CurrencyDisplay::Code => ("USD", true),
+    };
+    let number = group_number(minor_units.unsigned_abs(), style);
+    let sign = if minor_units < 0 { "-" } else { "" };
+    let space = if spaced { "\u{a0}" } else { "" };
+    if style.currency_first {
+        format!("{sign}{currency}{space}{number}")
+    } else {
+        format!("{sign}{number}{space}{currency}")
+    }
+}
+
+/// `123456` minor units as `1,234.56` in `style`.
+fn group_number(minor_units: u64, style: &LocaleStyle) -> String {
+    let units = (minor_units / 100).to_string();
+    let mut grouped = String::with_capacity(units.len() + units.len() / 3 * style.group.len());
+    for (i, digit) in units.chars().enumerate() {
+        if i > 0 && (units.len() - i).is_multiple_of(3) {
+            grouped.push_str(style.group);
+        }
+        grouped.push(digit);
+    }
+    format!("{grouped}{}{:02}", style.decimal, minor_units % 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_usd_per_locale() {
+        let symbol = CurrencyDisplay::Symbol;
+        assert_eq!(format_usd(123_456, "en-US", symbol), "$1,234.56");
+        assert_eq!(format_usd(123_456, "en-GB", symbol), "US$1,234.56");
+        assert_eq!(format_usd(123_456, "de-DE", symbol), "1.234,56\u{a0}US$");
+        assert_eq!(
+            format_usd(123_456, "de-CH", symbol),
+            "US$\u{a0}1\u{2019}234.56"
+        );
+        assert_eq!(format_usd(123_456, "nl-NL", symbol), "US$\u{a0}1.234,56");
+        assert_eq!(
+            format_usd(123_456, "fr-FR", symbol),
+            "1\u{202f}234,56\u{a0}US$"
+        );
+        assert_eq!(
+            format_usd(-5, "en-US", CurrencyDisplay::Code),
+            "-USD\u{a0}0.05"
+        );
+        assert_eq!(format_usd(100_000_000, "en-US", symbol), "$1,000,000.00");
+        assert_eq!(format_usd(99, "xx-YY", symbol), "US$0.99");
+    }
+
+    #[test]
+    fn test_is_valid_locale() {
+        assert!(is_valid_locale("de"));
+        assert!(is_valid_locale("en-GB"));
+        assert!(is_valid_locale("es-419"));
+        assert!(!is_valid_locale("en_US"));
+        assert!(!is_valid_locale("english"));
+        assert!(!is_valid_locale("en-GB-x"));
+        assert!(!is_valid_locale(""));
+    }
+}
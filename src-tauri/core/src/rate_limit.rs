@@ -0,0 +1,290 @@
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::clock::now_millis;
+use crate::debug_error;
+use crate::models::Provider;
+
+/// Backoff applied when a provider answers 429 without a usable `Retry-After` header.
+pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Upper bound on server-requested backoff so a bogus header can't silence a provider for days.
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// Error returned by services when a provider responds with HTTP 429.
+/// Commands downcast to this to start the provider's do-not-fetch window.
+#[derive(Debug, Clone)]
+pub struct RateLimited {
+    pub retry_after: Duration,
+    message: String,
+}
+
+impl RateLimited {
+    pub fn from_headers(headers: &HeaderMap, message: &str) -> Self {
+        Self::new(retry_after(headers), message)
+    }
+
+    pub fn new(retry_after: Duration, message: &str) -> Self {
+        Self {
+            retry_after,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Reset headers tried when a 429 has no usable `Retry-After`. Claude's OAuth endpoints send
+/// the unified (subscription) limits; the rest match `QUOTA_HEADER_NAMES`.
+const RESET_HEADER_NAMES: [&str; 5] = [
+    "anthropic-ratelimit-unified-reset",
+    "anthropic-ratelimit-requests-reset",
+    "anthropic-ratelimit-tokens-reset",
+    "x-ratelimit-reset-requests",
+    "x-ratelimit-reset",
+];
+
+/// Parses `Retry-After` as either delta-seconds or an HTTP-date, falling back to the first
+/// rate-limit reset header, clamped to `MAX_BACKOFF`.
+pub fn retry_after(headers: &HeaderMap) -> Duration {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+    };
+
+    let now_ms = now_millis();
+    let parsed = header(RETRY_AFTER.as_str())
+        .and_then(|value| {
+            value
+                .parse::<u64>()
+                .map(Duration::from_secs)
+                .ok()
+                .or_else(|| {
+                    let date = httpdate::parse_http_date(value).ok()?;
+                    let date_ms = date.duration_since(UNIX_EPOCH).ok()?.as_millis() as i64;
+                    Some(Duration::from_millis((date_ms - now_ms).max(0) as u64))
+                })
+        })
+        .or_else(|| {
+            RESET_HEADER_NAMES.iter().find_map(|name| {
+                let resets_at = parse_reset(header(name)?, now_ms)?;
+                Some(Duration::from_millis((resets_at - now_ms).max(0) as u64))
+            })
+        });
+
+    parsed.unwrap_or(DEFAULT_BACKOFF).min(MAX_BACKOFF)
+}
+
+/// Request quota advertised by a provider's `x-ratelimit-*` / `anthropic-ratelimit-*`
+/// response headers on its last response.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RequestQuota {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    /// Epoch millis at which `remaining` is restored.
+    pub resets_at: Option<i64>,
+    /// Epoch millis of the response the headers came from.
+    pub observed_at: i64,
+}
+
+/// Header prefixes in the order they are tried; Anthropic's own names win over the generic ones.
+const QUOTA_HEADER_NAMES: [[&str; 3]; 3] = [
+    [
+        "anthropic-ratelimit-requests-limit",
+        "anthropic-ratelimit-requests-remaining",
+        "anthropic-ratelimit-requests-reset",
+    ],
+    [
+        "x-ratelimit-limit-requests",
+        "x-ratelimit-remaining-requests",
+        "x-ratelimit-reset-requests",
+    ],
+    [
+        "x-ratelimit-limit",
+        "x-ratelimit-remaining",
+        "x-ratelimit-reset",
+    ],
+];
+
+static REQUEST_QUOTAS: LazyLock<Mutex<HashMap<Provider, RequestQuota>>> =
+    LazyLock::new(Default::default);
+
+/// Reads the request quota from `headers`, if the provider sends one.
+pub fn request_quota(headers: &HeaderMap, now_ms: i64) -> Option<RequestQuota> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+    };
+    QUOTA_HEADER_NAMES
+        .iter()
+        .find_map(|[limit, remaining, reset]| {
+            let limit = header(limit).and_then(|v| v.parse().ok());
+            let remaining = header(remaining).and_then(|v| v.parse().ok());
+            if limit.is_none() && remaining.is_none() {
+                return None;
+            }
+            Some(RequestQuota {
+                limit,
+                remaining,
+                resets_at: header(reset).and_then(|v| parse_reset(v, now_ms)),
+                observed_at: now_ms,
+            })
+        })
+}
+
+/// Reset values come as RFC 3339 (Anthropic), epoch seconds, delta seconds, or Go-style
+/// durations such as `6m0s` / `120ms` (OpenAI).
+fn parse_reset(value: &str, now_ms: i64) -> Option<i64> {
+    if value.is_empty() {
+        return None;
+    }
+    if let Ok(at) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(at.timestamp_millis());
+    }
+    if let Ok(secs) = value.parse::<f64>() {
+        // Anything past 2001 is an epoch timestamp rather than a delta.
+        return Some(if secs > 1e9 {
+            (secs * 1000.0) as i64
+        } else {
+            now_ms + (secs * 1000.0) as i64
+        });
+    }
+
+    let mut total_ms = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .filter(|&i| i > 0)?;
+        let (number, tail) = rest.split_at(split);
+        let number: f64 = number.parse().ok()?;
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        total_ms += number
+            * match unit {
+                "ms" => 1.0,
+                "s" => 1000.0,
+                "m" => 60_000.0,
+                "h" => 3_600_000.0,
+                _ => return None,
+            };
+        rest = tail;
+    }
+    Some(now_ms + total_ms as i64)
+}
+
+fn lock_quotas() -> std::sync::MutexGuard<'static, HashMap<Provider, RequestQuota>> {
+    REQUEST_QUOTAS.lock().unwrap_or_else(|poisoned| {
+        debug_error!("Request quota mutex poisoned, recovering...");
+        poisoned.into_inner()
+    })
+}
+
+/// Remembers the request quota from a provider response. Called by services for every
+/// response; responses without quota headers leave the previous value in place.
+pub fn record_quota(provider: Provider, headers: &HeaderMap) {
+    if let Some(quota) = request_quota(headers, now_millis()) {
+        lock_quotas().insert(provider, quota);
+    }
+}
+
+pub fn last_quota(provider: Provider) -> Option<RequestQuota> {
+    lock_quotas().get(&provider).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers_with(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_retry_after_seconds() {
+        assert_eq!(retry_after(&headers_with("120")), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn test_retry_after_missing_uses_default() {
+        assert_eq!(retry_after(&HeaderMap::new()), DEFAULT_BACKOFF);
+        assert_eq!(retry_after(&headers_with("soon")), DEFAULT_BACKOFF);
+    }
+
+    #[test]
+    fn test_retry_after_falls_back_to_reset_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-reset", HeaderValue::from_static("90"));
+        assert_eq!(retry_after(&headers), Duration::from_secs(90));
+
+        let reset = (now_millis() / 1000 + 300).to_string();
+        headers.insert(
+            "anthropic-ratelimit-unified-reset",
+            HeaderValue::from_str(&reset).unwrap(),
+        );
+        let wait = retry_after(&headers);
+        assert!(wait > Duration::from_secs(298) && wait <= Duration::from_secs(300));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("5"));
+        assert_eq!(retry_after(&headers), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_retry_after_is_clamped() {
+        assert_eq!(retry_after(&headers_with("999999")), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_request_quota_prefers_anthropic_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "anthropic-ratelimit-requests-remaining",
+            HeaderValue::from_static("42"),
+        );
+        headers.insert(
+            "anthropic-ratelimit-requests-reset",
+            HeaderValue::from_static("2025-01-01T00:00:00Z"),
+        );
+        headers.insert("x-ratelimit-remaining", HeaderValue::from_static("7"));
+        let quota = request_quota(&headers, 0).unwrap();
+        assert_eq!(quota.remaining, Some(42));
+        assert_eq!(quota.limit, None);
+        assert_eq!(quota.resets_at, Some(1_735_689_600_000));
+        assert_eq!(request_quota(&HeaderMap::new(), 0), None);
+    }
+
+    #[test]
+    fn test_parse_reset_formats() {
+        assert_eq!(parse_reset("30", 1_000), Some(31_000));
+        assert_eq!(parse_reset("1735689600", 0), Some(1_735_689_600_000));
+        assert_eq!(parse_reset("6m0s", 0), Some(360_000));
+        assert_eq!(parse_reset("1.5s", 0), Some(1_500));
+        assert_eq!(parse_reset("120ms", 0), Some(120));
+        assert_eq!(parse_reset("soon", 0), None);
+    }
+
+    #[test]
+    fn test_retry_after_past_http_date_is_zero() {
+        let headers = headers_with("Wed, 21 Oct 2015 07:28:00 GMT");
+        assert_eq!(retry_after(&headers), Duration::ZERO);
+    }
+}
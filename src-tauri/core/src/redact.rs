@@ -0,0 +1,114 @@
+use regex::Regex;
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::LazyLock;
+
+const PLACEHOLDER: &str = "***REDACTED***";
+
+/// Wraps a secret so it can be passed to the debug macros without ever printing it.
+/// `Display` and `Debug` both write a placeholder.
+pub struct Redacted<T>(pub T);
+
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(PLACEHOLDER)
+    }
+}
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(PLACEHOLDER)
+    }
+}
+
+/// Patterns for secrets that may slip into a message unwrapped (e.g. inside an API error body),
+/// each with its replacement.
+static PATTERNS: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    [
+        // Authorization headers
+        (r"(?i)\b(bearer)\s+[A-Za-z0-9._~+/=-]+", "$1 ***REDACTED***"),
+        // JWTs
+        (
+            r"\beyJ[A-Za-z0-9_-]+\.[A-Za-z0-9_-]+\.[A-Za-z0-9_-]*",
+            PLACEHOLDER,
+        ),
+        // Anthropic / OpenAI style keys
+        (r"\bsk-[A-Za-z0-9_-]{8,}", PLACEHOLDER),
+        // Cookies and token fields in headers, query strings and JSON
+        (
+            r#"(?i)("?\b(?:session|cookie|access_token|refresh_token|id_token|api_key|apikey|token|password)"?\s*[:=]\s*)"?[^"\s,;&}]+"?"#,
+            "$1***REDACTED***",
+        ),
+        // Email addresses
+        (
+            r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+            "***@***",
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(pattern, replacement)| Regex::new(pattern).ok().map(|re| (re, replacement)))
+    .collect()
+});
+
+/// Strips bearer tokens, cookies, API keys and email addresses from `message`.
+/// Every debug macro runs its output through this.
+pub fn scrub(message: &str) -> Cow<'_, str> {
+    let mut scrubbed = Cow::Borrowed(message);
+    for (re, replacement) in PATTERNS.iter() {
+        if let Cow::Owned(replaced) = re.replace_all(&scrubbed, *replacement) {
+            scrubbed = Cow::Owned(replaced);
+        }
+    }
+    scrubbed
+}
+
+static RE_UUID: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b").unwrap()
+});
+
+/// `scrub` plus UUIDs (user, team and organization IDs), for provider payloads that are
+/// written to disk and may end up attached to a bug report.
+pub fn scrub_payload(payload: &str) -> String {
+    RE_UUID
+        .replace_all(&scrub(payload), "00000000-0000-0000-0000-000000000000")
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacted_never_prints_value() {
+        let secret = Redacted("hunter2");
+        assert_eq!(format!("{secret}"), PLACEHOLDER);
+        assert_eq!(format!("{secret:?}"), PLACEHOLDER);
+    }
+
+    #[test]
+    fn test_scrub_removes_secrets() {
+        assert_eq!(
+            scrub("Authorization: Bearer abc.def-123"),
+            "Authorization: Bearer ***REDACTED***"
+        );
+        assert_eq!(
+            scrub("session=s3cr3t; path=/"),
+            "session=***REDACTED***; path=/"
+        );
+        assert_eq!(
+            scrub(r#"{"access_token":"tok","expires_in":3600}"#),
+            r#"{"access_token":***REDACTED***,"expires_in":3600}"#
+        );
+        assert_eq!(scrub("key sk-ant-api03-abcdefgh"), "key ***REDACTED***");
+        assert_eq!(
+            scrub("signed in as jane.doe@example.com"),
+            "signed in as ***@***"
+        );
+    }
+
+    #[test]
+    fn test_scrub_leaves_plain_messages_borrowed() {
+        let message = "GET https://api.anthropic.com/api/oauth/usage";
+        assert!(matches!(scrub(message), Cow::Borrowed(_)));
+    }
+}
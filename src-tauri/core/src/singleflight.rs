@@ -0,0 +1,148 @@
+use anyhow::Result;
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::OnceCell;
+
+use crate::debug_error;
+use crate::i18n::{ParseError, UsageError, UserError};
+use crate::rate_limit::RateLimited;
+
+type Flight<T> = Arc<OnceCell<Result<T, Arc<anyhow::Error>>>>;
+
+/// Coalesces concurrent calls to one provider endpoint: while a fetch is in flight, later
+/// callers wait for it and share its result instead of sending the same request again.
+/// Nothing is kept once the fetch finishes; caching is `ResponseCache`'s job.
+pub struct SingleFlight<T> {
+    inflight: Mutex<Option<Flight<T>>>,
+}
+
+impl<T> SingleFlight<T> {
+    pub const fn new() -> Self {
+        Self {
+            inflight: Mutex::new(None),
+        }
+    }
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    fn lock(&self) -> MutexGuard<'_, Option<Flight<T>>> {
+        self.inflight.lock().unwrap_or_else(|poisoned| {
+            debug_error!("Single-flight mutex poisoned, recovering...");
+            poisoned.into_inner()
+        })
+    }
+
+    /// Runs `fetch` unless a call is already in flight, in which case its result is awaited
+    /// instead. If the running caller is cancelled, a waiting one takes over.
+    pub async fn run<F, Fut>(&self, fetch: F) -> Result<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let flight = Arc::clone(self.lock().get_or_insert_with(Default::default));
+        let result = flight
+            .get_or_init(|| async { fetch().await.map_err(Arc::new) })
+            .await
+            .clone();
+
+        let mut guard = self.lock();
+        if guard.as_ref().is_some_and(|f| Arc::ptr_eq(f, &flight)) {
+            *guard = None;
+        }
+        drop(guard);
+
+        result.map_err(|e| rebuild(&e))
+    }
+}
+
+/// `anyhow::Error` is not `Clone`, so each caller gets a copy rebuilt from the shared one.
+/// Errors commands branch on keep their type; the rest are classified once and carried as
+/// the `UsageError` the original converts to, so every caller reports the same kind.
+fn rebuild(error: &anyhow::Error) -> anyhow::Error {
+    if let Some(e) = error.downcast_ref::<UserError>() {
+        return e.0.into();
+    }
+    if let Some(e) = error.downcast_ref::<RateLimited>() {
+        return e.clone().into();
+    }
+    if let Some(e) = error.downcast_ref::<ParseError>() {
+        return ParseError(e.0.clone()).into();
+    }
+    UsageError::from(error).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i18n::ErrorCode;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_concurrent_calls_share_one_fetch() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let flight = SingleFlight::<u32>::new();
+        let calls = AtomicUsize::new(0);
+        let fetch = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok(7)
+        };
+
+        let (a, b) = runtime.block_on(async { tokio::join!(flight.run(fetch), flight.run(fetch)) });
+        assert_eq!((a.unwrap(), b.unwrap()), (7, 7));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // The finished flight is not reused.
+        runtime.block_on(flight.run(fetch)).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        let error = runtime
+            .block_on(flight.run(|| async { Err(ErrorCode::AuthFailed.into()) }))
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<UserError>(),
+            Some(UserError(ErrorCode::AuthFailed))
+        ));
+    }
+
+    #[test]
+    fn test_rebuilt_errors_classify_like_the_original() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        // Accepts and hangs up, so the request fails after connecting.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(1) {
+                drop(stream);
+            }
+        });
+        let hung_up = runtime
+            .block_on(reqwest::Client::new().get(&url).send())
+            .map(|_| ())
+            .unwrap_err();
+
+        let hung_up = anyhow::Error::from(hung_up).context("Failed to fetch usage");
+        assert_eq!(UsageError::from(&hung_up).kind(), "network");
+        for error in [
+            hung_up,
+            anyhow::Error::from(UserError(ErrorCode::AuthFailed)).context("refresh"),
+            ParseError("missing field".to_string()).into(),
+            anyhow::anyhow!("disk full"),
+        ] {
+            assert_eq!(UsageError::from(&rebuild(&error)), UsageError::from(&error));
+        }
+    }
+}
@@ -0,0 +1,822 @@
+//! Where `CredentialManager` keeps the Z.ai key and Amp cookie. `OsStore` is the platform's
+//! own store: Windows Credential Manager, the login Keychain on macOS, or the freedesktop
+//! Secret Service (GNOME Keyring, KWallet) on Linux. `FallbackStore` puts entries the OS
+//! store refuses into a `ProtectedFileStore`, `MemoryStore` backs tests and
+//! `EncryptedFileStore` machines without a usable OS store. Entries are keyed by target
+//! name (`usage-bar-zai-api-key`, ...) in every store.
+
+use anyhow::{anyhow, Context, Result};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fs;
+use std::io::Write;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{debug_cred, debug_error};
+
+pub trait SecretStore: Send + Sync {
+    /// The stored blob, or an error if `target_name` has none.
+    fn read(&self, target_name: &str) -> Result<Vec<u8>>;
+
+    /// Creates or replaces the entry.
+    fn write(&self, target_name: &str, data: &str) -> Result<()>;
+
+    /// Fails if `target_name` has no entry.
+    fn delete(&self, target_name: &str) -> Result<()>;
+}
+
+#[cfg(target_os = "macos")]
+pub use platform::{keychain_read, keychain_write};
+
+/// The platform's secret store; the default for `CredentialManager`.
+pub struct OsStore;
+
+impl SecretStore for OsStore {
+    fn read(&self, target_name: &str) -> Result<Vec<u8>> {
+        platform::read(target_name)
+    }
+
+    fn write(&self, target_name: &str, data: &str) -> Result<()> {
+        platform::write(target_name, data)
+    }
+
+    fn delete(&self, target_name: &str) -> Result<()> {
+        platform::delete(target_name)
+    }
+}
+
+/// (target name, blob)
+type Entry = (String, Vec<u8>);
+
+fn find_entry(entries: &[Entry], target_name: &str) -> Result<Vec<u8>> {
+    entries
+        .iter()
+        .find(|(target, _)| target == target_name)
+        .map(|(_, blob)| blob.clone())
+        .ok_or_else(|| anyhow!("Credential not found: {target_name}"))
+}
+
+fn upsert_entry(entries: &mut Vec<Entry>, target_name: &str, data: &str) {
+    entries.retain(|(target, _)| target != target_name);
+    entries.push((target_name.to_string(), data.as_bytes().to_vec()));
+}
+
+fn remove_entry(entries: &mut Vec<Entry>, target_name: &str) -> Result<()> {
+    let before = entries.len();
+    entries.retain(|(target, _)| target != target_name);
+    if entries.len() == before {
+        return Err(anyhow!("Failed to delete credential: {target_name}"));
+    }
+    Ok(())
+}
+
+fn read_file(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow!("Failed to read {}: {e}", path.display())),
+    }
+}
+
+/// Temp file + rename, readable only by the current user on Unix.
+fn write_file_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let temp_path = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&temp_path)
+        .and_then(|mut file| file.write_all(bytes))
+        .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+    fs::rename(&temp_path, path).map_err(|e| {
+        let _ = fs::remove_file(&temp_path);
+        anyhow!("Failed to replace {}: {e}", path.display())
+    })
+}
+
+fn lock_entries<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        debug_error!("Secret store mutex poisoned, recovering...");
+        poisoned.into_inner()
+    })
+}
+
+/// Entries that live only as long as the process. The E2E harness installs one so tests
+/// never touch the real store.
+#[derive(Default)]
+pub struct MemoryStore {
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl SecretStore for MemoryStore {
+    fn read(&self, target_name: &str) -> Result<Vec<u8>> {
+        find_entry(&lock_entries(&self.entries), target_name)
+    }
+
+    fn write(&self, target_name: &str, data: &str) -> Result<()> {
+        upsert_entry(&mut lock_entries(&self.entries), target_name, data);
+        Ok(())
+    }
+
+    fn delete(&self, target_name: &str) -> Result<()> {
+        remove_entry(&mut lock_entries(&self.entries), target_name)
+    }
+}
+
+/// Identifies the file format; also bound into every ciphertext as associated data.
+const FILE_MAGIC: &[u8; 4] = b"UBV1";
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Every entry in one file, sealed with ChaCha20-Poly1305 under a key derived from a
+/// passphrase (PBKDF2-HMAC-SHA256). Layout: magic, salt, nonce, then the sealed JSON list
+/// of entries. Each write re-seals the whole file under a fresh nonce and replaces it
+/// atomically.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    salt: [u8; SALT_LEN],
+    key: LessSafeKey,
+    rng: SystemRandom,
+    /// Serializes read-modify-write cycles within this process.
+    lock: Mutex<()>,
+}
+
+impl EncryptedFileStore {
+    /// Opens `path`, or prepares a new file there on first write. An existing file must
+    /// decrypt with `passphrase`, so a typo fails here instead of on the first read.
+    pub fn open(path: impl Into<PathBuf>, passphrase: &str) -> Result<Self> {
+        let path = path.into();
+        let rng = SystemRandom::new();
+        let existing = read_file(&path)?;
+
+        let mut salt = [0u8; SALT_LEN];
+        match &existing {
+            Some(bytes) => {
+                let stored = bytes
+                    .strip_prefix(FILE_MAGIC.as_slice())
+                    .and_then(|rest| rest.get(..SALT_LEN))
+                    .ok_or_else(|| anyhow!("{} is not a secret store file", path.display()))?;
+                salt.copy_from_slice(stored);
+            }
+            None => rng
+                .fill(&mut salt)
+                .map_err(|_| anyhow!("Failed to generate a salt"))?,
+        }
+
+        let store = Self {
+            key: derive_key(passphrase, &salt)?,
+            path,
+            salt,
+            rng,
+            lock: Mutex::new(()),
+        };
+        if let Some(bytes) = existing {
+            store.decrypt(bytes)?;
+        }
+        Ok(store)
+    }
+
+    fn decrypt(&self, mut bytes: Vec<u8>) -> Result<Vec<Entry>> {
+        let header = FILE_MAGIC.len() + SALT_LEN;
+        if bytes.len() < header + NONCE_LEN {
+            return Err(anyhow!("{} is truncated", self.path.display()));
+        }
+        let mut sealed = bytes.split_off(header);
+        let mut body = sealed.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&sealed)
+            .map_err(|_| anyhow!("{} has an invalid nonce", self.path.display()))?;
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(FILE_MAGIC), &mut body)
+            .map_err(|_| {
+                anyhow!(
+                    "Failed to decrypt {}: wrong passphrase or damaged file",
+                    self.path.display()
+                )
+            })?;
+        serde_json::from_slice(plaintext)
+            .with_context(|| format!("Failed to parse {}", self.path.display()))
+    }
+
+    fn load(&self) -> Result<Vec<Entry>> {
+        match read_file(&self.path)? {
+            Some(bytes) => self.decrypt(bytes),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn save(&self, entries: &[Entry]) -> Result<()> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("Failed to generate a nonce"))?;
+        let mut body = serde_json::to_vec(entries)?;
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(FILE_MAGIC),
+                &mut body,
+            )
+            .map_err(|_| anyhow!("Failed to encrypt {}", self.path.display()))?;
+
+        let mut file = Vec::with_capacity(FILE_MAGIC.len() + SALT_LEN + NONCE_LEN + body.len());
+        file.extend_from_slice(FILE_MAGIC);
+        file.extend_from_slice(&self.salt);
+        file.extend_from_slice(&nonce);
+        file.extend_from_slice(&body);
+        write_file_atomic(&self.path, &file)
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<LessSafeKey> {
+    let iterations =
+        NonZeroU32::new(PBKDF2_ITERATIONS).ok_or_else(|| anyhow!("Invalid PBKDF2 iterations"))?;
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+        .map_err(|_| anyhow!("Failed to create the secret store key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+impl SecretStore for EncryptedFileStore {
+    fn read(&self, target_name: &str) -> Result<Vec<u8>> {
+        let _guard = lock_entries(&self.lock);
+        find_entry(&self.load()?, target_name)
+    }
+
+    fn write(&self, target_name: &str, data: &str) -> Result<()> {
+        let _guard = lock_entries(&self.lock);
+        let mut entries = self.load()?;
+        upsert_entry(&mut entries, target_name, data);
+        self.save(&entries)
+            .map_err(|e| anyhow!("Failed to write credential: {target_name}: {e}"))
+    }
+
+    fn delete(&self, target_name: &str) -> Result<()> {
+        let _guard = lock_entries(&self.lock);
+        let mut entries = self.load()?;
+        remove_entry(&mut entries, target_name)?;
+        self.save(&entries)
+    }
+}
+
+/// Every entry in one local file, sealed with the OS's per-user data protection: DPAPI on
+/// Windows, so only this account on this machine can read it. Other platforms have no
+/// equivalent, so there the file is protected only by its 0600 mode. Meant as the
+/// `FallbackStore` fallback, not a store of its own.
+pub struct ProtectedFileStore {
+    path: PathBuf,
+    /// Serializes read-modify-write cycles within this process.
+    lock: Mutex<()>,
+}
+
+impl ProtectedFileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn load(&self) -> Result<Vec<Entry>> {
+        let Some(bytes) = read_file(&self.path)? else {
+            return Ok(Vec::new());
+        };
+        let plaintext = protect::unprotect(&bytes)
+            .with_context(|| format!("Failed to unprotect {}", self.path.display()))?;
+        serde_json::from_slice(&plaintext)
+            .with_context(|| format!("Failed to parse {}", self.path.display()))
+    }
+
+    fn save(&self, entries: &[Entry]) -> Result<()> {
+        let sealed = protect::protect(&serde_json::to_vec(entries)?)?;
+        write_file_atomic(&self.path, &sealed)
+    }
+}
+
+impl SecretStore for ProtectedFileStore {
+    fn read(&self, target_name: &str) -> Result<Vec<u8>> {
+        let _guard = lock_entries(&self.lock);
+        find_entry(&self.load()?, target_name)
+    }
+
+    fn write(&self, target_name: &str, data: &str) -> Result<()> {
+        let _guard = lock_entries(&self.lock);
+        let mut entries = self.load()?;
+        upsert_entry(&mut entries, target_name, data);
+        self.save(&entries)
+            .map_err(|e| anyhow!("Failed to write credential: {target_name}: {e}"))
+    }
+
+    fn delete(&self, target_name: &str) -> Result<()> {
+        let _guard = lock_entries(&self.lock);
+        let mut entries = self.load()?;
+        remove_entry(&mut entries, target_name)?;
+        self.save(&entries)
+    }
+}
+
+/// `primary` while it works, `fallback` for entries it refuses, e.g. when policy blocks
+/// `CredWriteW` on a managed machine. An entry in `fallback` is newer than anything the
+/// primary holds, so it is read first and moved back as soon as the primary accepts it.
+pub struct FallbackStore {
+    primary: Arc<dyn SecretStore>,
+    fallback: Arc<dyn SecretStore>,
+}
+
+impl FallbackStore {
+    pub fn new(primary: Arc<dyn SecretStore>, fallback: Arc<dyn SecretStore>) -> Self {
+        Self { primary, fallback }
+    }
+
+    fn migrate_back(&self, target_name: &str, blob: &[u8]) {
+        let Ok(data) = std::str::from_utf8(blob) else {
+            return;
+        };
+        if self.primary.write(target_name, data).is_ok()
+            && self.fallback.delete(target_name).is_ok()
+        {
+            debug_cred!("Moved {target_name} from the fallback file back to the secret store");
+        }
+    }
+}
+
+impl SecretStore for FallbackStore {
+    fn read(&self, target_name: &str) -> Result<Vec<u8>> {
+        match self.fallback.read(target_name) {
+            Ok(blob) => {
+                self.migrate_back(target_name, &blob);
+                Ok(blob)
+            }
+            Err(_) => self.primary.read(target_name),
+        }
+    }
+
+    fn write(&self, target_name: &str, data: &str) -> Result<()> {
+        match self.primary.write(target_name, data) {
+            Ok(()) => {
+                // Drop any older copy so reads don't prefer it.
+                let _ = self.fallback.delete(target_name);
+                Ok(())
+            }
+            Err(e) => {
+                debug_error!("Secret store refused {target_name}, using the fallback file: {e}");
+                self.fallback.write(target_name, data)
+            }
+        }
+    }
+
+    fn delete(&self, target_name: &str) -> Result<()> {
+        let primary = self.primary.delete(target_name);
+        let fallback = self.fallback.delete(target_name);
+        primary.or(fallback)
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) mod protect {
+    use anyhow::{anyhow, Result};
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::{LocalFree, HLOCAL};
+    use windows::Win32::Security::Cryptography::{
+        CryptProtectData, CryptUnprotectData, CRYPTPROTECT_UI_FORBIDDEN, CRYPT_INTEGER_BLOB,
+    };
+
+    fn input(data: &[u8]) -> CRYPT_INTEGER_BLOB {
+        CRYPT_INTEGER_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        }
+    }
+
+    /// Copies out and frees a blob DPAPI allocated.
+    fn take(blob: CRYPT_INTEGER_BLOB) -> Vec<u8> {
+        unsafe {
+            let bytes = std::slice::from_raw_parts(blob.pbData, blob.cbData as usize).to_vec();
+            LocalFree(Some(HLOCAL(blob.pbData as *mut _)));
+            bytes
+        }
+    }
+
+    pub fn protect(data: &[u8]) -> Result<Vec<u8>> {
+        let mut output = CRYPT_INTEGER_BLOB::default();
+        unsafe {
+            CryptProtectData(
+                &input(data),
+                PCWSTR::null(),
+                None,
+                None,
+                None,
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        }
+        .map_err(|e| anyhow!("CryptProtectData failed: {e}"))?;
+        Ok(take(output))
+    }
+
+    pub fn unprotect(data: &[u8]) -> Result<Vec<u8>> {
+        let mut output = CRYPT_INTEGER_BLOB::default();
+        unsafe {
+            CryptUnprotectData(
+                &input(data),
+                None,
+                None,
+                None,
+                None,
+                CRYPTPROTECT_UI_FORBIDDEN,
+                &mut output,
+            )
+        }
+        .map_err(|e| anyhow!("CryptUnprotectData failed: {e}"))?;
+        Ok(take(output))
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) mod protect {
+    use anyhow::Result;
+
+    pub fn protect(data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+
+    pub fn unprotect(data: &[u8]) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use anyhow::{anyhow, Result};
+    use windows::core::{PCWSTR, PWSTR};
+    use windows::Win32::Foundation::FILETIME;
+    use windows::Win32::Security::Credentials::*;
+
+    fn wide(target_name: &str) -> Vec<u16> {
+        target_name.encode_utf16().chain(Some(0)).collect()
+    }
+
+    pub fn read(target_name: &str) -> Result<Vec<u8>> {
+        let target_name_wide = wide(target_name);
+
+        let mut credential_ptr: *mut CREDENTIALW = std::ptr::null_mut();
+
+        unsafe {
+            let result = CredReadW(
+                PCWSTR(target_name_wide.as_ptr()),
+                CRED_TYPE_GENERIC,
+                Some(0),
+                &mut credential_ptr,
+            );
+
+            if result.is_err() {
+                return Err(anyhow!("Credential not found: {target_name}"));
+            }
+
+            let blob = std::slice::from_raw_parts(
+                (*credential_ptr).CredentialBlob,
+                (*credential_ptr).CredentialBlobSize as usize,
+            )
+            .to_vec();
+            CredFree(credential_ptr as *const _);
+
+            Ok(blob)
+        }
+    }
+
+    pub fn write(target_name: &str, data: &str) -> Result<()> {
+        let target_name_wide = wide(target_name);
+        let blob: Vec<u8> = data.as_bytes().to_vec();
+
+        let credential = CREDENTIALW {
+            Flags: CRED_FLAGS(0),
+            Type: CRED_TYPE_GENERIC,
+            TargetName: PWSTR(target_name_wide.as_ptr() as *mut u16),
+            Comment: PWSTR::null(),
+            LastWritten: FILETIME::default(),
+            CredentialBlobSize: blob.len() as u32,
+            CredentialBlob: blob.as_ptr() as *mut u8,
+            Persist: CRED_PERSIST_LOCAL_MACHINE,
+            TargetAlias: PWSTR::null(),
+            UserName: PWSTR::null(),
+            AttributeCount: 0,
+            Attributes: std::ptr::null_mut(),
+        };
+
+        unsafe {
+            // Vectors are still alive here because credential borrows from them
+            let result = CredWriteW(&credential, 0);
+
+            if result.is_err() {
+                return Err(anyhow!("Failed to write credential: {target_name}"));
+            }
+
+            Ok(())
+        } // Vectors dropped here, after CredWriteW completes
+    }
+
+    pub fn delete(target_name: &str) -> Result<()> {
+        let target_name_wide = wide(target_name);
+
+        unsafe {
+            let result = CredDeleteW(
+                PCWSTR(target_name_wide.as_ptr()),
+                CRED_TYPE_GENERIC,
+                Some(0),
+            );
+
+            if result.is_err() {
+                return Err(anyhow!("Failed to delete credential: {target_name}"));
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Generic passwords in the login Keychain, through `/usr/bin/security` so no extra
+/// framework bindings are needed. The target name is the service, `ACCOUNT` the account.
+#[cfg(target_os = "macos")]
+mod platform {
+    use anyhow::{anyhow, Result};
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    const SECURITY: &str = "/usr/bin/security";
+    const ACCOUNT: &str = "usage-bar";
+
+    pub fn read(target_name: &str) -> Result<Vec<u8>> {
+        find_password(&["-s", target_name, "-a", ACCOUNT])
+            .ok_or_else(|| anyhow!("Credential not found: {target_name}"))
+    }
+
+    fn find_password(query: &[&str]) -> Option<Vec<u8>> {
+        let output = Command::new(SECURITY)
+            .arg("find-generic-password")
+            .args(query)
+            .arg("-w")
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        // `-w` prints the password followed by a newline.
+        let mut blob = output.stdout;
+        if blob.last() == Some(&b'\n') {
+            blob.pop();
+        }
+        Some(blob)
+    }
+
+    /// The account of `service`'s item, from the `"acct"<blob>="..."` line `security` prints.
+    fn find_account(service: &str) -> Option<String> {
+        let output = Command::new(SECURITY)
+            .args(["find-generic-password", "-s", service])
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let attributes = String::from_utf8_lossy(&output.stdout);
+        attributes
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("\"acct\"<blob>="))
+            .and_then(|value| value.strip_prefix('"')?.strip_suffix('"'))
+            .map(str::to_string)
+    }
+
+    /// An item another app owns, looked up by service alone since its account is not ours.
+    pub fn keychain_read(service: &str) -> Result<Vec<u8>> {
+        find_password(&["-s", service]).ok_or_else(|| anyhow!("No Keychain item for {service}"))
+    }
+
+    /// Replaces the password of another app's item, keeping its account.
+    pub fn keychain_write(service: &str, data: &str) -> Result<()> {
+        let account = find_account(service)
+            .or_else(|| std::env::var("USER").ok())
+            .ok_or_else(|| anyhow!("No Keychain account for {service}"))?;
+        add_password(service, &account, data)
+    }
+
+    pub fn write(target_name: &str, data: &str) -> Result<()> {
+        add_password(target_name, ACCOUNT, data)
+    }
+
+    /// The secret goes in hex over stdin (`security -i`), never on the command line where
+    /// other processes could read it.
+    fn add_password(service: &str, account: &str, data: &str) -> Result<()> {
+        let hex: String = data.bytes().map(|b| format!("{b:02x}")).collect();
+        let mut child = Command::new(SECURITY)
+            .arg("-i")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to run security: {e}"))?;
+        let command =
+            format!("add-generic-password -U -s \"{service}\" -a \"{account}\" -X {hex}\n");
+        let written = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("security stdin unavailable"))
+            .and_then(|mut stdin| Ok(stdin.write_all(command.as_bytes())?));
+        let status = child.wait()?;
+        if written.is_err() || !status.success() {
+            return Err(anyhow!("Failed to write credential: {service}"));
+        }
+        Ok(())
+    }
+
+    pub fn delete(target_name: &str) -> Result<()> {
+        let status = Command::new(SECURITY)
+            .args(["delete-generic-password", "-s", target_name, "-a", ACCOUNT])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| anyhow!("Failed to run security: {e}"))?;
+        if !status.success() {
+            return Err(anyhow!("Failed to delete credential: {target_name}"));
+        }
+        Ok(())
+    }
+}
+
+/// Secret Service items through libsecret's `secret-tool`, so no D-Bus bindings are
+/// needed. Items carry the attributes `service=usage-bar` and `target=<target name>`.
+#[cfg(target_os = "linux")]
+mod platform {
+    use anyhow::{anyhow, Result};
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    const SECRET_TOOL: &str = "secret-tool";
+    const SERVICE: &str = "usage-bar";
+
+    fn secret_tool(action: &str, target_name: &str) -> Command {
+        let mut command = Command::new(SECRET_TOOL);
+        command
+            .arg(action)
+            .args(["service", SERVICE, "target", target_name])
+            .stderr(Stdio::null());
+        command
+    }
+
+    pub fn read(target_name: &str) -> Result<Vec<u8>> {
+        let output = secret_tool("lookup", target_name)
+            .output()
+            .map_err(|e| anyhow!("Failed to run {SECRET_TOOL} (is libsecret installed?): {e}"))?;
+        // `lookup` exits 1 with no output when nothing matches.
+        if !output.status.success() || output.stdout.is_empty() {
+            return Err(anyhow!("Credential not found: {target_name}"));
+        }
+        Ok(output.stdout)
+    }
+
+    /// `store` reads the secret from stdin, so it never shows up in the process list.
+    pub fn write(target_name: &str, data: &str) -> Result<()> {
+        let mut child = Command::new(SECRET_TOOL)
+            .args(["store", &format!("--label=Usage Bar ({target_name})")])
+            .args(["service", SERVICE, "target", target_name])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to run {SECRET_TOOL} (is libsecret installed?): {e}"))?;
+        let written = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("{SECRET_TOOL} stdin unavailable"))
+            .and_then(|mut stdin| Ok(stdin.write_all(data.as_bytes())?));
+        let status = child.wait()?;
+        if written.is_err() || !status.success() {
+            return Err(anyhow!("Failed to write credential: {target_name}"));
+        }
+        Ok(())
+    }
+
+    /// `clear` succeeds whether or not an item matched, so existence is checked first to
+    /// report a missing credential like the other platforms do.
+    pub fn delete(target_name: &str) -> Result<()> {
+        read(target_name).map_err(|_| anyhow!("Failed to delete credential: {target_name}"))?;
+        let status = secret_tool("clear", target_name)
+            .stdout(Stdio::null())
+            .status()
+            .map_err(|e| anyhow!("Failed to run {SECRET_TOOL}: {e}"))?;
+        if !status.success() {
+            return Err(anyhow!("Failed to delete credential: {target_name}"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+mod platform {
+    use anyhow::{anyhow, Result};
+
+    pub fn read(target_name: &str) -> Result<Vec<u8>> {
+        Err(anyhow!("Credential not found: {target_name}"))
+    }
+
+    pub fn write(target_name: &str, _data: &str) -> Result<()> {
+        Err(anyhow!(
+            "No credential store on this platform to save {target_name}"
+        ))
+    }
+
+    pub fn delete(target_name: &str) -> Result<()> {
+        Err(anyhow!("Failed to delete credential: {target_name}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_file_store_round_trips_and_rejects_wrong_passphrase() {
+        let dir =
+            std::env::temp_dir().join(format!("usage-bar-secret-store-{}", std::process::id()));
+        let path = dir.join("secrets.bin");
+        let _ = fs::remove_dir_all(&dir);
+
+        let store = EncryptedFileStore::open(&path, "hunter2").unwrap();
+        assert!(store.read("zai").is_err());
+        store.write("zai", "sk-zai").unwrap();
+        store.write("amp", "cookie").unwrap();
+        store.delete("amp").unwrap();
+        assert!(store.delete("amp").is_err());
+
+        let raw = fs::read(&path).unwrap();
+        assert!(!raw.windows(6).any(|w| w == b"sk-zai"));
+
+        let reopened = EncryptedFileStore::open(&path, "hunter2").unwrap();
+        assert_eq!(reopened.read("zai").unwrap(), b"sk-zai");
+        assert!(EncryptedFileStore::open(&path, "wrong").is_err());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    /// An OS store with write access blocked by policy.
+    struct Refusing(MemoryStore);
+
+    impl SecretStore for Refusing {
+        fn read(&self, target_name: &str) -> Result<Vec<u8>> {
+            self.0.read(target_name)
+        }
+
+        fn write(&self, _target_name: &str, _data: &str) -> Result<()> {
+            Err(anyhow!("Access denied"))
+        }
+
+        fn delete(&self, target_name: &str) -> Result<()> {
+            self.0.delete(target_name)
+        }
+    }
+
+    #[test]
+    fn test_fallback_store_moves_entries_back() {
+        let primary = Arc::new(MemoryStore::default());
+        let fallback = Arc::new(MemoryStore::default());
+        let blocked =
+            FallbackStore::new(Arc::new(Refusing(MemoryStore::default())), fallback.clone());
+        blocked.write("zai", "sk-zai").unwrap();
+        assert_eq!(fallback.read("zai").unwrap(), b"sk-zai");
+        assert_eq!(blocked.read("zai").unwrap(), b"sk-zai");
+
+        // The primary accepts writes again: the next read moves the entry over.
+        let store = FallbackStore::new(primary.clone(), fallback.clone());
+        assert_eq!(store.read("zai").unwrap(), b"sk-zai");
+        assert_eq!(primary.read("zai").unwrap(), b"sk-zai");
+        assert!(fallback.read("zai").is_err());
+
+        store.delete("zai").unwrap();
+        assert!(store.delete("zai").is_err());
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let store = MemoryStore::default();
+        store.write("zai", "a").unwrap();
+        store.write("zai", "b").unwrap();
+        assert_eq!(store.read("zai").unwrap(), b"b");
+        store.delete("zai").unwrap();
+        assert!(store.read("zai").is_err());
+    }
+}
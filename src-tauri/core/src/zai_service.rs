@@ -0,0 +1,734 @@
+use crate::clock;
+use crate::credentials::CredentialManager;
+use crate::endpoint;
+use crate::failed_payloads;
+use crate::i18n::{ErrorCode, ParseError, UsageError};
+use crate::local_usage::Bounds;
+use crate::models::{
+    McpUsage, Provider, TokenUsage, ZaiAccountUsage, ZaiBalanceData, ZaiBalanceResponse, ZaiLimit,
+    ZaiModelUsage, ZaiModelUsageReport, ZaiModelUsageResponse, ZaiQuotaResponse, ZaiStatusBody,
+    ZaiStatusCode, ZaiSubscription, ZaiSubscriptionResponse, ZaiUsageData,
+};
+use crate::rate_limit::{self, RateLimited};
+use crate::redact::Redacted;
+use crate::singleflight::SingleFlight;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, Utc};
+use reqwest::StatusCode;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{debug_error, debug_net, debug_zai};
+
+/// Origin `zai_base_urls` stand in for; every Z.ai URL below starts with it.
+pub const ZAI_ORIGIN: &str = "https://api.z.ai";
+const ZAI_API_URL: &str = "https://api.z.ai/api/monitor/usage/quota/limit";
+/// Calls and tokens per model over a time range.
+const ZAI_MODEL_USAGE_URL: &str = "https://api.z.ai/api/monitor/usage/model-usage";
+/// `startTime`/`endTime` format of `ZAI_MODEL_USAGE_URL`, in local time.
+const ZAI_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// Coding-plan subscriptions of the account; names the tier when it answers.
+const ZAI_SUBSCRIPTION_URL: &str = "https://api.z.ai/api/biz/subscription/list";
+/// Account report of the API (pay-as-you-go) balance; the coding plan isn't part of it.
+const ZAI_BALANCE_URL: &str = "https://api.z.ai/api/biz/account/query-customer-account-report";
+
+/// Base URLs from settings by key label, e.g. `https://open.bigmodel.cn` for a key on the
+/// mainland plan.
+static BASE_URLS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+pub struct ZaiService;
+
+impl ZaiService {
+    /// Base URLs from settings by key label (`default` for the main key); a key without one
+    /// goes to `https://api.z.ai`.
+    pub fn zai_set_base_urls(bases: BTreeMap<String, String>) {
+        *lock_base_urls() = bases
+            .into_iter()
+            .map(|(label, base)| (label, base.trim_end_matches('/').to_string()))
+            .collect();
+    }
+
+    /// Concurrent calls share one request.
+    pub async fn zai_fetch_quota(client: Arc<reqwest::Client>) -> Result<ZaiUsageData> {
+        static FLIGHT: SingleFlight<ZaiUsageData> = SingleFlight::new();
+        FLIGHT.run(|| Self::fetch_quota(client)).await
+    }
+
+    async fn fetch_quota(client: Arc<reqwest::Client>) -> Result<ZaiUsageData> {
+        debug_zai!("zai_fetch_quota: Starting request");
+        let api_key = CredentialManager::zai_read_api_key_async().await?;
+        Self::quota_with_key(
+            client,
+            CredentialManager::ZAI_DEFAULT_LABEL.to_string(),
+            api_key,
+        )
+        .await
+    }
+
+    /// The quota for `api_key`, with the tier from the subscription list when it names one
+    /// and from `parse_quota`'s heuristic otherwise.
+    async fn quota_with_key(
+        client: Arc<reqwest::Client>,
+        label: String,
+        api_key: String,
+    ) -> Result<ZaiUsageData> {
+        let plan = tokio::spawn(Self::fetch_plan_name(
+            client.clone(),
+            label.clone(),
+            api_key.clone(),
+        ));
+        let response_text = Self::get_with_key(&client, &label, &api_key, ZAI_API_URL, &[]).await?;
+        debug_zai!("Successfully fetched quota data");
+        let mut usage = Self::parse_quota(&response_text)?;
+        match plan.await {
+            Ok(Some(plan_name)) => usage.tier_name = Some(plan_name),
+            _ => {
+                debug_zai!("No plan from the subscription list, keeping the inferred tier");
+            }
+        }
+        Ok(usage)
+    }
+
+    /// `None` on any failure; the caller falls back to the inferred tier.
+    async fn fetch_plan_name(
+        client: Arc<reqwest::Client>,
+        label: String,
+        api_key: String,
+    ) -> Option<String> {
+        match Self::get_with_key(&client, &label, &api_key, ZAI_SUBSCRIPTION_URL, &[]).await {
+            Ok(response_text) => Self::parse_plan_name(&response_text),
+            Err(e) => {
+                debug_zai!("Z.ai subscription list failed: {e}");
+                None
+            }
+        }
+    }
+
+    /// Pay-as-you-go balance and spend of the account, separate from the coding-plan quota.
+    /// Concurrent calls share one request.
+    pub async fn zai_fetch_balance(client: Arc<reqwest::Client>) -> Result<ZaiBalanceData> {
+        static FLIGHT: SingleFlight<ZaiBalanceData> = SingleFlight::new();
+        FLIGHT.run(|| Self::fetch_balance(client)).await
+    }
+
+    async fn fetch_balance(client: Arc<reqwest::Client>) -> Result<ZaiBalanceData> {
+        debug_zai!("zai_fetch_balance: Starting request");
+        let response_text = Self::get(&client, ZAI_BALANCE_URL, &[]).await?;
+        debug_zai!("Successfully fetched balance data");
+        Self::parse_balance(&response_text)
+    }
+
+    /// Calls and tokens per model between `bounds.since` and `bounds.until` (now when open),
+    /// most tokens first.
+    pub async fn zai_fetch_model_usage(
+        client: Arc<reqwest::Client>,
+        bounds: Bounds,
+    ) -> Result<ZaiModelUsageReport> {
+        debug_zai!("zai_fetch_model_usage: Starting request");
+        let until = bounds.until.unwrap_or_else(clock::now_millis);
+        let query = [
+            ("startTime", zai_time(bounds.since)?),
+            ("endTime", zai_time(until)?),
+        ];
+        let response_text = Self::get(&client, ZAI_MODEL_USAGE_URL, &query).await?;
+        debug_zai!("Successfully fetched model usage");
+        Self::parse_model_usage(&response_text, bounds.since, until)
+    }
+
+    /// Quota of every named key (`CredentialManager::zai_labels`), fetched in parallel;
+    /// per-key failures are reported in each entry. `zai_get_all` caches the result.
+    pub async fn zai_fetch_named_quotas(
+        client: Arc<reqwest::Client>,
+    ) -> BTreeMap<String, ZaiAccountUsage> {
+        let tasks: Vec<_> = CredentialManager::zai_labels_async()
+            .await
+            .into_iter()
+            .map(|label| {
+                let client = client.clone();
+                tokio::spawn(async move {
+                    let result = async {
+                        let api_key =
+                            CredentialManager::zai_read_named_api_key_async(label.clone()).await?;
+                        Self::quota_with_key(client, label.clone(), api_key).await
+                    }
+                    .await;
+                    let account = match result {
+                        Ok(usage) => ZaiAccountUsage {
+                            usage: Some(usage),
+                            error: None,
+                        },
+                        Err(e) => {
+                            debug_zai!("Z.ai key {label:?} failed: {e}");
+                            ZaiAccountUsage {
+                                usage: None,
+                                error: Some(UsageError::from(&e)),
+                            }
+                        }
+                    };
+                    (label, account)
+                })
+            })
+            .collect();
+
+        let mut accounts = BTreeMap::new();
+        for task in tasks {
+            match task.await {
+                Ok((label, account)) => {
+                    accounts.insert(label, account);
+                }
+                Err(e) => {
+                    debug_error!("Z.ai key task failed: {e}");
+                }
+            }
+        }
+        accounts
+    }
+
+    /// GETs `url` with the saved API key; the body of a successful response.
+    async fn get(
+        client: &reqwest::Client,
+        url: &'static str,
+        query: &[(&str, String)],
+    ) -> Result<String> {
+        let api_key = CredentialManager::zai_read_api_key_async().await?;
+        Self::get_with_key(
+            client,
+            CredentialManager::ZAI_DEFAULT_LABEL,
+            &api_key,
+            url,
+            query,
+        )
+        .await
+    }
+
+    /// `url` goes to the base configured for `label`, if any.
+    async fn get_with_key(
+        client: &reqwest::Client,
+        label: &str,
+        api_key: &str,
+        url: &'static str,
+        query: &[(&str, String)],
+    ) -> Result<String> {
+        debug_net!("GET {url}");
+        debug_zai!("Using API key: {}", Redacted(api_key));
+
+        let response = client
+            .get(url_for(label, url).as_ref())
+            .query(query)
+            .timeout(endpoint::timeout(Provider::Zai))
+            .header("Authorization", api_key)
+            .header("Accept-Language", "en-US,en")
+            .header("Content-Type", "application/json")
+            .send()
+            .await?;
+
+        let status = response.status();
+        debug_net!("Response status: {status}");
+        rate_limit::record_quota(Provider::Zai, response.headers());
+
+        match response.status() {
+            StatusCode::UNAUTHORIZED => {
+                debug_error!("Invalid Z.ai API key");
+                Err(ErrorCode::InvalidApiKey.into())
+            }
+            StatusCode::FORBIDDEN => {
+                debug_error!("Access denied to Z.ai API");
+                Err(ErrorCode::AccessDenied.into())
+            }
+            StatusCode::TOO_MANY_REQUESTS => {
+                debug_error!("Z.ai rate limit exceeded");
+                Err(RateLimited::from_headers(
+                    response.headers(),
+                    "z.ai: Rate limited — please wait",
+                )
+                .into())
+            }
+            status if status.is_success() => {
+                let response_text = response.text().await?;
+                debug_zai!("Response body: {response_text}");
+                Ok(response_text)
+            }
+            status if status.is_server_error() => {
+                debug_error!("Z.ai server error");
+                Err(ErrorCode::ServerError.into())
+            }
+            _ => {
+                debug_error!("Failed to fetch Z.ai data from {url}");
+                Err(ErrorCode::FetchFailed.into())
+            }
+        }
+    }
+
+    fn parse_quota(response_text: &str) -> Result<ZaiUsageData> {
+        // Z.ai reports some errors in a 200 body
+        if let Some(e) = body_error(response_text) {
+            return Err(e);
+        }
+
+        let quota_response: ZaiQuotaResponse =
+            serde_json::from_str(response_text).map_err(|e| {
+                failed_payloads::capture(Provider::Zai, response_text, &e);
+                ParseError(format!(
+                    "Failed to parse quota response: {e}\nResponse: {response_text}"
+                ))
+            })?;
+
+        let limits: Vec<ZaiLimit> = quota_response
+            .data
+            .limits
+            .into_iter()
+            .map(ZaiLimit::from)
+            .collect();
+
+        let mut token_usage: Option<TokenUsage> = None;
+        let mut mcp_usage: Option<McpUsage> = None;
+        let mut time_limit_total: Option<i32> = None;
+
+        for limit in &limits {
+            match limit.limit_type.as_str() {
+                "TOKENS_LIMIT" => {
+                    token_usage = Some(TokenUsage {
+                        percentage: limit.percentage,
+                        resets_at: limit.resets_at,
+                    });
+                }
+                "TIME_LIMIT" => {
+                    time_limit_total = limit.total;
+                    mcp_usage = Some(McpUsage {
+                        percentage: limit.percentage,
+                        used: limit.current.unwrap_or(0),
+                        total: limit.total.unwrap_or(0),
+                    });
+                }
+                _ => {
+                    debug_zai!("Limit type without a field: {}", limit.limit_type);
+                }
+            }
+        }
+
+        // Fallback for when the subscription list names no plan. Infer tier from TIME_LIMIT
+        // total (based on Z.ai FAQ):
+        // Lite: ~80 prompts per 5 hours
+        // Pro: ~400 prompts per 5 hours
+        // Max: ~1600 prompts per 5 hours
+        let tier_name = time_limit_total.and_then(|total| {
+            let tier = if total >= 1400 {
+                Some("Max".to_string())
+            } else if total >= 300 {
+                Some("Pro".to_string())
+            } else if total > 0 {
+                Some("Lite".to_string())
+            } else {
+                None
+            };
+            debug_zai!("Inferred tier: {tier:?} from time_limit_total={total}");
+            tier
+        });
+
+        Ok(ZaiUsageData {
+            token_usage,
+            mcp_usage,
+            tier_name,
+            limits,
+        })
+    }
+
+    /// The plan of the first active subscription as `Lite`, `Pro` or `Max`, or its name as
+    /// given when it is none of those.
+    fn parse_plan_name(response_text: &str) -> Option<String> {
+        let response: ZaiSubscriptionResponse = match serde_json::from_str(response_text) {
+            Ok(response) => response,
+            Err(e) => {
+                debug_zai!("Unreadable Z.ai subscription list: {e}");
+                return None;
+            }
+        };
+        let name = response
+            .data
+            .into_iter()
+            .find(ZaiSubscription::is_active)?
+            .product_name?;
+        let tier = name
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .find_map(|word| match word.to_ascii_lowercase().as_str() {
+                "lite" => Some("Lite"),
+                "pro" => Some("Pro"),
+                "max" => Some("Max"),
+                _ => None,
+            });
+        debug_zai!("Plan from subscription list: {name:?} ({tier:?})");
+        Some(tier.map_or_else(|| name.trim().to_string(), str::to_string)).filter(|t| !t.is_empty())
+    }
+
+    fn parse_balance(response_text: &str) -> Result<ZaiBalanceData> {
+        if let Some(e) = body_error(response_text) {
+            return Err(e);
+        }
+
+        let balance: ZaiBalanceResponse = serde_json::from_str(response_text).map_err(|e| {
+            failed_payloads::capture(Provider::Zai, response_text, &e);
+            ParseError(format!(
+                "Failed to parse balance response: {e}\nResponse: {response_text}"
+            ))
+        })?;
+        Ok(balance.data.into())
+    }
+
+    /// Rows for the same model are added up, since the report may split a model by day.
+    fn parse_model_usage(
+        response_text: &str,
+        since: i64,
+        until: i64,
+    ) -> Result<ZaiModelUsageReport> {
+        if let Some(e) = body_error(response_text) {
+            return Err(e);
+        }
+
+        let report: ZaiModelUsageResponse = serde_json::from_str(response_text).map_err(|e| {
+            failed_payloads::capture(Provider::Zai, response_text, &e);
+            ParseError(format!(
+                "Failed to parse model usage response: {e}\nResponse: {response_text}"
+            ))
+        })?;
+
+        let mut models: Vec<ZaiModelUsage> = Vec::new();
+        for row in report.data.into_rows() {
+            match models.iter_mut().find(|model| model.model == row.model) {
+                Some(model) => {
+                    model.calls += row.calls;
+                    model.tokens += row.tokens;
+                }
+                None => models.push(ZaiModelUsage {
+                    model: row.model,
+                    calls: row.calls,
+                    tokens: row.tokens,
+                    token_share: 0.0,
+                }),
+            }
+        }
+        let total: u64 = models.iter().map(|model| model.tokens).sum();
+        for model in &mut models {
+            if total > 0 {
+                model.token_share = model.tokens as f64 / total as f64 * 100.0;
+            }
+        }
+        models.sort_by(|a, b| b.tokens.cmp(&a.tokens).then_with(|| a.model.cmp(&b.model)));
+
+        Ok(ZaiModelUsageReport {
+            since,
+            until,
+            models,
+        })
+    }
+
+    pub async fn zai_has_api_key() -> bool {
+        CredentialManager::zai_has_api_key_async().await
+    }
+
+    /// Checks `api_key` against the base URL configured for `label`, the key it is saved as.
+    /// A valid key's quota comes back so setup can show the plan and reset right away;
+    /// `None` for an `{env:...}` reference, which isn't checked.
+    pub async fn validate_api_key(
+        client: Arc<reqwest::Client>,
+        label: &str,
+        api_key: &str,
+    ) -> Result<Option<ZaiUsageData>> {
+        debug_zai!("validate_api_key: Starting validation");
+        let api_key = api_key.trim();
+
+        if api_key.is_empty() {
+            debug_error!("API key cannot be empty");
+            return Err(ErrorCode::ApiKeyEmpty.into());
+        }
+
+        // Skip validation for environment variable syntax (case-insensitive)
+        let api_key_lower = api_key.to_lowercase();
+        if api_key_lower.starts_with("{env:") || api_key_lower.starts_with("$env:") {
+            debug_zai!("Skipping validation for env var reference");
+            return Ok(None);
+        }
+
+        if api_key.len() < 10 {
+            debug_error!("API key is too short");
+            return Err(ErrorCode::ApiKeyTooShort.into());
+        }
+
+        // Resolve environment variable if using {env:varname} syntax
+        let api_key = CredentialManager::resolve_env_reference(api_key)?;
+
+        debug_net!("GET {ZAI_API_URL} (validating key)");
+
+        let response = client
+            .get(url_for(label, ZAI_API_URL).as_ref())
+            .timeout(endpoint::timeout(Provider::Zai))
+            .header("Authorization", &api_key)
+            .header("Accept-Language", "en-US,en")
+            .header("Content-Type", "application/json")
+            .send()
+            .await
+            .map_err(|e| {
+                debug_error!("Network error during validation: {e}");
+                if e.is_timeout() {
+                    ErrorCode::NetworkTimeout.into()
+                } else if e.is_connect() {
+                    ErrorCode::NetworkUnreachable.into()
+                } else {
+                    anyhow::Error::from(ErrorCode::NetworkUnreachable)
+                }
+            })?;
+
+        let status = response.status();
+        debug_net!("Validation response status: {status}");
+
+        match response.status() {
+            StatusCode::UNAUTHORIZED => {
+                debug_error!("Invalid API key (401)");
+                Err(ErrorCode::InvalidApiKey.into())
+            }
+            StatusCode::FORBIDDEN => {
+                debug_error!("Access denied - key may lack permissions (403)");
+                Err(ErrorCode::AccessDenied.into())
+            }
+            StatusCode::TOO_MANY_REQUESTS => {
+                debug_error!("Rate limited during validation (429)");
+                Err(ErrorCode::RateLimited.into())
+            }
+            status if status.is_server_error() => {
+                debug_error!("Z.AI server error (5xx)");
+                Err(ErrorCode::ServerError.into())
+            }
+            status if status.is_success() => {
+                let body = response.text().await.map_err(|e| {
+                    debug_error!("Failed to read response: {e}");
+                    anyhow::Error::from(ErrorCode::FetchFailed)
+                })?;
+
+                if let Some(e) = body_error(&body) {
+                    return Err(e);
+                }
+
+                if !body.contains("\"limits\"") && !body.contains("\"data\"") {
+                    return Err(ErrorCode::UnexpectedResponse.into());
+                }
+
+                let mut usage = Self::parse_quota(&body)?;
+                debug_zai!("API key validation successful");
+                if let Some(plan_name) =
+                    Self::fetch_plan_name(client, label.to_string(), api_key).await
+                {
+                    usage.tier_name = Some(plan_name);
+                }
+                Ok(Some(usage))
+            }
+            _ => {
+                let status = response.status();
+                debug_error!("Failed to validate API key (HTTP {status})");
+                Err(ErrorCode::FetchFailed.into())
+            }
+        }
+    }
+}
+
+/// The error in a body Z.ai sent with a success status, if it is one: `success: false`, a
+/// `code` other than 200, or an `error` object.
+fn body_error(response_text: &str) -> Option<anyhow::Error> {
+    let status: ZaiStatusBody = serde_json::from_str(response_text).ok()?;
+    let (code, msg) = match (status.error, status.success) {
+        (Some(error), _) => (error.code, error.message),
+        (None, Some(false)) => (status.code, status.msg),
+        (None, _) => match status.code.as_ref().and_then(ZaiStatusCode::value) {
+            Some(0 | 200) | None => return None,
+            Some(_) => (status.code, status.msg),
+        },
+    };
+    let code = code.as_ref().and_then(ZaiStatusCode::value);
+    let msg = msg.unwrap_or_default();
+    debug_error!("Z.ai error in response body: {code:?} {msg}");
+    // Codes without a catalog entry keep their detail in the log line above
+    Some(
+        zai_error_code(code, &msg)
+            .unwrap_or(ErrorCode::FetchFailed)
+            .into(),
+    )
+}
+
+/// Z.ai's (Zhipu's) business error codes, and the message for ones without a stable code.
+fn zai_error_code(code: Option<i64>, msg: &str) -> Option<ErrorCode> {
+    let msg = msg.to_lowercase();
+    if ["region", "country", "area"]
+        .iter()
+        .any(|word| msg.contains(word))
+    {
+        return Some(ErrorCode::RegionRestricted);
+    }
+    match code? {
+        // Missing, invalid or expired token
+        1000..=1004 => Some(ErrorCode::InvalidApiKey),
+        // Account inactive, locked or without access to the API
+        1110..=1112 | 1120 | 1121 | 1220 => Some(ErrorCode::AccessDenied),
+        // Arrears, daily limit, plan usage limit (5-hour or weekly)
+        1113 | 1304 | 1308 | 1310 => Some(ErrorCode::QuotaExhausted),
+        // Concurrency and request-rate limits
+        1302 | 1303 | 1305 => Some(ErrorCode::RateLimited),
+        _ => None,
+    }
+}
+
+fn lock_base_urls() -> MutexGuard<'static, BTreeMap<String, String>> {
+    BASE_URLS.lock().unwrap_or_else(|poisoned| {
+        debug_error!("Z.ai base URL mutex poisoned, recovering...");
+        poisoned.into_inner()
+    })
+}
+
+/// `url` with `ZAI_ORIGIN` swapped for the base configured for the key `label`, keeping the
+/// path; otherwise `endpoint::resolve(url)`.
+fn url_for(label: &str, url: &'static str) -> Cow<'static, str> {
+    let bases = lock_base_urls();
+    match (bases.get(label), url.strip_prefix(ZAI_ORIGIN)) {
+        (Some(base), Some(path)) => Cow::Owned(format!("{base}{path}")),
+        _ => endpoint::resolve(url),
+    }
+}
+
+fn zai_time(millis: i64) -> Result<String> {
+    let time = DateTime::<Utc>::from_timestamp_millis(millis)
+        .ok_or_else(|| anyhow!("Time out of range: {millis}"))?;
+    Ok(time
+        .with_timezone(&Local)
+        .format(ZAI_TIME_FORMAT)
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_quota_keeps_unknown_limit_types() {
+        let usage = ZaiService::parse_quota(
+            r#"{"data":{"limits":[
+                {"type":"TOKENS_LIMIT","percentage":12.5,"nextResetTime":1000},
+                {"type":"TIME_LIMIT","percentage":5,"currentValue":20,"usage":400},
+                {"type":"SEARCH_LIMIT","percentage":40,"currentValue":8,"usage":20,"unit":3}
+            ]}}"#,
+        )
+        .unwrap();
+        assert_eq!(usage.token_usage.unwrap().resets_at, Some(1000));
+        assert_eq!(usage.mcp_usage.unwrap().total, 400);
+        assert_eq!(usage.tier_name.as_deref(), Some("Pro"));
+        assert_eq!(usage.limits.len(), 3);
+        assert_eq!(
+            usage.limits[2],
+            ZaiLimit {
+                limit_type: "SEARCH_LIMIT".to_string(),
+                percentage: 40.0,
+                current: Some(8),
+                total: Some(20),
+                resets_at: None,
+                details: serde_json::Map::from_iter([("unit".to_string(), 3.into())]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_plan_name() {
+        let parse = |body: &str| ZaiService::parse_plan_name(body);
+        assert_eq!(
+            parse(
+                r#"{"data":[
+                    {"productName":"GLM Coding Lite","status":"EXPIRED"},
+                    {"productName":"GLM Coding Pro-Quarterly","status":"VALID"}
+                ]}"#
+            )
+            .as_deref(),
+            Some("Pro")
+        );
+        assert_eq!(
+            parse(r#"{"data":[{"productName":"Enterprise Plan"}]}"#).as_deref(),
+            Some("Enterprise Plan")
+        );
+        assert_eq!(parse(r#"{"data":[]}"#), None);
+        assert_eq!(parse("not json"), None);
+    }
+
+    #[test]
+    fn test_parse_balance() {
+        let balance = ZaiService::parse_balance(
+            r#"{"code":200,"success":true,"data":{"balance":"12.50","rechargeAmount":20,"giveAmount":5,"totalSpendAmount":12.5}}"#,
+        )
+        .unwrap();
+        assert_eq!(balance.balance, 12.5);
+        assert_eq!(balance.recharged, Some(20.0));
+        assert_eq!(balance.granted, Some(5.0));
+        assert_eq!(balance.spent, Some(12.5));
+        assert_eq!(balance.currency, "USD");
+        assert!(ZaiService::parse_balance(r#"{"success":false,"msg":"no"}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_model_usage_merges_and_sorts() {
+        let report = ZaiService::parse_model_usage(
+            r#"{"data":{"list":[
+                {"modelName":"glm-4.5v","modelCallCount":2,"tokensUsage":100},
+                {"modelName":"glm-4.6","modelCallCount":5,"tokensUsage":250},
+                {"modelName":"glm-4.6","modelCallCount":1,"tokensUsage":50}
+            ]}}"#,
+            1,
+            2,
+        )
+        .unwrap();
+        assert_eq!(report.models.len(), 2);
+        assert_eq!(report.models[0].model, "glm-4.6");
+        assert_eq!(report.models[0].calls, 6);
+        assert_eq!(report.models[0].tokens, 300);
+        assert_eq!(report.models[0].token_share, 75.0);
+        assert_eq!(report.models[1].token_share, 25.0);
+
+        let bare = ZaiService::parse_model_usage(
+            r#"{"data":[{"model":"glm-4.6","calls":3,"tokens":0}]}"#,
+            1,
+            2,
+        )
+        .unwrap();
+        assert_eq!(bare.models[0].token_share, 0.0);
+    }
+
+    #[test]
+    fn test_url_for_uses_label_base() {
+        ZaiService::zai_set_base_urls(BTreeMap::from([(
+            "test-cn".to_string(),
+            "https://open.bigmodel.cn/".to_string(),
+        )]));
+        assert_eq!(
+            url_for("test-cn", ZAI_API_URL),
+            "https://open.bigmodel.cn/api/monitor/usage/quota/limit"
+        );
+        assert_eq!(url_for("other", ZAI_API_URL), ZAI_API_URL);
+        ZaiService::zai_set_base_urls(BTreeMap::new());
+    }
+
+    #[test]
+    fn test_body_error_maps_codes() {
+        let code = |body: &str| body_error(body).and_then(|e| UsageError::from(&e).code());
+        assert!(body_error(r#"{"code":200,"success":true,"data":{}}"#).is_none());
+        assert!(body_error(r#"{"data":{"limits":[]}}"#).is_none());
+        assert_eq!(
+            code(r#"{"code":1001,"msg":"Token missing","success":false}"#),
+            Some(ErrorCode::InvalidApiKey)
+        );
+        assert_eq!(
+            code(r#"{"code":"1308","msg":"Usage limit reached for 5 hour"}"#),
+            Some(ErrorCode::QuotaExhausted)
+        );
+        assert_eq!(
+            code(r#"{"error":{"code":"1312","message":"Not available in your region"}}"#),
+            Some(ErrorCode::RegionRestricted)
+        );
+        assert_eq!(
+            code(r#"{"code":1999,"msg":"nope","success":false}"#),
+            Some(ErrorCode::FetchFailed)
+        );
+    }
+}
@@ -0,0 +1,726 @@
+use anyhow::{anyhow, Result};
+use chrono::Timelike;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::claude_service::ClaudeService;
+use crate::clock::{now_millis, Clock, SystemClock};
+use crate::models::{
+    AmpSessionStatus, AmpUsageData, AnthropicUsageData, CodexUsageData, GeminiUsageData, Provider,
+    UsageData, ZaiUsageData,
+};
+use crate::presence;
+use crate::settings::{app_data_dir, write_json_atomic};
+use crate::{debug_app, debug_error};
+
+const RULES_FILE_NAME: &str = "alert_rules.json";
+
+/// A user-defined alert: when `condition` becomes true for a usage window, run `actions`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    /// Stable identifier; generated on first save when empty.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    /// e.g. `provider == claude && window == five_hour && percent > 95 && hour >= 9`
+    pub condition: String,
+    pub actions: Vec<AlertAction>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertAction {
+    /// Windows toast notification.
+    Notify,
+    /// POSTs the alert as JSON.
+    Webhook { url: String },
+    /// Plays the system exclamation sound.
+    Sound,
+}
+
+/// Payload sent to webhooks and emitted to the frontend as `alert-fired`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FiredAlert {
+    pub rule_id: String,
+    pub rule_name: String,
+    pub provider: Provider,
+    pub window: &'static str,
+    pub percent: f64,
+    pub fired_at: i64,
+}
+
+// ── Condition language ──
+//
+// condition := all ( "||" all )*
+// all       := clause ( "&&" clause )*
+// clause    := field op value
+// field     := provider | window | percent | hour
+// op        := == | != | > | >= | < | <=
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Provider,
+    Window,
+    Percent,
+    Hour,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Text(String),
+    Number(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Clause {
+    field: Field,
+    op: Op,
+    value: Value,
+}
+
+/// Parsed rule condition: any of the `&&` groups matching makes the condition true.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition(Vec<Vec<Clause>>);
+
+/// What a condition is evaluated against: one usage window of one sample.
+struct WindowContext<'a> {
+    provider: Provider,
+    window: &'a str,
+    percent: f64,
+    hour: u32,
+}
+
+impl Condition {
+    pub fn parse(input: &str) -> Result<Self> {
+        let groups = input
+            .split("||")
+            .map(|group| group.split("&&").map(parse_clause).collect())
+            .collect::<Result<Vec<Vec<Clause>>>>()?;
+        Ok(Self(groups))
+    }
+
+    fn matches(&self, ctx: &WindowContext) -> bool {
+        self.0
+            .iter()
+            .any(|group| group.iter().all(|clause| clause.matches(ctx)))
+    }
+}
+
+fn parse_clause(input: &str) -> Result<Clause> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(anyhow!("Empty clause in condition"));
+    }
+
+    // Two-character operators first so `>=` isn't read as `>`.
+    const OPS: [(&str, Op); 6] = [
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        (">=", Op::Ge),
+        ("<=", Op::Le),
+        (">", Op::Gt),
+        ("<", Op::Lt),
+    ];
+    let (index, token, op) = OPS
+        .iter()
+        .find_map(|(token, op)| input.find(token).map(|index| (index, *token, *op)))
+        .ok_or_else(|| anyhow!("Missing comparison operator in `{input}`"))?;
+
+    let field_name = input[..index].trim();
+    let raw_value = input[index + token.len()..].trim().trim_matches('"');
+
+    let field = match field_name {
+        "provider" => Field::Provider,
+        "window" => Field::Window,
+        "percent" => Field::Percent,
+        "hour" => Field::Hour,
+        other => {
+            return Err(anyhow!(
+                "Unknown field `{other}` (expected provider, window, percent or hour)"
+            ))
+        }
+    };
+
+    let value = match field {
+        Field::Provider | Field::Window => {
+            if !matches!(op, Op::Eq | Op::Ne) {
+                return Err(anyhow!("`{field_name}` only supports == and !="));
+            }
+            if raw_value.is_empty() {
+                return Err(anyhow!("Missing value for `{field_name}`"));
+            }
+            if field == Field::Provider && !Provider::ALL.iter().any(|p| p.key() == raw_value) {
+                return Err(anyhow!("Unknown provider `{raw_value}`"));
+            }
+            Value::Text(raw_value.to_string())
+        }
+        Field::Percent | Field::Hour => Value::Number(
+            raw_value
+                .parse::<f64>()
+                .map_err(|_| anyhow!("`{field_name}` needs a number, got `{raw_value}`"))?,
+        ),
+    };
+
+    Ok(Clause { field, op, value })
+}
+
+impl Clause {
+    fn matches(&self, ctx: &WindowContext) -> bool {
+        match (&self.value, self.field) {
+            (Value::Text(expected), Field::Provider) => {
+                self.compare_text(ctx.provider.key(), expected)
+            }
+            (Value::Text(expected), Field::Window) => self.compare_text(ctx.window, expected),
+            (Value::Number(expected), Field::Percent) => {
+                self.compare_number(ctx.percent, *expected)
+            }
+            (Value::Number(expected), Field::Hour) => {
+                self.compare_number(f64::from(ctx.hour), *expected)
+            }
+            _ => false,
+        }
+    }
+
+    fn compare_text(&self, actual: &str, expected: &str) -> bool {
+        match self.op {
+            Op::Eq => actual == expected,
+            Op::Ne => actual != expected,
+            _ => false,
+        }
+    }
+
+    fn compare_number(&self, actual: f64, expected: f64) -> bool {
+        match self.op {
+            Op::Eq => actual == expected,
+            Op::Ne => actual != expected,
+            Op::Gt => actual > expected,
+            Op::Ge => actual >= expected,
+            Op::Lt => actual < expected,
+            Op::Le => actual <= expected,
+        }
+    }
+}
+
+// ── Usage windows ──
+
+/// Named utilization windows of a usage payload, as referenced by `window == ...`.
+pub trait UsageWindows {
+    fn windows(&self) -> Vec<(&'static str, f64)>;
+
+    /// Dollars left in windows that have a spend budget, by window name.
+    fn budgets(&self) -> Vec<(&'static str, f64)> {
+        Vec::new()
+    }
+}
+
+impl UsageWindows for UsageData {
+    fn windows(&self) -> Vec<(&'static str, f64)> {
+        let mut windows = vec![
+            ("five_hour", self.five_hour_utilization),
+            ("seven_day", self.seven_day_utilization),
+        ];
+        if let Some(opus) = self.seven_day_opus_utilization {
+            windows.push(("seven_day_opus", opus));
+        }
+        if let Some(extra) = self
+            .extra_usage_utilization
+            .filter(|_| self.extra_usage_enabled)
+        {
+            windows.push(("extra_usage", extra));
+        }
+        if let Some(budget) = self
+            .extra_usage_budget_utilization
+            .filter(|_| self.extra_usage_enabled)
+        {
+            windows.push(("extra_usage_budget", budget));
+        }
+        windows
+    }
+
+    fn budgets(&self) -> Vec<(&'static str, f64)> {
+        if !self.extra_usage_enabled {
+            return Vec::new();
+        }
+        // Extra usage amounts are reported in cents.
+        let used = self.extra_usage_used_credits.unwrap_or(0.0) / 100.0;
+        let limit = self
+            .extra_usage_monthly_limit
+            .map(|limit| ("extra_usage", limit / 100.0 - used));
+        let budget = ClaudeService::extra_usage_budget()
+            .filter(|_| self.extra_usage_budget_utilization.is_some())
+            .map(|budget| ("extra_usage_budget", budget - used));
+        limit.into_iter().chain(budget).collect()
+    }
+}
+
+impl UsageWindows for CodexUsageData {
+    fn windows(&self) -> Vec<(&'static str, f64)> {
+        [
+            ("session", self.session_usage.as_ref()),
+            ("weekly", self.weekly_usage.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, usage)| usage.map(|u| (name, u.percentage)))
+        .collect()
+    }
+}
+
+impl UsageWindows for ZaiUsageData {
+    fn windows(&self) -> Vec<(&'static str, f64)> {
+        let tokens = self.token_usage.as_ref().map(|u| ("tokens", u.percentage));
+        let mcp = self.mcp_usage.as_ref().map(|u| ("mcp", u.percentage));
+        tokens.into_iter().chain(mcp).collect()
+    }
+}
+
+impl UsageWindows for AmpUsageData {
+    fn windows(&self) -> Vec<(&'static str, f64)> {
+        vec![("quota", self.used_percent)]
+    }
+
+    fn budgets(&self) -> Vec<(&'static str, f64)> {
+        vec![("quota", self.quota - self.used)]
+    }
+}
+
+/// API spend has no limit to be a percentage of, so no alert or headroom window.
+impl UsageWindows for AnthropicUsageData {
+    fn windows(&self) -> Vec<(&'static str, f64)> {
+        Vec::new()
+    }
+}
+
+/// Windows whose limit Cloud Monitoring hasn't reported are left out.
+impl UsageWindows for GeminiUsageData {
+    fn windows(&self) -> Vec<(&'static str, f64)> {
+        let day = self.per_day.used_percent.map(|p| ("day", p));
+        let minute = self.per_minute.used_percent.map(|p| ("minute", p));
+        day.into_iter().chain(minute).collect()
+    }
+}
+
+// ── Engine ──
+
+struct AlertsInner {
+    rules: Vec<(AlertRule, Condition)>,
+    /// Entries of `alert_rules.json` that failed validation, written back as they were so a
+    /// save doesn't drop rules the user is still fixing by hand.
+    skipped: Vec<serde_json::Value>,
+    /// (rule id, provider, window) currently matching; alerts fire only on the transition into this set.
+    firing: HashSet<(String, Provider, &'static str)>,
+}
+
+/// Holds the configured rules and runs their actions as samples arrive.
+pub struct AlertEngine {
+    /// `None` for an engine built by `detached`, which matches rules but runs no actions.
+    app: Option<AppHandle>,
+    client: Arc<reqwest::Client>,
+    path: Option<PathBuf>,
+    /// `alert_rules.json` exists but isn't a JSON array; saving would overwrite it.
+    unreadable: bool,
+    inner: Mutex<AlertsInner>,
+}
+
+fn validate_rule(rule: &AlertRule) -> Result<Condition> {
+    if rule.name.trim().is_empty() {
+        return Err(anyhow!("Alert rule name is required"));
+    }
+    if rule.actions.is_empty() {
+        return Err(anyhow!("Alert rule needs at least one action"));
+    }
+    for action in &rule.actions {
+        if let AlertAction::Webhook { url } = action {
+            let parsed = reqwest::Url::parse(url).map_err(|_| anyhow!("Invalid webhook URL"))?;
+            if parsed.scheme() != "http" && parsed.scheme() != "https" {
+                return Err(anyhow!("Webhook URL must use http or https scheme"));
+            }
+        }
+    }
+    Condition::parse(&rule.condition)
+}
+
+/// Valid rules with their parsed conditions, and the raw entries that failed validation.
+type StoredRules = (Vec<(AlertRule, Condition)>, Vec<serde_json::Value>);
+
+/// Splits the entries of `alert_rules.json` into valid rules and the raw entries that aren't.
+fn parse_stored(json: &str) -> Result<StoredRules> {
+    let entries: Vec<serde_json::Value> = serde_json::from_str(json)?;
+    let mut rules = Vec::new();
+    let mut skipped = Vec::new();
+    for entry in entries {
+        let parsed = serde_json::from_value::<AlertRule>(entry.clone())
+            .map_err(anyhow::Error::from)
+            .and_then(|rule| validate_rule(&rule).map(|condition| (rule, condition)));
+        match parsed {
+            Ok(rule) => rules.push(rule),
+            Err(e) => {
+                // Not the whole entry: webhook URLs can carry tokens.
+                let name = entry.get("name").and_then(serde_json::Value::as_str);
+                debug_error!("Skipping invalid alert rule {name:?}: {e}");
+                skipped.push(entry);
+            }
+        }
+    }
+    Ok((rules, skipped))
+}
+
+/// Valid rules first, then the skipped entries unchanged.
+fn stored_entries(
+    rules: &[(AlertRule, Condition)],
+    skipped: &[serde_json::Value],
+) -> Result<Vec<serde_json::Value>> {
+    let mut entries = rules
+        .iter()
+        .map(|(rule, _)| serde_json::to_value(rule))
+        .collect::<serde_json::Result<Vec<_>>>()?;
+    entries.extend_from_slice(skipped);
+    Ok(entries)
+}
+
+impl AlertEngine {
+    /// Loads `alert_rules.json`. Invalid rules are skipped (and logged) rather than failing
+    /// startup, and kept in the file on the next save.
+    pub fn load(app: AppHandle, client: Arc<reqwest::Client>) -> Self {
+        let path = app_data_dir()
+            .map(|dir| dir.join(RULES_FILE_NAME))
+            .inspect_err(|e| {
+                debug_error!("Alert rules path unavailable: {e}");
+            })
+            .ok();
+
+        let json = path.as_ref().and_then(|p| std::fs::read_to_string(p).ok());
+        let (rules, skipped, unreadable) = match json.as_deref().map(parse_stored) {
+            None => (Vec::new(), Vec::new(), false),
+            Some(Ok((rules, skipped))) => (rules, skipped, false),
+            Some(Err(e)) => {
+                debug_error!("Failed to parse {RULES_FILE_NAME}: {e}");
+                (Vec::new(), Vec::new(), true)
+            }
+        };
+        let rule_count = rules.len();
+        debug_app!("Loaded {rule_count} alert rule(s)");
+
+        Self {
+            app: Some(app),
+            client,
+            path,
+            unreadable,
+            inner: Mutex::new(AlertsInner {
+                rules,
+                skipped,
+                firing: HashSet::new(),
+            }),
+        }
+    }
+
+    /// An engine with no rules and nowhere to save them, for the E2E harness, which runs
+    /// commands without an app window.
+    #[cfg(test)]
+    pub fn detached(client: Arc<reqwest::Client>) -> Self {
+        Self {
+            app: None,
+            client,
+            path: None,
+            unreadable: false,
+            inner: Mutex::new(AlertsInner {
+                rules: Vec::new(),
+                skipped: Vec::new(),
+                firing: HashSet::new(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AlertsInner> {
+        self.inner.lock().unwrap_or_else(|poisoned| {
+            debug_error!("Alert mutex poisoned, recovering...");
+            poisoned.into_inner()
+        })
+    }
+
+    fn persist(
+        &self,
+        rules: &[(AlertRule, Condition)],
+        skipped: &[serde_json::Value],
+    ) -> Result<()> {
+        let path = self
+            .path
+            .as_ref()
+            .ok_or_else(|| anyhow!("Alert rules can't be saved: APPDATA not set"))?;
+        if self.unreadable {
+            return Err(anyhow!(
+                "{RULES_FILE_NAME} is not valid JSON; fix or delete it before editing rules"
+            ));
+        }
+        write_json_atomic(path, &stored_entries(rules, skipped)?)
+    }
+
+    pub fn rules(&self) -> Vec<AlertRule> {
+        self.lock()
+            .rules
+            .iter()
+            .map(|(rule, _)| rule.clone())
+            .collect()
+    }
+
+    /// Adds a rule, or replaces the one with the same id. Returns the saved rule (with its id).
+    pub fn save_rule(&self, mut rule: AlertRule) -> Result<AlertRule> {
+        let condition = validate_rule(&rule)?;
+        if rule.id.trim().is_empty() {
+            rule.id = format!("rule-{}", now_millis());
+        }
+
+        let mut inner = self.lock();
+        let mut rules = inner.rules.clone();
+        match rules
+            .iter_mut()
+            .find(|(existing, _)| existing.id == rule.id)
+        {
+            Some(slot) => *slot = (rule.clone(), condition),
+            None => rules.push((rule.clone(), condition)),
+        }
+        // A saved rule replaces a skipped entry with its id.
+        let skipped: Vec<_> = inner
+            .skipped
+            .iter()
+            .filter(|entry| entry.get("id").and_then(serde_json::Value::as_str) != Some(&rule.id))
+            .cloned()
+            .collect();
+        self.persist(&rules, &skipped)?;
+        inner.rules = rules;
+        inner.skipped = skipped;
+        inner.firing.retain(|(id, _, _)| id != &rule.id);
+        Ok(rule)
+    }
+
+    pub fn delete_rule(&self, id: &str) -> Result<()> {
+        let mut inner = self.lock();
+        let rules: Vec<_> = inner
+            .rules
+            .iter()
+            .filter(|(rule, _)| rule.id != id)
+            .cloned()
+            .collect();
+        if rules.len() == inner.rules.len() {
+            return Err(anyhow!("No alert rule with id '{id}'"));
+        }
+        self.persist(&rules, &inner.skipped)?;
+        inner.rules = rules;
+        inner.firing.retain(|(rule_id, _, _)| rule_id != id);
+        Ok(())
+    }
+
+    /// Checks every enabled rule against each window of a fresh usage sample.
+    pub fn evaluate(&self, provider: Provider, windows: &[(&'static str, f64)]) {
+        let hour = SystemClock.now_local().hour();
+        let mut fired = Vec::new();
+
+        {
+            let mut inner = self.lock();
+            let AlertsInner { rules, firing, .. } = &mut *inner;
+            for (rule, condition) in rules.iter().filter(|(rule, _)| rule.enabled) {
+                for &(window, percent) in windows {
+                    let ctx = WindowContext {
+                        provider,
+                        window,
+                        percent,
+                        hour,
+                    };
+                    let key = (rule.id.clone(), provider, window);
+                    if !condition.matches(&ctx) {
+                        firing.remove(&key);
+                    } else if firing.insert(key) {
+                        fired.push((rule.clone(), window, percent));
+                    }
+                }
+            }
+        }
+
+        for (rule, window, percent) in fired {
+            self.fire(
+                &rule,
+                FiredAlert {
+                    rule_id: rule.id.clone(),
+                    rule_name: rule.name.clone(),
+                    provider,
+                    window,
+                    percent,
+                    fired_at: now_millis(),
+                },
+            );
+        }
+    }
+
+    /// Emits `amp-session-changed` and, unless it went back to `ok`, shows a toast asking
+    /// for a fresh cookie. Not a user rule, so it fires whatever the rules say.
+    pub fn amp_session_changed(&self, status: &AmpSessionStatus) {
+        debug_app!("Amp session is now {:?}", status.state);
+        let Some(app) = &self.app else {
+            return;
+        };
+        if app.emit("amp-session-changed", status).is_err() {
+            debug_error!("Failed to emit amp-session-changed");
+        }
+        let Some(error) = &status.error else {
+            return;
+        };
+        if presence::notifications_suppressed() {
+            debug_app!("Amp session notification held back: session locked or presenting");
+            return;
+        }
+        let shown = app
+            .notification()
+            .builder()
+            .title("Amp session")
+            .body(error.to_string())
+            .show();
+        if let Err(e) = shown {
+            debug_error!("Failed to show Amp session notification: {e}");
+        }
+    }
+
+    fn fire(&self, rule: &AlertRule, alert: FiredAlert) {
+        debug_app!(
+            "Alert '{}' fired for {:?} {}",
+            rule.name,
+            alert.provider,
+            alert.window
+        );
+        let Some(app) = &self.app else {
+            return;
+        };
+        if app.emit("alert-fired", &alert).is_err() {
+            debug_error!("Failed to emit alert-fired");
+        }
+
+        for action in &rule.actions {
+            match action {
+                AlertAction::Notify if presence::notifications_suppressed() => {
+                    debug_app!("Alert notification held back: session locked or presenting");
+                }
+                AlertAction::Notify => {
+                    let body = format!(
+                        "{:?} {} is at {:.0}%",
+                        alert.provider, alert.window, alert.percent
+                    );
+                    let shown = app
+                        .notification()
+                        .builder()
+                        .title(&rule.name)
+                        .body(body)
+                        .show();
+                    if let Err(e) = shown {
+                        debug_error!("Failed to show alert notification: {e}");
+                    }
+                }
+                AlertAction::Webhook { url } => {
+                    let client = Arc::clone(&self.client);
+                    let url = url.clone();
+                    let payload = alert.clone();
+                    tauri::async_runtime::spawn(async move {
+                        let sent = client.post(&url).json(&payload).send().await;
+                        if let Err(e) = sent.and_then(|r| r.error_for_status()) {
+                            debug_error!("Alert webhook failed: {e}");
+                        }
+                    });
+                }
+                #[cfg(target_os = "windows")]
+                AlertAction::Sound => {
+                    use windows::Win32::System::Diagnostics::Debug::MessageBeep;
+                    use windows::Win32::UI::WindowsAndMessaging::MB_ICONEXCLAMATION;
+                    // SAFETY: MessageBeep takes no pointers; it only queues a system sound.
+                    if unsafe { MessageBeep(MB_ICONEXCLAMATION) }.is_err() {
+                        debug_error!("Failed to play alert sound");
+                    }
+                }
+                #[cfg(not(target_os = "windows"))]
+                AlertAction::Sound => {
+                    debug_app!("Alert sounds are only played on Windows");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx(provider: Provider, window: &str, percent: f64, hour: u32) -> WindowContext<'_> {
+        WindowContext {
+            provider,
+            window,
+            percent,
+            hour,
+        }
+    }
+
+    #[test]
+    fn test_condition_matches_all_clauses() {
+        let condition = Condition::parse(
+            "provider == claude && window == five_hour && percent > 95 && hour >= 9",
+        )
+        .unwrap();
+        assert!(condition.matches(&ctx(Provider::Claude, "five_hour", 96.0, 10)));
+        assert!(!condition.matches(&ctx(Provider::Claude, "five_hour", 96.0, 8)));
+        assert!(!condition.matches(&ctx(Provider::Claude, "seven_day", 96.0, 10)));
+        assert!(!condition.matches(&ctx(Provider::Zai, "five_hour", 96.0, 10)));
+    }
+
+    #[test]
+    fn test_condition_or_groups() {
+        let condition = Condition::parse("percent >= 90 || provider == \"amp\"").unwrap();
+        assert!(condition.matches(&ctx(Provider::Amp, "quota", 10.0, 0)));
+        assert!(condition.matches(&ctx(Provider::Codex, "weekly", 90.0, 0)));
+        assert!(!condition.matches(&ctx(Provider::Codex, "weekly", 89.9, 0)));
+    }
+
+    #[test]
+    fn test_invalid_stored_rules_survive_a_save() {
+        let json = r#"[
+            {"id": "a", "name": "High", "condition": "percent > 90", "actions": [{"type": "notify"}]},
+            {"id": "b", "name": "Typo", "condition": "percnt > 90", "actions": [{"type": "notify"}]},
+            {"note": "not a rule at all"}
+        ]"#;
+        let (rules, skipped) = parse_stored(json).unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].0.id, "a");
+        assert_eq!(skipped.len(), 2);
+
+        let entries = stored_entries(&rules, &skipped).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1]["condition"], "percnt > 90");
+        assert_eq!(entries[2]["note"], "not a rule at all");
+
+        assert!(parse_stored("{not json").is_err());
+    }
+
+    #[test]
+    fn test_condition_parse_errors() {
+        assert!(Condition::parse("percent > ").is_err());
+        assert!(Condition::parse("usage > 5").is_err());
+        assert!(Condition::parse("provider > claude").is_err());
+        assert!(Condition::parse("provider == openai").is_err());
+        assert!(Condition::parse("percent > 90 &&").is_err());
+        assert!(Condition::parse("percent 90").is_err());
+    }
+}
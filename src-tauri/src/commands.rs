@@ -1,211 +1,877 @@
+use crate::alerts::{AlertEngine, AlertRule, Condition, UsageWindows};
 use crate::amp_service::AmpService;
+use crate::anthropic_service::AnthropicService;
+use crate::cache::{
+    CacheKey, CacheMap, Cached, Endpoint, ResponseCache, AMP_USAGE, ANTHROPIC_USAGE, CLAUDE_TIER,
+    CLAUDE_USAGE, CODEX_TIER, CODEX_USAGE, GEMINI_USAGE, ZAI_ACCOUNTS, ZAI_BALANCE, ZAI_TIER,
+    ZAI_USAGE,
+};
+use crate::claude_login::ClaudeLogin;
 use crate::claude_service::ClaudeService;
+use crate::clock::{self, Clock, SystemClock};
 use crate::codex_service::CodexService;
 use crate::credentials::CredentialManager;
+use crate::failed_payloads::{self, FailedPayloadEntry};
+use crate::feed::{UsageFeed, UsageUpdate};
+use crate::forecast;
+use crate::gemini_service::GeminiService;
+use crate::headroom;
+use crate::history::{
+    self, ClaudeHistoryPoint, HistoryBucket, HistoryStats, HistoryStore, Series, ZaiHistory,
+};
+use crate::i18n::{ErrorCode, UsageError};
+use crate::links;
+use crate::local_usage::{Bounds, LocalUsageReader};
+use crate::models::{
+    AggregateUsage, AmpAccount, AmpCookieImport, AmpSessionStatus, AmpTeamUsageData, AmpUsageData,
+    AnthropicUsageData, BurnForecast, ClaudeAuthStatus, ClaudeOrganization, ClaudeProfile,
+    ClaudeReauth, CodexUsageData, CredentialMigration, CredentialSourceStatus, DateRange, Envelope,
+    ExtraUsageContext, GeminiUsageData, HeadroomCheck, LocalUsage, ProjectSort, ProjectUsageReport,
+    Provider, ProviderHealth, RunEstimate, UsageData, UsageSnapshot, ZaiAccountUsage,
+    ZaiAccountsUsage, ZaiBalanceData, ZaiKeySource, ZaiModelUsageReport, ZaiPromptForecast,
+    ZaiTierData, ZaiUsageData,
+};
+use crate::plan_limits::{self, PromptEstimate};
+use crate::presence::{self, SessionState};
+use crate::providers::{ProviderListing, ProviderUsage};
+use crate::rate_limit;
+use crate::settings::{self, PollIntervals, Settings};
+use crate::snapshot;
+use crate::template::{self, ConfigTemplate, StoredCredentials};
+use crate::tray::{self, ResetCountdown};
 use crate::zai_service::ZaiService;
 use crate::{
-    AmpHttpClient, AmpUsageCache, ClaudeTierCache, ClaudeUsageCache, CodexTierCache,
-    CodexUsageCache, HttpClient, ZaiTierCache, ZaiUsageCache,
+    AlertState, AmpHttpClient, CacheState, ClaudeLoginState, CredentialMigrations, FeedState,
+    HttpClient, ProviderRegistryState, SettingsState, UsageHistory,
 };
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::sync::Arc;
-use tauri::State;
-
-use crate::{debug_amp, debug_cache, debug_claude, debug_cred, debug_zai};
-
-#[derive(Debug, serde::Serialize)]
-pub struct RefreshAllResult {
-    pub claude: Option<crate::models::UsageData>,
-    pub codex: Option<crate::models::CodexUsageData>,
-    pub zai: Option<crate::models::ZaiUsageData>,
-    pub amp: Option<crate::models::AmpUsageData>,
-    pub claude_error: Option<String>,
-    pub codex_error: Option<String>,
-    pub zai_error: Option<String>,
-    pub amp_error: Option<String>,
+use std::time::Duration;
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::{debug_amp, debug_app, debug_cache, debug_claude, debug_cred, debug_error, debug_zai};
+
+/// One result per fetched provider. Serialized as `{claude, claude_error, codex, …}` with
+/// every provider present and `null` for the providers that weren't fetched.
+#[derive(Debug, Default)]
+pub struct RefreshAllResult(Vec<(Provider, Result<Envelope<ProviderUsage>, UsageError>)>);
+
+impl Serialize for RefreshAllResult {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(Provider::ALL.len() * 2))?;
+        for provider in Provider::ALL {
+            let result = self.0.iter().find(|(p, _)| *p == provider).map(|(_, r)| r);
+            let key = provider.key();
+            map.serialize_entry(key, &result.and_then(|r| r.as_ref().ok()))?;
+            map.serialize_entry(
+                &format!("{key}_error"),
+                &result.and_then(|r| r.as_ref().err()),
+            )?;
+        }
+        map.end()
+    }
 }
 
 #[cfg(target_os = "windows")]
 const RPC_E_CHANGED_MODE: i32 = -2147417850; // 0x80010106
 
-#[tauri::command]
-pub async fn claude_get_all(
-    client: State<'_, HttpClient>,
-    usage_cache: State<'_, ClaudeUsageCache>,
-    tier_cache: State<'_, ClaudeTierCache>,
-) -> Result<(crate::models::UsageData, crate::models::ClaudeTierData), String> {
-    debug_claude!("claude_get_all called");
+/// How old a history sample may be and still stand in for a failed fetch.
+const LAST_GOOD_MAX_AGE: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Records a successful fetch. History is best-effort and never fails the command.
+fn record_sample<T: Serialize>(
+    history: &HistoryStore,
+    provider: Provider,
+    series: Series,
+    data: &T,
+) {
+    if let Err(e) = history.record(provider, series, data) {
+        debug_error!("Failed to record {provider:?} history: {e}");
+    }
+}
 
-    let client = Arc::clone(&client.0);
+/// Records a successful usage fetch, checks it against the alert rules and pushes it to
+/// `subscribe_usage` channels and the `snapshot_file`.
+fn record_usage<T: Serialize + UsageWindows>(
+    history: &HistoryStore,
+    alerts: &AlertEngine,
+    feed: &UsageFeed,
+    provider: Provider,
+    data: &T,
+) {
+    record_sample(history, provider, Series::Usage, data);
+    alerts.evaluate(provider, &data.windows());
+    if let Some(update) = feed.publish(provider, data) {
+        snapshot::record(update);
+    }
+}
 
-    if let (Some(usage), Some(tier)) = (usage_cache.0.get(), tier_cache.0.get()) {
-        debug_cache!("Returning cached Claude usage and tier data");
-        return Ok((usage, tier));
+fn latest_sample<T: DeserializeOwned>(
+    history: &HistoryStore,
+    provider: Provider,
+    series: Series,
+) -> Option<crate::history::Sample<T>> {
+    history
+        .latest(provider, series, LAST_GOOD_MAX_AGE)
+        .unwrap_or_else(|e| {
+            debug_error!("Failed to read {provider:?} history: {e}");
+            None
+        })
+}
+
+/// Last good usage from history, annotated with the error that prevented a fresh fetch.
+/// Missing credentials are not a failed fetch, so nothing is served for them.
+fn last_good<T: DeserializeOwned>(
+    history: &HistoryStore,
+    provider: Provider,
+    series: Series,
+    error: &anyhow::Error,
+) -> Option<Envelope<Arc<T>>> {
+    let error = UsageError::from(error);
+    if matches!(error, UsageError::NotConfigured { .. }) {
+        return None;
     }
+    let sample = latest_sample(history, provider, series)?;
+    debug_cache!("Serving {provider:?} sample from history after failed fetch");
+    Some(Envelope::last_good(
+        Arc::new(sample.data),
+        error.message().to_string(),
+        sample.fetched_at,
+    ))
+}
+
+/// Last known tier: the expired cache entry if there is one, otherwise history.
+fn last_good_tier<V: DeserializeOwned>(
+    history: &HistoryStore,
+    provider: Provider,
+    tier_cache: &ResponseCache<V>,
+) -> Option<Arc<V>> {
+    tier_cache.get_stale().or_else(|| {
+        latest_sample(history, provider, Series::Tier).map(|sample| Arc::new(sample.data))
+    })
+}
+
+/// Command result for a `get_or_fetch` outcome: a rate-limited provider or a failed fetch
+/// serves the last stored value, and with nothing stored the last good sample from history.
+fn serve<T: DeserializeOwned>(
+    history: &HistoryStore,
+    provider: Provider,
+    result: anyhow::Result<Cached<T>>,
+) -> Result<Envelope<Arc<T>>, UsageError> {
+    serve_series(history, provider, Series::Usage, result)
+}
 
-    debug_claude!("Calling check_and_refresh_if_needed...");
-    if let Err(e) = ClaudeService::check_and_refresh_if_needed(client.clone()).await {
-        debug_claude!("check_and_refresh_if_needed failed: {e}");
-        return Err(e.to_string());
+/// `serve` for a value recorded under another history series.
+fn serve_series<T: DeserializeOwned>(
+    history: &HistoryStore,
+    provider: Provider,
+    series: Series,
+    result: anyhow::Result<Cached<T>>,
+) -> Result<Envelope<Arc<T>>, UsageError> {
+    match result {
+        Ok(Cached::Fresh(data)) => Ok(Envelope::new(data)),
+        Ok(Cached::RateLimited { data, until }) => Ok(Envelope::rate_limited(data, until)),
+        Ok(Cached::Stale {
+            data,
+            fetched_at,
+            error,
+        }) => Ok(Envelope::last_good(
+            data,
+            UsageError::from(&error).message().to_string(),
+            fetched_at,
+        )),
+        Err(e) => last_good(history, provider, series, &e).ok_or_else(|| UsageError::from(&e)),
     }
-    debug_claude!("check_and_refresh_if_needed succeeded");
-
-    debug_claude!("Calling claude_fetch_usage_and_tier...");
-    match ClaudeService::claude_fetch_usage_and_tier(client).await {
-        Ok((usage_data, tier_data)) => {
-            debug_claude!("claude_fetch_usage_and_tier succeeded, caching results");
-            usage_cache.0.set(usage_data.clone());
-            tier_cache.0.set(tier_data.clone());
-            Ok((usage_data, tier_data))
+}
+
+/// `serve` for the `*_get_all` commands, which also return the tier the fetch stored.
+fn serve_pair<U: DeserializeOwned, V: DeserializeOwned>(
+    history: &HistoryStore,
+    provider: Provider,
+    tier_cache: &ResponseCache<V>,
+    result: anyhow::Result<Cached<U>>,
+) -> Result<(Envelope<Arc<U>>, Arc<V>), UsageError> {
+    let usage = serve(history, provider, result)?;
+    let tier = last_good_tier(history, provider, tier_cache).ok_or_else(|| {
+        UsageError::from(match usage.rate_limited_until {
+            Some(_) => ErrorCode::RateLimited,
+            None => ErrorCode::FetchFailed,
+        })
+    })?;
+    Ok((usage, tier))
+}
+
+/// `serve` for the `*_get_tier` commands, which fetch through the usage cache.
+fn serve_tier<U, V: DeserializeOwned>(
+    history: &HistoryStore,
+    provider: Provider,
+    tier_cache: &ResponseCache<V>,
+    result: anyhow::Result<Cached<U>>,
+) -> Result<Arc<V>, UsageError> {
+    let error = result.err();
+    last_good_tier(history, provider, tier_cache).ok_or_else(|| match error {
+        Some(e) => UsageError::from(&e),
+        None => ErrorCode::RateLimited.into(),
+    })
+}
+
+/// The app state a `*_fetch` helper borrows.
+pub(crate) struct FetchState<'a> {
+    pub caches: &'a CacheMap,
+    pub history: &'a HistoryStore,
+    pub alerts: &'a AlertEngine,
+    pub feed: &'a UsageFeed,
+}
+
+/// `serve` for a registry provider: runs one of the `*_fetch` helpers against the app state
+/// and erases the data type.
+pub(crate) async fn serve_provider<'a, T, Fut>(
+    app: &'a AppHandle,
+    provider: Provider,
+    fetch: impl FnOnce(FetchState<'a>) -> Fut,
+) -> Result<Envelope<ProviderUsage>, UsageError>
+where
+    T: DeserializeOwned + Serialize + UsageWindows,
+    Fut: std::future::Future<Output = anyhow::Result<Cached<T>>>,
+{
+    let history = &app.state::<UsageHistory>().inner().0;
+    let result = fetch(FetchState {
+        caches: &app.state::<CacheState>().inner().0,
+        history,
+        alerts: &app.state::<AlertState>().inner().0,
+        feed: &app.state::<FeedState>().inner().0,
+    })
+    .await;
+    let envelope = serve(history, provider, result)?;
+    let usage = ProviderUsage::from_data(&*envelope.data).map_err(UsageError::from)?;
+    Ok(envelope.map(|_| usage))
+}
+
+/// Latest known usage under `key` without fetching: this session's last response, else
+/// history. Returned with the epoch millis it was fetched at.
+pub(crate) fn last_usage<T: DeserializeOwned + Serialize + UsageWindows + Send + Sync + 'static>(
+    app: &AppHandle,
+    key: CacheKey<T>,
+) -> Option<(ProviderUsage, i64)> {
+    let cache = app.state::<CacheState>().inner().0.get(key);
+    let (usage, fetched_at) = match cache.get_stale() {
+        Some(data) => {
+            let now = clock::now_millis();
+            let fetched_at = cache.age().map_or(now, |age| now - age.as_millis() as i64);
+            (ProviderUsage::from_data(&*data), fetched_at)
         }
-        Err(e) => {
-            debug_claude!("claude_fetch_usage_and_tier failed: {e}");
-            Err(e.to_string())
+        None => {
+            let history = &app.state::<UsageHistory>().inner().0;
+            let sample = latest_sample::<T>(history, key.provider, Series::Usage)?;
+            (ProviderUsage::from_data(&sample.data), sample.fetched_at)
+        }
+    };
+    usage
+        .inspect_err(|e| {
+            debug_error!("Failed to read {:?} usage: {e}", key.provider);
+        })
+        .ok()
+        .map(|usage| (usage, fetched_at))
+}
+
+/// Claude usage through the cache; the tier from the same response goes to `tier_cache`.
+pub(crate) async fn claude_fetch(
+    client: Arc<reqwest::Client>,
+    caches: &CacheMap,
+    history: &HistoryStore,
+    alerts: &AlertEngine,
+    feed: &UsageFeed,
+    force: bool,
+) -> anyhow::Result<Cached<UsageData>> {
+    caches
+        .get(CLAUDE_USAGE)
+        .get_or_fetch(force, || async {
+            debug_claude!("Calling check_and_refresh_if_needed...");
+            ClaudeService::check_and_refresh_if_needed(client.clone()).await?;
+            debug_claude!("Calling claude_fetch_usage_and_tier...");
+            let (usage_data, tier_data) =
+                ClaudeService::claude_fetch_usage_and_tier(client).await?;
+            let plan_name = &tier_data.plan_name;
+            debug_claude!("claude_fetch_usage_and_tier succeeded: plan={plan_name}");
+            record_usage(history, alerts, feed, Provider::Claude, &usage_data);
+            record_sample(history, Provider::Claude, Series::Tier, &tier_data);
+            caches.get(CLAUDE_TIER).set(tier_data);
+            Ok(usage_data)
+        })
+        .await
+}
+
+pub(crate) async fn codex_fetch(
+    client: Arc<reqwest::Client>,
+    caches: &CacheMap,
+    history: &HistoryStore,
+    alerts: &AlertEngine,
+    feed: &UsageFeed,
+    force: bool,
+) -> anyhow::Result<Cached<CodexUsageData>> {
+    if !CodexService::codex_has_auth() {
+        return Err(ErrorCode::NotConfigured.into());
+    }
+    caches
+        .get(CODEX_USAGE)
+        .get_or_fetch(force, || async {
+            let (usage_data, tier_data) = CodexService::codex_fetch_usage_and_tier(client).await?;
+            record_usage(history, alerts, feed, Provider::Codex, &usage_data);
+            record_sample(history, Provider::Codex, Series::Tier, &tier_data);
+            caches.get(CODEX_TIER).set(tier_data);
+            Ok(usage_data)
+        })
+        .await
+}
+
+/// Z.ai has no tier endpoint; the tier is read off the quota response.
+pub(crate) async fn zai_fetch(
+    client: Arc<reqwest::Client>,
+    caches: &CacheMap,
+    history: &HistoryStore,
+    alerts: &AlertEngine,
+    feed: &UsageFeed,
+    force: bool,
+) -> anyhow::Result<Cached<ZaiUsageData>> {
+    if !ZaiService::zai_has_api_key().await {
+        debug_zai!("Z.ai API key not configured");
+        return Err(ErrorCode::NotConfigured.into());
+    }
+    caches
+        .get(ZAI_USAGE)
+        .get_or_fetch(force, || async {
+            debug_zai!("Calling ZaiService::zai_fetch_quota...");
+            let data = ZaiService::zai_fetch_quota(client).await?;
+            debug_zai!("zai_fetch_quota succeeded, caching result");
+            let tier_data = ZaiTierData {
+                plan_name: data
+                    .tier_name
+                    .clone()
+                    .unwrap_or_else(|| "Unknown".to_string()),
+            };
+            record_usage(history, alerts, feed, Provider::Zai, &data);
+            record_sample(history, Provider::Zai, Series::Tier, &tier_data);
+            caches.get(ZAI_TIER).set(tier_data);
+            Ok(data)
+        })
+        .await
+}
+
+/// Usage and tier of the default Z.ai key, then the quota of every saved key.
+type ZaiAll = (
+    Envelope<Arc<ZaiUsageData>>,
+    Arc<ZaiTierData>,
+    ZaiAccountsUsage,
+);
+
+/// `zai_get_all`'s last value: the default key's usage next to every named key's quota.
+/// The named keys share the default's TTL through `ZAI_ACCOUNTS`; while the default key is
+/// backing off they aren't fetched either and their last quotas are served.
+async fn zai_accounts(
+    client: Arc<reqwest::Client>,
+    caches: &CacheMap,
+    default: &Envelope<Arc<ZaiUsageData>>,
+    force: bool,
+) -> ZaiAccountsUsage {
+    let cache = caches.get(ZAI_ACCOUNTS);
+    let named = if default.rate_limited_until.is_some() {
+        cache.get_stale()
+    } else {
+        match cache
+            .get_or_fetch(force, || async {
+                Ok(ZaiService::zai_fetch_named_quotas(client).await)
+            })
+            .await
+        {
+            Ok(
+                Cached::Fresh(data) | Cached::RateLimited { data, .. } | Cached::Stale { data, .. },
+            ) => Some(data),
+            Err(e) => {
+                debug_zai!("Z.ai named keys unavailable: {e}");
+                None
+            }
         }
+    };
+    let mut accounts = named.map(|named| (*named).clone()).unwrap_or_default();
+    accounts.insert(
+        CredentialManager::ZAI_DEFAULT_LABEL.to_string(),
+        ZaiAccountUsage {
+            usage: Some((*default.data).clone()),
+            error: None,
+        },
+    );
+    ZaiAccountsUsage::new(accounts)
+}
+
+pub(crate) async fn amp_fetch(
+    client: Arc<reqwest::Client>,
+    caches: &CacheMap,
+    history: &HistoryStore,
+    alerts: &AlertEngine,
+    feed: &UsageFeed,
+    force: bool,
+) -> anyhow::Result<Cached<AmpUsageData>> {
+    if !AmpService::amp_has_credentials().await {
+        debug_amp!("Amp credentials not configured");
+        return Err(ErrorCode::NotConfigured.into());
+    }
+    caches
+        .get(AMP_USAGE)
+        .get_or_fetch(force, || async {
+            let result = AmpService::amp_fetch_usage(&client).await;
+            report_amp_session(alerts).await;
+            let data = result?;
+            debug_amp!("amp_fetch_usage succeeded, caching result");
+            record_usage(history, alerts, feed, Provider::Amp, &data);
+            Ok(data)
+        })
+        .await
+}
+
+/// Tells the tray when the Amp session starts or stops needing a new cookie.
+async fn report_amp_session(alerts: &AlertEngine) {
+    if let Some(status) = AmpService::amp_session_changed().await {
+        alerts.amp_session_changed(&status);
+    }
+}
+
+pub(crate) async fn anthropic_fetch(
+    client: Arc<reqwest::Client>,
+    caches: &CacheMap,
+    history: &HistoryStore,
+    alerts: &AlertEngine,
+    feed: &UsageFeed,
+    force: bool,
+) -> anyhow::Result<Cached<AnthropicUsageData>> {
+    if !AnthropicService::anthropic_has_admin_key().await {
+        debug_cred!("Anthropic Admin API key not configured");
+        return Err(ErrorCode::NotConfigured.into());
+    }
+    caches
+        .get(ANTHROPIC_USAGE)
+        .get_or_fetch(force, || async {
+            let data = AnthropicService::anthropic_fetch_usage(client).await?;
+            debug_cache!("anthropic_fetch_usage succeeded, caching result");
+            record_usage(history, alerts, feed, Provider::Anthropic, &data);
+            Ok(data)
+        })
+        .await
+}
+
+pub(crate) async fn gemini_fetch(
+    client: Arc<reqwest::Client>,
+    caches: &CacheMap,
+    history: &HistoryStore,
+    alerts: &AlertEngine,
+    feed: &UsageFeed,
+    force: bool,
+) -> anyhow::Result<Cached<GeminiUsageData>> {
+    if !GeminiService::gemini_has_credentials().await {
+        debug_cred!("Gemini OAuth credentials not configured");
+        return Err(ErrorCode::NotConfigured.into());
     }
+    caches
+        .get(GEMINI_USAGE)
+        .get_or_fetch(force, || async {
+            let data = GeminiService::gemini_fetch_usage(client).await?;
+            debug_cache!("gemini_fetch_usage succeeded, caching result");
+            record_usage(history, alerts, feed, Provider::Gemini, &data);
+            Ok(data)
+        })
+        .await
+}
+
+/// Approximate prompts left in the 5-hour window for the current plan, from the last fetch.
+#[tauri::command]
+pub fn claude_get_prompt_estimate(caches: State<'_, CacheState>) -> Option<PromptEstimate> {
+    let usage = caches.0.get(CLAUDE_USAGE).get_stale()?;
+    let tier = caches.0.get(CLAUDE_TIER).get_stale()?;
+    plan_limits::estimate(&usage, &tier)
+}
+
+#[tauri::command]
+pub async fn claude_get_all(
+    client: State<'_, HttpClient>,
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<
+    (
+        Envelope<Arc<crate::models::UsageData>>,
+        Arc<crate::models::ClaudeTierData>,
+    ),
+    UsageError,
+> {
+    debug_claude!("claude_get_all called");
+
+    let result = claude_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        caches.0.get(CLAUDE_TIER).get().is_none(),
+    )
+    .await;
+    serve_pair(
+        &history.0,
+        Provider::Claude,
+        &caches.0.get(CLAUDE_TIER),
+        result,
+    )
 }
 
 #[tauri::command]
 pub async fn claude_get_usage(
     client: State<'_, HttpClient>,
-    usage_cache: State<'_, ClaudeUsageCache>,
-    tier_cache: State<'_, ClaudeTierCache>,
-) -> Result<crate::models::UsageData, String> {
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Envelope<Arc<crate::models::UsageData>>, UsageError> {
     debug_claude!("claude_get_usage called");
 
-    // Check cache first
-    if let Some(data) = usage_cache.0.get() {
-        debug_cache!("Returning cached Claude usage data");
-        return Ok(data);
-    }
+    let result = claude_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        false,
+    )
+    .await;
+    serve(&history.0, Provider::Claude, result)
+}
 
-    let client = Arc::clone(&client.0);
+/// Current extra usage spend, served from the usage cache when it is fresh. The popup shows
+/// it in a confirm dialog before calling `claude_open_billing`.
+#[tauri::command]
+pub async fn claude_get_extra_usage(
+    client: State<'_, HttpClient>,
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<ExtraUsageContext, UsageError> {
+    let usage = claude_get_usage(client, caches, history, alerts, feed).await?;
+    Ok(ExtraUsageContext::from_usage(
+        &usage.data,
+        ClaudeService::extra_usage_budget(),
+    ))
+}
 
-    debug_claude!("Calling check_and_refresh_if_needed...");
-    if let Err(e) = ClaudeService::check_and_refresh_if_needed(client.clone()).await {
-        debug_claude!("check_and_refresh_if_needed failed: {e}");
-        return Err(e.to_string());
-    }
-    debug_claude!("check_and_refresh_if_needed succeeded");
-
-    debug_claude!("Calling claude_fetch_usage_and_tier...");
-    match ClaudeService::claude_fetch_usage_and_tier(client).await {
-        Ok((usage_data, tier_data)) => {
-            debug_claude!("claude_fetch_usage_and_tier succeeded, caching results");
-            usage_cache.0.set(usage_data.clone());
-            tier_cache.0.set(tier_data);
-            Ok(usage_data)
-        }
-        Err(e) => {
-            debug_claude!("claude_fetch_usage_and_tier failed: {e}");
-            Err(e.to_string())
+#[tauri::command]
+pub fn claude_open_billing() -> Result<(), UsageError> {
+    debug_claude!("Opening Claude billing page");
+    open_url(ExtraUsageContext::BILLING_URL.to_string())
+}
+
+/// Starts signing in to Claude in the browser, for when Claude Code isn't installed or
+/// logged in. Returns the sign-in URL too, in case the browser did not open.
+#[tauri::command]
+pub fn claude_start_login(login: State<'_, ClaudeLoginState>) -> Result<String, UsageError> {
+    let (pending, url) = ClaudeLogin::start().map_err(UsageError::from)?;
+    *login
+        .0
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(pending);
+    open_url(url.clone())?;
+    Ok(url)
+}
+
+/// Finishes the login from `claude_start_login` with the code the callback page showed,
+/// saving the tokens to the credentials file.
+#[tauri::command]
+pub async fn claude_complete_login(
+    code: String,
+    client: State<'_, HttpClient>,
+    caches: State<'_, CacheState>,
+    login: State<'_, ClaudeLoginState>,
+) -> Result<(), UsageError> {
+    let pending = login
+        .0
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .take()
+        .ok_or_else(|| UsageError::other("No Claude login in progress; start again"))?;
+    ClaudeLogin::complete(&client.0, pending, &code)
+        .await
+        .map_err(UsageError::from)?;
+    caches.0.clear(Provider::Claude);
+    Ok(())
+}
+
+/// Recovers from a Claude login that stopped working, e.g. a revoked refresh token. Forgets
+/// the cached credentials and usage, checks the login against the API, and when only signing
+/// in again helps starts a login (opened in the browser if `open_login`) and reports
+/// `needs_login` with its URL. Network and server errors are returned as errors instead.
+#[tauri::command]
+pub async fn claude_reauth(
+    open_login: Option<bool>,
+    client: State<'_, HttpClient>,
+    caches: State<'_, CacheState>,
+    login: State<'_, ClaudeLoginState>,
+) -> Result<ClaudeReauth, UsageError> {
+    CredentialManager::claude_forget_cached();
+    caches.0.clear(Provider::Claude);
+
+    // Straight to the API: a cache miss could be answered with stale data.
+    let probe = async {
+        ClaudeService::check_and_refresh_if_needed(client.0.clone()).await?;
+        ClaudeService::claude_fetch_usage_and_tier(client.0.clone()).await
+    };
+    let code = match probe.await.map_err(|e| UsageError::from(&e)) {
+        Ok(_) => {
+            return Ok(ClaudeReauth {
+                status: ClaudeAuthStatus::Ok,
+                code: None,
+                login_url: None,
+            })
         }
+        Err(UsageError::AuthExpired { code, .. } | UsageError::NotConfigured { code, .. }) => code,
+        Err(e) => return Err(e),
+    };
+
+    debug_claude!("Claude needs a new login ({})", code.key());
+    let (pending, url) = ClaudeLogin::start().map_err(UsageError::from)?;
+    *login
+        .0
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(pending);
+    if open_login.unwrap_or(false) {
+        open_url(url.clone())?;
     }
+    Ok(ClaudeReauth {
+        status: ClaudeAuthStatus::NeedsLogin,
+        code: Some(code),
+        login_url: Some(url),
+    })
+}
+
+/// Today's Claude Code tokens and estimated cost per model, read from the transcripts on
+/// this machine rather than from the API.
+#[tauri::command]
+pub async fn claude_get_local_usage() -> Result<LocalUsage, UsageError> {
+    LocalUsageReader::claude_local_usage_async()
+        .await
+        .map_err(UsageError::from)
+}
+
+/// Local Claude Code usage per project (session working directory) inside `range`, today
+/// by default, sorted by cost unless `sort` says otherwise.
+#[tauri::command]
+pub async fn claude_get_project_usage(
+    range: Option<DateRange>,
+    sort: Option<ProjectSort>,
+) -> Result<ProjectUsageReport, UsageError> {
+    LocalUsageReader::claude_project_usage_async(
+        range.unwrap_or_default(),
+        sort.unwrap_or_default(),
+    )
+    .await
+    .map_err(UsageError::from)
+}
+
+/// Local Claude Code tokens and cost per model inside `range`, today by default, most
+/// expensive first; each model is tagged with its family to compare Opus, Sonnet and Haiku.
+#[tauri::command]
+pub async fn claude_get_model_breakdown(
+    range: Option<DateRange>,
+) -> Result<LocalUsage, UsageError> {
+    LocalUsageReader::claude_model_breakdown_async(range.unwrap_or_default())
+        .await
+        .map_err(UsageError::from)
+}
+
+/// Usage for every Claude login across the credential sources, plus combined utilization.
+/// Bypasses the caches; per-login failures are reported in each entry.
+#[tauri::command]
+pub async fn claude_get_accounts_usage(
+    client: State<'_, HttpClient>,
+) -> Result<AggregateUsage, UsageError> {
+    Ok(ClaudeService::claude_fetch_accounts_usage(Arc::clone(&client.0)).await)
+}
+
+/// Email, organization and plan of the login whose usage is shown, so the popup can say
+/// which account it is. Bypasses the caches.
+#[tauri::command]
+pub async fn claude_get_profile(
+    client: State<'_, HttpClient>,
+) -> Result<ClaudeProfile, UsageError> {
+    debug_claude!("claude_get_profile called");
+    ClaudeService::claude_fetch_profile(Arc::clone(&client.0))
+        .await
+        .map_err(UsageError::from)
 }
 
 #[tauri::command]
 pub async fn claude_get_tier(
     client: State<'_, HttpClient>,
-    usage_cache: State<'_, ClaudeUsageCache>,
-    tier_cache: State<'_, ClaudeTierCache>,
-) -> Result<crate::models::ClaudeTierData, String> {
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Arc<crate::models::ClaudeTierData>, UsageError> {
     debug_claude!("claude_get_tier called");
 
-    // Check tier cache first
-    if let Some(data) = tier_cache.0.get() {
+    if let Some(data) = caches.0.get(CLAUDE_TIER).get() {
         debug_cache!("Returning cached Claude tier data");
         return Ok(data);
     }
 
-    let client = Arc::clone(&client.0);
-
-    debug_claude!("Calling check_and_refresh_if_needed for tier...");
-    if let Err(e) = ClaudeService::check_and_refresh_if_needed(client.clone()).await {
-        debug_claude!("check_and_refresh_if_needed failed: {e}");
-        return Err(e.to_string());
-    }
-    debug_claude!("check_and_refresh_if_needed succeeded");
-
-    debug_claude!("Calling claude_fetch_usage_and_tier for tier...");
-    match ClaudeService::claude_fetch_usage_and_tier(client).await {
-        Ok((usage_data, tier_data)) => {
-            let plan_name = &tier_data.plan_name;
-            debug_claude!("claude_fetch_usage_and_tier succeeded: plan={plan_name}");
-            // Cache both results to avoid duplicate fetches
-            usage_cache.0.set(usage_data);
-            tier_cache.0.set(tier_data.clone());
-            Ok(tier_data)
-        }
-        Err(e) => {
-            debug_claude!("claude_fetch_usage_and_tier failed: {e}");
-            Err(e.to_string())
-        }
-    }
+    // The tier only comes with a usage fetch, so fetch even if usage is still fresh
+    let result = claude_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        true,
+    )
+    .await;
+    serve_tier(
+        &history.0,
+        Provider::Claude,
+        &caches.0.get(CLAUDE_TIER),
+        result,
+    )
 }
 
 #[tauri::command]
 pub async fn codex_get_all(
     client: State<'_, HttpClient>,
-    usage_cache: State<'_, CodexUsageCache>,
-    tier_cache: State<'_, CodexTierCache>,
-) -> Result<(crate::models::CodexUsageData, crate::models::CodexTierData), String> {
-    if let (Some(usage), Some(tier)) = (usage_cache.0.get(), tier_cache.0.get()) {
-        debug_cache!("Returning cached Codex usage and tier data");
-        return Ok((usage, tier));
-    }
-
-    if !CodexService::codex_has_auth() {
-        return Err("Codex auth not configured".to_string());
-    }
-
-    match CodexService::codex_fetch_usage_and_tier(Arc::clone(&client.0)).await {
-        Ok((usage_data, tier_data)) => {
-            usage_cache.0.set(usage_data.clone());
-            tier_cache.0.set(tier_data.clone());
-            Ok((usage_data, tier_data))
-        }
-        Err(e) => Err(e.to_string()),
-    }
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<
+    (
+        Envelope<Arc<crate::models::CodexUsageData>>,
+        Arc<crate::models::CodexTierData>,
+    ),
+    UsageError,
+> {
+    let result = codex_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        caches.0.get(CODEX_TIER).get().is_none(),
+    )
+    .await;
+    serve_pair(
+        &history.0,
+        Provider::Codex,
+        &caches.0.get(CODEX_TIER),
+        result,
+    )
 }
 
 #[tauri::command]
 pub async fn codex_refresh_all(
     client: State<'_, HttpClient>,
-    usage_cache: State<'_, CodexUsageCache>,
-    tier_cache: State<'_, CodexTierCache>,
-) -> Result<(crate::models::CodexUsageData, crate::models::CodexTierData), String> {
-    usage_cache.0.clear();
-    tier_cache.0.clear();
-    codex_get_all(client, usage_cache, tier_cache).await
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<
+    (
+        Envelope<Arc<crate::models::CodexUsageData>>,
+        Arc<crate::models::CodexTierData>,
+    ),
+    UsageError,
+> {
+    let result = codex_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        true,
+    )
+    .await;
+    serve_pair(
+        &history.0,
+        Provider::Codex,
+        &caches.0.get(CODEX_TIER),
+        result,
+    )
 }
 
 #[tauri::command]
 pub async fn codex_get_usage(
     client: State<'_, HttpClient>,
-    usage_cache: State<'_, CodexUsageCache>,
-    tier_cache: State<'_, CodexTierCache>,
-) -> Result<crate::models::CodexUsageData, String> {
-    if let Some(data) = usage_cache.0.get() {
-        debug_cache!("Returning cached Codex usage data");
-        return Ok(data);
-    }
-
-    let (usage, _) = codex_get_all(client, usage_cache, tier_cache).await?;
-    Ok(usage)
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Envelope<Arc<crate::models::CodexUsageData>>, UsageError> {
+    let result = codex_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        false,
+    )
+    .await;
+    serve(&history.0, Provider::Codex, result)
 }
 
 #[tauri::command]
 pub async fn codex_get_tier(
     client: State<'_, HttpClient>,
-    usage_cache: State<'_, CodexUsageCache>,
-    tier_cache: State<'_, CodexTierCache>,
-) -> Result<crate::models::CodexTierData, String> {
-    if let Some(data) = tier_cache.0.get() {
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Arc<crate::models::CodexTierData>, UsageError> {
+    if let Some(data) = caches.0.get(CODEX_TIER).get() {
         debug_cache!("Returning cached Codex tier data");
         return Ok(data);
     }
 
-    let (_, tier) = codex_get_all(client, usage_cache, tier_cache).await?;
-    Ok(tier)
+    let result = codex_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        true,
+    )
+    .await;
+    serve_tier(
+        &history.0,
+        Provider::Codex,
+        &caches.0.get(CODEX_TIER),
+        result,
+    )
+}
+
+/// Claude credential sources in priority order, with the active one and the reason for each.
+#[tauri::command]
+pub async fn get_credential_sources() -> Vec<CredentialSourceStatus> {
+    CredentialManager::claude_credential_sources_async().await
+}
+
+/// Credentials moved or re-encoded by the startup migration.
+#[tauri::command]
+pub fn get_credential_migrations(
+    migrations: State<'_, CredentialMigrations>,
+) -> Vec<CredentialMigration> {
+    migrations.0.clone()
+}
+
+/// Organizations the Claude credential sources are logged in to; pick one with
+/// `claude_organization` in settings.json.
+#[tauri::command]
+pub async fn claude_get_organizations() -> Vec<ClaudeOrganization> {
+    CredentialManager::claude_organizations_async().await
 }
 
 #[tauri::command]
@@ -216,329 +882,553 @@ pub fn codex_check_auth() -> bool {
 #[tauri::command]
 pub async fn zai_get_all(
     client: State<'_, HttpClient>,
-    usage_cache: State<'_, ZaiUsageCache>,
-    tier_cache: State<'_, ZaiTierCache>,
-) -> Result<(crate::models::ZaiUsageData, crate::models::ZaiTierData), String> {
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<ZaiAll, UsageError> {
     debug_zai!("zai_get_all called");
 
-    let client = Arc::clone(&client.0);
-
-    if !ZaiService::zai_has_api_key() {
-        debug_zai!("Z.ai API key not configured");
-        return Err("Z.ai API key not configured".to_string());
-    }
-
-    if let (Some(usage), Some(tier)) = (usage_cache.0.get(), tier_cache.0.get()) {
-        debug_cache!("Returning cached Z.ai usage and tier data");
-        return Ok((usage, tier));
-    }
-
-    debug_zai!("Calling ZaiService::zai_fetch_quota...");
-    match ZaiService::zai_fetch_quota(client).await {
-        Ok(data) => {
-            debug_zai!("zai_fetch_quota succeeded, caching result");
-            let tier_data = crate::models::ZaiTierData {
-                plan_name: data
-                    .tier_name
-                    .clone()
-                    .unwrap_or_else(|| "Unknown".to_string()),
-            };
-            usage_cache.0.set(data.clone());
-            tier_cache.0.set(tier_data.clone());
-            Ok((data, tier_data))
-        }
-        Err(e) => {
-            debug_zai!("zai_fetch_quota failed: {e}");
-            Err(e.to_string())
-        }
-    }
+    let result = zai_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        caches.0.get(ZAI_TIER).get().is_none(),
+    )
+    .await;
+    let (usage, tier) = serve_pair(&history.0, Provider::Zai, &caches.0.get(ZAI_TIER), result)?;
+    let accounts = zai_accounts(Arc::clone(&client.0), &caches.0, &usage, false).await;
+    Ok((usage, tier, accounts))
 }
 
 #[tauri::command]
 pub async fn zai_refresh_all(
     client: State<'_, HttpClient>,
-    usage_cache: State<'_, ZaiUsageCache>,
-    tier_cache: State<'_, ZaiTierCache>,
-) -> Result<(crate::models::ZaiUsageData, crate::models::ZaiTierData), String> {
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<ZaiAll, UsageError> {
     debug_zai!("zai_refresh_all called (force refresh)");
 
-    // Clear cache before force-refresh to ensure fresh data
-    usage_cache.0.clear();
-    tier_cache.0.clear();
-
-    let client = Arc::clone(&client.0);
-
-    if !ZaiService::zai_has_api_key() {
-        debug_zai!("Z.ai API key not configured");
-        return Err("Z.ai API key not configured".to_string());
-    }
-
-    debug_zai!("Calling ZaiService::zai_fetch_quota...");
-    match ZaiService::zai_fetch_quota(client).await {
-        Ok(data) => {
-            debug_zai!("zai_fetch_quota succeeded, caching result");
-            let tier_data = crate::models::ZaiTierData {
-                plan_name: data
-                    .tier_name
-                    .clone()
-                    .unwrap_or_else(|| "Unknown".to_string()),
-            };
-            usage_cache.0.set(data.clone());
-            tier_cache.0.set(tier_data.clone());
-            Ok((data, tier_data))
-        }
-        Err(e) => {
-            debug_zai!("zai_fetch_quota failed: {e}");
-            Err(e.to_string())
-        }
-    }
+    let result = zai_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        true,
+    )
+    .await;
+    let (usage, tier) = serve_pair(&history.0, Provider::Zai, &caches.0.get(ZAI_TIER), result)?;
+    let accounts = zai_accounts(Arc::clone(&client.0), &caches.0, &usage, true).await;
+    Ok((usage, tier, accounts))
 }
 
 #[tauri::command]
 pub async fn zai_get_usage(
     client: State<'_, HttpClient>,
-    usage_cache: State<'_, ZaiUsageCache>,
-    tier_cache: State<'_, ZaiTierCache>,
-) -> Result<crate::models::ZaiUsageData, String> {
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Envelope<Arc<crate::models::ZaiUsageData>>, UsageError> {
     debug_zai!("zai_get_usage called");
 
-    // Check cache first
-    if let Some(data) = usage_cache.0.get() {
-        debug_cache!("Returning cached Z.ai usage data");
-        return Ok(data);
-    }
-
-    let client = Arc::clone(&client.0);
-
-    if !ZaiService::zai_has_api_key() {
-        debug_zai!("Z.ai API key not configured");
-        return Err("Z.ai API key not configured".to_string());
-    }
-
-    debug_zai!("Calling ZaiService::zai_fetch_quota...");
-    match ZaiService::zai_fetch_quota(client).await {
-        Ok(data) => {
-            debug_zai!("zai_fetch_quota succeeded, caching result");
-            // Also populate tier cache from the usage response
-            if let Some(tier_name) = &data.tier_name {
-                tier_cache.0.set(crate::models::ZaiTierData {
-                    plan_name: tier_name.clone(),
-                });
-            }
-            usage_cache.0.set(data.clone());
-            Ok(data)
-        }
-        Err(e) => {
-            debug_zai!("zai_fetch_quota failed: {e}");
-            Err(e.to_string())
-        }
-    }
+    let result = zai_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        false,
+    )
+    .await;
+    serve(&history.0, Provider::Zai, result)
 }
 
 #[tauri::command]
 pub async fn zai_refresh_usage(
     client: State<'_, HttpClient>,
-    usage_cache: State<'_, ZaiUsageCache>,
-    tier_cache: State<'_, ZaiTierCache>,
-) -> Result<crate::models::ZaiUsageData, String> {
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Envelope<Arc<crate::models::ZaiUsageData>>, UsageError> {
     debug_zai!("zai_refresh_usage called (force refresh)");
 
-    // Clear cache before force-refresh to ensure fresh data
-    usage_cache.0.clear();
-    tier_cache.0.clear();
-
-    let client = Arc::clone(&client.0);
-
-    if !ZaiService::zai_has_api_key() {
-        debug_zai!("Z.ai API key not configured");
-        return Err("Z.ai API key not configured".to_string());
-    }
-
-    debug_zai!("Calling ZaiService::zai_fetch_quota...");
-    match ZaiService::zai_fetch_quota(client).await {
-        Ok(data) => {
-            debug_zai!("zai_fetch_quota succeeded, caching result");
-            // Also populate tier cache from the usage response
-            if let Some(tier_name) = &data.tier_name {
-                tier_cache.0.set(crate::models::ZaiTierData {
-                    plan_name: tier_name.clone(),
-                });
-            }
-            usage_cache.0.set(data.clone());
-            Ok(data)
-        }
-        Err(e) => {
-            debug_zai!("zai_fetch_quota failed: {e}");
-            Err(e.to_string())
-        }
-    }
+    let result = zai_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        true,
+    )
+    .await;
+    serve(&history.0, Provider::Zai, result)
 }
 
 #[tauri::command]
 pub async fn zai_get_tier(
     client: State<'_, HttpClient>,
-    usage_cache: State<'_, ZaiUsageCache>,
-    tier_cache: State<'_, ZaiTierCache>,
-) -> Result<crate::models::ZaiTierData, String> {
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Arc<crate::models::ZaiTierData>, UsageError> {
     debug_zai!("zai_get_tier called");
 
-    // Check tier cache first
-    if let Some(data) = tier_cache.0.get() {
+    if let Some(data) = caches.0.get(ZAI_TIER).get() {
         debug_cache!("Returning cached Z.ai tier data");
         return Ok(data);
     }
 
-    if !ZaiService::zai_has_api_key() {
-        debug_zai!("Z.ai API key not configured");
-        return Err("Z.ai API key not configured".to_string());
-    }
+    let result = zai_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        true,
+    )
+    .await;
+    serve_tier(&history.0, Provider::Zai, &caches.0.get(ZAI_TIER), result)
+}
 
-    let client = Arc::clone(&client.0);
+/// Z.ai pay-as-you-go balance and spend, for API usage outside the coding plan. Cached like
+/// the quota; a failed fetch serves the last recorded balance.
+#[tauri::command]
+pub async fn zai_get_balance(
+    client: State<'_, HttpClient>,
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    settings: State<'_, SettingsState>,
+) -> Result<Envelope<Arc<ZaiBalanceData>>, UsageError> {
+    debug_zai!("zai_get_balance called");
+    if !settings.0.get().is_enabled(Provider::Zai) || !ZaiService::zai_has_api_key().await {
+        return Err(ErrorCode::NotConfigured.into());
+    }
+    let result = caches
+        .0
+        .get(ZAI_BALANCE)
+        .get_or_fetch(false, || async {
+            let data = ZaiService::zai_fetch_balance(Arc::clone(&client.0)).await?;
+            record_sample(&history.0, Provider::Zai, Series::Balance, &data);
+            Ok(data)
+        })
+        .await;
+    serve_series(&history.0, Provider::Zai, Series::Balance, result)
+}
 
-    debug_zai!("Calling ZaiService::zai_fetch_quota for tier...");
-    match ZaiService::zai_fetch_quota(client).await {
-        Ok(data) => {
-            let plan_name = data
-                .tier_name
-                .clone()
-                .unwrap_or_else(|| "Unknown".to_string());
-            debug_zai!("zai_fetch_quota succeeded: plan={plan_name}");
-            // Cache both results to avoid duplicate fetches
-            usage_cache.0.set(data);
-            let tier_data = crate::models::ZaiTierData { plan_name };
-            tier_cache.0.set(tier_data.clone());
-            Ok(tier_data)
-        }
-        Err(e) => {
-            debug_zai!("zai_fetch_quota failed: {e}");
-            Err(e.to_string())
-        }
+/// Z.ai calls and tokens per model inside `range` (today by default), most tokens first,
+/// to see which models use up the plan. Not cached.
+#[tauri::command]
+pub async fn zai_get_model_usage(
+    range: Option<DateRange>,
+    client: State<'_, HttpClient>,
+    settings: State<'_, SettingsState>,
+) -> Result<ZaiModelUsageReport, UsageError> {
+    debug_zai!("zai_get_model_usage called");
+    if !settings.0.get().is_enabled(Provider::Zai) || !ZaiService::zai_has_api_key().await {
+        return Err(ErrorCode::NotConfigured.into());
     }
+    let bounds = Bounds::resolve(range.unwrap_or_default()).map_err(UsageError::from)?;
+    ZaiService::zai_fetch_model_usage(Arc::clone(&client.0), bounds)
+        .await
+        .map_err(UsageError::from)
 }
 
 #[tauri::command]
 pub async fn amp_get_usage(
     amp_client: State<'_, AmpHttpClient>,
-    usage_cache: State<'_, AmpUsageCache>,
-) -> Result<crate::models::AmpUsageData, String> {
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Envelope<Arc<crate::models::AmpUsageData>>, UsageError> {
     debug_amp!("amp_get_usage called");
 
-    if let Some(data) = usage_cache.0.get() {
-        debug_cache!("Returning cached Amp usage data");
-        return Ok(data);
-    }
-
-    let client = Arc::clone(&amp_client.0);
-
-    if !AmpService::amp_has_session_cookie() {
-        debug_amp!("Amp session cookie not configured");
-        return Err("Amp session cookie not configured".to_string());
-    }
-
-    match AmpService::amp_fetch_usage(&client).await {
-        Ok(data) => {
-            debug_amp!("amp_fetch_usage succeeded, caching result");
-            usage_cache.0.set(data.clone());
-            Ok(data)
-        }
-        Err(e) => {
-            debug_amp!("amp_fetch_usage failed: {e}");
-            Err(e.to_string())
-        }
-    }
+    let result = amp_fetch(
+        Arc::clone(&amp_client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        false,
+    )
+    .await;
+    serve(&history.0, Provider::Amp, result)
 }
 
+/// The team workspace's shared credits, from the same settings data as `amp_get_usage`;
+/// `None` when the session isn't in a team (or Amp is read with an API key).
 #[tauri::command]
-pub async fn amp_refresh_usage(
+pub async fn amp_get_team_usage(
     amp_client: State<'_, AmpHttpClient>,
-    usage_cache: State<'_, AmpUsageCache>,
-) -> Result<crate::models::AmpUsageData, String> {
-    debug_amp!("amp_refresh_usage called (force refresh)");
-    // Clear cache before force-refresh to ensure fresh data
-    usage_cache.0.clear();
-
-    let client = Arc::clone(&amp_client.0);
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Option<AmpTeamUsageData>, UsageError> {
+    let usage = amp_get_usage(amp_client, caches, history, alerts, feed).await?;
+    Ok(usage.data.team.clone())
+}
 
-    if !AmpService::amp_has_session_cookie() {
-        debug_amp!("Amp session cookie not configured");
-        return Err("Amp session cookie not configured".to_string());
-    }
+#[tauri::command]
+pub async fn amp_refresh_usage(
+    amp_client: State<'_, AmpHttpClient>,
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Envelope<Arc<crate::models::AmpUsageData>>, UsageError> {
+    debug_amp!("amp_refresh_usage called (force refresh)");
 
-    match AmpService::amp_fetch_usage(&client).await {
-        Ok(data) => {
-            debug_amp!("amp_fetch_usage succeeded, caching result");
-            usage_cache.0.set(data.clone());
-            Ok(data)
-        }
-        Err(e) => {
-            debug_amp!("amp_fetch_usage failed: {e}");
-            Err(e.to_string())
-        }
-    }
+    let result = amp_fetch(
+        Arc::clone(&amp_client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        true,
+    )
+    .await;
+    serve(&history.0, Provider::Amp, result)
 }
 
 #[tauri::command]
-pub fn amp_check_session_cookie() -> bool {
+pub async fn amp_check_session_cookie() -> bool {
     debug_cred!("amp_check_session_cookie called");
-    let has_cookie = AmpService::amp_has_session_cookie();
+    let has_cookie = AmpService::amp_has_session_cookie().await;
     debug_cred!("[Amp] has_session_cookie: {has_cookie}");
     has_cookie
 }
 
 #[tauri::command]
-pub fn amp_save_session_cookie(cookie: String) -> Result<(), String> {
-    CredentialManager::amp_write_session_cookie(&cookie).map_err(|e| e.to_string())
+pub async fn amp_save_session_cookie(
+    alerts: State<'_, AlertState>,
+    cookie: String,
+) -> Result<(), UsageError> {
+    CredentialManager::amp_write_session_cookie_async(cookie)
+        .await
+        .map_err(UsageError::from)?;
+    AmpService::amp_reset_session();
+    report_amp_session(&alerts.0).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn amp_delete_session_cookie(alerts: State<'_, AlertState>) -> Result<(), UsageError> {
+    CredentialManager::amp_delete_session_cookie_async()
+        .await
+        .map_err(UsageError::from)?;
+    AmpService::amp_reset_session();
+    report_amp_session(&alerts.0).await;
+    Ok(())
 }
 
+/// Whether the saved Amp cookie is close to expiring or already rejected. Changes are also
+/// emitted as `amp-session-changed`.
 #[tauri::command]
-pub fn amp_delete_session_cookie() -> Result<(), String> {
-    CredentialManager::amp_delete_session_cookie().map_err(|e| e.to_string())
+pub async fn amp_get_session_status() -> AmpSessionStatus {
+    AmpService::amp_session_status().await
 }
 
 #[tauri::command]
 pub async fn amp_validate_session_cookie(
     amp_client: State<'_, AmpHttpClient>,
     cookie: String,
-) -> Result<(), String> {
+) -> Result<AmpAccount, UsageError> {
     let client = Arc::clone(&amp_client.0);
     AmpService::validate_session_cookie(&client, &cookie)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(UsageError::from)
 }
 
 #[tauri::command]
-pub fn zai_check_api_key() -> bool {
-    debug_cred!("zai_check_api_key called");
-    let has_key = ZaiService::zai_has_api_key();
-    debug_cred!("[Z.ai] has_api_key: {has_key}");
+pub async fn amp_import_cookie_from_browser(
+    amp_client: State<'_, AmpHttpClient>,
+    alerts: State<'_, AlertState>,
+) -> Result<AmpCookieImport, UsageError> {
+    let client = Arc::clone(&amp_client.0);
+    let import = AmpService::import_cookie_from_browser(&client)
+        .await
+        .map_err(UsageError::from)?;
+    AmpService::amp_reset_session();
+    report_amp_session(&alerts.0).await;
+    Ok(import)
+}
+
+#[tauri::command]
+pub async fn amp_check_api_key() -> bool {
+    debug_cred!("amp_check_api_key called");
+    let has_key = AmpService::amp_has_api_key().await;
+    debug_cred!("[Amp] has_api_key: {has_key}");
+    has_key
+}
+
+#[tauri::command]
+pub async fn amp_validate_api_key(
+    amp_client: State<'_, AmpHttpClient>,
+    api_key: String,
+) -> Result<(), UsageError> {
+    let client = Arc::clone(&amp_client.0);
+    AmpService::validate_api_key(&client, &api_key)
+        .await
+        .map_err(UsageError::from)
+}
+
+#[tauri::command]
+pub async fn amp_save_api_key(api_key: String) -> Result<(), UsageError> {
+    CredentialManager::amp_write_api_key_async(api_key.trim().to_string())
+        .await
+        .map_err(UsageError::from)
+}
+
+#[tauri::command]
+pub async fn amp_delete_api_key() -> Result<(), UsageError> {
+    CredentialManager::amp_delete_api_key_async()
+        .await
+        .map_err(UsageError::from)
+}
+
+#[tauri::command]
+pub async fn anthropic_get_usage(
+    client: State<'_, HttpClient>,
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Envelope<Arc<AnthropicUsageData>>, UsageError> {
+    debug_app!("anthropic_get_usage called");
+
+    let result = anthropic_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        false,
+    )
+    .await;
+    serve(&history.0, Provider::Anthropic, result)
+}
+
+#[tauri::command]
+pub async fn anthropic_refresh_usage(
+    client: State<'_, HttpClient>,
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Envelope<Arc<AnthropicUsageData>>, UsageError> {
+    debug_app!("anthropic_refresh_usage called (force refresh)");
+
+    let result = anthropic_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        true,
+    )
+    .await;
+    serve(&history.0, Provider::Anthropic, result)
+}
+
+#[tauri::command]
+pub async fn anthropic_check_admin_key() -> bool {
+    debug_cred!("anthropic_check_admin_key called");
+    let has_key = AnthropicService::anthropic_has_admin_key().await;
+    debug_cred!("[Anthropic] has_admin_key: {has_key}");
     has_key
 }
 
+#[tauri::command]
+pub async fn anthropic_save_admin_key(api_key: String) -> Result<(), UsageError> {
+    CredentialManager::anthropic_write_admin_key_async(api_key)
+        .await
+        .map_err(UsageError::from)
+}
+
+#[tauri::command]
+pub async fn anthropic_delete_admin_key() -> Result<(), UsageError> {
+    CredentialManager::anthropic_delete_admin_key_async()
+        .await
+        .map_err(UsageError::from)
+}
+
+#[tauri::command]
+pub async fn anthropic_validate_admin_key(
+    client: State<'_, HttpClient>,
+    api_key: String,
+) -> Result<(), UsageError> {
+    AnthropicService::validate_admin_key(Arc::clone(&client.0), &api_key)
+        .await
+        .map_err(UsageError::from)
+}
+
+#[tauri::command]
+pub async fn gemini_get_usage(
+    client: State<'_, HttpClient>,
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Envelope<Arc<GeminiUsageData>>, UsageError> {
+    debug_app!("gemini_get_usage called");
+
+    let result = gemini_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        false,
+    )
+    .await;
+    serve(&history.0, Provider::Gemini, result)
+}
+
+#[tauri::command]
+pub async fn gemini_refresh_usage(
+    client: State<'_, HttpClient>,
+    caches: State<'_, CacheState>,
+    history: State<'_, UsageHistory>,
+    alerts: State<'_, AlertState>,
+    feed: State<'_, FeedState>,
+) -> Result<Envelope<Arc<GeminiUsageData>>, UsageError> {
+    debug_app!("gemini_refresh_usage called (force refresh)");
+
+    let result = gemini_fetch(
+        Arc::clone(&client.0),
+        &caches.0,
+        &history.0,
+        &alerts.0,
+        &feed.0,
+        true,
+    )
+    .await;
+    serve(&history.0, Provider::Gemini, result)
+}
+
+/// Whether OAuth credentials (stored, or gcloud's application default login) are available.
+#[tauri::command]
+pub async fn gemini_check_credentials() -> bool {
+    debug_cred!("gemini_check_credentials called");
+    let has_credentials = GeminiService::gemini_has_credentials().await;
+    debug_cred!("[Gemini] has_credentials: {has_credentials}");
+    has_credentials
+}
+
+/// Stores the contents of an `authorized_user` credential file
+/// (`application_default_credentials.json`).
+#[tauri::command]
+pub async fn gemini_save_oauth_credentials(json: String) -> Result<(), UsageError> {
+    CredentialManager::gemini_write_oauth_credentials_async(json)
+        .await
+        .map_err(UsageError::from)?;
+    GeminiService::gemini_reset_session();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn gemini_delete_oauth_credentials() -> Result<(), UsageError> {
+    CredentialManager::gemini_delete_oauth_credentials_async()
+        .await
+        .map_err(UsageError::from)?;
+    GeminiService::gemini_reset_session();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn gemini_save_api_key(api_key: String) -> Result<(), UsageError> {
+    CredentialManager::gemini_write_api_key_async(api_key)
+        .await
+        .map_err(UsageError::from)?;
+    GeminiService::gemini_reset_session();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn gemini_delete_api_key() -> Result<(), UsageError> {
+    CredentialManager::gemini_delete_api_key_async()
+        .await
+        .map_err(UsageError::from)?;
+    GeminiService::gemini_reset_session();
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn gemini_validate_api_key(
+    client: State<'_, HttpClient>,
+    api_key: String,
+) -> Result<(), UsageError> {
+    GeminiService::validate_api_key(Arc::clone(&client.0), &api_key)
+        .await
+        .map_err(UsageError::from)
+}
+
+/// Where the Z.ai key comes from (`stored`, `environment`, `claude_settings`, `open_code`), or
+/// `null` without one.
+#[tauri::command]
+pub async fn zai_check_api_key() -> Option<ZaiKeySource> {
+    debug_cred!("zai_check_api_key called");
+    let source = CredentialManager::zai_api_key_source_async().await;
+    debug_cred!("[Z.ai] API key source: {source:?}");
+    source
+}
+
+/// Checks `api_key` against the base URL of `label` (the main key's by default). A valid
+/// key's quota and plan are returned, so setup can confirm the tier and reset time; `null`
+/// for an `{env:...}` reference, which isn't checked.
 #[tauri::command]
 pub async fn zai_validate_api_key(
     client: State<'_, HttpClient>,
     api_key: String,
-) -> Result<(), String> {
+    label: Option<String>,
+) -> Result<Option<ZaiUsageData>, UsageError> {
     debug_zai!("zai_validate_api_key called");
     let client = Arc::clone(&client.0);
-    ZaiService::validate_api_key(client, &api_key)
+    let label = label
+        .as_deref()
+        .unwrap_or(CredentialManager::ZAI_DEFAULT_LABEL);
+    ZaiService::validate_api_key(client, label, &api_key)
         .await
-        .map_err(|e| e.to_string())
+        .map_err(UsageError::from)
 }
 
+/// Saves the Z.ai key, or with `label` a named one whose quota `zai_get_all` also returns.
 #[tauri::command]
-pub fn zai_save_api_key(api_key: String) -> Result<(), String> {
-    CredentialManager::zai_write_api_key(&api_key).map_err(|e| e.to_string())
+pub async fn zai_save_api_key(api_key: String, label: Option<String>) -> Result<(), UsageError> {
+    match label {
+        Some(label) => CredentialManager::zai_write_named_api_key_async(label, api_key).await,
+        None => CredentialManager::zai_write_api_key_async(api_key).await,
+    }
+    .map_err(UsageError::from)
+}
+
+#[tauri::command]
+pub async fn zai_delete_api_key(label: Option<String>) -> Result<(), UsageError> {
+    match label {
+        Some(label) => CredentialManager::zai_delete_named_api_key_async(label).await,
+        None => CredentialManager::zai_delete_api_key_async().await,
+    }
+    .map_err(UsageError::from)
 }
 
+/// Labels of the named Z.ai keys saved with `zai_save_api_key`.
 #[tauri::command]
-pub fn zai_delete_api_key() -> Result<(), String> {
-    CredentialManager::zai_delete_api_key().map_err(|e| e.to_string())
+pub async fn zai_list_api_keys() -> Vec<String> {
+    CredentialManager::zai_labels_async().await
 }
 
 #[cfg(target_os = "windows")]
 #[tauri::command]
-pub fn open_url(url: String) -> Result<(), String> {
+pub fn open_url(url: String) -> Result<(), UsageError> {
     use std::ffi::OsStr;
     use std::os::windows::ffi::OsStrExt;
     use windows::core::PCWSTR;
@@ -546,11 +1436,8 @@ pub fn open_url(url: String) -> Result<(), String> {
     use windows::Win32::UI::Shell::ShellExecuteW;
     use windows::Win32::UI::WindowsAndMessaging::SW_SHOW;
 
-    // Validate URL scheme using the URL parser — rejects javascript:, data:, file:, malformed URLs.
-    let parsed = reqwest::Url::parse(&url).map_err(|_| "Invalid URL format".to_string())?;
-    if parsed.scheme() != "http" && parsed.scheme() != "https" {
-        return Err("URL must use http or https scheme".to_string());
-    }
+    // Only https links to allow-listed sites reach the shell; see links.rs.
+    let url = links::validate(&url)?.to_string();
 
     unsafe {
         let init_result = CoInitializeEx(None, COINIT_MULTITHREADED);
@@ -560,7 +1447,9 @@ pub fn open_url(url: String) -> Result<(), String> {
             let hresult = init_result;
             if hresult.0 != 1 && hresult.0 != RPC_E_CHANGED_MODE {
                 let hresult_code = hresult.0;
-                return Err(format!("Failed to initialize COM: HRESULT={hresult_code}"));
+                return Err(UsageError::other(format!(
+                    "Failed to initialize COM: HRESULT={hresult_code}"
+                )));
             }
         }
 
@@ -579,7 +1468,9 @@ pub fn open_url(url: String) -> Result<(), String> {
         // ShellExecuteW returns a value > 32 on success
         if result.0 as i32 <= 32 {
             let error_code = result.0 as i32;
-            return Err(format!("Failed to open URL: error code {error_code}"));
+            return Err(UsageError::other(format!(
+                "Failed to open URL: error code {error_code}"
+            )));
         }
     }
     Ok(())
@@ -587,19 +1478,16 @@ pub fn open_url(url: String) -> Result<(), String> {
 
 #[cfg(not(target_os = "windows"))]
 #[tauri::command]
-pub fn open_url(url: String) -> Result<(), String> {
-    // Validate URL scheme using the URL parser — rejects javascript:, data:, file:, malformed URLs.
-    let parsed = reqwest::Url::parse(&url).map_err(|_| "Invalid URL format".to_string())?;
-    if parsed.scheme() != "http" && parsed.scheme() != "https" {
-        return Err("URL must use http or https scheme".to_string());
-    }
+pub fn open_url(url: String) -> Result<(), UsageError> {
+    // Only https links to allow-listed sites reach the shell; see links.rs.
+    let url = links::validate(&url)?.to_string();
 
     std::process::Command::new("open")
         .arg(&url)
         .status()
         .or_else(|_| std::process::Command::new("xdg-open").arg(&url).status())
         .map(|_| ())
-        .map_err(|e| format!("Failed to open URL: {e}"))
+        .map_err(|e| UsageError::other(format!("Failed to open URL: {e}")))
 }
 
 #[tauri::command]
@@ -608,126 +1496,564 @@ pub fn quit_app(app: tauri::AppHandle) {
 }
 
 #[tauri::command]
-#[allow(clippy::too_many_arguments)]
-pub async fn refresh_all(
-    client: State<'_, HttpClient>,
-    amp_client: State<'_, AmpHttpClient>,
-    claude_usage_cache: State<'_, ClaudeUsageCache>,
-    claude_tier_cache: State<'_, ClaudeTierCache>,
-    codex_usage_cache: State<'_, CodexUsageCache>,
-    codex_tier_cache: State<'_, CodexTierCache>,
-    zai_usage_cache: State<'_, ZaiUsageCache>,
-    zai_tier_cache: State<'_, ZaiTierCache>,
-    amp_usage_cache: State<'_, AmpUsageCache>,
-) -> Result<RefreshAllResult, String> {
-    let client = Arc::clone(&client.0);
+pub fn get_settings(settings: State<'_, SettingsState>) -> Settings {
+    settings.0.get()
+}
 
-    // Clear cache before force-refresh to ensure fresh data
-    claude_usage_cache.0.clear();
-    claude_tier_cache.0.clear();
-    codex_usage_cache.0.clear();
-    codex_tier_cache.0.clear();
-    zai_usage_cache.0.clear();
-    zai_tier_cache.0.clear();
-    amp_usage_cache.0.clear();
-
-    // Fetch all APIs in parallel using tokio::join!
-    let (claude_result, codex_result, zai_result, amp_result) = tokio::join!(
-        async {
-            if let Err(e) = ClaudeService::check_and_refresh_if_needed(client.clone()).await {
-                return Err(e.to_string());
-            }
-            match ClaudeService::claude_fetch_usage_and_tier(client.clone()).await {
-                Ok((usage_data, tier_data)) => {
-                    claude_usage_cache.0.set(usage_data.clone());
-                    claude_tier_cache.0.set(tier_data);
-                    Ok(Some(usage_data))
-                }
-                Err(e) => Err(e.to_string()),
-            }
-        },
-        async {
-            if CodexService::codex_has_auth() {
-                match CodexService::codex_fetch_usage_and_tier(client.clone()).await {
-                    Ok((usage_data, tier_data)) => {
-                        codex_usage_cache.0.set(usage_data.clone());
-                        codex_tier_cache.0.set(tier_data);
-                        Ok(Some(usage_data))
-                    }
-                    Err(e) => Err(e.to_string()),
-                }
-            } else {
-                Ok(None)
-            }
-        },
-        async {
-            if ZaiService::zai_has_api_key() {
-                match ZaiService::zai_fetch_quota(client.clone()).await {
-                    Ok(data) => {
-                        if let Some(tier_name) = &data.tier_name {
-                            zai_tier_cache.0.set(crate::models::ZaiTierData {
-                                plan_name: tier_name.clone(),
-                            });
-                        }
-                        zai_usage_cache.0.set(data.clone());
-                        Ok(Some(data))
-                    }
-                    Err(e) => Err(e.to_string()),
-                }
-            } else {
-                Ok(None)
-            }
-        },
-        async {
-            if AmpService::amp_has_session_cookie() {
-                let amp = Arc::clone(&amp_client.0);
-                match AmpService::amp_fetch_usage(&amp).await {
-                    Ok(data) => {
-                        amp_usage_cache.0.set(data.clone());
-                        Ok(Some(data))
-                    }
-                    Err(e) => Err(e.to_string()),
-                }
-            } else {
-                Ok(None)
-            }
-        }
-    );
+/// Saves `settings` to settings.json and applies them. Emits `settings-changed` like an
+/// edit on disk would; invalid settings are rejected and nothing is written.
+#[tauri::command]
+pub fn update_settings(
+    app: tauri::AppHandle,
+    settings_state: State<'_, SettingsState>,
+    settings: Settings,
+) -> Result<Settings, UsageError> {
+    settings_state
+        .0
+        .update(settings.clone())
+        .map_err(|e| UsageError::other(e.to_string()))?;
+    settings::apply_to_app(&app, &settings);
+    debug_app!("Settings updated from the popup");
+    if app.emit("settings-changed", &settings).is_err() {
+        debug_error!("Failed to emit settings-changed");
+    }
+    Ok(settings)
+}
 
-    let (claude, claude_error) = match claude_result {
-        Ok(data) => (data, None),
-        Err(e) => {
-            debug_claude!("refresh_all: Claude failed: {e}");
-            (None, Some(e))
-        }
+/// Switches Claude to the `claude_profiles` entry `name`, or back to
+/// `claude_credential_sources` with `None`, and saves the choice to settings.json.
+#[tauri::command]
+pub fn claude_switch_profile(
+    app: tauri::AppHandle,
+    settings_state: State<'_, SettingsState>,
+    name: Option<String>,
+) -> Result<Settings, UsageError> {
+    debug_claude!("claude_switch_profile called: {name:?}");
+    let mut settings = settings_state.0.get();
+    settings.claude_active_profile = name;
+    update_settings(app, settings_state, settings)
+}
+
+/// Effective per-provider refresh intervals, with overrides and floors applied.
+#[tauri::command]
+pub fn get_poll_intervals(settings: State<'_, SettingsState>) -> PollIntervals {
+    settings.0.get().poll_intervals()
+}
+
+/// Whether the session is locked or presenting, for a popup that loads while it is.
+#[tauri::command]
+pub fn get_session_state() -> SessionState {
+    presence::current()
+}
+
+/// Why the settings file on disk is being ignored, if it is.
+#[tauri::command]
+pub fn get_settings_error(settings: State<'_, SettingsState>) -> Option<String> {
+    settings.0.error()
+}
+
+#[tauri::command]
+pub async fn refresh_all(app: AppHandle) -> Result<RefreshAllResult, UsageError> {
+    // `force` skips fresh cache entries, never a 429 backoff
+    Ok(fetch_providers(&app, &Provider::ALL, true).await)
+}
+
+#[tauri::command]
+pub fn get_history_db_stats(history: State<'_, UsageHistory>) -> Result<HistoryStats, UsageError> {
+    history.0.stats().map_err(UsageError::from)
+}
+
+/// Provider responses saved because they failed to parse, newest first.
+#[tauri::command]
+pub fn list_failed_payloads() -> Vec<FailedPayloadEntry> {
+    failed_payloads::list()
+}
+
+/// Opens the failed payloads folder so the files can be attached to a bug report.
+#[tauri::command]
+pub fn open_failed_payloads_folder() -> Result<(), UsageError> {
+    let dir =
+        failed_payloads::dir().ok_or_else(|| UsageError::other("App data folder unavailable"))?;
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| UsageError::other(format!("Failed to create folder: {e}")))?;
+    let opener = if cfg!(target_os = "windows") {
+        "explorer"
+    } else {
+        "xdg-open"
     };
-    let (codex, codex_error) = match codex_result {
-        Ok(data) => (data, None),
-        Err(e) => (None, Some(e)),
+    // explorer exits non-zero even when it opens the folder, so only spawning is checked.
+    std::process::Command::new(opener)
+        .arg(&dir)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| UsageError::other(format!("Failed to open folder: {e}")))
+}
+
+/// Compacts `history.db`. A vacuum rewrites the whole file, so it runs on a blocking thread.
+#[tauri::command]
+pub async fn vacuum_history_db(app: AppHandle) -> Result<HistoryStats, UsageError> {
+    debug_app!("Vacuuming history database");
+    tauri::async_runtime::spawn_blocking(move || {
+        let history = &app.state::<UsageHistory>().0;
+        history.vacuum()?;
+        history.stats()
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Vacuum task failed: {e}"))?
+    .map_err(UsageError::from)
+}
+
+#[tauri::command]
+pub fn get_alert_rules(alerts: State<'_, AlertState>) -> Vec<AlertRule> {
+    alerts.0.rules()
+}
+
+/// Adds or replaces a rule (matched by id) and returns it with its id filled in.
+#[tauri::command]
+pub fn save_alert_rule(
+    rule: AlertRule,
+    alerts: State<'_, AlertState>,
+) -> Result<AlertRule, UsageError> {
+    alerts.0.save_rule(rule).map_err(UsageError::from)
+}
+
+/// Settings and alert rules with secrets and personal paths replaced by `{env:NAME}`
+/// placeholders, for sharing a team setup.
+#[tauri::command]
+pub async fn export_config_template(
+    settings: State<'_, SettingsState>,
+    alerts: State<'_, AlertState>,
+) -> Result<ConfigTemplate, UsageError> {
+    let stored = StoredCredentials {
+        zai_api_key: CredentialManager::zai_has_api_key_async().await,
+        amp_session_cookie: CredentialManager::amp_has_session_cookie_async().await,
+        amp_api_key: CredentialManager::amp_has_api_key_async().await,
+        anthropic_admin_key: CredentialManager::anthropic_has_admin_key_async().await,
+        gemini_api_key: CredentialManager::gemini_has_api_key_async().await,
     };
-    let (zai, zai_error) = match zai_result {
-        Ok(data) => (data, None),
-        Err(e) => {
-            debug_zai!("refresh_all: Z.ai failed: {e}");
-            (None, Some(e))
-        }
+    template::build(&settings.0.get(), alerts.0.rules(), stored)
+        .map_err(|e| UsageError::other(e.to_string()))
+}
+
+#[tauri::command]
+pub fn delete_alert_rule(id: String, alerts: State<'_, AlertState>) -> Result<(), UsageError> {
+    alerts.0.delete_rule(&id).map_err(UsageError::from)
+}
+
+/// Parses a condition without saving it, so the UI can show errors while typing.
+#[tauri::command]
+pub fn validate_alert_condition(condition: String) -> Result<(), UsageError> {
+    Condition::parse(&condition)
+        .map(|_| ())
+        .map_err(UsageError::from)
+}
+
+/// Burn rate and time to the limit for the Claude 5-hour window, from the usage samples
+/// polled during it. Never makes a request itself.
+#[tauri::command]
+pub fn claude_get_forecast(history: State<'_, UsageHistory>) -> Result<BurnForecast, UsageError> {
+    let now = clock::now_millis();
+    let samples = history
+        .0
+        .since(
+            Provider::Claude,
+            Series::Usage,
+            now - forecast::WINDOW_MILLIS,
+        )
+        .map_err(UsageError::from)?;
+    Ok(forecast::five_hour(&samples, now))
+}
+
+/// Prompt rate and time to the Z.ai `TIME_LIMIT` allowance for the current 5-hour window,
+/// from the usage samples polled during it. Never makes a request itself.
+#[tauri::command]
+pub fn zai_get_forecast(history: State<'_, UsageHistory>) -> Result<ZaiPromptForecast, UsageError> {
+    let now = clock::now_millis();
+    let samples = history
+        .0
+        .since(Provider::Zai, Series::Usage, now - forecast::WINDOW_MILLIS)
+        .map_err(UsageError::from)?;
+    Ok(forecast::zai_prompts(&samples, now))
+}
+
+/// Claude's 5-hour, 7-day and extra usage over time from the samples recorded on each
+/// successful fetch, for graphs. `range` defaults to today; with a `bucket` (`hour` or `day`)
+/// each point holds the highest readings in that bucket.
+#[tauri::command]
+pub fn claude_get_history(
+    range: Option<DateRange>,
+    bucket: Option<HistoryBucket>,
+    history: State<'_, UsageHistory>,
+) -> Result<Vec<ClaudeHistoryPoint>, UsageError> {
+    let bounds = Bounds::resolve(range.unwrap_or_default()).map_err(UsageError::from)?;
+    let samples: Vec<history::Sample<UsageData>> = history
+        .0
+        .since(Provider::Claude, Series::Usage, bounds.since)
+        .map_err(UsageError::from)?
+        .into_iter()
+        .filter(|sample| bounds.contains(sample.fetched_at))
+        .collect();
+    let utc_offset_millis = SystemClock.now_local().offset().local_minus_utc() as i64 * 1000;
+    Ok(history::claude_points(&samples, bucket, utc_offset_millis))
+}
+
+/// Z.ai's token and MCP percentages over time from the samples recorded on each
+/// successful fetch, with the 5-hour token windows they span and per-week totals, so
+/// typical use per window and per week can be read off. `range` defaults to today;
+/// `bucket` groups the points as in `claude_get_history`.
+#[tauri::command]
+pub fn zai_get_history(
+    range: Option<DateRange>,
+    bucket: Option<HistoryBucket>,
+    history: State<'_, UsageHistory>,
+) -> Result<ZaiHistory, UsageError> {
+    let bounds = Bounds::resolve(range.unwrap_or_default()).map_err(UsageError::from)?;
+    let samples: Vec<history::Sample<ZaiUsageData>> = history
+        .0
+        .since(Provider::Zai, Series::Usage, bounds.since)
+        .map_err(UsageError::from)?
+        .into_iter()
+        .filter(|sample| bounds.contains(sample.fetched_at))
+        .collect();
+    let utc_offset_millis = SystemClock.now_local().offset().local_minus_utc() as i64 * 1000;
+    Ok(history::zai_history(&samples, bucket, utc_offset_millis))
+}
+
+/// Pre-flight check for wrapper scripts: is there room for `estimate` in the current
+/// windows? Checks `provider` only if given, otherwise answers whether any provider has room.
+/// Uses the latest polled data and never makes a request itself.
+#[tauri::command]
+pub fn can_i_run(
+    estimate: RunEstimate,
+    provider: Option<Provider>,
+    app: AppHandle,
+    registry: State<'_, ProviderRegistryState>,
+) -> HeadroomCheck {
+    let checks = registry
+        .0
+        .iter()
+        .filter(|source| provider.is_none_or(|only| only == source.info().id))
+        .map(|source| {
+            let usage = source.last_usage(&app).map(|(usage, _)| usage);
+            headroom::check_provider(source.info().id, usage.as_ref(), estimate)
+        })
+        .collect();
+    headroom::summarize(checks)
+}
+
+/// Shifts the clock used for reset, expiry, countdown and alert-hour math by `offset_secs`
+/// (0 restores real time). Debug builds only. Returns the shifted "now" in epoch millis.
+#[tauri::command]
+pub fn debug_set_clock_offset(offset_secs: i64) -> Result<i64, UsageError> {
+    if !cfg!(debug_assertions) {
+        return Err(UsageError::other(
+            "Clock offset is only available in debug builds",
+        ));
+    }
+    clock::set_offset_millis(offset_secs.saturating_mul(1000));
+    let now = clock::now_millis();
+    debug_app!("Clock offset set to {offset_secs}s (now={now})");
+    Ok(now)
+}
+
+/// Saves a sanitized copy of the current Amp settings page as a parser fixture.
+/// Debug builds only. Returns the fixture path.
+#[tauri::command]
+pub async fn debug_record_amp_fixture(
+    amp_client: State<'_, AmpHttpClient>,
+) -> Result<String, UsageError> {
+    if !cfg!(debug_assertions) {
+        return Err(UsageError::other(
+            "Fixture recording is only available in debug builds",
+        ));
+    }
+    AmpService::record_fixture(&amp_client.0)
+        .await
+        .map(|path| path.display().to_string())
+        .map_err(UsageError::from)
+}
+
+async fn refresh_provider(app: &AppHandle, provider: Provider) -> Result<(), UsageError> {
+    let registry = &app.state::<ProviderRegistryState>().inner().0;
+    let source = registry.get(provider).ok_or(ErrorCode::NotConfigured)?;
+    source.fetch_usage(app, true).await.map(|_| ())
+}
+
+/// How far back `seed_caches` looks; older samples would only show long-expired windows.
+const SEED_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+fn seed<T: DeserializeOwned + Send + Sync + 'static>(
+    caches: &CacheMap,
+    history: &HistoryStore,
+    key: CacheKey<T>,
+) {
+    let series = match key.endpoint {
+        Endpoint::Usage => Series::Usage,
+        Endpoint::Tier => Series::Tier,
+        Endpoint::Balance => Series::Balance,
+        // Named Z.ai keys aren't recorded.
+        Endpoint::Accounts => return,
     };
-    let (amp, amp_error) = match amp_result {
-        Ok(data) => (data, None),
-        Err(e) => {
-            debug_amp!("refresh_all: Amp failed: {e}");
-            (None, Some(e))
+    let sample = history
+        .latest::<T>(key.provider, series, SEED_MAX_AGE)
+        .unwrap_or_else(|e| {
+            debug_error!("Failed to read {:?} history: {e}", key.provider);
+            None
+        });
+    if let Some(sample) = sample {
+        let age = (clock::now_millis() - sample.fetched_at).max(0) as u64;
+        caches
+            .get(key)
+            .seed(sample.data, Duration::from_millis(age));
+    }
+}
+
+/// Loads the last sample of every provider from history into the caches at startup, already
+/// expired, so the tray has data before the first fetch lands. Commands still fetch.
+pub fn seed_caches(app: &AppHandle) {
+    let caches = &app.state::<CacheState>().0;
+    let history = &app.state::<UsageHistory>().0;
+    seed(caches, history, CLAUDE_USAGE);
+    seed(caches, history, CLAUDE_TIER);
+    seed(caches, history, CODEX_USAGE);
+    seed(caches, history, CODEX_TIER);
+    seed(caches, history, ZAI_USAGE);
+    seed(caches, history, ZAI_TIER);
+    seed(caches, history, ZAI_BALANCE);
+    seed(caches, history, AMP_USAGE);
+    seed(caches, history, ANTHROPIC_USAGE);
+    seed(caches, history, GEMINI_USAGE);
+    debug_cache!("Caches seeded from history");
+}
+
+/// Called when the main window gains focus: refreshes each provider whose data is older
+/// than `refresh_on_show_stale_secs`. Results reach the popup through `subscribe_usage`.
+pub fn refresh_stale_on_show(app: &AppHandle) {
+    let settings = app.state::<SettingsState>().0.get();
+    if !settings.refresh_on_show {
+        return;
+    }
+    let stale_after = Duration::from_secs(settings.refresh_on_show_stale_secs);
+    let caches = &app.state::<CacheState>().0;
+
+    for provider in Provider::ALL {
+        if !settings.is_enabled(provider) {
+            continue;
         }
-    };
+        if caches
+            .usage_age(provider)
+            .is_some_and(|age| age < stale_after)
+        {
+            continue;
+        }
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            debug_cache!("Window shown, refreshing stale {provider:?} data");
+            if let Err(e) = refresh_provider(&app, provider).await {
+                debug_error!("Refresh on show failed for {provider:?}: {e}");
+            }
+        });
+    }
+}
+
+fn clear_caches(app: &AppHandle, provider: Provider) {
+    app.state::<CacheState>().0.clear(provider);
+}
+
+/// Fetches every provider in the named group in parallel, through the caches unless `force`.
+async fn fetch_group(
+    app: &AppHandle,
+    name: &str,
+    force: bool,
+) -> Result<RefreshAllResult, UsageError> {
+    let providers = app
+        .state::<SettingsState>()
+        .0
+        .get()
+        .group(name)
+        .map(|group| group.providers.clone())
+        .ok_or_else(|| UsageError::other(format!("Unknown provider group: {name}")))?;
+    debug_app!("Fetching group {name} ({providers:?}), force: {force}");
+
+    if force {
+        for &provider in &providers {
+            clear_caches(app, provider);
+        }
+    }
+    Ok(fetch_providers(app, &providers, false).await)
+}
+
+/// Fetches `providers` in parallel through the registry (caches, backoff, history), skipping
+/// fresh cache entries when `force`. Disabled providers and those without credentials are
+/// left out.
+async fn fetch_providers(app: &AppHandle, providers: &[Provider], force: bool) -> RefreshAllResult {
+    let settings = app.state::<SettingsState>().0.get();
+    let registry = &app.state::<ProviderRegistryState>().inner().0;
+    let tasks: Vec<_> = registry
+        .iter()
+        .map(|source| source.info().id)
+        .filter(|id| providers.contains(id) && settings.is_enabled(*id))
+        .map(|id| {
+            let app = app.clone();
+            let task = tauri::async_runtime::spawn(async move {
+                let registry = &app.state::<ProviderRegistryState>().inner().0;
+                let source = registry.get(id)?;
+                if !source.has_credentials().await {
+                    return None;
+                }
+                Some(source.fetch_usage(&app, force).await)
+            });
+            (id, task)
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    for (provider, task) in tasks {
+        let result = match task.await {
+            Ok(Some(result)) => result,
+            Ok(None) => continue,
+            Err(e) => Err(UsageError::other(e.to_string())),
+        };
+        if let Err(e) = &result {
+            debug_error!("{provider:?} fetch failed: {e}");
+        }
+        results.push((provider, result));
+    }
+    RefreshAllResult(results)
+}
 
-    Ok(RefreshAllResult {
-        claude,
-        codex,
-        zai,
-        amp,
-        claude_error,
-        codex_error,
-        zai_error,
-        amp_error,
+/// When `envelope`'s data came from the provider: the history sample's time, else the
+/// cache's.
+fn fetched_at<T>(app: &AppHandle, provider: Provider, envelope: &Envelope<T>) -> i64 {
+    let now = clock::now_millis();
+    envelope.stale_since.unwrap_or_else(|| {
+        app.state::<CacheState>()
+            .0
+            .usage_age(provider)
+            .map_or(now, |age| now - age.as_millis() as i64)
     })
 }
+
+/// Every configured provider's usage in one shape, served from cache where fresh. Providers
+/// without credentials or whose fetch failed are left out, and so is Anthropic API spend,
+/// which has no limit to report a percentage of.
+#[tauri::command]
+pub async fn get_all_snapshots(app: AppHandle) -> Vec<UsageSnapshot> {
+    let RefreshAllResult(results) = fetch_providers(&app, &Provider::ALL, false).await;
+    let registry = &app.state::<ProviderRegistryState>().inner().0;
+    results
+        .into_iter()
+        .filter_map(|(provider, result)| {
+            let usage = result.ok()?;
+            let fetched_at = fetched_at(&app, provider, &usage);
+            registry
+                .get(provider)?
+                .snapshot(&app, &usage.data, fetched_at)
+        })
+        .collect()
+}
+
+/// Usage for the providers in a settings group, served from cache where fresh.
+#[tauri::command]
+pub async fn get_group_usage(app: AppHandle, name: String) -> Result<RefreshAllResult, UsageError> {
+    fetch_group(&app, &name, false).await
+}
+
+/// Like `refresh_all`, but only for the providers in a settings group.
+#[tauri::command]
+pub async fn refresh_group(app: AppHandle, name: String) -> Result<RefreshAllResult, UsageError> {
+    fetch_group(&app, &name, true).await
+}
+
+/// Seconds until each usage window of the enabled providers resets, from the last fetched
+/// usage. Windows without a known reset are left out.
+#[tauri::command]
+pub fn get_reset_countdowns(app: AppHandle) -> Vec<ResetCountdown> {
+    tray::reset_countdowns(&app)
+}
+
+/// Freshness, backoff and advertised request quota for each provider.
+#[tauri::command]
+pub fn get_provider_health(app: AppHandle) -> Vec<ProviderHealth> {
+    Provider::ALL
+        .into_iter()
+        .map(|provider| {
+            let caches = &app.state::<CacheState>().0;
+            ProviderHealth {
+                provider,
+                fetched_secs_ago: caches.usage_age(provider).map(|age| age.as_secs()),
+                rate_limited_until: caches.rate_limited_until(provider),
+                request_quota: rate_limit::last_quota(provider),
+            }
+        })
+        .collect()
+}
+
+/// Every enabled provider with its display metadata and whether a credential is stored.
+#[tauri::command]
+pub async fn list_providers(
+    registry: State<'_, ProviderRegistryState>,
+    settings: State<'_, SettingsState>,
+) -> Result<Vec<ProviderListing>, UsageError> {
+    let settings = settings.0.get();
+    let mut listings = Vec::new();
+    for provider in registry
+        .0
+        .iter()
+        .filter(|p| settings.is_enabled(p.info().id))
+    {
+        listings.push(ProviderListing {
+            info: provider.info(),
+            has_credentials: provider.has_credentials().await,
+        });
+    }
+    Ok(listings)
+}
+
+/// Fetches any registered provider through the registry, with the same cache, 429 backoff
+/// and last-good fallback as its `*_get_usage` command.
+#[tauri::command]
+pub async fn get_provider_usage(
+    provider: Provider,
+    app: AppHandle,
+    registry: State<'_, ProviderRegistryState>,
+) -> Result<Envelope<ProviderUsage>, UsageError> {
+    let source = registry.0.get(provider).ok_or(ErrorCode::NotConfigured)?;
+    source.fetch_usage(&app, false).await
+}
+
+/// Checks a provider's stored credential against its API.
+#[tauri::command]
+pub async fn validate_provider_credentials(
+    provider: Provider,
+    registry: State<'_, ProviderRegistryState>,
+) -> Result<(), UsageError> {
+    let source = registry.0.get(provider).ok_or(ErrorCode::NotConfigured)?;
+    source
+        .validate_credentials()
+        .await
+        .map_err(UsageError::from)
+}
+
+/// Opens a push channel for one provider's usage: the last known value is sent at once,
+/// then every fresh fetch as it lands.
+#[tauri::command]
+pub fn subscribe_usage(
+    provider: Provider,
+    on_update: Channel<UsageUpdate>,
+    app: AppHandle,
+    registry: State<'_, ProviderRegistryState>,
+    feed: State<'_, FeedState>,
+) {
+    let initial = registry
+        .0
+        .get(provider)
+        .and_then(|source| source.last_usage(&app))
+        .and_then(|(usage, fetched_at)| UsageUpdate::new(provider, &usage, fetched_at));
+    feed.0.subscribe(provider, on_update, initial);
+}
+
+#[tauri::command]
+pub fn unsubscribe_usage(channel_id: u32, feed: State<'_, FeedState>) {
+    feed.0.unsubscribe(channel_id);
+}
@@ -0,0 +1,639 @@
+use serde_json::json;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tauri::ipc::{Channel, InvokeResponseBody};
+use tauri::test::{mock_app, MockRuntime};
+use tauri::{App, Manager};
+
+use crate::alerts::AlertEngine;
+use crate::cache::{CacheMap, ZAI_USAGE};
+use crate::clock::now_millis;
+use crate::commands;
+use crate::credentials::CredentialManager;
+use crate::endpoint;
+use crate::feed::UsageFeed;
+use crate::history::HistoryStore;
+use crate::i18n::{ErrorCode, UsageError};
+use crate::models::{CodexUsageData, Envelope, Provider, UsageData, ZaiUsageData};
+use crate::{AlertState, AmpHttpClient, CacheState, FeedState, HttpClient, UsageHistory};
+
+/// Scenarios share process-wide state (environment variables, the credential vault, the mock
+/// base URL), so they run one at a time.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// Distinguishes the temp directories of scenarios in one run.
+static SCENARIO: AtomicUsize = AtomicUsize::new(0);
+
+const CLAUDE_USAGE: &str = "GET /api/oauth/usage";
+const CLAUDE_TOKEN: &str = "POST /v1/oauth/token";
+const CODEX_USAGE: &str = "GET /backend-api/wham/usage";
+const CODEX_TOKEN: &str = "POST /oauth/token";
+const ZAI_QUOTA: &str = "GET /api/monitor/usage/quota/limit";
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[derive(Clone)]
+struct MockResponse {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl MockResponse {
+    fn json(status: u16, body: serde_json::Value) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.to_string(),
+        }
+    }
+
+    fn status(status: u16) -> Self {
+        Self::json(status, json!({}))
+    }
+
+    fn header(mut self, name: &'static str, value: &str) -> Self {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+}
+
+/// A request the mock server received.
+#[derive(Debug, Clone)]
+struct Hit {
+    route: String,
+    authorization: Option<String>,
+}
+
+#[derive(Default)]
+struct Routes {
+    scripted: Vec<(String, VecDeque<MockResponse>)>,
+    hits: Vec<Hit>,
+}
+
+impl Routes {
+    fn respond(&mut self, hit: Hit) -> MockResponse {
+        let response = self
+            .scripted
+            .iter_mut()
+            .find(|(route, _)| *route == hit.route)
+            .and_then(|(_, queue)| {
+                if queue.len() > 1 {
+                    queue.pop_front()
+                } else {
+                    queue.front().cloned()
+                }
+            })
+            .unwrap_or_else(|| MockResponse::status(404));
+        self.hits.push(hit);
+        response
+    }
+}
+
+/// HTTP/1.1 server on a random local port standing in for every provider API at once.
+/// Each route (`"GET /api/oauth/usage"`) answers with its scripted responses in order, then
+/// keeps repeating the last one; anything unscripted gets a 404.
+struct MockServer {
+    addr: SocketAddr,
+    routes: Arc<Mutex<Routes>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock server");
+        let addr = listener.local_addr().expect("mock server address");
+        let routes = Arc::new(Mutex::new(Routes::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let (shared, stop) = (Arc::clone(&routes), Arc::clone(&stopped));
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Err(e) = serve(stream, &shared) {
+                    eprintln!("mock server: {e}");
+                }
+            }
+        });
+
+        Self {
+            addr,
+            routes,
+            stopped,
+        }
+    }
+
+    fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    fn script(&self, route: &str, responses: Vec<MockResponse>) {
+        lock(&self.routes)
+            .scripted
+            .push((route.to_string(), responses.into()));
+    }
+
+    fn hits(&self, route: &str) -> Vec<Hit> {
+        lock(&self.routes)
+            .hits
+            .iter()
+            .filter(|hit| hit.route == route)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wakes the accept loop so it sees the flag.
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+fn serve(stream: TcpStream, routes: &Mutex<Routes>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default();
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse().unwrap_or(0);
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.to_string());
+            }
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let response = lock(routes).respond(Hit {
+        route: format!("{method} {path}"),
+        authorization,
+    });
+
+    let mut stream = stream;
+    write!(stream, "HTTP/1.1 {} Mock\r\n", response.status)?;
+    for (name, value) in &response.headers {
+        write!(stream, "{name}: {value}\r\n")?;
+    }
+    write!(
+        stream,
+        "Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn block_on<F: Future>(scenario: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("test runtime")
+        .block_on(scenario)
+}
+
+/// The real command handlers on a mock Tauri app (no webview), with fake credentials in a
+/// temp directory and an in-memory vault, and every provider API served by `server`.
+struct Harness {
+    app: App<MockRuntime>,
+    server: MockServer,
+    dir: PathBuf,
+    _serial: MutexGuard<'static, ()>,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let serial = lock(&SERIAL);
+
+        let dir = std::env::temp_dir().join(format!(
+            "usage-bar-e2e-{}-{}",
+            std::process::id(),
+            SCENARIO.fetch_add(1, Ordering::SeqCst)
+        ));
+        for sub in ["claude", "codex"] {
+            std::fs::create_dir_all(dir.join(sub)).expect("create scenario dir");
+        }
+        std::env::set_var("CLAUDE_CONFIG_DIR", dir.join("claude"));
+        std::env::set_var("CODEX_HOME", dir.join("codex"));
+        std::env::remove_var("CLAUDE_CODE_OAUTH_TOKEN");
+        CredentialManager::install_test_vault();
+
+        let server = MockServer::start();
+        endpoint::set_mock_base(Some(server.base_url()));
+
+        let client = Arc::new(reqwest::Client::new());
+        let app = mock_app();
+        app.manage(HttpClient(client.clone()));
+        app.manage(AmpHttpClient(client.clone()));
+        app.manage(CacheState(CacheMap::new(30)));
+        app.manage(UsageHistory(
+            HistoryStore::in_memory().expect("in-memory history"),
+        ));
+        app.manage(AlertState(AlertEngine::detached(client)));
+        app.manage(FeedState(UsageFeed::default()));
+
+        Self {
+            app,
+            server,
+            dir,
+            _serial: serial,
+        }
+    }
+
+    fn claude_login(&self, access_token: &str, expires_at: i64) {
+        let credentials = json!({
+            "claudeAiOauth": {
+                "accessToken": access_token,
+                "refreshToken": "claude-refresh-1",
+                "expiresAt": expires_at,
+                "subscriptionType": "max",
+            }
+        });
+        std::fs::write(
+            self.dir.join("claude").join(".credentials.json"),
+            credentials.to_string(),
+        )
+        .expect("write Claude credentials");
+    }
+
+    fn claude_access_token(&self) -> String {
+        let json = std::fs::read_to_string(self.dir.join("claude").join(".credentials.json"))
+            .expect("read Claude credentials");
+        let credentials: serde_json::Value = serde_json::from_str(&json).expect("parse");
+        credentials["claudeAiOauth"]["accessToken"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn codex_login(&self, access_token: &str) {
+        let codex = self.dir.join("codex");
+        let auth = json!({
+            "tokens": {
+                "access_token": access_token,
+                "refresh_token": "codex-refresh-1",
+                "account_id": "acct-1",
+            }
+        });
+        std::fs::write(codex.join("auth.json"), auth.to_string()).expect("write Codex auth");
+        std::fs::write(
+            codex.join("config.toml"),
+            format!(
+                "chatgpt_base_url = \"{}/backend-api\"\n",
+                self.server.base_url()
+            ),
+        )
+        .expect("write Codex config");
+    }
+
+    fn codex_access_token(&self) -> String {
+        let json = std::fs::read_to_string(self.dir.join("codex").join("auth.json"))
+            .expect("read Codex auth");
+        let auth: serde_json::Value = serde_json::from_str(&json).expect("parse");
+        auth["tokens"]["access_token"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Collects the JSON of every `subscribe_usage` message pushed for `provider`.
+    fn subscribe(&self, provider: Provider) -> Arc<Mutex<Vec<serde_json::Value>>> {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&received);
+        let channel = Channel::new(move |body| {
+            if let InvokeResponseBody::Json(json) = body {
+                lock(&sink).push(serde_json::from_str(&json).unwrap_or_default());
+            }
+            Ok(())
+        });
+        self.app
+            .state::<FeedState>()
+            .0
+            .subscribe(provider, channel, None);
+        received
+    }
+
+    async fn claude_usage(&self) -> Result<Envelope<Arc<UsageData>>, UsageError> {
+        let app = &self.app;
+        commands::claude_get_usage(
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+        )
+        .await
+    }
+
+    async fn codex_usage(&self) -> Result<Envelope<Arc<CodexUsageData>>, UsageError> {
+        let app = &self.app;
+        commands::codex_get_usage(
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+        )
+        .await
+    }
+
+    async fn zai_usage(&self) -> Result<Envelope<Arc<ZaiUsageData>>, UsageError> {
+        let app = &self.app;
+        commands::zai_get_usage(
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+        )
+        .await
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        endpoint::set_mock_base(None);
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn claude_usage_body(five_hour: f64) -> serde_json::Value {
+    json!({
+        "five_hour": { "utilization": five_hour, "resets_at": "2030-01-01T00:00:00Z" },
+        "seven_day": { "utilization": 10.0, "resets_at": null },
+        "extra_usage": null,
+    })
+}
+
+fn zai_quota_body() -> serde_json::Value {
+    json!({
+        "data": {
+            "limits": [
+                { "type": "TOKENS_LIMIT", "percentage": 25.0, "nextResetTime": 1_893_456_000_000_i64 },
+                { "type": "TIME_LIMIT", "percentage": 10.0, "currentValue": 40, "usage": 400 },
+            ]
+        }
+    })
+}
+
+#[test]
+fn test_claude_usage_is_cached_and_pushed_to_subscribers() {
+    let harness = Harness::new();
+    harness.claude_login("claude-token-1", now_millis() + 3_600_000);
+    harness.server.script(
+        CLAUDE_USAGE,
+        vec![MockResponse::json(200, claude_usage_body(42.0))],
+    );
+    let updates = harness.subscribe(Provider::Claude);
+
+    block_on(async {
+        let first = harness.claude_usage().await.expect("first fetch");
+        assert_eq!(first.data.five_hour_utilization, 42.0);
+        assert!(first.rate_limited_until.is_none());
+
+        let second = harness.claude_usage().await.expect("cached fetch");
+        assert_eq!(second.data.five_hour_utilization, 42.0);
+    });
+
+    let hits = harness.server.hits(CLAUDE_USAGE);
+    assert_eq!(hits.len(), 1, "second call should be served from the cache");
+    assert_eq!(
+        hits[0].authorization.as_deref(),
+        Some("Bearer claude-token-1")
+    );
+    assert!(harness.server.hits(CLAUDE_TOKEN).is_empty());
+
+    let updates = lock(&updates);
+    assert_eq!(updates.len(), 1, "only fresh samples are pushed");
+    assert_eq!(updates[0]["provider"], "claude");
+    assert_eq!(updates[0]["data"]["five_hour_utilization"], 42.0);
+}
+
+#[test]
+fn test_claude_refreshes_token_and_retries_after_unauthorized() {
+    let harness = Harness::new();
+    harness.claude_login("claude-token-1", now_millis() + 3_600_000);
+    harness.server.script(
+        CLAUDE_USAGE,
+        vec![
+            MockResponse::status(401),
+            MockResponse::json(200, claude_usage_body(7.0)),
+        ],
+    );
+    harness.server.script(
+        CLAUDE_TOKEN,
+        vec![MockResponse::json(
+            200,
+            json!({
+                "access_token": "claude-token-2",
+                "refresh_token": "claude-refresh-2",
+                "expires_in": 3600,
+            }),
+        )],
+    );
+
+    let usage = block_on(harness.claude_usage()).expect("fetch after refresh");
+    assert_eq!(usage.data.five_hour_utilization, 7.0);
+
+    let hits = harness.server.hits(CLAUDE_USAGE);
+    assert_eq!(hits.len(), 2);
+    assert_eq!(
+        hits[1].authorization.as_deref(),
+        Some("Bearer claude-token-2")
+    );
+    assert_eq!(harness.server.hits(CLAUDE_TOKEN).len(), 1);
+    assert_eq!(harness.claude_access_token(), "claude-token-2");
+}
+
+#[test]
+fn test_claude_expired_token_is_refreshed_before_fetching() {
+    let harness = Harness::new();
+    harness.claude_login("claude-token-1", now_millis() - 1_000);
+    harness.server.script(
+        CLAUDE_USAGE,
+        vec![MockResponse::json(200, claude_usage_body(1.0))],
+    );
+    harness.server.script(
+        CLAUDE_TOKEN,
+        vec![MockResponse::json(
+            200,
+            json!({
+                "access_token": "claude-token-2",
+                "refresh_token": "claude-refresh-2",
+                "expires_in": 3600,
+            }),
+        )],
+    );
+
+    block_on(harness.claude_usage()).expect("fetch with refreshed token");
+
+    let hits = harness.server.hits(CLAUDE_USAGE);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(
+        hits[0].authorization.as_deref(),
+        Some("Bearer claude-token-2")
+    );
+}
+
+#[test]
+fn test_claude_env_token_is_used_and_never_refreshed() {
+    let harness = Harness::new();
+    std::env::set_var("CLAUDE_CODE_OAUTH_TOKEN", "claude-long-lived");
+    harness.server.script(
+        CLAUDE_USAGE,
+        vec![
+            MockResponse::json(200, claude_usage_body(3.0)),
+            MockResponse::status(401),
+        ],
+    );
+
+    block_on(async {
+        let usage = harness.claude_usage().await.expect("fetch with env token");
+        assert_eq!(usage.data.five_hour_utilization, 3.0);
+
+        harness.app.state::<CacheState>().0.clear(Provider::Claude);
+        let error = harness
+            .claude_usage()
+            .await
+            .expect_err("rejected env token");
+        assert_eq!(error.code(), Some(ErrorCode::AuthFailed));
+    });
+
+    let hits = harness.server.hits(CLAUDE_USAGE);
+    assert_eq!(
+        hits[0].authorization.as_deref(),
+        Some("Bearer claude-long-lived")
+    );
+    assert!(harness.server.hits(CLAUDE_TOKEN).is_empty());
+    std::env::remove_var("CLAUDE_CODE_OAUTH_TOKEN");
+}
+
+#[test]
+fn test_zai_rate_limit_serves_stale_data_and_backs_off() {
+    let harness = Harness::new();
+    CredentialManager::zai_write_api_key("zai-test-key").expect("store Z.ai key");
+    harness.server.script(
+        ZAI_QUOTA,
+        vec![
+            MockResponse::json(200, zai_quota_body()),
+            MockResponse::status(429).header("Retry-After", "120"),
+        ],
+    );
+
+    block_on(async {
+        let fresh = harness.zai_usage().await.expect("first fetch");
+        assert_eq!(fresh.data.tier_name.as_deref(), Some("Pro"));
+        assert!(fresh.rate_limited_until.is_none());
+
+        harness.app.state::<CacheState>().0.get(ZAI_USAGE).clear();
+        let stale = harness.zai_usage().await.expect("stale data while limited");
+        assert!(stale.rate_limited_until.is_some());
+        assert_eq!(
+            stale
+                .data
+                .token_usage
+                .as_ref()
+                .map(|usage| usage.percentage),
+            Some(25.0)
+        );
+
+        let backed_off = harness
+            .zai_usage()
+            .await
+            .expect("stale data during backoff");
+        assert!(backed_off.rate_limited_until.is_some());
+    });
+
+    let hits = harness.server.hits(ZAI_QUOTA);
+    assert_eq!(
+        hits.len(),
+        2,
+        "no request is made during the backoff window"
+    );
+    assert_eq!(hits[0].authorization.as_deref(), Some("zai-test-key"));
+}
+
+#[test]
+fn test_zai_without_api_key_is_not_configured() {
+    let harness = Harness::new();
+
+    let error = block_on(harness.zai_usage()).expect_err("no key stored");
+    assert_eq!(error.code(), Some(ErrorCode::NotConfigured));
+    assert!(harness.server.hits(ZAI_QUOTA).is_empty());
+}
+
+#[test]
+fn test_codex_refreshes_auth_and_retries_after_unauthorized() {
+    let harness = Harness::new();
+    harness.codex_login("codex-token-1");
+    harness.server.script(
+        CODEX_USAGE,
+        vec![
+            MockResponse::status(401),
+            MockResponse::json(
+                200,
+                json!({
+                    "plan_type": "plus",
+                    "rate_limit": {
+                        "primary_window": {
+                            "used_percent": 30,
+                            "reset_at": 1_893_456_000,
+                            "limit_window_seconds": 18_000,
+                        }
+                    }
+                }),
+            ),
+        ],
+    );
+    harness.server.script(
+        CODEX_TOKEN,
+        vec![MockResponse::json(
+            200,
+            json!({ "access_token": "codex-token-2" }),
+        )],
+    );
+
+    let usage = block_on(harness.codex_usage()).expect("fetch after refresh");
+    assert_eq!(
+        usage
+            .data
+            .session_usage
+            .as_ref()
+            .map(|window| window.percentage),
+        Some(30.0)
+    );
+
+    let hits = harness.server.hits(CODEX_USAGE);
+    assert_eq!(hits.len(), 2);
+    assert_eq!(
+        hits[1].authorization.as_deref(),
+        Some("Bearer codex-token-2")
+    );
+    assert_eq!(harness.codex_access_token(), "codex-token-2");
+}
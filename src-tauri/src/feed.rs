@@ -0,0 +1,221 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use tauri::ipc::Channel;
+
+use crate::alerts::UsageWindows;
+use crate::clock::now_millis;
+use crate::debug_error;
+use crate::models::Provider;
+
+/// Utilization of one named window, as used in alert conditions (`five_hour`, `quota`, ...).
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowUtilization {
+    pub window: &'static str,
+    pub percent: f64,
+}
+
+/// One message on a `subscribe_usage` channel.
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageUpdate {
+    pub provider: Provider,
+    pub windows: Vec<WindowUtilization>,
+    /// The provider's usage payload, same shape as its `*_get_usage` command returns.
+    pub data: serde_json::Value,
+    /// Epoch millis at which `data` was fetched.
+    pub fetched_at: i64,
+    /// Change since the provider's previous fetch; absent for the first one and for the
+    /// initial value sent on subscribe.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delta: Option<UsageDelta>,
+}
+
+impl UsageUpdate {
+    pub fn new<T: Serialize + UsageWindows>(
+        provider: Provider,
+        data: &T,
+        fetched_at: i64,
+    ) -> Option<Self> {
+        let windows = data
+            .windows()
+            .into_iter()
+            .map(|(window, percent)| WindowUtilization { window, percent })
+            .collect();
+        let data = serde_json::to_value(data)
+            .inspect_err(|e| {
+                debug_error!("Failed to serialize {provider:?} usage update: {e}");
+            })
+            .ok()?;
+        Some(Self {
+            provider,
+            windows,
+            data,
+            fetched_at,
+            delta: None,
+        })
+    }
+}
+
+/// Percentage-point change of one window between two fetches.
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowChange {
+    pub window: &'static str,
+    pub change: f64,
+}
+
+/// Amount spent from one budget between two fetches, in the budget's unit (dollars for
+/// Claude extra usage, credits for Amp). Negative when the budget was topped up or reset.
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetChange {
+    pub budget: &'static str,
+    pub spent: f64,
+}
+
+/// What changed since the provider's previous fetch, so the popup and notifications can say
+/// "+4% in the last 5 minutes" without keeping state of their own.
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageDelta {
+    /// Epoch millis of the previous fetch.
+    pub since: i64,
+    /// Windows present in both fetches.
+    pub windows: Vec<WindowChange>,
+    /// Budgets present in both fetches.
+    pub spend: Vec<BudgetChange>,
+}
+
+/// The parts of a fetch the next one is compared against.
+#[derive(Debug, Clone)]
+struct Reading {
+    fetched_at: i64,
+    windows: Vec<(&'static str, f64)>,
+    budgets: Vec<(&'static str, f64)>,
+}
+
+impl UsageDelta {
+    fn between(previous: &Reading, current: &Reading) -> Self {
+        let paired = |before: &[(&'static str, f64)], after: &[(&'static str, f64)]| {
+            after
+                .iter()
+                .filter_map(|&(name, now)| {
+                    before
+                        .iter()
+                        .find(|(n, _)| *n == name)
+                        .map(|&(_, then)| (name, then, now))
+                })
+                .collect::<Vec<_>>()
+        };
+        Self {
+            since: previous.fetched_at,
+            windows: paired(&previous.windows, &current.windows)
+                .into_iter()
+                .map(|(window, then, now)| WindowChange {
+                    window,
+                    change: now - then,
+                })
+                .collect(),
+            // Budgets are amounts left, so spending shows up as a decrease.
+            spend: paired(&previous.budgets, &current.budgets)
+                .into_iter()
+                .map(|(budget, then, now)| BudgetChange {
+                    budget,
+                    spent: then - now,
+                })
+                .collect(),
+        }
+    }
+}
+
+struct Subscriber {
+    provider: Provider,
+    channel: Channel<UsageUpdate>,
+}
+
+/// Per-provider push channels opened by the popup. Every fresh usage sample is sent to the
+/// channels subscribed to its provider.
+#[derive(Default)]
+pub struct UsageFeed {
+    subscribers: Mutex<Vec<Subscriber>>,
+    /// Each provider's last fetch, which the next one is diffed against.
+    previous: Mutex<HashMap<Provider, Reading>>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        debug_error!("Usage feed mutex poisoned, recovering...");
+        poisoned.into_inner()
+    })
+}
+
+impl UsageFeed {
+    /// Registers `channel` and sends it `initial` (the last known value) right away.
+    pub fn subscribe(
+        &self,
+        provider: Provider,
+        channel: Channel<UsageUpdate>,
+        initial: Option<UsageUpdate>,
+    ) {
+        if let Some(update) = initial {
+            if channel.send(update).is_err() {
+                debug_error!("Failed to send initial {provider:?} usage");
+                return;
+            }
+        }
+        lock(&self.subscribers).push(Subscriber { provider, channel });
+    }
+
+    pub fn unsubscribe(&self, channel_id: u32) {
+        lock(&self.subscribers).retain(|s| s.channel.id() != channel_id);
+    }
+
+    /// Diffs a freshly fetched sample against the provider's previous one and sends it to
+    /// every subscriber of `provider`, dropping channels whose webview has gone away.
+    /// Returns the update for the other sinks (the snapshot file).
+    pub fn publish<T: Serialize + UsageWindows>(
+        &self,
+        provider: Provider,
+        data: &T,
+    ) -> Option<UsageUpdate> {
+        let fetched_at = now_millis();
+        let reading = Reading {
+            fetched_at,
+            windows: data.windows(),
+            budgets: data.budgets(),
+        };
+        let delta = lock(&self.previous)
+            .insert(provider, reading.clone())
+            .map(|previous| UsageDelta::between(&previous, &reading));
+
+        let mut update = UsageUpdate::new(provider, data, fetched_at)?;
+        update.delta = delta;
+        lock(&self.subscribers)
+            .retain(|s| s.provider != provider || s.channel.send(update.clone()).is_ok());
+        Some(update)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta_pairs_windows_and_budgets() {
+        let previous = Reading {
+            fetched_at: 1_000,
+            windows: vec![("five_hour", 40.0), ("extra_usage", 10.0)],
+            budgets: vec![("extra_usage", 25.0)],
+        };
+        let current = Reading {
+            fetched_at: 301_000,
+            windows: vec![("five_hour", 44.0), ("seven_day", 12.0)],
+            budgets: vec![("extra_usage", 20.5)],
+        };
+
+        let delta = UsageDelta::between(&previous, &current);
+        assert_eq!(delta.since, 1_000);
+        assert_eq!(delta.windows.len(), 1);
+        assert_eq!(delta.windows[0].window, "five_hour");
+        assert_eq!(delta.windows[0].change, 4.0);
+        assert_eq!(delta.spend.len(), 1);
+        assert_eq!(delta.spend[0].spent, 4.5);
+    }
+}
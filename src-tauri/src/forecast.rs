@@ -0,0 +1,244 @@
+use crate::history::Sample;
+use crate::models::{BurnForecast, UsageData, ZaiPromptForecast, ZaiUsageData};
+
+/// How long the 5-hour window is, i.e. how far back samples can belong to it.
+pub const WINDOW_MILLIS: i64 = 5 * 60 * 60 * 1000;
+/// Samples closer together than this give too noisy a rate to project from.
+const MIN_SPAN_MILLIS: i64 = 5 * 60 * 1000;
+/// The reported reset time moves by up to a second between fetches of the same window.
+const RESET_JITTER_MILLIS: i64 = 60 * 1000;
+const MILLIS_PER_HOUR: f64 = 3_600_000.0;
+
+fn resets_at(sample: &Sample<UsageData>) -> Option<i64> {
+    sample
+        .data
+        .five_hour_resets_at
+        .map(|reset| reset.epoch_millis())
+}
+
+/// Least-squares slope of utilization over time, in percentage points per hour.
+fn slope(points: &[(i64, f64)]) -> Option<f64> {
+    let (first, last) = (points.first()?.0, points.last()?.0);
+    if points.len() < 2 || last - first < MIN_SPAN_MILLIS {
+        return None;
+    }
+    let n = points.len() as f64;
+    let hours = |at: i64| (at - first) as f64 / MILLIS_PER_HOUR;
+    let mean_x = points.iter().map(|&(at, _)| hours(at)).sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, value)| value).sum::<f64>() / n;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for &(at, value) in points {
+        let dx = hours(at) - mean_x;
+        covariance += dx * (value - mean_y);
+        variance += dx * dx;
+    }
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+/// Projects the 5-hour window from `samples` (oldest first). Only samples from the same
+/// window as the latest one count, so a reset mid-way doesn't read as negative usage.
+pub fn five_hour(samples: &[Sample<UsageData>], now: i64) -> BurnForecast {
+    let Some(latest) = samples.last() else {
+        return BurnForecast::default();
+    };
+    let resets_at = resets_at(latest);
+    let points: Vec<(i64, f64)> = samples
+        .iter()
+        .filter(|sample| match (resets_at, self::resets_at(sample)) {
+            (Some(current), Some(reset)) => (current - reset).abs() <= RESET_JITTER_MILLIS,
+            (None, None) => latest.fetched_at - sample.fetched_at < WINDOW_MILLIS,
+            _ => false,
+        })
+        .map(|sample| (sample.fetched_at, sample.data.five_hour_utilization))
+        .collect();
+
+    let utilization = latest.data.five_hour_utilization;
+    let rate = slope(&points);
+    let limit_at = if utilization >= 100.0 {
+        Some(latest.fetched_at)
+    } else {
+        rate.filter(|rate| *rate > 0.0)
+            .map(|rate| latest.fetched_at + ((100.0 - utilization) / rate * MILLIS_PER_HOUR) as i64)
+    };
+    let projected_at_reset = match (rate, resets_at) {
+        (Some(rate), Some(reset)) => {
+            let hours_left = (reset - now).max(0) as f64 / MILLIS_PER_HOUR;
+            Some((utilization + rate.max(0.0) * hours_left).max(utilization))
+        }
+        _ => None,
+    };
+    BurnForecast {
+        utilization: Some(utilization),
+        burn_rate_per_hour: rate,
+        limit_at,
+        resets_at,
+        projected_at_reset,
+        will_hit_limit: match (limit_at, resets_at) {
+            (Some(limit), Some(reset)) => limit < reset,
+            (Some(_), None) => true,
+            _ => false,
+        },
+        samples: points.len(),
+    }
+}
+
+/// The prompt window's reset: `TIME_LIMIT`'s own if Z.ai sent one, else the token window's,
+/// which runs on the same 5 hours.
+fn zai_resets_at(data: &ZaiUsageData) -> Option<i64> {
+    data.limits
+        .iter()
+        .find(|limit| limit.limit_type == "TIME_LIMIT")
+        .and_then(|limit| limit.resets_at)
+        .or_else(|| data.token_usage.as_ref().and_then(|usage| usage.resets_at))
+}
+
+/// Projects the Z.ai prompt allowance from `samples` (oldest first). Only samples from the
+/// same window as the latest one count; a count that drops going forward is a reset too.
+pub fn zai_prompts(samples: &[Sample<ZaiUsageData>], now: i64) -> ZaiPromptForecast {
+    let Some((latest, usage)) = samples
+        .iter()
+        .rev()
+        .find_map(|sample| sample.data.mcp_usage.as_ref().map(|usage| (sample, usage)))
+    else {
+        return ZaiPromptForecast::default();
+    };
+    let resets_at = zai_resets_at(&latest.data);
+
+    let mut points: Vec<(i64, f64)> = Vec::new();
+    let mut later_used = None;
+    for sample in samples.iter().rev() {
+        let Some(mcp) = &sample.data.mcp_usage else {
+            continue;
+        };
+        let same_window = match (resets_at, zai_resets_at(&sample.data)) {
+            (Some(current), Some(reset)) => (current - reset).abs() <= RESET_JITTER_MILLIS,
+            (None, None) => latest.fetched_at - sample.fetched_at < WINDOW_MILLIS,
+            _ => false,
+        };
+        if !same_window || later_used.is_some_and(|later| mcp.used > later) {
+            break;
+        }
+        later_used = Some(mcp.used);
+        points.push((sample.fetched_at, mcp.used as f64));
+    }
+    points.reverse();
+
+    let (used, total) = (usage.used as f64, usage.total as f64);
+    let rate = slope(&points);
+    let limit_at = if usage.total <= 0 {
+        None
+    } else if used >= total {
+        Some(latest.fetched_at)
+    } else {
+        rate.filter(|rate| *rate > 0.0)
+            .map(|rate| latest.fetched_at + ((total - used) / rate * MILLIS_PER_HOUR) as i64)
+    };
+    let projected_at_reset = match (rate, resets_at) {
+        (Some(rate), Some(reset)) => {
+            let hours_left = (reset - now).max(0) as f64 / MILLIS_PER_HOUR;
+            Some(used + rate.max(0.0) * hours_left)
+        }
+        _ => None,
+    };
+    ZaiPromptForecast {
+        used: Some(usage.used),
+        total: Some(usage.total),
+        prompts_per_hour: rate,
+        limit_at,
+        resets_at,
+        projected_at_reset,
+        will_hit_limit: match (limit_at, resets_at) {
+            (Some(limit), Some(reset)) => limit < reset,
+            (Some(_), None) => true,
+            _ => false,
+        },
+        samples: points.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ResetTime;
+
+    const HOUR: i64 = 3_600_000;
+    // 2025-03-01T00:00:00Z
+    const START: i64 = 1_740_787_200_000;
+
+    fn sample(fetched_at: i64, utilization: f64, resets_at: i64) -> Sample<UsageData> {
+        let data: UsageData = serde_json::from_value(serde_json::json!({
+            "five_hour_utilization": utilization,
+            "five_hour_resets_at": resets_at,
+            "seven_day_utilization": 0.0,
+            "extra_usage_enabled": false,
+            "extra_usage_monthly_limit": null,
+            "extra_usage_used_credits": null,
+            "extra_usage_utilization": null,
+        }))
+        .unwrap();
+        assert_eq!(
+            data.five_hour_resets_at.map(ResetTime::epoch_millis),
+            Some(resets_at)
+        );
+        Sample { data, fetched_at }
+    }
+
+    #[test]
+    fn test_five_hour_forecast() {
+        let reset = START + 5 * HOUR;
+        let samples = [
+            // Previous window, ignored
+            sample(START - HOUR, 90.0, START),
+            sample(START, 0.0, reset),
+            sample(START + HOUR, 20.0, reset + 1000),
+            sample(START + 2 * HOUR, 40.0, reset),
+        ];
+        let forecast = five_hour(&samples, START + 2 * HOUR);
+        assert_eq!(forecast.samples, 3);
+        assert!((forecast.burn_rate_per_hour.unwrap() - 20.0).abs() < 1e-9);
+        assert_eq!(forecast.limit_at, Some(START + 5 * HOUR));
+        assert!(!forecast.will_hit_limit);
+        assert!((forecast.projected_at_reset.unwrap() - 100.0).abs() < 1e-9);
+
+        let faster = [sample(START, 0.0, reset), sample(START + HOUR, 50.0, reset)];
+        let forecast = five_hour(&faster, START + HOUR);
+        assert_eq!(forecast.limit_at, Some(START + 2 * HOUR));
+        assert!(forecast.will_hit_limit);
+
+        let single = five_hour(&faster[..1], START);
+        assert_eq!(single.burn_rate_per_hour, None);
+        assert!(!single.will_hit_limit);
+    }
+
+    fn zai_sample(fetched_at: i64, used: i32, resets_at: i64) -> Sample<ZaiUsageData> {
+        let data: ZaiUsageData = serde_json::from_value(serde_json::json!({
+            "token_usage": { "percentage": 0.0, "resets_at": resets_at },
+            "mcp_usage": { "percentage": used as f64 / 4.0, "used": used, "total": 400 },
+            "tier_name": null,
+        }))
+        .unwrap();
+        Sample { data, fetched_at }
+    }
+
+    #[test]
+    fn test_zai_prompt_forecast() {
+        let reset = START + 5 * HOUR;
+        let samples = [
+            // Previous window, ignored
+            zai_sample(START - HOUR, 300, START),
+            // Counted down early within the window: only what follows counts
+            zai_sample(START - HOUR / 2, 90, reset),
+            zai_sample(START, 0, reset),
+            zai_sample(START + HOUR, 100, reset + 1000),
+        ];
+        let forecast = zai_prompts(&samples, START + HOUR);
+        assert_eq!(forecast.samples, 2);
+        assert_eq!((forecast.used, forecast.total), (Some(100), Some(400)));
+        assert!((forecast.prompts_per_hour.unwrap() - 100.0).abs() < 1e-9);
+        assert_eq!(forecast.limit_at, Some(START + 4 * HOUR));
+        assert!(forecast.will_hit_limit);
+        assert!((forecast.projected_at_reset.unwrap() - 500.0).abs() < 1e-9);
+
+        assert_eq!(zai_prompts(&[], START).used, None);
+    }
+}
@@ -0,0 +1,125 @@
+use crate::alerts::UsageWindows;
+use crate::models::{HeadroomCheck, Provider, ProviderHeadroom, RunEstimate};
+
+/// Headroom left in each window, in the estimate's unit.
+fn remaining<T: UsageWindows>(data: &T, estimate: RunEstimate) -> Vec<(&'static str, f64)> {
+    match estimate {
+        RunEstimate::Percent(_) => data
+            .windows()
+            .into_iter()
+            .map(|(window, percent)| (window, (100.0 - percent).max(0.0)))
+            .collect(),
+        RunEstimate::Cost(_) => data
+            .budgets()
+            .into_iter()
+            .map(|(window, dollars)| (window, dollars.max(0.0)))
+            .collect(),
+    }
+}
+
+/// Checks one provider's latest usage against `estimate`. Every window must have room,
+/// since exhausting any of them blocks the run.
+pub fn check_provider<T: UsageWindows>(
+    provider: Provider,
+    data: Option<&T>,
+    estimate: RunEstimate,
+) -> ProviderHeadroom {
+    let needed = match estimate {
+        RunEstimate::Percent(value) | RunEstimate::Cost(value) => value,
+    };
+    let Some(data) = data else {
+        return ProviderHeadroom {
+            provider,
+            allowed: false,
+            limiting_window: None,
+            remaining: None,
+            reason: "No recent usage data".to_string(),
+        };
+    };
+
+    let tightest = remaining(data, estimate)
+        .into_iter()
+        .min_by(|a, b| a.1.total_cmp(&b.1));
+    let Some((window, left)) = tightest else {
+        let reason = match estimate {
+            RunEstimate::Percent(_) => "No usage windows reported",
+            RunEstimate::Cost(_) => "No spend budget to check a cost against",
+        };
+        return ProviderHeadroom {
+            provider,
+            allowed: false,
+            limiting_window: None,
+            remaining: None,
+            reason: reason.to_string(),
+        };
+    };
+
+    let allowed = left >= needed;
+    let reason = match (allowed, estimate) {
+        (true, RunEstimate::Percent(_)) => format!("{left:.1}% left in {window}"),
+        (true, RunEstimate::Cost(_)) => format!("${left:.2} left in {window}"),
+        (false, RunEstimate::Percent(_)) => {
+            format!("Only {left:.1}% left in {window}, {needed:.1}% needed")
+        }
+        (false, RunEstimate::Cost(_)) => {
+            format!("Only ${left:.2} left in {window}, ${needed:.2} needed")
+        }
+    };
+    ProviderHeadroom {
+        provider,
+        allowed,
+        limiting_window: Some(window.to_string()),
+        remaining: Some(left),
+        reason,
+    }
+}
+
+pub fn summarize(providers: Vec<ProviderHeadroom>) -> HeadroomCheck {
+    HeadroomCheck {
+        allowed: providers.iter().any(|p| p.allowed),
+        providers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AmpUsageData;
+
+    fn amp(quota: f64, used: f64) -> AmpUsageData {
+        AmpUsageData {
+            quota,
+            used,
+            used_percent: used / quota * 100.0,
+            hourly_replenishment: 0.0,
+            window_hours: None,
+            resets_at: None,
+            resets_at_estimated: false,
+            balance: None,
+            team: None,
+            meta: None,
+            money: None,
+        }
+    }
+
+    #[test]
+    fn test_percent_headroom_uses_tightest_window() {
+        let data = amp(10.0, 9.0);
+        let ok = check_provider(Provider::Amp, Some(&data), RunEstimate::Percent(5.0));
+        assert!(ok.allowed);
+        let blocked = check_provider(Provider::Amp, Some(&data), RunEstimate::Percent(15.0));
+        assert!(!blocked.allowed);
+        assert_eq!(blocked.limiting_window.as_deref(), Some("quota"));
+    }
+
+    #[test]
+    fn test_cost_headroom_and_missing_data() {
+        let data = amp(10.0, 7.5);
+        assert!(check_provider(Provider::Amp, Some(&data), RunEstimate::Cost(2.0)).allowed);
+        assert!(!check_provider(Provider::Amp, Some(&data), RunEstimate::Cost(3.0)).allowed);
+
+        let missing = check_provider::<AmpUsageData>(Provider::Amp, None, RunEstimate::Cost(1.0));
+        assert!(!missing.allowed);
+        assert!(!summarize(vec![missing]).allowed);
+    }
+}
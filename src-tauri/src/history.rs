@@ -0,0 +1,780 @@
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::clock::now_millis;
+use crate::models::{Provider, UsageData, ZaiUsageData};
+use crate::settings::app_data_dir;
+use crate::{debug_app, debug_error};
+
+const HISTORY_FILE_NAME: &str = "history.db";
+/// Samples older than this are deleted when the database is opened and before a vacuum.
+/// Longer than any history range the popup offers.
+const RETENTION_DAYS: i64 = 90;
+
+/// Which payload a sample holds. Usage and tier are fetched together but
+/// returned by separate commands, so both are kept for fallbacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Series {
+    Usage,
+    Tier,
+    Balance,
+}
+
+impl Series {
+    fn as_str(self) -> &'static str {
+        match self {
+            Series::Usage => "usage",
+            Series::Tier => "tier",
+            Series::Balance => "balance",
+        }
+    }
+}
+
+/// A stored payload and the epoch millis at which it was fetched.
+#[derive(Debug, Clone)]
+pub struct Sample<T> {
+    pub data: T,
+    pub fetched_at: i64,
+}
+
+/// Local SQLite log of successful fetches, stored as the same JSON the commands return.
+pub struct HistoryStore {
+    /// `None` only if SQLite couldn't open even an in-memory database; history is then disabled.
+    conn: Option<Mutex<Connection>>,
+}
+
+impl HistoryStore {
+    /// Opens `%APPDATA%\com.usage-bar.windows\history.db`. If the file can't be opened the
+    /// store falls back to an in-memory database so fallbacks still work for this session.
+    pub fn open() -> Self {
+        let opened = app_data_dir().and_then(|dir| {
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| anyhow!("Failed to create {}: {e}", dir.display()))?;
+            Self::open_at(&dir.join(HISTORY_FILE_NAME))
+        });
+
+        match opened {
+            Ok(store) => {
+                debug_app!("History database opened");
+                store
+            }
+            Err(e) => {
+                debug_error!("History unavailable, keeping samples in memory: {e}");
+                Self::in_memory().unwrap_or_else(|e| {
+                    debug_error!("In-memory history unavailable, history disabled: {e}");
+                    Self { conn: None }
+                })
+            }
+        }
+    }
+
+    /// Opens `path`, rebuilding it from scratch if it fails the integrity check even after a
+    /// reindex. The damaged file is kept next to it as `history.db.corrupt`.
+    fn open_at(path: &Path) -> Result<Self> {
+        match Self::open_checked(path) {
+            Ok(store) => Ok(store),
+            Err(e) => {
+                debug_error!("History database damaged, rebuilding: {e}");
+                set_aside(path)?;
+                Self::open_checked(path)
+            }
+        }
+    }
+
+    fn open_checked(path: &Path) -> Result<Self> {
+        let conn =
+            Connection::open(path).map_err(|e| anyhow!("Failed to open history database: {e}"))?;
+        // WAL keeps the main file intact if the app is killed mid-write; NORMAL sync is
+        // durable enough for a log whose newest rows can be refetched.
+        let mode: String = conn
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get(0))
+            .map_err(|e| anyhow!("Failed to enable WAL: {e}"))?;
+        conn.pragma_update(None, "synchronous", "NORMAL")
+            .map_err(|e| anyhow!("Failed to set synchronous mode: {e}"))?;
+        debug_app!("History journal mode: {mode}");
+        check_integrity(&conn)?;
+        Self::init(conn)
+    }
+
+    pub fn in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory()
+            .map_err(|e| anyhow!("Failed to open in-memory history: {e}"))?;
+        Self::init(conn)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS samples (
+                id INTEGER PRIMARY KEY,
+                provider TEXT NOT NULL,
+                series TEXT NOT NULL,
+                fetched_at INTEGER NOT NULL,
+                payload TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS samples_lookup
+                ON samples (provider, series, fetched_at);",
+        )
+        .map_err(|e| anyhow!("Failed to initialize history schema: {e}"))?;
+        let store = Self {
+            conn: Some(Mutex::new(conn)),
+        };
+        match store.prune() {
+            Ok(pruned) => {
+                debug_app!("Pruned {pruned} history samples older than {RETENTION_DAYS} days");
+            }
+            Err(e) => {
+                debug_error!("History prune failed: {e}");
+            }
+        }
+        Ok(store)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Connection>> {
+        let conn = self
+            .conn
+            .as_ref()
+            .ok_or_else(|| anyhow!("History is disabled"))?;
+        Ok(conn.lock().unwrap_or_else(|poisoned| {
+            debug_error!("History mutex poisoned, recovering...");
+            poisoned.into_inner()
+        }))
+    }
+
+    pub fn record<T: Serialize>(&self, provider: Provider, series: Series, data: &T) -> Result<()> {
+        let payload = serde_json::to_string(data)?;
+        self.lock()?
+            .execute(
+                "INSERT INTO samples (provider, series, fetched_at, payload) VALUES (?1, ?2, ?3, ?4)",
+                params![provider.key(), series.as_str(), now_millis(), payload],
+            )
+            .map_err(|e| anyhow!("Failed to record history sample: {e}"))?;
+        Ok(())
+    }
+
+    /// Most recent sample no older than `max_age`.
+    pub fn latest<T: DeserializeOwned>(
+        &self,
+        provider: Provider,
+        series: Series,
+        max_age: Duration,
+    ) -> Result<Option<Sample<T>>> {
+        let cutoff = now_millis() - max_age.as_millis() as i64;
+        let row: Option<(i64, String)> = self
+            .lock()?
+            .query_row(
+                "SELECT fetched_at, payload FROM samples
+                 WHERE provider = ?1 AND series = ?2 AND fetched_at >= ?3
+                 ORDER BY fetched_at DESC, id DESC LIMIT 1",
+                params![provider.key(), series.as_str(), cutoff],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| anyhow!("Failed to read history: {e}"))?;
+
+        row.map(|(fetched_at, payload)| {
+            let data = serde_json::from_str(&payload)
+                .map_err(|e| anyhow!("Failed to parse history sample: {e}"))?;
+            Ok(Sample { data, fetched_at })
+        })
+        .transpose()
+    }
+
+    /// Every sample fetched at or after `since` (epoch millis), oldest first. Samples that no
+    /// longer parse as `T` are skipped.
+    pub fn since<T: DeserializeOwned>(
+        &self,
+        provider: Provider,
+        series: Series,
+        since: i64,
+    ) -> Result<Vec<Sample<T>>> {
+        let conn = self.lock()?;
+        let mut statement = conn
+            .prepare(
+                "SELECT fetched_at, payload FROM samples
+                 WHERE provider = ?1 AND series = ?2 AND fetched_at >= ?3
+                 ORDER BY fetched_at, id",
+            )
+            .map_err(|e| anyhow!("Failed to read history: {e}"))?;
+        let rows = statement
+            .query_map(params![provider.key(), series.as_str(), since], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|e| anyhow!("Failed to read history: {e}"))?;
+
+        let mut samples = Vec::new();
+        for row in rows {
+            let (fetched_at, payload) = row.map_err(|e| anyhow!("Failed to read history: {e}"))?;
+            match serde_json::from_str(&payload) {
+                Ok(data) => samples.push(Sample { data, fetched_at }),
+                Err(e) => debug_error!("Skipping unreadable history sample: {e}"),
+            }
+        }
+        Ok(samples)
+    }
+
+    /// Size and contents of the database, for `get_history_db_stats`.
+    pub fn stats(&self) -> Result<HistoryStats> {
+        let conn = self.lock()?;
+        let pragma = |name: &str| -> Result<u64> {
+            conn.pragma_query_value(None, name, |row| row.get::<_, i64>(0))
+                .map(|value| value.max(0) as u64)
+                .map_err(|e| anyhow!("Failed to read {name}: {e}"))
+        };
+        let page_size = pragma("page_size")?;
+        let size_bytes = pragma("page_count")? * page_size;
+        let free_bytes = pragma("freelist_count")? * page_size;
+        let (sample_count, oldest_sample_at): (i64, Option<i64>) = conn
+            .query_row("SELECT COUNT(*), MIN(fetched_at) FROM samples", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .map_err(|e| anyhow!("Failed to count history samples: {e}"))?;
+        Ok(HistoryStats {
+            size_bytes,
+            free_bytes,
+            sample_count: sample_count.max(0) as u64,
+            oldest_sample_at,
+        })
+    }
+
+    /// Deletes samples older than `RETENTION_DAYS`; returns how many.
+    pub fn prune(&self) -> Result<usize> {
+        let cutoff = now_millis() - RETENTION_DAYS * DAY_MILLIS;
+        self.lock()?
+            .execute("DELETE FROM samples WHERE fetched_at < ?1", params![cutoff])
+            .map_err(|e| anyhow!("Failed to prune history: {e}"))
+    }
+
+    /// Prunes old samples, folds the write-ahead log into the main file and compacts it.
+    pub fn vacuum(&self) -> Result<()> {
+        self.prune()?;
+        self.lock()?
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE); VACUUM;")
+            .map_err(|e| anyhow!("Failed to vacuum history: {e}"))
+    }
+}
+
+/// Returned by `get_history_db_stats` and `vacuum_history_db`.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryStats {
+    /// Database file size, not counting the write-ahead log.
+    pub size_bytes: u64,
+    /// Unused space inside the file that a vacuum gives back.
+    pub free_bytes: u64,
+    pub sample_count: u64,
+    /// Epoch millis of the oldest stored sample.
+    pub oldest_sample_at: Option<i64>,
+}
+
+/// Width of the buckets `claude_get_history` and `zai_get_history` group samples into, in local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryBucket {
+    Hour,
+    Day,
+}
+
+impl HistoryBucket {
+    fn width_millis(self) -> i64 {
+        match self {
+            HistoryBucket::Hour => 60 * 60 * 1000,
+            HistoryBucket::Day => 24 * 60 * 60 * 1000,
+        }
+    }
+}
+
+/// One point of `claude_get_history`: a sample, or the highest readings of a bucket's samples.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClaudeHistoryPoint {
+    /// Epoch millis of the sample, or of the start of the bucket.
+    pub at: i64,
+    pub five_hour: f64,
+    pub seven_day: f64,
+    /// Extra usage utilization; `None` if it was off for the whole bucket.
+    pub extra_usage: Option<f64>,
+    pub samples: usize,
+}
+
+/// Claude samples (oldest first) as points, one per sample or one per `bucket`. Buckets
+/// start at local midnight or on the hour, `utc_offset_millis` from UTC.
+pub fn claude_points(
+    samples: &[Sample<UsageData>],
+    bucket: Option<HistoryBucket>,
+    utc_offset_millis: i64,
+) -> Vec<ClaudeHistoryPoint> {
+    let mut points: Vec<ClaudeHistoryPoint> = Vec::new();
+    for sample in samples {
+        let at = match bucket {
+            Some(bucket) => {
+                let width = bucket.width_millis();
+                (sample.fetched_at + utc_offset_millis).div_euclid(width) * width
+                    - utc_offset_millis
+            }
+            None => sample.fetched_at,
+        };
+        let data = &sample.data;
+        match points.last_mut() {
+            Some(point) if bucket.is_some() && point.at == at => {
+                point.five_hour = point.five_hour.max(data.five_hour_utilization);
+                point.seven_day = point.seven_day.max(data.seven_day_utilization);
+                point.extra_usage = match (point.extra_usage, data.extra_usage_utilization) {
+                    (Some(a), Some(b)) => Some(a.max(b)),
+                    (a, b) => a.or(b),
+                };
+                point.samples += 1;
+            }
+            _ => points.push(ClaudeHistoryPoint {
+                at,
+                five_hour: data.five_hour_utilization,
+                seven_day: data.seven_day_utilization,
+                extra_usage: data.extra_usage_utilization,
+                samples: 1,
+            }),
+        }
+    }
+    points
+}
+
+/// One point of `zai_get_history`: a sample, or the highest readings of a bucket's samples.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ZaiHistoryPoint {
+    /// Epoch millis of the sample, or of the start of the bucket.
+    pub at: i64,
+    /// Token (5-hour) quota percentage; `None` if Z.ai didn't report it.
+    pub tokens: Option<f64>,
+    /// MCP (`TIME_LIMIT`) percentage and count.
+    pub mcp: Option<f64>,
+    pub mcp_used: Option<i32>,
+    pub samples: usize,
+}
+
+/// One Z.ai token window, from the samples fetched while it was open. A window ends when
+/// Z.ai reports a different reset time or the percentage drops.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ZaiCycle {
+    /// Epoch millis of the first sample in the window.
+    pub first_sample_at: i64,
+    /// Epoch millis the window reset (or resets, for the open one), if Z.ai reported it.
+    pub resets_at: Option<i64>,
+    /// Highest token percentage seen in the window.
+    pub peak_percentage: f64,
+    /// Prompts used in the window: the highest `TIME_LIMIT` count seen in it.
+    pub prompts: Option<i32>,
+    pub samples: usize,
+}
+
+/// Z.ai windows grouped by local week (from Monday), by when their first sample was taken.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ZaiWeek {
+    /// Epoch millis of local Monday midnight.
+    pub week_start: i64,
+    pub cycles: usize,
+    pub average_peak_percentage: f64,
+    /// Sum of the windows' prompts; `None` if no window reported a count.
+    pub prompts: Option<i32>,
+}
+
+/// `zai_get_history`: the samples as points, and the token windows and weeks they span.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ZaiHistory {
+    pub points: Vec<ZaiHistoryPoint>,
+    pub cycles: Vec<ZaiCycle>,
+    pub weeks: Vec<ZaiWeek>,
+    /// Means over the windows that have reset, i.e. all but the last.
+    pub average_peak_percentage: Option<f64>,
+    pub average_prompts: Option<f64>,
+}
+
+/// Readings within this of each other are the same reset time; Z.ai rounds it differently
+/// between responses.
+const ZAI_RESET_JITTER_MILLIS: i64 = 60 * 1000;
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Z.ai samples (oldest first) as points, one per sample or one per `bucket`, plus their
+/// token windows and weeks. Buckets and weeks are in local time, `utc_offset_millis` from UTC.
+pub fn zai_history(
+    samples: &[Sample<ZaiUsageData>],
+    bucket: Option<HistoryBucket>,
+    utc_offset_millis: i64,
+) -> ZaiHistory {
+    let mut points: Vec<ZaiHistoryPoint> = Vec::new();
+    let mut cycles: Vec<ZaiCycle> = Vec::new();
+    for sample in samples {
+        let data = &sample.data;
+        let tokens = data.token_usage.as_ref().map(|usage| usage.percentage);
+        let mcp_used = data.mcp_usage.as_ref().map(|usage| usage.used);
+
+        let at = match bucket {
+            Some(bucket) => {
+                let width = bucket.width_millis();
+                (sample.fetched_at + utc_offset_millis).div_euclid(width) * width
+                    - utc_offset_millis
+            }
+            None => sample.fetched_at,
+        };
+        let mcp = data.mcp_usage.as_ref().map(|usage| usage.percentage);
+        match points.last_mut() {
+            Some(point) if bucket.is_some() && point.at == at => {
+                point.tokens = max_of(point.tokens, tokens);
+                point.mcp = max_of(point.mcp, mcp);
+                point.mcp_used = point.mcp_used.max(mcp_used);
+                point.samples += 1;
+            }
+            _ => points.push(ZaiHistoryPoint {
+                at,
+                tokens,
+                mcp,
+                mcp_used,
+                samples: 1,
+            }),
+        }
+
+        let Some(usage) = &data.token_usage else {
+            continue;
+        };
+        match cycles.last_mut() {
+            Some(cycle) if same_cycle(cycle, usage.percentage, usage.resets_at) => {
+                cycle.peak_percentage = cycle.peak_percentage.max(usage.percentage);
+                cycle.prompts = cycle.prompts.max(mcp_used);
+                cycle.resets_at = cycle.resets_at.or(usage.resets_at);
+                cycle.samples += 1;
+            }
+            _ => cycles.push(ZaiCycle {
+                first_sample_at: sample.fetched_at,
+                resets_at: usage.resets_at,
+                peak_percentage: usage.percentage,
+                prompts: mcp_used,
+                samples: 1,
+            }),
+        }
+    }
+
+    let mut weeks: Vec<ZaiWeek> = Vec::new();
+    for cycle in &cycles {
+        let day = (cycle.first_sample_at + utc_offset_millis).div_euclid(DAY_MILLIS);
+        // 1970-01-01 was a Thursday, three days after Monday.
+        let week_start = ((day + 3).div_euclid(7) * 7 - 3) * DAY_MILLIS - utc_offset_millis;
+        match weeks.last_mut() {
+            Some(week) if week.week_start == week_start => {
+                week.average_peak_percentage += (cycle.peak_percentage
+                    - week.average_peak_percentage)
+                    / (week.cycles + 1) as f64;
+                week.cycles += 1;
+                week.prompts = sum_of(week.prompts, cycle.prompts);
+            }
+            _ => weeks.push(ZaiWeek {
+                week_start,
+                cycles: 1,
+                average_peak_percentage: cycle.peak_percentage,
+                prompts: cycle.prompts,
+            }),
+        }
+    }
+
+    let done = &cycles[..cycles.len().saturating_sub(1)];
+    let average_peak_percentage = (!done.is_empty())
+        .then(|| done.iter().map(|c| c.peak_percentage).sum::<f64>() / done.len() as f64);
+    let prompts: Vec<i32> = done.iter().filter_map(|c| c.prompts).collect();
+    let average_prompts = (!prompts.is_empty())
+        .then(|| prompts.iter().map(|&p| p as f64).sum::<f64>() / prompts.len() as f64);
+
+    ZaiHistory {
+        points,
+        cycles,
+        weeks,
+        average_peak_percentage,
+        average_prompts,
+    }
+}
+
+/// A sample belongs to the open window unless its reset time moved or its percentage fell.
+fn same_cycle(cycle: &ZaiCycle, percentage: f64, resets_at: Option<i64>) -> bool {
+    let same_reset = match (cycle.resets_at, resets_at) {
+        (Some(a), Some(b)) => (a - b).abs() <= ZAI_RESET_JITTER_MILLIS,
+        _ => true,
+    };
+    same_reset && percentage >= cycle.peak_percentage
+}
+
+fn max_of(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+fn sum_of(a: Option<i32>, b: Option<i32>) -> Option<i32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.saturating_add(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Passes if the database is intact, or becomes intact after rebuilding its indexes (the
+/// usual damage after a crash or a full disk).
+fn check_integrity(conn: &Connection) -> Result<()> {
+    let intact = || -> Result<bool> {
+        let result: String = conn
+            .query_row("PRAGMA integrity_check(1)", [], |row| row.get(0))
+            .map_err(|e| anyhow!("Failed to check history integrity: {e}"))?;
+        Ok(result == "ok")
+    };
+    if intact()? {
+        return Ok(());
+    }
+    debug_error!("History integrity check failed, reindexing");
+    conn.execute_batch("REINDEX;")
+        .map_err(|e| anyhow!("Failed to reindex history: {e}"))?;
+    if intact()? {
+        Ok(())
+    } else {
+        Err(anyhow!("History integrity check failed after reindex"))
+    }
+}
+
+/// Moves a damaged database (and its WAL files) out of the way, replacing an older copy.
+fn set_aside(path: &Path) -> Result<()> {
+    let backup = path.with_extension("db.corrupt");
+    let _ = std::fs::remove_file(&backup);
+    std::fs::rename(path, &backup)
+        .map_err(|e| anyhow!("Failed to move damaged history aside: {e}"))?;
+    for suffix in ["-wal", "-shm"] {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(suffix);
+        let _ = std::fs::remove_file(PathBuf::from(sidecar));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ZaiTierData;
+
+    #[test]
+    fn test_latest_returns_most_recent_sample() {
+        let store = HistoryStore::in_memory().unwrap();
+        for plan in ["Lite", "Pro"] {
+            let tier = ZaiTierData {
+                plan_name: plan.to_string(),
+            };
+            store.record(Provider::Zai, Series::Tier, &tier).unwrap();
+        }
+
+        let sample: Sample<ZaiTierData> = store
+            .latest(Provider::Zai, Series::Tier, Duration::from_secs(60))
+            .unwrap()
+            .unwrap();
+        assert_eq!(sample.data.plan_name, "Pro");
+    }
+
+    #[test]
+    fn test_latest_ignores_other_providers_and_old_samples() {
+        let store = HistoryStore::in_memory().unwrap();
+        let tier = ZaiTierData {
+            plan_name: "Pro".to_string(),
+        };
+        store.record(Provider::Zai, Series::Tier, &tier).unwrap();
+        store
+            .lock()
+            .unwrap()
+            .execute("UPDATE samples SET fetched_at = fetched_at - 120000", [])
+            .unwrap();
+
+        let other: Option<Sample<ZaiTierData>> = store
+            .latest(Provider::Amp, Series::Tier, Duration::from_secs(600))
+            .unwrap();
+        assert!(other.is_none());
+
+        let expired: Option<Sample<ZaiTierData>> = store
+            .latest(Provider::Zai, Series::Tier, Duration::from_secs(60))
+            .unwrap();
+        assert!(expired.is_none());
+    }
+
+    #[test]
+    fn test_since_and_claude_points() {
+        let store = HistoryStore::in_memory().unwrap();
+        let usage = |five_hour: f64, extra: Option<f64>| -> UsageData {
+            serde_json::from_value(serde_json::json!({
+                "five_hour_utilization": five_hour,
+                "seven_day_utilization": 10.0,
+                "extra_usage_enabled": extra.is_some(),
+                "extra_usage_monthly_limit": null,
+                "extra_usage_used_credits": null,
+                "extra_usage_utilization": extra,
+            }))
+            .unwrap()
+        };
+        for (five_hour, extra) in [(10.0, None), (30.0, Some(5.0)), (20.0, None)] {
+            store
+                .record(Provider::Claude, Series::Usage, &usage(five_hour, extra))
+                .unwrap();
+        }
+        {
+            let conn = store.lock().unwrap();
+            for (id, fetched_at) in [(1, 3_600_000), (2, 5_400_000), (3, 7_300_000)] {
+                conn.execute(
+                    "UPDATE samples SET fetched_at = ?1 WHERE id = ?2",
+                    params![fetched_at, id],
+                )
+                .unwrap();
+            }
+        }
+
+        let samples: Vec<Sample<UsageData>> = store
+            .since(Provider::Claude, Series::Usage, 3_600_000)
+            .unwrap();
+        assert_eq!(samples.len(), 3);
+        assert_eq!(claude_points(&samples, None, 0).len(), 3);
+
+        let hourly = claude_points(&samples, Some(HistoryBucket::Hour), 0);
+        assert_eq!(hourly.len(), 2);
+        assert_eq!(
+            (
+                hourly[0].at,
+                hourly[0].five_hour,
+                hourly[0].extra_usage,
+                hourly[0].samples
+            ),
+            (3_600_000, 30.0, Some(5.0), 2)
+        );
+        assert_eq!((hourly[1].at, hourly[1].extra_usage), (7_200_000, None));
+
+        // Half an hour east of UTC, the first two samples fall in different hours.
+        let shifted = claude_points(&samples, Some(HistoryBucket::Hour), 1_800_000);
+        assert_eq!(shifted.len(), 2);
+        assert_eq!(shifted[0].at, 1_800_000);
+        assert_eq!(shifted[1].at, 5_400_000);
+        assert_eq!(shifted[1].samples, 2);
+
+        let later: Vec<Sample<UsageData>> = store
+            .since(Provider::Claude, Series::Usage, 6_000_000)
+            .unwrap();
+        assert_eq!(later.len(), 1);
+    }
+
+    #[test]
+    fn test_zai_history_splits_cycles_and_weeks() {
+        use crate::models::{McpUsage, TokenUsage};
+        let hour = 3_600_000;
+        let sample = |at: i64, percentage: f64, resets_at: i64, used: i32| Sample {
+            data: ZaiUsageData {
+                token_usage: Some(TokenUsage {
+                    percentage,
+                    resets_at: Some(resets_at),
+                }),
+                mcp_usage: Some(McpUsage {
+                    percentage: used as f64,
+                    used,
+                    total: 100,
+                }),
+                tier_name: None,
+                limits: Vec::new(),
+            },
+            fetched_at: at,
+        };
+        // 1970-01-05 was a Monday.
+        let monday = 4 * DAY_MILLIS;
+        let samples = [
+            sample(monday, 10.0, monday + 5 * hour, 4),
+            sample(monday + hour, 40.0, monday + 5 * hour + 1_000, 12),
+            // Same reset time but lower: the window reset early.
+            sample(monday + 2 * hour, 5.0, monday + 5 * hour, 2),
+            sample(monday + 3 * hour, 20.0, monday + 5 * hour, 6),
+            sample(
+                monday + 7 * DAY_MILLIS,
+                30.0,
+                monday + 7 * DAY_MILLIS + hour,
+                8,
+            ),
+        ];
+
+        let history = zai_history(&samples, Some(HistoryBucket::Day), 0);
+        assert_eq!(history.points.len(), 2);
+        assert_eq!(history.points[0].tokens, Some(40.0));
+        assert_eq!(history.points[0].samples, 4);
+
+        let peaks: Vec<(f64, Option<i32>)> = history
+            .cycles
+            .iter()
+            .map(|c| (c.peak_percentage, c.prompts))
+            .collect();
+        assert_eq!(peaks, [(40.0, Some(12)), (20.0, Some(6)), (30.0, Some(8))]);
+
+        assert_eq!(history.weeks.len(), 2);
+        assert_eq!(
+            (history.weeks[0].week_start, history.weeks[0].cycles),
+            (monday, 2)
+        );
+        assert_eq!(history.weeks[0].average_peak_percentage, 30.0);
+        assert_eq!(history.weeks[0].prompts, Some(18));
+
+        // The open window isn't in the averages.
+        assert_eq!(history.average_peak_percentage, Some(30.0));
+        assert_eq!(history.average_prompts, Some(9.0));
+    }
+
+    #[test]
+    fn test_stats_count_samples_and_survive_vacuum() {
+        let store = HistoryStore::in_memory().unwrap();
+        let tier = ZaiTierData {
+            plan_name: "Pro".to_string(),
+        };
+        store.record(Provider::Zai, Series::Tier, &tier).unwrap();
+        store.record(Provider::Zai, Series::Usage, &tier).unwrap();
+
+        store.vacuum().unwrap();
+        let stats = store.stats().unwrap();
+        assert_eq!(stats.sample_count, 2);
+        assert!(stats.oldest_sample_at.is_some());
+        assert!(stats.size_bytes > 0);
+    }
+
+    #[test]
+    fn test_prune_drops_samples_past_retention() {
+        let store = HistoryStore::in_memory().unwrap();
+        let tier = ZaiTierData {
+            plan_name: "Pro".to_string(),
+        };
+        for _ in 0..3 {
+            store.record(Provider::Zai, Series::Tier, &tier).unwrap();
+        }
+        let now = now_millis();
+        {
+            let conn = store.lock().unwrap();
+            let old = now - (RETENTION_DAYS + 1) * DAY_MILLIS;
+            let recent = now - (RETENTION_DAYS - 1) * DAY_MILLIS;
+            for (id, fetched_at) in [(1, old), (2, recent)] {
+                conn.execute(
+                    "UPDATE samples SET fetched_at = ?1 WHERE id = ?2",
+                    params![fetched_at, id],
+                )
+                .unwrap();
+            }
+        }
+
+        assert_eq!(store.prune().unwrap(), 1);
+        assert_eq!(store.stats().unwrap().sample_count, 2);
+        assert_eq!(store.prune().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_open_rebuilds_damaged_database() {
+        let dir = std::env::temp_dir().join(format!("usage-bar-history-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(HISTORY_FILE_NAME);
+        std::fs::write(&path, b"not a database, just garbage bytes").unwrap();
+
+        let store = HistoryStore::open_at(&path).unwrap();
+        assert_eq!(store.stats().unwrap().sample_count, 0);
+        assert!(path.with_extension("db.corrupt").exists());
+
+        drop(store);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,112 +1,179 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod amp_service;
-mod cache;
-mod claude_service;
-mod codex_service;
+mod alerts;
 mod commands;
-mod credentials;
-mod logging;
-mod models;
-mod zai_service;
-
-// Re-export logging constants so macros can find them via $crate
-pub use logging::{
-    COLOR_BLUE, COLOR_BRIGHT_CYAN, COLOR_BRIGHT_RED, COLOR_CYAN, COLOR_GRAY, COLOR_GREEN,
-    COLOR_MAGENTA, COLOR_RED, COLOR_RESET, COLOR_YELLOW,
-};
+#[cfg(test)]
+mod e2e;
+mod feed;
+mod forecast;
+mod headroom;
+mod history;
+mod links;
+mod plan_limits;
+mod presence;
+mod providers;
+mod settings;
+mod snapshot;
+mod template;
+mod token_refresh;
+mod tray;
 
-use cache::ResponseCache;
-use models::{
-    AmpUsageData, ClaudeTierData, CodexTierData, CodexUsageData, UsageData, ZaiTierData,
-    ZaiUsageData,
+// Provider clients, models and credentials live in the Tauri-free core crate; imported at
+// the root so modules keep using `crate::models` etc.
+use usage_bar_core::{
+    amp_service, anthropic_service, cache, claude_login, claude_service, clock, codex_service,
+    credentials, endpoint, failed_payloads, gemini_service, i18n, local_usage, models, money,
+    rate_limit, vault, zai_service,
+};
+use usage_bar_core::{
+    debug_amp, debug_app, debug_cache, debug_claude, debug_cred, debug_error, debug_net, debug_zai,
 };
+
+use alerts::AlertEngine;
+use cache::CacheMap;
+use history::HistoryStore;
+use settings::SettingsStore;
 use std::sync::Arc;
-use std::time::Duration;
-use tauri::{tray::TrayIconBuilder, Manager};
+use tauri::{tray::TrayIconBuilder, Listener, Manager};
 
 pub struct HttpClient(pub Arc<reqwest::Client>);
 pub struct AmpHttpClient(pub Arc<reqwest::Client>);
-pub struct ClaudeUsageCache(pub ResponseCache<UsageData>);
-pub struct ClaudeTierCache(pub ResponseCache<ClaudeTierData>);
-pub struct CodexUsageCache(pub ResponseCache<CodexUsageData>);
-pub struct CodexTierCache(pub ResponseCache<CodexTierData>);
-pub struct ZaiUsageCache(pub ResponseCache<ZaiUsageData>);
-pub struct ZaiTierCache(pub ResponseCache<ZaiTierData>);
-pub struct AmpUsageCache(pub ResponseCache<AmpUsageData>);
+pub struct CacheState(pub CacheMap);
+pub struct SettingsState(pub Arc<SettingsStore>);
+pub struct UsageHistory(pub HistoryStore);
+pub struct AlertState(pub AlertEngine);
+pub struct FeedState(pub feed::UsageFeed);
+pub struct ProviderRegistryState(pub providers::ProviderRegistry);
+pub struct CredentialMigrations(pub Vec<models::CredentialMigration>);
+/// The Claude login waiting for its pasted code; a new `claude_start_login` replaces it.
+pub struct ClaudeLoginState(pub std::sync::Mutex<Option<claude_login::PendingLogin>>);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     debug_app!("Usage Bar starting...");
 
     tauri::Builder::default()
+        .plugin(tauri_plugin_notification::init())
         .setup(|app| {
             debug_app!("Initializing application state");
 
+            // Installed before anything reads credentials. Secrets the OS store refuses (e.g.
+            // CredWriteW blocked by policy) go to a DPAPI-protected file instead, and move back
+            // once the store accepts them.
+            if let Ok(dir) = settings::app_data_dir() {
+                credentials::CredentialManager::set_secret_store(Arc::new(
+                    vault::FallbackStore::new(
+                        Arc::new(vault::OsStore),
+                        Arc::new(vault::ProtectedFileStore::new(dir.join("credentials.bin"))),
+                    ),
+                ));
+            }
+
+            // Credentials saved by older builds are moved to the current target names before
+            // the first fetch; the popup reports what was moved.
+            app.manage(CredentialMigrations(
+                credentials::CredentialManager::migrate_legacy_credentials(),
+            ));
+
             // Initialize shared HTTP client (with redirects)
             let client = reqwest::Client::builder()
-                .timeout(Duration::from_secs(15))
+                .timeout(endpoint::DEFAULT_TIMEOUT)
                 .build()
                 .map_err(|e| anyhow::anyhow!("Failed to build HTTP client: {e}"))?;
-            app.manage(HttpClient(Arc::new(client)));
+            let client = Arc::new(client);
+            app.manage(HttpClient(client.clone()));
             debug_app!("HTTP client initialized (timeout: 15s, redirects enabled)");
+            plan_limits::spawn_updater(client.clone());
+            token_refresh::spawn_scheduler(client.clone());
 
-            // Redirects disabled: Amp returns HTTP 302 to /login when the session cookie expires.
-            // We detect this by inspecting the redirect Location header instead of following it,
-            // which lets us distinguish "valid session" from "expired session" responses.
-            // Chrome UA used to avoid bot-detection heuristics on ampcode.com.
-            // If Amp tightens bot detection, consider rotating or using a generic UA.
-            let amp_client = reqwest::Client::builder()
-                .timeout(Duration::from_secs(15))
-                .redirect(reqwest::redirect::Policy::none())
-                .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/143.0.0.0 Safari/537.36")
-                .build()
-                .map_err(|e| anyhow::anyhow!("Failed to build Amp HTTP client: {e}"))?;
-            app.manage(AmpHttpClient(Arc::new(amp_client)));
+            // Redirects disabled so an expired session shows up as a 302 to /login.
+            let amp_client = Arc::new(amp_service::AmpService::http_client()?);
+            app.manage(AmpHttpClient(amp_client.clone()));
             debug_app!("Amp HTTP client initialized (timeout: 15s, redirects disabled)");
 
             // 30s TTL balances freshness with external API rate limits:
             // - Amp replenishes hourly, so 30s is more than precise enough
             // - Claude resets every 5 hours, Z.ai resets daily
             // - Short enough that manual refreshes feel responsive
-            app.manage(ClaudeUsageCache(ResponseCache::new(30)));
-            app.manage(ClaudeTierCache(ResponseCache::new(30)));
-            app.manage(CodexUsageCache(ResponseCache::new(30)));
-            app.manage(CodexTierCache(ResponseCache::new(30)));
-            app.manage(ZaiUsageCache(ResponseCache::new(30)));
-            app.manage(ZaiTierCache(ResponseCache::new(30)));
-            app.manage(AmpUsageCache(ResponseCache::new(30)));
+            // `cache_ttl_secs` in settings replaces it once settings are loaded.
+            app.manage(CacheState(CacheMap::new(30)));
+            app.manage(ClaudeLoginState(Default::default()));
             debug_app!("Response caches initialized (TTL: 30s)");
 
+            // Everything but the typed `*_get_*` commands goes through the registry
+            // (refreshes, groups, snapshots, headroom, the usage feed, the tray).
+            let mut registry = providers::ProviderRegistry::default();
+            registry.register(providers::ClaudeProvider {
+                client: client.clone(),
+            });
+            registry.register(providers::CodexProvider {
+                client: client.clone(),
+            });
+            registry.register(providers::ZaiProvider {
+                client: client.clone(),
+            });
+            registry.register(providers::AmpProvider { client: amp_client });
+            registry.register(providers::AnthropicProvider {
+                client: client.clone(),
+            });
+            registry.register(providers::GeminiProvider {
+                client: client.clone(),
+            });
+            app.manage(ProviderRegistryState(registry));
+
+            // Settings live in a JSON file users may edit by hand; the watcher picks up
+            // external changes and reports invalid edits instead of applying them.
+            let settings = Arc::new(SettingsStore::load());
+            settings::spawn_watcher(app.handle().clone(), settings.clone());
+            settings::apply_to_app(app.handle(), &settings.get());
+            app.manage(SettingsState(settings));
+            debug_app!("Settings loaded and file watcher started");
+
+            // Every successful fetch is kept so a failed one can fall back to the last good value.
+            app.manage(UsageHistory(HistoryStore::open()));
+            // Last run's samples, so the tray isn't blank until the first fetch.
+            commands::seed_caches(app.handle());
+
+            // Responses that fail to parse are kept (sanitized) for bug reports.
+            failed_payloads::set_dir(
+                settings::app_data_dir()
+                    .ok()
+                    .map(|dir| dir.join("failed-payloads")),
+            );
+
+            // User-defined alert rules are checked against every fresh usage sample.
+            app.manage(AlertState(AlertEngine::load(app.handle().clone(), client)));
+            app.manage(FeedState(feed::UsageFeed::default()));
+
             // Get the window that was automatically created from tauri.conf.json
             if let Some(window) = app.get_webview_window("main") {
                 window.set_ignore_cursor_events(false)?;
 
                 // Handle window close event for graceful shutdown
                 let window_clone = window.clone();
-                window.on_window_event(move |event| {
-                    if let tauri::WindowEvent::CloseRequested { api, .. } = event {
+                window.on_window_event(move |event| match event {
+                    tauri::WindowEvent::CloseRequested { api, .. } => {
                         debug_app!("Window close requested, hiding to tray");
                         api.prevent_close();
                         if window_clone.hide().is_err() {
                             debug_error!("Failed to hide window");
                         }
                     }
+                    // Covers every way the popup is opened (tray menu, shortcuts).
+                    tauri::WindowEvent::Focused(true) => {
+                        commands::refresh_stale_on_show(window_clone.app_handle());
+                    }
+                    _ => {}
                 });
 
                 debug_app!("Main window configured");
             }
 
-            // Create tray icon with menu
-            let _tray = TrayIconBuilder::new()
-                .menu(&tauri::menu::Menu::with_items(
-                    app,
-                    &[
-                        &tauri::menu::MenuItem::with_id(app, "open", "Open", true, None::<&str>)?,
-                        &tauri::menu::PredefinedMenuItem::separator(app)?,
-                        &tauri::menu::MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?,
-                    ],
-                )?)
+            // Create tray icon with menu. Provider submenus show reset countdowns
+            // as disabled items so a right-click answers "when does it reset?".
+            let menu = tray::initial_menu(app.handle())?;
+            let _tray = TrayIconBuilder::with_id(tray::TRAY_ID)
+                .menu(&menu)
                 .on_menu_event(move |app, event| match event.id.as_ref() {
                     "open" => {
                         if let Some(window) = app.get_webview_window("main") {
@@ -131,6 +198,17 @@ async fn main() -> anyhow::Result<()> {
                 .build(app)?;
 
             debug_app!("System tray icon registered");
+            tray::spawn_updater(app.handle().clone());
+
+            // Polling pauses and alert toasts are held back while the session is locked.
+            presence::spawn_monitor(app.handle().clone());
+
+            // `tray_group` decides which providers the tray menu and title list.
+            let handle = app.handle().clone();
+            app.listen_any("settings-changed", move |_| {
+                tray::rebuild_menu(&handle);
+                tray::update_title(&handle);
+            });
             debug_app!("Initialization complete");
 
             Ok(())
@@ -139,6 +217,23 @@ async fn main() -> anyhow::Result<()> {
             commands::claude_get_all,
             commands::claude_get_usage,
             commands::claude_get_tier,
+            commands::claude_get_profile,
+            commands::claude_start_login,
+            commands::claude_complete_login,
+            commands::claude_reauth,
+            commands::claude_get_local_usage,
+            commands::claude_get_project_usage,
+            commands::claude_get_model_breakdown,
+            commands::claude_get_forecast,
+            commands::claude_get_history,
+            commands::claude_switch_profile,
+            commands::get_credential_sources,
+            commands::get_credential_migrations,
+            commands::claude_get_organizations,
+            commands::claude_get_accounts_usage,
+            commands::claude_get_extra_usage,
+            commands::claude_open_billing,
+            commands::claude_get_prompt_estimate,
             commands::codex_get_all,
             commands::codex_refresh_all,
             commands::codex_get_usage,
@@ -148,20 +243,73 @@ async fn main() -> anyhow::Result<()> {
             commands::zai_refresh_all,
             commands::zai_get_usage,
             commands::zai_get_tier,
+            commands::zai_get_balance,
+            commands::zai_get_model_usage,
+            commands::zai_get_history,
+            commands::zai_get_forecast,
             commands::zai_refresh_usage,
             commands::zai_check_api_key,
             commands::zai_validate_api_key,
             commands::zai_save_api_key,
             commands::zai_delete_api_key,
+            commands::zai_list_api_keys,
             commands::amp_get_usage,
             commands::amp_refresh_usage,
+            commands::amp_get_team_usage,
             commands::amp_check_session_cookie,
             commands::amp_validate_session_cookie,
             commands::amp_save_session_cookie,
             commands::amp_delete_session_cookie,
+            commands::amp_import_cookie_from_browser,
+            commands::amp_get_session_status,
+            commands::amp_check_api_key,
+            commands::amp_validate_api_key,
+            commands::amp_save_api_key,
+            commands::amp_delete_api_key,
+            commands::anthropic_get_usage,
+            commands::anthropic_refresh_usage,
+            commands::anthropic_check_admin_key,
+            commands::anthropic_validate_admin_key,
+            commands::anthropic_save_admin_key,
+            commands::anthropic_delete_admin_key,
+            commands::gemini_get_usage,
+            commands::gemini_refresh_usage,
+            commands::gemini_check_credentials,
+            commands::gemini_save_oauth_credentials,
+            commands::gemini_delete_oauth_credentials,
+            commands::gemini_validate_api_key,
+            commands::gemini_save_api_key,
+            commands::gemini_delete_api_key,
             commands::quit_app,
             commands::refresh_all,
+            commands::get_provider_health,
+            commands::get_reset_countdowns,
+            commands::list_providers,
+            commands::get_provider_usage,
+            commands::validate_provider_credentials,
+            commands::get_history_db_stats,
+            commands::vacuum_history_db,
+            commands::list_failed_payloads,
+            commands::open_failed_payloads_folder,
+            commands::get_group_usage,
+            commands::refresh_group,
+            commands::get_all_snapshots,
             commands::open_url,
+            commands::get_settings,
+            commands::update_settings,
+            commands::get_settings_error,
+            commands::get_poll_intervals,
+            commands::get_session_state,
+            commands::get_alert_rules,
+            commands::export_config_template,
+            commands::save_alert_rule,
+            commands::delete_alert_rule,
+            commands::validate_alert_condition,
+            commands::can_i_run,
+            commands::subscribe_usage,
+            commands::unsubscribe_usage,
+            commands::debug_set_clock_offset,
+            commands::debug_record_amp_fixture,
         ])
         .run(tauri::generate_context!())
         .map_err(|e| {
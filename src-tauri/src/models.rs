@@ -1,323 +0,0 @@
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UsageResponse {
-    pub five_hour: Option<UsagePeriod>,
-    pub seven_day: Option<UsagePeriod>,
-    pub extra_usage: Option<ExtraUsageResponse>,
-    // Tier info also comes from the same /usage endpoint
-    #[serde(default)]
-    pub rate_limit_tier: Option<String>,
-    #[serde(default)]
-    pub billing_type: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ExtraUsageResponse {
-    pub is_enabled: bool,
-    pub monthly_limit: Option<f64>,
-    pub used_credits: Option<f64>,
-    pub utilization: Option<f64>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UsagePeriod {
-    pub utilization: f64,
-    #[serde(default)]
-    pub resets_at: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct UsageData {
-    pub five_hour_utilization: f64,
-    pub five_hour_resets_at: Option<String>,
-    pub seven_day_utilization: f64,
-    pub seven_day_resets_at: Option<String>,
-    pub extra_usage_enabled: bool,
-    pub extra_usage_monthly_limit: Option<f64>,
-    pub extra_usage_used_credits: Option<f64>,
-    pub extra_usage_utilization: Option<f64>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ZaiQuotaResponse {
-    pub data: ZaiQuotaData,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ZaiQuotaData {
-    pub limits: Vec<ZaiQuotaLimit>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ZaiQuotaLimit {
-    #[serde(rename = "type")]
-    pub limit_type: String,
-    pub percentage: f64,
-    #[serde(rename = "nextResetTime")]
-    pub next_reset_time: Option<i64>,
-    #[serde(rename = "currentValue")]
-    pub current_value: Option<i32>,
-    pub usage: Option<i32>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ZaiUsageData {
-    pub token_usage: Option<TokenUsage>,
-    pub mcp_usage: Option<McpUsage>,
-    pub tier_name: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ZaiTierData {
-    pub plan_name: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TokenUsage {
-    pub percentage: f64,
-    pub resets_at: Option<i64>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct McpUsage {
-    pub percentage: f64,
-    pub used: i32,
-    pub total: i32,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ClaudeOAuthCredentials {
-    #[serde(rename = "claudeAiOauth")]
-    pub claude_ai_oauth: ClaudeOAuth,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ClaudeOAuth {
-    #[serde(rename = "accessToken")]
-    pub access_token: String,
-    #[serde(rename = "refreshToken")]
-    pub refresh_token: String,
-    #[serde(rename = "expiresAt")]
-    #[serde(default)]
-    #[serde(deserialize_with = "deserialize_expires_at")]
-    pub expires_at: Option<i64>,
-    #[serde(rename = "subscriptionType", default)]
-    pub subscription_type: Option<String>,
-    #[serde(rename = "rateLimitTier", default)]
-    pub rate_limit_tier: Option<String>,
-}
-
-fn deserialize_expires_at<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    use serde::de::{self, Deserialize};
-    use serde_json::Value;
-
-    let value = Value::deserialize(deserializer)?;
-    match value {
-        Value::Null => Ok(None),
-        Value::Number(n) => {
-            if let Some(i) = n.as_i64() {
-                Ok(Some(i))
-            } else if let Some(f) = n.as_f64() {
-                Ok(Some(f as i64))
-            } else {
-                Err(de::Error::custom("invalid number for expires_at"))
-            }
-        }
-        _ => Err(de::Error::custom("expected number or null for expires_at")),
-    }
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TokenRefreshResponse {
-    #[serde(rename = "access_token")]
-    pub access_token: String,
-    #[serde(rename = "refresh_token")]
-    pub refresh_token: String,
-    #[serde(rename = "expires_in")]
-    pub expires_in: i64,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ClaudeTierData {
-    pub plan_name: String,
-    pub rate_limit_tier: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AmpUsageData {
-    pub quota: f64,
-    pub used: f64,
-    /// Clamped to [0.0, 100.0]. If quota is 0, division yields infinity → clamped to 100.0.
-    pub used_percent: f64,
-    pub hourly_replenishment: f64,
-    /// Duration of the usage window in hours. Stored as f64 because the Amp JS object
-    /// may theoretically use fractional hours; use `as u32` when integer precision suffices.
-    pub window_hours: Option<f64>,
-    #[serde(default)]
-    #[serde(deserialize_with = "deserialize_expires_at")]
-    pub resets_at: Option<i64>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodexUsageData {
-    pub session_usage: Option<CodexWindowUsage>,
-    pub weekly_usage: Option<CodexWindowUsage>,
-    pub credits: Option<CodexCredits>,
-    pub tier_name: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodexTierData {
-    pub plan_name: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodexWindowUsage {
-    pub percentage: f64,
-    /// Epoch milliseconds.
-    pub resets_at: Option<i64>,
-    pub window_seconds: Option<i64>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodexCredits {
-    pub has_credits: bool,
-    pub unlimited: bool,
-    pub balance: Option<f64>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodexAuthFile {
-    #[serde(rename = "OPENAI_API_KEY", default)]
-    pub openai_api_key: Option<String>,
-    #[serde(default)]
-    pub tokens: Option<CodexAuthTokens>,
-    #[serde(default)]
-    pub last_refresh: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodexAuthTokens {
-    pub access_token: String,
-    pub refresh_token: Option<String>,
-    #[serde(default)]
-    pub id_token: Option<String>,
-    #[serde(default)]
-    pub account_id: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodexUsageResponse {
-    #[serde(default)]
-    pub plan_type: Option<String>,
-    #[serde(default)]
-    pub rate_limit: Option<CodexRateLimitDetails>,
-    #[serde(default)]
-    pub credits: Option<CodexUsageCredits>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodexRateLimitDetails {
-    #[serde(default)]
-    pub primary_window: Option<CodexUsageWindow>,
-    #[serde(default)]
-    pub secondary_window: Option<CodexUsageWindow>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodexUsageWindow {
-    #[serde(deserialize_with = "deserialize_f64_from_number_or_string")]
-    pub used_percent: f64,
-    #[serde(deserialize_with = "deserialize_i64_from_number_or_string")]
-    pub reset_at: i64,
-    #[serde(deserialize_with = "deserialize_i64_from_number_or_string")]
-    pub limit_window_seconds: i64,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodexUsageCredits {
-    #[serde(default)]
-    pub has_credits: bool,
-    #[serde(default)]
-    pub unlimited: bool,
-    #[serde(default)]
-    #[serde(deserialize_with = "deserialize_optional_f64_from_number_or_string")]
-    pub balance: Option<f64>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CodexRefreshResponse {
-    pub access_token: String,
-    #[serde(default)]
-    pub refresh_token: Option<String>,
-    #[serde(default)]
-    pub id_token: Option<String>,
-}
-
-fn deserialize_f64_from_number_or_string<'de, D>(deserializer: D) -> Result<f64, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    use serde::de::{self, Deserialize};
-    use serde_json::Value;
-
-    match Value::deserialize(deserializer)? {
-        Value::Number(number) => number
-            .as_f64()
-            .ok_or_else(|| de::Error::custom("invalid number for f64")),
-        Value::String(value) => value
-            .parse::<f64>()
-            .map_err(|_| de::Error::custom("invalid string for f64")),
-        _ => Err(de::Error::custom("expected number or numeric string")),
-    }
-}
-
-fn deserialize_i64_from_number_or_string<'de, D>(deserializer: D) -> Result<i64, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    use serde::de::{self, Deserialize};
-    use serde_json::Value;
-
-    match Value::deserialize(deserializer)? {
-        Value::Number(number) => number
-            .as_i64()
-            .or_else(|| number.as_f64().map(|value| value as i64))
-            .ok_or_else(|| de::Error::custom("invalid number for i64")),
-        Value::String(value) => value
-            .parse::<i64>()
-            .or_else(|_| value.parse::<f64>().map(|value| value as i64))
-            .map_err(|_| de::Error::custom("invalid string for i64")),
-        _ => Err(de::Error::custom("expected number or numeric string")),
-    }
-}
-
-fn deserialize_optional_f64_from_number_or_string<'de, D>(
-    deserializer: D,
-) -> Result<Option<f64>, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    use serde::de::{self, Deserialize};
-    use serde_json::Value;
-
-    match Value::deserialize(deserializer)? {
-        Value::Null => Ok(None),
-        Value::Number(number) => number
-            .as_f64()
-            .map(Some)
-            .ok_or_else(|| de::Error::custom("invalid number for optional f64")),
-        Value::String(value) if value.trim().is_empty() => Ok(None),
-        Value::String(value) => value
-            .parse::<f64>()
-            .map(Some)
-            .map_err(|_| de::Error::custom("invalid string for optional f64")),
-        _ => Err(de::Error::custom(
-            "expected number, numeric string, or null",
-        )),
-    }
-}
@@ -0,0 +1,231 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::Duration;
+
+use crate::endpoint;
+use crate::models::{ClaudeTierData, UsageData};
+use crate::{debug_app, debug_claude, debug_error, debug_net};
+
+/// The same file the app embeds, served from the default branch so estimates can be
+/// corrected without a release.
+const REMOTE_URL: &str =
+    "https://raw.githubusercontent.com/mynameistito/usage-bar/main/src-tauri/plan-limits.json";
+const BUILTIN: &str = include_str!("../plan-limits.json");
+const REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Guards against a bad edit upstream turning every estimate into nonsense.
+const MAX_PROMPTS: u32 = 100_000;
+
+/// Approximate prompts per 5-hour window for one plan. Anthropic publishes these as
+/// ranges that depend on message length, so they are rough by nature.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlanLimit {
+    /// `ClaudeTierData::plan_name` (`"Pro"`, `"Max"`).
+    plan: String,
+    /// Narrows a plan by `rate_limit_tier`, e.g. `"20x"` for Max 20x. The first matching
+    /// entry wins, so specific entries go before the plan's fallback.
+    #[serde(default)]
+    rate_limit_tier_contains: Option<String>,
+    prompts_per_five_hours: u32,
+}
+
+/// `plan-limits.json`; its `version` is for editors and ignored here.
+#[derive(Debug, Clone, Deserialize)]
+struct PlanLimits {
+    plans: Vec<PlanLimit>,
+}
+
+impl PlanLimits {
+    fn parse(json: &str) -> Result<Self> {
+        let limits: Self = serde_json::from_str(json)?;
+        if limits.plans.is_empty() {
+            return Err(anyhow!("plan limits list no plans"));
+        }
+        if let Some(bad) = limits
+            .plans
+            .iter()
+            .find(|p| p.prompts_per_five_hours == 0 || p.prompts_per_five_hours > MAX_PROMPTS)
+        {
+            return Err(anyhow!(
+                "plan {} has an implausible prompt count {}",
+                bad.plan,
+                bad.prompts_per_five_hours
+            ));
+        }
+        Ok(limits)
+    }
+
+    fn find(&self, tier: &ClaudeTierData) -> Option<&PlanLimit> {
+        let rate_limit_tier = tier.rate_limit_tier.to_lowercase();
+        self.plans.iter().find(|p| {
+            p.plan.eq_ignore_ascii_case(&tier.plan_name)
+                && p.rate_limit_tier_contains
+                    .as_deref()
+                    .is_none_or(|needle| rate_limit_tier.contains(&needle.to_lowercase()))
+        })
+    }
+}
+
+/// Where the figures behind an estimate came from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitSource {
+    Builtin,
+    Remote,
+}
+
+/// Result of `claude_get_prompt_estimate`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptEstimate {
+    pub plan_prompts: u32,
+    /// Prompts left in the current 5-hour window at the plan's average, rounded down.
+    pub prompts_remaining: u32,
+    pub source: LimitSource,
+}
+
+static LIMITS: LazyLock<RwLock<(Arc<PlanLimits>, LimitSource)>> = LazyLock::new(|| {
+    let builtin = PlanLimits::parse(BUILTIN).unwrap_or_else(|e| {
+        debug_error!("Embedded plan limits are invalid: {e}");
+        PlanLimits { plans: Vec::new() }
+    });
+    RwLock::new((Arc::new(builtin), LimitSource::Builtin))
+});
+
+fn current() -> (Arc<PlanLimits>, LimitSource) {
+    let guard = LIMITS
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    (Arc::clone(&guard.0), guard.1)
+}
+
+fn estimate_with(
+    limits: &PlanLimits,
+    source: LimitSource,
+    usage: &UsageData,
+    tier: &ClaudeTierData,
+) -> Option<PromptEstimate> {
+    let plan = limits.find(tier)?;
+    let left = (100.0 - usage.five_hour_utilization).clamp(0.0, 100.0) / 100.0;
+    Some(PromptEstimate {
+        plan_prompts: plan.prompts_per_five_hours,
+        prompts_remaining: (f64::from(plan.prompts_per_five_hours) * left).floor() as u32,
+        source,
+    })
+}
+
+/// `None` for plans without a reference figure (Free, Team, Enterprise).
+pub fn estimate(usage: &UsageData, tier: &ClaudeTierData) -> Option<PromptEstimate> {
+    let (limits, source) = current();
+    estimate_with(&limits, source, usage, tier)
+}
+
+async fn fetch_remote(client: &reqwest::Client) -> Result<PlanLimits> {
+    debug_net!("GET {REMOTE_URL}");
+    let body = client
+        .get(endpoint::resolve(REMOTE_URL).as_ref())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    PlanLimits::parse(&body)
+}
+
+/// Replaces the embedded table with the published one now and once a day. Failures keep
+/// whatever table is in use.
+pub fn spawn_updater(client: Arc<reqwest::Client>) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            match fetch_remote(&client).await {
+                Ok(limits) => {
+                    debug_claude!("Loaded {} plan limits from remote", limits.plans.len());
+                    *LIMITS
+                        .write()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner()) =
+                        (Arc::new(limits), LimitSource::Remote);
+                }
+                Err(e) => {
+                    debug_error!("Failed to update plan limits: {e}");
+                }
+            }
+        }
+    });
+    debug_app!("Plan limit updater started");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tier(plan_name: &str, rate_limit_tier: &str) -> ClaudeTierData {
+        ClaudeTierData {
+            plan_name: plan_name.into(),
+            rate_limit_tier: rate_limit_tier.into(),
+            organization_name: None,
+        }
+    }
+
+    fn usage(five_hour_utilization: f64) -> UsageData {
+        UsageData {
+            five_hour_utilization,
+            five_hour_resets_at: None,
+            seven_day_utilization: 0.0,
+            seven_day_resets_at: None,
+            seven_day_opus_utilization: None,
+            seven_day_opus_resets_at: None,
+            extra_usage_enabled: false,
+            extra_usage_monthly_limit: None,
+            extra_usage_used_credits: None,
+            extra_usage_utilization: None,
+            extra_usage_resets_at: None,
+            extra_usage_budget_utilization: None,
+        }
+    }
+
+    #[test]
+    fn test_builtin_table_parses() {
+        assert!(PlanLimits::parse(BUILTIN).is_ok());
+    }
+
+    #[test]
+    fn test_estimate_matches_plan_and_tier() {
+        let limits = PlanLimits::parse(BUILTIN).unwrap();
+        let max20 = estimate_with(
+            &limits,
+            LimitSource::Builtin,
+            &usage(50.0),
+            &tier("Max", "default_claude_max_20x"),
+        )
+        .unwrap();
+        assert_eq!(max20.plan_prompts, 900);
+        assert_eq!(max20.prompts_remaining, 450);
+
+        let pro = estimate_with(
+            &limits,
+            LimitSource::Builtin,
+            &usage(110.0),
+            &tier("Pro", ""),
+        )
+        .unwrap();
+        assert_eq!(pro.prompts_remaining, 0);
+
+        assert!(estimate_with(
+            &limits,
+            LimitSource::Builtin,
+            &usage(0.0),
+            &tier("Free", "")
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_parse_rejects_implausible_counts() {
+        let json = r#"{"version": 1, "plans": [{"plan": "Pro", "prompts_per_five_hours": 0}]}"#;
+        assert!(PlanLimits::parse(json).is_err());
+        assert!(PlanLimits::parse(r#"{"version": 1, "plans": []}"#).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use tauri::{AppHandle, Emitter};
+
+use crate::{debug_app, debug_error};
+
+/// Whether anyone is looking at the screen. Emitted as `session-state-changed` whenever
+/// either flag flips.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SessionState {
+    /// The Windows session is locked; the popup pauses polling until it is unlocked.
+    pub locked: bool,
+    /// A presentation, full-screen game or projection is running (or Windows reports
+    /// "busy"); toasts would interrupt it.
+    pub presenting: bool,
+}
+
+static LOCKED: AtomicBool = AtomicBool::new(false);
+static PRESENTING: AtomicBool = AtomicBool::new(false);
+static APP: OnceLock<AppHandle> = OnceLock::new();
+
+pub fn current() -> SessionState {
+    SessionState {
+        locked: LOCKED.load(Ordering::Relaxed),
+        presenting: PRESENTING.load(Ordering::Relaxed),
+    }
+}
+
+/// Alert toasts are held back while the session is locked or presenting.
+pub fn notifications_suppressed() -> bool {
+    let state = current();
+    state.locked || state.presenting
+}
+
+#[cfg(target_os = "windows")]
+fn set(flag: &AtomicBool, value: bool) {
+    if flag.swap(value, Ordering::Relaxed) == value {
+        return;
+    }
+    let state = current();
+    debug_app!("Session state changed: {state:?}");
+    if let Some(app) = APP.get() {
+        if app.emit("session-state-changed", state).is_err() {
+            debug_error!("Failed to emit session-state-changed");
+        }
+    }
+}
+
+/// How often the notification state (presentation mode, full-screen apps) is checked.
+/// Windows has no change notification for it.
+#[cfg(target_os = "windows")]
+const PRESENTATION_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Starts listening for session lock/unlock and polling for presentation mode.
+pub fn spawn_monitor(app: AppHandle) {
+    if APP.set(app).is_err() {
+        return;
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        std::thread::spawn(|| {
+            if let Err(e) = windows_session::run() {
+                debug_error!("Session lock notifications unavailable: {e}");
+            }
+        });
+
+        tauri::async_runtime::spawn(async {
+            let mut interval = tokio::time::interval(PRESENTATION_POLL_INTERVAL);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                interval.tick().await;
+                set(&PRESENTING, windows_session::is_presenting());
+            }
+        });
+        debug_app!("Session lock and presentation monitor started");
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod windows_session {
+    use super::{set, LOCKED};
+    use windows::core::w;
+    use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows::Win32::System::RemoteDesktop::{
+        WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+    };
+    use windows::Win32::UI::Shell::{
+        SHQueryUserNotificationState, QUNS_BUSY, QUNS_PRESENTATION_MODE,
+        QUNS_RUNNING_D3D_FULL_SCREEN,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW,
+        HWND_MESSAGE, MSG, WINDOW_EX_STYLE, WINDOW_STYLE, WM_WTSSESSION_CHANGE, WNDCLASSW,
+        WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+    };
+
+    unsafe extern "system" fn window_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        if msg == WM_WTSSESSION_CHANGE {
+            match wparam.0 as u32 {
+                WTS_SESSION_LOCK => set(&LOCKED, true),
+                WTS_SESSION_UNLOCK => set(&LOCKED, false),
+                _ => {}
+            }
+            return LRESULT(0);
+        }
+        unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) }
+    }
+
+    /// Creates a hidden message-only window registered for session notifications and
+    /// pumps its messages. Blocks for the life of the app.
+    pub fn run() -> windows::core::Result<()> {
+        unsafe {
+            let instance = GetModuleHandleW(None)?;
+            let class_name = w!("UsageBarSessionMonitor");
+            let class = WNDCLASSW {
+                lpfnWndProc: Some(window_proc),
+                hInstance: instance.into(),
+                lpszClassName: class_name,
+                ..Default::default()
+            };
+            if RegisterClassW(&class) == 0 {
+                return Err(windows::core::Error::from_win32());
+            }
+            let hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                class_name,
+                w!(""),
+                WINDOW_STYLE::default(),
+                0,
+                0,
+                0,
+                0,
+                Some(HWND_MESSAGE),
+                None,
+                Some(instance.into()),
+                None,
+            )?;
+            WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION)?;
+
+            let mut msg = MSG::default();
+            while GetMessageW(&mut msg, None, 0, 0).as_bool() {
+                DispatchMessageW(&msg);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_presenting() -> bool {
+        matches!(
+            unsafe { SHQueryUserNotificationState() },
+            Ok(QUNS_PRESENTATION_MODE | QUNS_RUNNING_D3D_FULL_SCREEN | QUNS_BUSY)
+        )
+    }
+}
@@ -0,0 +1,538 @@
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tauri::{AppHandle, Manager};
+
+use crate::alerts::UsageWindows;
+use crate::amp_service::AmpService;
+use crate::anthropic_service::AnthropicService;
+use crate::cache::{
+    AMP_USAGE, ANTHROPIC_USAGE, CLAUDE_TIER, CLAUDE_USAGE, CODEX_USAGE, GEMINI_USAGE, ZAI_USAGE,
+};
+use crate::claude_service::ClaudeService;
+use crate::codex_service::CodexService;
+use crate::commands;
+use crate::credentials::CredentialManager;
+use crate::gemini_service::GeminiService;
+use crate::i18n::UsageError;
+use crate::models::{AmpAuth, Envelope, Provider, UsageSnapshot};
+use crate::zai_service::ZaiService;
+use crate::CacheState;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Display metadata for the popup and `list_providers`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderInfo {
+    pub id: Provider,
+    pub name: &'static str,
+    /// Prefix in the menu bar title, short enough for several to fit.
+    pub short_code: &'static str,
+    /// Where the user signs in or manages the credential this provider reads.
+    pub dashboard_url: &'static str,
+}
+
+/// One entry of `list_providers`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderListing {
+    #[serde(flatten)]
+    pub info: ProviderInfo,
+    pub has_credentials: bool,
+}
+
+/// One fetch, in the provider's own command shape, with the windows alerts and the usage
+/// feed read from it.
+#[derive(Debug, Clone)]
+pub struct ProviderUsage {
+    pub data: serde_json::Value,
+    windows: Vec<(&'static str, f64)>,
+    budgets: Vec<(&'static str, f64)>,
+}
+
+impl ProviderUsage {
+    pub fn from_data<T: Serialize + UsageWindows>(data: &T) -> Result<Self> {
+        Ok(Self {
+            data: serde_json::to_value(data)?,
+            windows: data.windows(),
+            budgets: data.budgets(),
+        })
+    }
+
+    /// The data as the provider's own type again.
+    fn typed<T: DeserializeOwned>(&self) -> Option<T> {
+        serde_json::from_value(self.data.clone()).ok()
+    }
+}
+
+impl Serialize for ProviderUsage {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.data.serialize(serializer)
+    }
+}
+
+impl UsageWindows for ProviderUsage {
+    fn windows(&self) -> Vec<(&'static str, f64)> {
+        self.windows.clone()
+    }
+
+    fn budgets(&self) -> Vec<(&'static str, f64)> {
+        self.budgets.clone()
+    }
+}
+
+/// A usage source. The typed `*_get_usage` commands stay the fast path for the popup's own
+/// tabs; everything that acts on any provider (refreshes, groups, snapshots, headroom, the
+/// usage feed, the tray) goes through the registry instead of a match per provider.
+pub trait UsageProvider: Send + Sync {
+    fn info(&self) -> ProviderInfo;
+
+    /// Whether a credential is stored, without contacting the provider.
+    fn has_credentials(&self) -> BoxFuture<'_, bool>;
+
+    /// Checks the stored credential against the provider's API.
+    fn validate_credentials(&self) -> BoxFuture<'_, Result<()>>;
+
+    /// Usage through the provider's cache, 429 backoff and last-good fallback, recorded like
+    /// the typed commands' fetches. `force` skips a fresh cache entry, never the backoff.
+    fn fetch_usage<'a>(
+        &'a self,
+        app: &'a AppHandle,
+        force: bool,
+    ) -> BoxFuture<'a, Result<Envelope<ProviderUsage>, UsageError>>;
+
+    /// The last known usage without a request (the cache, even expired, else history) and
+    /// the epoch millis it was fetched at.
+    fn last_usage(&self, app: &AppHandle) -> Option<(ProviderUsage, i64)>;
+
+    /// `usage` in `get_all_snapshots`' shape; `None` when it has no limit to report against.
+    fn snapshot(
+        &self,
+        _app: &AppHandle,
+        _usage: &ProviderUsage,
+        _fetched_at: i64,
+    ) -> Option<UsageSnapshot> {
+        None
+    }
+}
+
+pub struct ClaudeProvider {
+    pub client: Arc<reqwest::Client>,
+}
+
+impl UsageProvider for ClaudeProvider {
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            id: Provider::Claude,
+            name: "Claude",
+            short_code: "CL",
+            dashboard_url: "https://claude.ai/settings/usage",
+        }
+    }
+
+    fn has_credentials(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async {
+            CredentialManager::claude_read_credentials_async()
+                .await
+                .is_ok()
+        })
+    }
+
+    fn validate_credentials(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async {
+            ClaudeService::check_and_refresh_if_needed(self.client.clone()).await?;
+            ClaudeService::claude_fetch_usage_and_tier(self.client.clone())
+                .await
+                .map(|_| ())
+        })
+    }
+
+    fn fetch_usage<'a>(
+        &'a self,
+        app: &'a AppHandle,
+        force: bool,
+    ) -> BoxFuture<'a, Result<Envelope<ProviderUsage>, UsageError>> {
+        Box::pin(commands::serve_provider(
+            app,
+            Provider::Claude,
+            move |state| {
+                commands::claude_fetch(
+                    self.client.clone(),
+                    state.caches,
+                    state.history,
+                    state.alerts,
+                    state.feed,
+                    force,
+                )
+            },
+        ))
+    }
+
+    fn last_usage(&self, app: &AppHandle) -> Option<(ProviderUsage, i64)> {
+        commands::last_usage(app, CLAUDE_USAGE)
+    }
+
+    fn snapshot(
+        &self,
+        app: &AppHandle,
+        usage: &ProviderUsage,
+        fetched_at: i64,
+    ) -> Option<UsageSnapshot> {
+        let tier = app.state::<CacheState>().0.get(CLAUDE_TIER).get_stale();
+        Some(UsageSnapshot::claude(
+            &usage.typed()?,
+            tier.as_deref(),
+            fetched_at,
+        ))
+    }
+}
+
+pub struct CodexProvider {
+    pub client: Arc<reqwest::Client>,
+}
+
+impl UsageProvider for CodexProvider {
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            id: Provider::Codex,
+            name: "Codex",
+            short_code: "CX",
+            dashboard_url: "https://chatgpt.com/codex/settings/usage",
+        }
+    }
+
+    fn has_credentials(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async { CodexService::codex_has_auth() })
+    }
+
+    fn validate_credentials(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async {
+            CodexService::codex_fetch_usage_and_tier(self.client.clone())
+                .await
+                .map(|_| ())
+        })
+    }
+
+    fn fetch_usage<'a>(
+        &'a self,
+        app: &'a AppHandle,
+        force: bool,
+    ) -> BoxFuture<'a, Result<Envelope<ProviderUsage>, UsageError>> {
+        Box::pin(commands::serve_provider(
+            app,
+            Provider::Codex,
+            move |state| {
+                commands::codex_fetch(
+                    self.client.clone(),
+                    state.caches,
+                    state.history,
+                    state.alerts,
+                    state.feed,
+                    force,
+                )
+            },
+        ))
+    }
+
+    fn last_usage(&self, app: &AppHandle) -> Option<(ProviderUsage, i64)> {
+        commands::last_usage(app, CODEX_USAGE)
+    }
+
+    fn snapshot(
+        &self,
+        _app: &AppHandle,
+        usage: &ProviderUsage,
+        fetched_at: i64,
+    ) -> Option<UsageSnapshot> {
+        UsageSnapshot::codex(&usage.typed()?, fetched_at)
+    }
+}
+
+pub struct ZaiProvider {
+    pub client: Arc<reqwest::Client>,
+}
+
+impl UsageProvider for ZaiProvider {
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            id: Provider::Zai,
+            name: "Z.ai",
+            short_code: "ZA",
+            dashboard_url: "https://z.ai/manage-apikey/apikey-list",
+        }
+    }
+
+    fn has_credentials(&self) -> BoxFuture<'_, bool> {
+        Box::pin(ZaiService::zai_has_api_key())
+    }
+
+    fn validate_credentials(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async {
+            let api_key = CredentialManager::zai_read_api_key_async().await?;
+            ZaiService::validate_api_key(
+                self.client.clone(),
+                CredentialManager::ZAI_DEFAULT_LABEL,
+                &api_key,
+            )
+            .await
+            .map(|_| ())
+        })
+    }
+
+    fn fetch_usage<'a>(
+        &'a self,
+        app: &'a AppHandle,
+        force: bool,
+    ) -> BoxFuture<'a, Result<Envelope<ProviderUsage>, UsageError>> {
+        Box::pin(commands::serve_provider(app, Provider::Zai, move |state| {
+            commands::zai_fetch(
+                self.client.clone(),
+                state.caches,
+                state.history,
+                state.alerts,
+                state.feed,
+                force,
+            )
+        }))
+    }
+
+    fn last_usage(&self, app: &AppHandle) -> Option<(ProviderUsage, i64)> {
+        commands::last_usage(app, ZAI_USAGE)
+    }
+
+    fn snapshot(
+        &self,
+        _app: &AppHandle,
+        usage: &ProviderUsage,
+        fetched_at: i64,
+    ) -> Option<UsageSnapshot> {
+        UsageSnapshot::zai(&usage.typed()?, fetched_at)
+    }
+}
+
+/// Uses the redirect-less Amp client: a redirect means the session cookie expired.
+pub struct AmpProvider {
+    pub client: Arc<reqwest::Client>,
+}
+
+impl UsageProvider for AmpProvider {
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            id: Provider::Amp,
+            name: "Amp",
+            short_code: "AM",
+            dashboard_url: "https://ampcode.com/settings",
+        }
+    }
+
+    fn has_credentials(&self) -> BoxFuture<'_, bool> {
+        Box::pin(AmpService::amp_has_credentials())
+    }
+
+    fn validate_credentials(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async {
+            match AmpService::amp_effective_auth().await {
+                AmpAuth::Cookie => {
+                    let cookie = CredentialManager::amp_read_session_cookie_async().await?;
+                    AmpService::validate_session_cookie(&self.client, &cookie)
+                        .await
+                        .map(|_| ())
+                }
+                AmpAuth::ApiKey => {
+                    let api_key = CredentialManager::amp_read_api_key_async().await?;
+                    AmpService::validate_api_key(&self.client, &api_key).await
+                }
+            }
+        })
+    }
+
+    fn fetch_usage<'a>(
+        &'a self,
+        app: &'a AppHandle,
+        force: bool,
+    ) -> BoxFuture<'a, Result<Envelope<ProviderUsage>, UsageError>> {
+        Box::pin(commands::serve_provider(app, Provider::Amp, move |state| {
+            commands::amp_fetch(
+                self.client.clone(),
+                state.caches,
+                state.history,
+                state.alerts,
+                state.feed,
+                force,
+            )
+        }))
+    }
+
+    fn last_usage(&self, app: &AppHandle) -> Option<(ProviderUsage, i64)> {
+        commands::last_usage(app, AMP_USAGE)
+    }
+
+    fn snapshot(
+        &self,
+        _app: &AppHandle,
+        usage: &ProviderUsage,
+        fetched_at: i64,
+    ) -> Option<UsageSnapshot> {
+        Some(UsageSnapshot::amp(&usage.typed()?, fetched_at))
+    }
+}
+
+pub struct AnthropicProvider {
+    pub client: Arc<reqwest::Client>,
+}
+
+impl UsageProvider for AnthropicProvider {
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            id: Provider::Anthropic,
+            name: "Anthropic API",
+            short_code: "AN",
+            dashboard_url: "https://console.anthropic.com/settings/cost",
+        }
+    }
+
+    fn has_credentials(&self) -> BoxFuture<'_, bool> {
+        Box::pin(AnthropicService::anthropic_has_admin_key())
+    }
+
+    fn validate_credentials(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async {
+            let api_key = CredentialManager::anthropic_read_admin_key_async().await?;
+            AnthropicService::validate_admin_key(self.client.clone(), &api_key).await
+        })
+    }
+
+    fn fetch_usage<'a>(
+        &'a self,
+        app: &'a AppHandle,
+        force: bool,
+    ) -> BoxFuture<'a, Result<Envelope<ProviderUsage>, UsageError>> {
+        Box::pin(commands::serve_provider(
+            app,
+            Provider::Anthropic,
+            move |state| {
+                commands::anthropic_fetch(
+                    self.client.clone(),
+                    state.caches,
+                    state.history,
+                    state.alerts,
+                    state.feed,
+                    force,
+                )
+            },
+        ))
+    }
+
+    fn last_usage(&self, app: &AppHandle) -> Option<(ProviderUsage, i64)> {
+        commands::last_usage(app, ANTHROPIC_USAGE)
+    }
+
+    // API spend has no limit to be a percentage of, so no snapshot.
+}
+
+pub struct GeminiProvider {
+    pub client: Arc<reqwest::Client>,
+}
+
+impl UsageProvider for GeminiProvider {
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            id: Provider::Gemini,
+            name: "Gemini API",
+            short_code: "GE",
+            dashboard_url: "https://aistudio.google.com/usage",
+        }
+    }
+
+    fn has_credentials(&self) -> BoxFuture<'_, bool> {
+        Box::pin(GeminiService::gemini_has_credentials())
+    }
+
+    fn validate_credentials(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(GeminiService::validate_credentials(self.client.clone()))
+    }
+
+    fn fetch_usage<'a>(
+        &'a self,
+        app: &'a AppHandle,
+        force: bool,
+    ) -> BoxFuture<'a, Result<Envelope<ProviderUsage>, UsageError>> {
+        Box::pin(commands::serve_provider(
+            app,
+            Provider::Gemini,
+            move |state| {
+                commands::gemini_fetch(
+                    self.client.clone(),
+                    state.caches,
+                    state.history,
+                    state.alerts,
+                    state.feed,
+                    force,
+                )
+            },
+        ))
+    }
+
+    fn last_usage(&self, app: &AppHandle) -> Option<(ProviderUsage, i64)> {
+        commands::last_usage(app, GEMINI_USAGE)
+    }
+
+    fn snapshot(
+        &self,
+        _app: &AppHandle,
+        usage: &ProviderUsage,
+        fetched_at: i64,
+    ) -> Option<UsageSnapshot> {
+        UsageSnapshot::gemini(&usage.typed()?, fetched_at)
+    }
+}
+
+/// Providers known at runtime, in display order. Built in `main.rs`.
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Box<dyn UsageProvider>>,
+}
+
+impl ProviderRegistry {
+    /// Adds `provider`, replacing any earlier registration with the same id.
+    pub fn register(&mut self, provider: impl UsageProvider + 'static) {
+        let id = provider.info().id;
+        self.providers.retain(|p| p.info().id != id);
+        self.providers.push(Box::new(provider));
+    }
+
+    pub fn get(&self, id: Provider) -> Option<&dyn UsageProvider> {
+        self.providers
+            .iter()
+            .find(|p| p.info().id == id)
+            .map(|p| p.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn UsageProvider> {
+        self.providers.iter().map(|p| p.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_replaces_same_id() {
+        let client = Arc::new(reqwest::Client::new());
+        let mut registry = ProviderRegistry::default();
+        registry.register(ZaiProvider {
+            client: client.clone(),
+        });
+        registry.register(AmpProvider {
+            client: client.clone(),
+        });
+        registry.register(ZaiProvider { client });
+
+        let ids: Vec<Provider> = registry.iter().map(|p| p.info().id).collect();
+        assert_eq!(ids, [Provider::Amp, Provider::Zai]);
+        assert!(registry.get(Provider::Claude).is_none());
+    }
+}
@@ -0,0 +1,74 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::feed::UsageUpdate;
+use crate::settings::write_json_atomic;
+use crate::{debug_app, debug_error};
+
+/// Contents of the `snapshot_file`: the latest sample of every provider fetched this session.
+#[derive(Debug, Serialize)]
+struct Snapshot {
+    /// Epoch millis of the last write.
+    updated_at: i64,
+    /// Keyed by provider (`"claude"`, `"zai"`, ...), each in `subscribe_usage` message shape.
+    providers: BTreeMap<&'static str, UsageUpdate>,
+}
+
+struct SnapshotWriter {
+    path: Option<PathBuf>,
+    snapshot: Snapshot,
+}
+
+static WRITER: Mutex<SnapshotWriter> = Mutex::new(SnapshotWriter {
+    path: None,
+    snapshot: Snapshot {
+        updated_at: 0,
+        providers: BTreeMap::new(),
+    },
+});
+
+fn lock() -> MutexGuard<'static, SnapshotWriter> {
+    WRITER.lock().unwrap_or_else(|poisoned| {
+        debug_error!("Snapshot mutex poisoned, recovering...");
+        poisoned.into_inner()
+    })
+}
+
+/// Sets (or clears) the file rewritten on every refresh, from the `snapshot_file` setting.
+/// A newly set file gets whatever has been fetched so far right away.
+pub fn set_path(path: Option<PathBuf>) {
+    let mut writer = lock();
+    if writer.path == path {
+        return;
+    }
+    writer.path = path;
+    if let Some(path) = &writer.path {
+        debug_app!("Writing usage snapshots to {}", path.display());
+        if !writer.snapshot.providers.is_empty() {
+            write(path, &writer.snapshot);
+        }
+    }
+}
+
+/// Adds a freshly fetched sample and rewrites the snapshot file, if one is configured.
+pub fn record(update: UsageUpdate) {
+    let mut writer = lock();
+    writer.snapshot.updated_at = update.fetched_at;
+    writer
+        .snapshot
+        .providers
+        .insert(update.provider.key(), update);
+    // Written under the lock so concurrent refreshes never share the temp file.
+    if let Some(path) = &writer.path {
+        write(path, &writer.snapshot);
+    }
+}
+
+/// Best-effort: a failed write is logged and retried on the next refresh.
+fn write(path: &Path, snapshot: &Snapshot) {
+    if let Err(e) = write_json_atomic(path, snapshot) {
+        debug_error!("Failed to write usage snapshot: {e}");
+    }
+}
@@ -0,0 +1,148 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::alerts::{AlertAction, AlertRule};
+use crate::settings::Settings;
+
+/// Bumped when the template layout changes.
+const TEMPLATE_VERSION: u32 = 1;
+
+/// A shareable Usage Bar setup: settings and alert rules with everything personal swapped
+/// for `{env:NAME}` placeholders that each person fills in.
+#[derive(Debug, Serialize)]
+pub struct ConfigTemplate {
+    pub version: u32,
+    /// `settings.json` contents; `claude_organization` and `snapshot_file` are placeholders.
+    pub settings: Value,
+    /// Rules without ids (they are generated on save), webhook URLs as placeholders.
+    pub alert_rules: Vec<AlertRule>,
+    /// Stored credentials the exporting user had, as placeholders. Claude and Codex log in
+    /// through their own CLIs, and Gemini OAuth usually through gcloud, so they never appear
+    /// here.
+    pub credentials: BTreeMap<&'static str, String>,
+}
+
+/// Which stored secrets exist, without their values.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StoredCredentials {
+    pub zai_api_key: bool,
+    pub amp_session_cookie: bool,
+    pub amp_api_key: bool,
+    pub anthropic_admin_key: bool,
+    pub gemini_api_key: bool,
+}
+
+fn placeholder(name: &str) -> String {
+    format!("{{env:{name}}}")
+}
+
+pub fn build(
+    settings: &Settings,
+    rules: Vec<AlertRule>,
+    stored: StoredCredentials,
+) -> Result<ConfigTemplate, serde_json::Error> {
+    let mut settings = serde_json::to_value(settings)?;
+    if let Some(fields) = settings.as_object_mut() {
+        for (field, env) in [
+            ("claude_organization", "USAGE_BAR_CLAUDE_ORGANIZATION"),
+            ("snapshot_file", "USAGE_BAR_SNAPSHOT_FILE"),
+        ] {
+            if let Some(value) = fields.get_mut(field) {
+                *value = Value::String(placeholder(env));
+            }
+        }
+    }
+
+    let mut webhooks = 0;
+    let alert_rules = rules
+        .into_iter()
+        .map(|mut rule| {
+            rule.id.clear();
+            for action in &mut rule.actions {
+                if let AlertAction::Webhook { url } = action {
+                    webhooks += 1;
+                    *url = placeholder(&format!("USAGE_BAR_WEBHOOK_URL_{webhooks}"));
+                }
+            }
+            rule
+        })
+        .collect();
+
+    let mut credentials = BTreeMap::new();
+    if stored.zai_api_key {
+        credentials.insert("zai_api_key", placeholder("ZAI_API_KEY"));
+    }
+    if stored.amp_session_cookie {
+        credentials.insert("amp_session_cookie", placeholder("AMP_SESSION_COOKIE"));
+    }
+    if stored.amp_api_key {
+        credentials.insert("amp_api_key", placeholder("AMP_API_KEY"));
+    }
+    if stored.anthropic_admin_key {
+        credentials.insert("anthropic_admin_key", placeholder("ANTHROPIC_ADMIN_KEY"));
+    }
+    if stored.gemini_api_key {
+        credentials.insert("gemini_api_key", placeholder("GEMINI_API_KEY"));
+    }
+
+    Ok(ConfigTemplate {
+        version: TEMPLATE_VERSION,
+        settings,
+        alert_rules,
+        credentials,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_build_replaces_personal_values() {
+        let settings = Settings {
+            claude_organization: Some("3f0c-org-uuid".into()),
+            snapshot_file: Some(PathBuf::from(r"C:\Users\jane\usage.json")),
+            ..Settings::default()
+        };
+        let rules = vec![AlertRule {
+            id: "rule-1".into(),
+            name: "Near limit".into(),
+            condition: "percent > 90".into(),
+            actions: vec![
+                AlertAction::Notify,
+                AlertAction::Webhook {
+                    url: "https://hooks.example.com/T0/B0/secret".into(),
+                },
+            ],
+            enabled: true,
+        }];
+        let stored = StoredCredentials {
+            zai_api_key: true,
+            amp_session_cookie: false,
+            amp_api_key: false,
+            anthropic_admin_key: false,
+            gemini_api_key: false,
+        };
+
+        let template = build(&settings, rules, stored).unwrap();
+        let json = serde_json::to_string(&template).unwrap();
+        for secret in ["3f0c-org-uuid", "jane", "secret", "rule-1"] {
+            assert!(!json.contains(secret), "{secret} leaked into {json}");
+        }
+        assert_eq!(
+            template.settings["claude_organization"],
+            "{env:USAGE_BAR_CLAUDE_ORGANIZATION}"
+        );
+        assert_eq!(
+            template.settings["poll_interval_secs"],
+            settings.poll_interval_secs
+        );
+        assert!(json.contains("{env:USAGE_BAR_WEBHOOK_URL_1}"));
+        assert_eq!(
+            template.credentials.keys().copied().collect::<Vec<_>>(),
+            ["zai_api_key"]
+        );
+    }
+}
@@ -0,0 +1,16 @@
+use std::sync::Arc;
+
+use crate::claude_service::ClaudeService;
+use crate::debug_app;
+
+/// Refreshes the Claude token shortly before it expires, so the first fetch after a long
+/// idle spell doesn't pay for a 401 and a refresh first.
+pub fn spawn_scheduler(client: Arc<reqwest::Client>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let wait = ClaudeService::claude_scheduled_refresh(client.clone()).await;
+            tokio::time::sleep(wait).await;
+        }
+    });
+    debug_app!("Claude token refresh scheduler started");
+}
@@ -0,0 +1,420 @@
+use serde::Serialize;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use tauri::menu::{Menu, MenuItem, PredefinedMenuItem, Submenu};
+use tauri::{AppHandle, Manager, Wry};
+
+use crate::alerts::UsageWindows;
+use crate::cache::{
+    AMP_USAGE, ANTHROPIC_USAGE, CLAUDE_USAGE, CODEX_USAGE, GEMINI_USAGE, ZAI_USAGE,
+};
+use crate::clock::now_millis;
+use crate::models::{Provider, ResetTime};
+use crate::providers::UsageProvider;
+use crate::{debug_app, debug_error};
+use crate::{CacheState, ProviderRegistryState, SettingsState};
+
+/// Countdowns only show minutes, so refreshing twice a minute keeps them accurate enough.
+const COUNTDOWN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A usage window shown as a countdown line in the tray menu.
+#[derive(Debug, Clone, Copy)]
+enum ResetWindow {
+    ClaudeFiveHour,
+    ClaudeSevenDay,
+    CodexSession,
+    CodexWeekly,
+    ZaiTokens,
+    AmpQuota,
+    AnthropicMonth,
+    GeminiDay,
+}
+
+impl ResetWindow {
+    const ALL: [ResetWindow; 8] = [
+        ResetWindow::ClaudeFiveHour,
+        ResetWindow::ClaudeSevenDay,
+        ResetWindow::CodexSession,
+        ResetWindow::CodexWeekly,
+        ResetWindow::ZaiTokens,
+        ResetWindow::AmpQuota,
+        ResetWindow::AnthropicMonth,
+        ResetWindow::GeminiDay,
+    ];
+
+    fn provider(self) -> Provider {
+        match self {
+            ResetWindow::ClaudeFiveHour | ResetWindow::ClaudeSevenDay => Provider::Claude,
+            ResetWindow::CodexSession | ResetWindow::CodexWeekly => Provider::Codex,
+            ResetWindow::ZaiTokens => Provider::Zai,
+            ResetWindow::AmpQuota => Provider::Amp,
+            ResetWindow::AnthropicMonth => Provider::Anthropic,
+            ResetWindow::GeminiDay => Provider::Gemini,
+        }
+    }
+
+    /// Window name as used in alert conditions; `month` for Anthropic API spend.
+    fn key(self) -> &'static str {
+        match self {
+            ResetWindow::ClaudeFiveHour => "five_hour",
+            ResetWindow::ClaudeSevenDay => "seven_day",
+            ResetWindow::CodexSession => "session",
+            ResetWindow::CodexWeekly => "weekly",
+            ResetWindow::ZaiTokens => "tokens",
+            ResetWindow::AmpQuota => "quota",
+            ResetWindow::AnthropicMonth => "month",
+            ResetWindow::GeminiDay => "day",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            ResetWindow::ClaudeFiveHour => "5-hour",
+            ResetWindow::ClaudeSevenDay => "7-day",
+            ResetWindow::CodexSession => "Session",
+            ResetWindow::CodexWeekly => "Weekly",
+            ResetWindow::ZaiTokens => "Tokens",
+            ResetWindow::AmpQuota => "Free tier",
+            ResetWindow::AnthropicMonth => "Monthly spend",
+            ResetWindow::GeminiDay => "Requests per day",
+        }
+    }
+
+    /// Epoch millis of the next reset, read from the last cached (possibly expired) usage.
+    fn resets_at(self, app: &AppHandle) -> Option<i64> {
+        match self {
+            ResetWindow::ClaudeFiveHour => app
+                .state::<CacheState>()
+                .0
+                .get(CLAUDE_USAGE)
+                .get_stale()
+                .and_then(|data| data.five_hour_resets_at)
+                .map(ResetTime::epoch_millis),
+            ResetWindow::ClaudeSevenDay => app
+                .state::<CacheState>()
+                .0
+                .get(CLAUDE_USAGE)
+                .get_stale()
+                .and_then(|data| data.seven_day_resets_at)
+                .map(ResetTime::epoch_millis),
+            ResetWindow::CodexSession => app
+                .state::<CacheState>()
+                .0
+                .get(CODEX_USAGE)
+                .get_stale()
+                .and_then(|data| {
+                    data.session_usage
+                        .as_ref()
+                        .and_then(|window| window.resets_at)
+                }),
+            ResetWindow::CodexWeekly => app
+                .state::<CacheState>()
+                .0
+                .get(CODEX_USAGE)
+                .get_stale()
+                .and_then(|data| {
+                    data.weekly_usage
+                        .as_ref()
+                        .and_then(|window| window.resets_at)
+                }),
+            ResetWindow::ZaiTokens => app
+                .state::<CacheState>()
+                .0
+                .get(ZAI_USAGE)
+                .get_stale()
+                .and_then(|data| data.token_usage.as_ref().and_then(|usage| usage.resets_at)),
+            ResetWindow::AmpQuota => app
+                .state::<CacheState>()
+                .0
+                .get(AMP_USAGE)
+                .get_stale()
+                .and_then(|data| data.resets_at),
+            ResetWindow::AnthropicMonth => app
+                .state::<CacheState>()
+                .0
+                .get(ANTHROPIC_USAGE)
+                .get_stale()
+                .map(|data| data.resets_at),
+            ResetWindow::GeminiDay => app
+                .state::<CacheState>()
+                .0
+                .get(GEMINI_USAGE)
+                .get_stale()
+                .map(|data| data.resets_at),
+        }
+    }
+
+    fn text(self, resets_at: Option<i64>, now_ms: i64) -> String {
+        let label = self.label();
+        match resets_at {
+            Some(at) if at > now_ms => {
+                let countdown = format_countdown(Duration::from_millis((at - now_ms) as u64));
+                format!("{label}: resets in {countdown}")
+            }
+            Some(_) => format!("{label}: resetting now"),
+            None => format!("{label}: no data yet"),
+        }
+    }
+}
+
+/// Time left until a usage window resets, for `get_reset_countdowns`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResetCountdown {
+    pub provider: Provider,
+    pub window: &'static str,
+    /// Epoch millis.
+    pub resets_at: i64,
+    /// 0 once the reset has passed but no newer usage has been fetched.
+    pub seconds_remaining: u64,
+}
+
+fn countdown(window: ResetWindow, resets_at: i64, now_ms: i64) -> ResetCountdown {
+    ResetCountdown {
+        provider: window.provider(),
+        window: window.key(),
+        resets_at,
+        seconds_remaining: (resets_at - now_ms).max(0) as u64 / 1000,
+    }
+}
+
+/// Every enabled provider's windows that have a known reset, from the last cached usage.
+pub fn reset_countdowns(app: &AppHandle) -> Vec<ResetCountdown> {
+    let settings = app.state::<SettingsState>().0.get();
+    let now_ms = now_millis();
+    ResetWindow::ALL
+        .into_iter()
+        .filter(|window| settings.is_enabled(window.provider()))
+        .filter_map(|window| Some(countdown(window, window.resets_at(app)?, now_ms)))
+        .collect()
+}
+
+/// Highest utilization across the provider's windows in its last known usage, i.e. how
+/// close it is to its nearest limit.
+fn peak_utilization(source: &dyn UsageProvider, app: &AppHandle) -> Option<f64> {
+    let (usage, _) = source.last_usage(app)?;
+    usage
+        .windows()
+        .into_iter()
+        .map(|(_, percent)| percent)
+        .reduce(f64::max)
+}
+
+/// `CL 72% | ZA 40%` from short codes and peaks, or `None` when no provider has usage yet.
+fn format_title(readings: &[(&'static str, f64)]) -> Option<String> {
+    if readings.is_empty() {
+        return None;
+    }
+    let parts: Vec<String> = readings
+        .iter()
+        .map(|&(code, percent)| format!("{code} {percent:.0}%"))
+        .collect();
+    Some(parts.join(" | "))
+}
+
+/// Compact countdown for menu text: `45m`, `2h 14m`, `3d 4h`.
+fn format_countdown(remaining: Duration) -> String {
+    let total_minutes = remaining.as_secs() / 60;
+    let (days, hours, minutes) = (
+        total_minutes / (24 * 60),
+        (total_minutes / 60) % 24,
+        total_minutes % 60,
+    );
+    if days > 0 {
+        format!("{days}d {hours}h")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else if minutes > 0 {
+        format!("{minutes}m")
+    } else {
+        "<1m".to_string()
+    }
+}
+
+/// Id of the tray icon, used to swap its menu when settings change.
+pub const TRAY_ID: &str = "main";
+
+type CountdownItems = Vec<(ResetWindow, MenuItem<Wry>)>;
+
+/// Countdown items in the current tray menu and the providers they were built for.
+/// Replaced whenever the menu is rebuilt.
+pub struct TrayCountdowns(Mutex<(Vec<Provider>, CountdownItems)>);
+
+fn build_item(app: &AppHandle, window: ResetWindow, now_ms: i64) -> tauri::Result<MenuItem<Wry>> {
+    let text = window.text(window.resets_at(app), now_ms);
+    MenuItem::new(app, text, false, None::<&str>)
+}
+
+/// Open, a countdown submenu per provider in `providers`, Quit.
+fn build_menu(
+    app: &AppHandle,
+    providers: &[Provider],
+) -> tauri::Result<(Menu<Wry>, CountdownItems)> {
+    let now_ms = now_millis();
+    let menu = Menu::new(app)?;
+    menu.append(&MenuItem::with_id(app, "open", "Open", true, None::<&str>)?)?;
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+
+    let registry = &app.state::<ProviderRegistryState>().inner().0;
+    let mut items = Vec::new();
+    for source in providers
+        .iter()
+        .filter_map(|&provider| registry.get(provider))
+    {
+        let provider = source.info().id;
+        let submenu = Submenu::new(app, source.info().name, true)?;
+        for window in ResetWindow::ALL
+            .into_iter()
+            .filter(|w| w.provider() == provider)
+        {
+            let item = build_item(app, window, now_ms)?;
+            submenu.append(&item)?;
+            items.push((window, item));
+        }
+        menu.append(&submenu)?;
+    }
+
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    menu.append(&MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?)?;
+    Ok((menu, items))
+}
+
+fn tray_providers(app: &AppHandle) -> Vec<Provider> {
+    app.state::<SettingsState>().0.get().tray_providers()
+}
+
+/// Builds the initial tray menu and registers its countdowns for `spawn_updater`.
+pub fn initial_menu(app: &AppHandle) -> tauri::Result<Menu<Wry>> {
+    let providers = tray_providers(app);
+    let (menu, items) = build_menu(app, &providers)?;
+    app.manage(TrayCountdowns(Mutex::new((providers, items))));
+    Ok(menu)
+}
+
+/// Rebuilds the tray menu if the providers shown in it (`tray_group`) changed.
+pub fn rebuild_menu(app: &AppHandle) {
+    let Some(countdowns) = app.try_state::<TrayCountdowns>() else {
+        return;
+    };
+    let providers = tray_providers(app);
+    let mut current = lock_countdowns(&countdowns);
+    if current.0 == providers {
+        return;
+    }
+
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    match build_menu(app, &providers) {
+        Ok((menu, items)) => {
+            if tray.set_menu(Some(menu)).is_err() {
+                debug_error!("Failed to replace tray menu");
+                return;
+            }
+            *current = (providers, items);
+            debug_app!("Tray menu rebuilt for {:?}", current.0);
+        }
+        Err(e) => {
+            debug_error!("Failed to rebuild tray menu: {e}");
+        }
+    }
+}
+
+fn lock_countdowns(countdowns: &TrayCountdowns) -> MutexGuard<'_, (Vec<Provider>, CountdownItems)> {
+    countdowns.0.lock().unwrap_or_else(|poisoned| {
+        debug_error!("Tray countdown mutex poisoned, recovering...");
+        poisoned.into_inner()
+    })
+}
+
+/// Sets the text next to the tray icon to the peak usage of the tray's providers, or clears
+/// it when `tray_title` is off. Only macOS and Linux show a title; elsewhere this is a no-op.
+pub fn update_title(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let settings = app.state::<SettingsState>().0.get();
+    let title = if settings.tray_title {
+        let registry = &app.state::<ProviderRegistryState>().inner().0;
+        let readings: Vec<(&'static str, f64)> = settings
+            .tray_providers()
+            .into_iter()
+            .filter_map(|provider| {
+                let source = registry.get(provider)?;
+                Some((source.info().short_code, peak_utilization(source, app)?))
+            })
+            .collect();
+        format_title(&readings)
+    } else {
+        None
+    };
+    if tray.set_title(title).is_err() {
+        debug_error!("Failed to update tray title");
+    }
+}
+
+fn refresh(app: &AppHandle) {
+    update_title(app);
+    let Some(countdowns) = app.try_state::<TrayCountdowns>() else {
+        return;
+    };
+    let now_ms = now_millis();
+    for (window, item) in &lock_countdowns(&countdowns).1 {
+        if item
+            .set_text(window.text(window.resets_at(app), now_ms))
+            .is_err()
+        {
+            debug_error!("Failed to update tray countdown");
+        }
+    }
+}
+
+/// Keeps the countdown items of whichever menu is current, and the title, up to date.
+pub fn spawn_updater(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(COUNTDOWN_REFRESH_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            refresh(&app);
+        }
+    });
+    debug_app!("Tray reset countdowns and title started");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_title() {
+        assert_eq!(format_title(&[]), None);
+        assert_eq!(
+            format_title(&[("CL", 72.4), ("ZA", 39.6)]).as_deref(),
+            Some("CL 72% | ZA 40%")
+        );
+    }
+
+    #[test]
+    fn test_countdown_seconds_remaining() {
+        let ahead = countdown(ResetWindow::ZaiTokens, 90_500, 0);
+        assert_eq!((ahead.window, ahead.seconds_remaining), ("tokens", 90));
+        assert_eq!(
+            countdown(ResetWindow::AmpQuota, 1_000, 5_000).seconds_remaining,
+            0
+        );
+    }
+
+    #[test]
+    fn test_format_countdown() {
+        assert_eq!(format_countdown(Duration::from_secs(30)), "<1m");
+        assert_eq!(format_countdown(Duration::from_secs(45 * 60)), "45m");
+        assert_eq!(
+            format_countdown(Duration::from_secs(2 * 3600 + 14 * 60)),
+            "2h 14m"
+        );
+        assert_eq!(
+            format_countdown(Duration::from_secs(3 * 86400 + 4 * 3600 + 59)),
+            "3d 4h"
+        );
+    }
+}
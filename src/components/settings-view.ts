@@ -4,6 +4,12 @@ import packageJson from "../../package.json";
 
 const APP_VERSION = packageJson.version ?? "dev";
 
+/** The Amp account a session cookie signs in to. */
+export interface AmpAccount {
+  email: string | null;
+  team: string | null;
+}
+
 export interface SettingsCallbacks {
   checkAmpSessionCookie: () => Promise<boolean>;
   checkCodexAuth: () => Promise<boolean>;
@@ -16,7 +22,7 @@ export interface SettingsCallbacks {
   openUrl: (url: string) => Promise<void>;
   saveAmpSessionCookie: (cookie: string) => Promise<void>;
   saveZaiApiKey: (apiKey: string) => Promise<void>;
-  validateAmpSessionCookie: (cookie: string) => Promise<void>;
+  validateAmpSessionCookie: (cookie: string) => Promise<AmpAccount>;
   validateZaiApiKey: (apiKey: string) => Promise<void>;
 }
 
@@ -445,7 +451,10 @@ function createZaiInputState(
       const hasKeyNow = await callbacks.checkZaiApiKey();
       rebuildZaiSection(section, callbacks, hasKeyNow);
     } catch (error) {
-      setValidationState(false, String(error));
+      setValidationState(
+        false,
+        error instanceof Error ? error.message : String(error)
+      );
     }
   });
 
@@ -647,14 +656,17 @@ function createAmpInputState(
     input.disabled = true;
 
     try {
-      await callbacks.validateAmpSessionCookie(cookie);
-      saveButton.textContent = "Saving...";
+      const account = await callbacks.validateAmpSessionCookie(cookie);
+      saveButton.textContent = account.email
+        ? `Saving ${account.email}...`
+        : "Saving...";
       await callbacks.saveAmpSessionCookie(cookie);
       await callbacks.onAmpCookieChanged();
       const hasCookieNow = await callbacks.checkAmpSessionCookie();
       rebuildAmpSection(section, callbacks, hasCookieNow);
     } catch (error) {
-      errorElement.textContent = String(error);
+      errorElement.textContent =
+        error instanceof Error ? error.message : String(error);
       errorElement.style.display = "block";
       saveButton.disabled = false;
       saveButton.textContent = "Save";
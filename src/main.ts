@@ -1,17 +1,65 @@
-import { invoke } from "@tauri-apps/api/core";
+import { Channel, invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { createMcpUsageGauge } from "./components/mcp-usage-gauge";
-import { createSettingsView } from "./components/settings-view";
+import {
+  type AmpAccount,
+  createSettingsView,
+} from "./components/settings-view";
 import { createUsageGauge } from "./components/usage-gauge";
 
-const POLL_INTERVAL = 300_000; // 5 minutes
-
-let pollingTimer: number | null = null;
+const POLL_INTERVAL = 300_000; // 5 minutes, used until settings.json is read
+
+type Provider = "claude" | "codex" | "zai" | "amp";
+const PROVIDERS: readonly Provider[] = ["claude", "codex", "zai", "amp"];
+
+// Effective seconds per provider, as resolved by the backend from settings.json.
+type PollIntervals = Record<Provider, number>;
+
+const pollIntervals: Record<Provider, number> = {
+  claude: POLL_INTERVAL,
+  codex: POLL_INTERVAL,
+  zai: POLL_INTERVAL,
+  amp: POLL_INTERVAL,
+};
+const pollingTimers: Record<Provider, number | null> = {
+  claude: null,
+  codex: null,
+  zai: null,
+  amp: null,
+};
 let claudeLastRefresh: Date | null = null;
 let codexLastRefresh: Date | null = null;
 let zaiLastRefresh: Date | null = null;
 let ampLastRefresh: Date | null = null;
 let timestampTimer: number | null = null;
 
+// Epoch millis until which the backend refuses to re-fetch a provider after a 429.
+const rateLimitedUntil: Record<Provider, number | null> = {
+  claude: null,
+  codex: null,
+  zai: null,
+  amp: null,
+};
+
+// Set when the backend served a last good value from history because the fetch failed.
+const lastFetchError: Record<Provider, string | null> = {
+  claude: null,
+  codex: null,
+  zai: null,
+  amp: null,
+};
+
+// Biggest window change since the previous fetch, e.g. "+4% in 5m", from the usage feed.
+const lastChange: Record<Provider, string | null> = {
+  claude: null,
+  codex: null,
+  zai: null,
+  amp: null,
+};
+
+// `disabled_providers` from settings.json: never polled, and their tabs are hidden.
+let disabledProviders = new Set<Provider>();
+
 // Renamed from hasAmpSession to match backend naming (amp_has_session_cookie).
 let hasAmpCookie = false;
 let hasCodexAuth = false;
@@ -30,7 +78,9 @@ async function checkZaiApiKey(): Promise<boolean> {
     return cachedZaiApiKeyCheck;
   }
 
-  cachedZaiApiKeyCheck = await invoke<boolean>("zai_check_api_key");
+  // The key's source (stored, environment, ...), or null without one.
+  cachedZaiApiKeyCheck =
+    (await invoke<string | null>("zai_check_api_key")) !== null;
   zaiApiKeyCacheTime = now;
   return cachedZaiApiKeyCheck;
 }
@@ -189,23 +239,56 @@ interface ClaudeUsageData {
   extra_usage_monthly_limit: number | null;
   extra_usage_used_credits: number | null;
   extra_usage_utilization: number | null;
-  five_hour_resets_at: string | null;
+  extra_usage_resets_at?: number | null;
+  extra_usage_budget_utilization?: number | null;
+  five_hour_resets_at: number | null; // epoch millis
   five_hour_utilization: number;
-  seven_day_resets_at: string | null;
+  rate_limited_until?: number | null;
+  is_stale?: boolean;
+  error?: string | null;
+  stale_since?: number | null;
+  seven_day_resets_at: number | null;
   seven_day_utilization: number;
+  seven_day_opus_resets_at?: number | null;
+  seven_day_opus_utilization?: number | null;
+}
+
+interface ClaudeProfile {
+  email: string | null;
+  organization_name: string | null;
+  subscription_type: string | null;
 }
 
+// Fetched once per session (retried after a failure); its email is the plan's tooltip.
+let claudeProfile: Promise<ClaudeProfile | null> | null = null;
+
 interface ClaudeTierData {
   plan_name: string;
   rate_limit_tier: string;
+  organization_name?: string | null;
+}
+
+// One entry of Z.ai's `limits`, known or not; `details` holds its other fields as sent.
+interface ZaiLimit {
+  limit_type: string;
+  percentage: number;
+  current?: number | null;
+  total?: number | null;
+  resets_at?: number | null;
+  details?: Record<string, unknown>;
 }
 
 interface ZaiUsageData {
+  limits?: ZaiLimit[];
   mcp_usage?: {
     percentage: number;
     used: number;
     total: number;
   };
+  rate_limited_until?: number | null;
+  is_stale?: boolean;
+  error?: string | null;
+  stale_since?: number | null;
   tier_name?: string;
   token_usage?: {
     percentage: number;
@@ -217,12 +300,28 @@ interface ZaiTierData {
   plan_name: string;
 }
 
+// One saved Z.ai key: its quota, or why it couldn't be read.
+interface ZaiAccountUsage {
+  usage?: ZaiUsageData | null;
+  error?: UsageError | null;
+}
+
+// Every saved Z.ai key, keyed by label (`default` is the key saved without one).
+interface ZaiAccountsUsage {
+  accounts: Record<string, ZaiAccountUsage>;
+  aggregate_percentage?: number | null;
+}
+
 interface CodexUsageData {
   credits?: {
     balance?: number | null;
     has_credits: boolean;
     unlimited: boolean;
   } | null;
+  rate_limited_until?: number | null;
+  is_stale?: boolean;
+  error?: string | null;
+  stale_since?: number | null;
   session_usage?: CodexWindowUsage | null;
   tier_name?: string | null;
   weekly_usage?: CodexWindowUsage | null;
@@ -241,10 +340,61 @@ interface CodexTierData {
 interface AmpUsageData {
   hourly_replenishment: number;
   quota: number;
+  rate_limited_until?: number | null;
+  is_stale?: boolean;
+  error?: string | null;
+  stale_since?: number | null;
   resets_at?: number | null; // epoch millis; optional to match Rust Option<i64>
+  resets_at_estimated?: boolean; // guessed from window_hours, Amp sent no reset time
   used: number;
   used_percent: number;
   window_hours: number | null;
+  balance?: AmpBalance; // paid credits, dollars
+  team?: AmpTeamUsageData;
+  meta?: {
+    fetched_at: number; // epoch millis
+    source: "loader_data" | "embedded_json" | "html_scrape" | "api_key";
+    parser_version: number;
+  };
+  money?: AmpMoney; // the dollar values formatted for currency_locale
+}
+
+interface Money {
+  currency: string; // ISO 4217
+  minor_units: number; // cents
+  formatted: string;
+}
+
+interface AmpMoney {
+  quota: Money;
+  used: Money;
+  remaining: Money;
+  hourly_replenishment: Money;
+  balance: Money | null;
+  recent_spend: Money | null;
+  team_quota: Money | null;
+  team_used: Money | null;
+  team_balance: Money | null;
+}
+
+interface AmpTeamUsageData {
+  name: string | null;
+  quota: number | null; // dollars
+  used: number | null;
+  used_percent: number | null;
+  balance: number | null;
+  recent_spend: number | null;
+  members: number | null;
+}
+
+interface AmpBalance {
+  balance: number;
+  recent_spend: number | null;
+  auto_top_up: {
+    enabled: boolean;
+    threshold: number | null;
+    amount: number | null;
+  } | null;
 }
 
 let settingsOpening = false;
@@ -269,10 +419,10 @@ async function openSettings(): Promise<void> {
       {
         checkZaiApiKey,
         validateZaiApiKey: async (apiKey: string) => {
-          await invoke("zai_validate_api_key", { apiKey });
+          await invokeForSettings("zai_validate_api_key", { apiKey });
         },
         saveZaiApiKey: async (apiKey: string) => {
-          await invoke("zai_save_api_key", { apiKey });
+          await invokeForSettings("zai_save_api_key", { apiKey });
         },
         deleteZaiApiKey: async () => {
           await invoke("zai_delete_api_key");
@@ -281,11 +431,12 @@ async function openSettings(): Promise<void> {
         checkCodexAuth: refreshCodexAuthState,
         checkAmpSessionCookie: async () =>
           invoke<boolean>("amp_check_session_cookie"),
-        validateAmpSessionCookie: async (cookie: string) => {
-          await invoke("amp_validate_session_cookie", { cookie });
-        },
+        validateAmpSessionCookie: async (cookie: string) =>
+          invokeForSettings<AmpAccount>("amp_validate_session_cookie", {
+            cookie,
+          }),
         saveAmpSessionCookie: async (cookie: string) => {
-          await invoke("amp_save_session_cookie", { cookie });
+          await invokeForSettings("amp_save_session_cookie", { cookie });
         },
         deleteAmpSessionCookie: async () => {
           await invoke("amp_delete_session_cookie");
@@ -371,12 +522,16 @@ async function loadContent() {
     hasCodexAuth = await refreshCodexAuthState();
     updateCodexConnectionBadge(hasCodexAuth);
 
+    await loadSettings();
+    await showCredentialMigrations().catch(console.error);
+
     await Promise.allSettled([
-      fetchClaudeData(),
-      fetchCodexData(),
-      fetchZaiData(),
-      ...(hasAmpCookie ? [fetchAmpData(false, true)] : []),
+      ...(isEnabled("claude") ? [fetchClaudeData()] : []),
+      ...(isEnabled("codex") ? [fetchCodexData()] : []),
+      ...(isEnabled("zai") ? [fetchZaiData()] : []),
+      ...(hasAmpCookie && isEnabled("amp") ? [fetchAmpData(false, true)] : []),
     ]);
+    await subscribeUsage();
 
     loading.style.display = "none";
     content.style.display = "flex";
@@ -384,14 +539,19 @@ async function loadContent() {
     setupTabSwitching();
 
     const savedTab = localStorage.getItem("activeTab");
-    if (savedTab === "codex" || savedTab === "zai" || savedTab === "amp") {
-      switchTab(savedTab as "codex" | "zai" | "amp");
+    if (
+      (savedTab === "codex" || savedTab === "zai" || savedTab === "amp") &&
+      isEnabled(savedTab)
+    ) {
+      switchTab(savedTab);
     } else {
-      switchTab("claude");
+      switchTab(PROVIDERS.find(isEnabled) ?? "claude");
     }
 
     startPolling();
     startTimestampUpdater();
+    await watchSessionState();
+    await watchAmpSession();
 
     // Show window after content is loaded
     const { getCurrentWindow } = await import("@tauri-apps/api/window");
@@ -410,6 +570,86 @@ async function loadContent() {
 
   const settingsButton = document.getElementById("settings-button");
   settingsButton?.addEventListener("click", () => openSettings());
+
+  const billingLink = document.getElementById("extra-usage-billing");
+  billingLink?.addEventListener("click", (e) => {
+    e.preventDefault();
+    openClaudeBilling().catch(console.error);
+  });
+
+  setupClaudeLogin();
+}
+
+// Login without Claude Code: the backend opens the sign-in page, which ends by showing a
+// code to paste back here.
+function setupClaudeLogin() {
+  const start = document.getElementById("claude-login-start");
+  const finish = document.getElementById("claude-login-finish");
+  const code = document.getElementById(
+    "claude-login-code"
+  ) as HTMLInputElement | null;
+  const status = document.getElementById("claude-login-status");
+  if (!(start && finish && code && status)) {
+    return;
+  }
+
+  start.addEventListener("click", async () => {
+    try {
+      await invoke<string>("claude_start_login");
+      status.textContent = "Sign in in your browser, then paste the code shown.";
+      code.focus();
+    } catch (error) {
+      status.textContent = toUsageError(error).message;
+    }
+  });
+  finish.addEventListener("click", async () => {
+    try {
+      await invoke("claude_complete_login", { code: code.value });
+      code.value = "";
+      status.textContent = "";
+      claudeProfile = null;
+      await fetchClaudeData();
+    } catch (error) {
+      status.textContent = toUsageError(error).message;
+    }
+  });
+}
+
+function showClaudeLogin(show: boolean) {
+  const login = document.getElementById("claude-login");
+  if (login) {
+    login.style.display = show ? "flex" : "none";
+  }
+}
+
+interface ExtraUsageContext {
+  enabled: boolean;
+  used: number | null;
+  monthly_limit: number | null;
+  utilization: number | null;
+  resets_at: number | null;
+  budget: number | null;
+  budget_utilization: number | null;
+  billing_url: string;
+}
+
+// Confirms with the current spend before leaving the app for the billing page.
+async function openClaudeBilling() {
+  let prompt = "Open Claude billing?";
+  try {
+    const context = await invoke<ExtraUsageContext>("claude_get_extra_usage");
+    if (context.enabled && context.monthly_limit !== null) {
+      const used = (context.used ?? 0).toFixed(2);
+      const limit = context.monthly_limit.toFixed(2);
+      prompt = `You've used $${used} of $${limit} extra usage — open billing?`;
+    }
+  } catch (error) {
+    // Still offer the page; the numbers are only context.
+    console.error("Failed to load extra usage:", toUsageError(error).message);
+  }
+  if (window.confirm(prompt)) {
+    await invoke("claude_open_billing");
+  }
 }
 
 function setupTabSwitching() {
@@ -463,17 +703,28 @@ function switchTab(tab: "claude" | "codex" | "zai" | "amp") {
   }
 }
 
+interface PromptEstimate {
+  plan_prompts: number;
+  prompts_remaining: number;
+  source: "builtin" | "remote";
+}
+
 function renderClaudeGauges(
   dataContainer: HTMLElement,
-  usageData: ClaudeUsageData
+  usageData: ClaudeUsageData,
+  estimate: PromptEstimate | null
 ) {
   dataContainer.style.display = "block";
   dataContainer.innerHTML = "";
 
   const sessionGauge = createUsageGauge({
-    title: "Session",
+    title: estimate
+      ? `Session · ~${estimate.prompts_remaining} prompts left`
+      : "Session",
     utilization: usageData.five_hour_utilization / 100,
-    resetsAt: usageData.five_hour_resets_at ?? "",
+    resetsAt: usageData.five_hour_resets_at
+      ? new Date(usageData.five_hour_resets_at).toISOString()
+      : "",
   });
   dataContainer.appendChild(sessionGauge);
 
@@ -481,10 +732,21 @@ function renderClaudeGauges(
     const weeklyGauge = createUsageGauge({
       title: "Weekly",
       utilization: usageData.seven_day_utilization / 100,
-      resetsAt: usageData.seven_day_resets_at,
+      resetsAt: new Date(usageData.seven_day_resets_at).toISOString(),
     });
     dataContainer.appendChild(weeklyGauge);
   }
+
+  if (usageData.seven_day_opus_utilization != null) {
+    const opusGauge = createUsageGauge({
+      title: "Weekly · Opus",
+      utilization: usageData.seven_day_opus_utilization / 100,
+      resetsAt: usageData.seven_day_opus_resets_at
+        ? new Date(usageData.seven_day_opus_resets_at).toISOString()
+        : "",
+    });
+    dataContainer.appendChild(opusGauge);
+  }
 }
 
 function renderClaudeExtraUsage(usageData: ClaudeUsageData) {
@@ -503,7 +765,11 @@ function renderClaudeExtraUsage(usageData: ClaudeUsageData) {
     const utilization = usageData.extra_usage_utilization ?? 0;
 
     extraUsageLabel.textContent = `This month: $${usedCredits.toFixed(2)} / $${monthlyLimit.toFixed(2)}`;
-    extraUsageValue.textContent = `${utilization.toFixed(0)}% used`;
+    const budget = usageData.extra_usage_budget_utilization;
+    extraUsageValue.textContent =
+      budget == null
+        ? `${utilization.toFixed(0)}% used`
+        : `${utilization.toFixed(0)}% used · ${budget.toFixed(0)}% of budget`;
     extraUsageValue.style.display = "block";
   } else {
     extraUsageSection.style.display = "none";
@@ -512,20 +778,48 @@ function renderClaudeExtraUsage(usageData: ClaudeUsageData) {
   }
 }
 
-function getClaudeErrorMessage(errorMsg: string): string {
-  if (
-    errorMsg.includes("Credential not found") ||
-    errorMsg.includes("not found")
-  ) {
-    return "Claude credentials not found. Please sign in to Claude Code first.";
-  }
-  if (errorMsg.includes("Access denied")) {
-    return "Access denied -- check your permissions";
+// Commands reject with `{ kind, code, message, retry_after? }`: `message` is already in
+// the configured language; `kind` (the recovery to offer) and the finer `code` stay the
+// same across languages, so branch on those.
+type UsageErrorKind =
+  | "auth_expired"
+  | "rate_limited"
+  | "network"
+  | "parse"
+  | "not_configured"
+  | "other";
+
+interface UsageError {
+  code: string | null;
+  kind: UsageErrorKind;
+  message: string;
+  /** Seconds, for `rate_limited` when the provider said how long to wait. */
+  retry_after?: number;
+}
+
+function toUsageError(error: unknown): UsageError {
+  if (typeof error === "object" && error !== null && "message" in error) {
+    const { code, kind, message, retry_after } = error as Partial<UsageError>;
+    return {
+      code: code ?? null,
+      kind: kind ?? "other",
+      message: String(message),
+      retry_after,
+    };
   }
-  if (errorMsg.includes("Rate limited")) {
-    return "Rate limited -- please wait and try again";
+  return { code: null, kind: "other", message: String(error) };
+}
+
+// Settings callbacks reject with the plain message, which the view shows as-is.
+async function invokeForSettings<T = void>(
+  command: string,
+  args?: Record<string, unknown>
+): Promise<T> {
+  try {
+    return await invoke<T>(command, args);
+  } catch (error) {
+    throw new Error(toUsageError(error).message);
   }
-  return errorMsg;
 }
 
 async function fetchClaudeData() {
@@ -539,28 +833,50 @@ async function fetchClaudeData() {
     if (errorContainer) {
       errorContainer.style.display = "none";
     }
+    showClaudeLogin(false);
     if (dataContainer) {
-      renderClaudeGauges(dataContainer, usageData);
+      const estimate = await invoke<PromptEstimate | null>(
+        "claude_get_prompt_estimate"
+      ).catch(() => null);
+      renderClaudeGauges(dataContainer, usageData, estimate);
     }
 
     renderClaudeExtraUsage(usageData);
 
     const tierEl = document.getElementById("claude-tier");
     if (tierEl) {
-      tierEl.textContent = tierData.plan_name;
+      tierEl.textContent = tierData.organization_name
+        ? `${tierData.plan_name} · ${tierData.organization_name}`
+        : tierData.plan_name;
       tierEl.title = "";
+      claudeProfile ??= invoke<ClaudeProfile>("claude_get_profile").catch(() => {
+        claudeProfile = null;
+        return null;
+      });
+      claudeProfile.then((profile) => {
+        if (profile?.email) {
+          tierEl.title = profile.email;
+        }
+      });
     }
 
-    claudeLastRefresh = new Date();
+    rateLimitedUntil.claude = usageData.rate_limited_until ?? null;
+    lastFetchError.claude = usageData.error ?? null;
+    if (usageData.stale_since) {
+      claudeLastRefresh = new Date(usageData.stale_since);
+    } else if (!rateLimitedUntil.claude) {
+      claudeLastRefresh = new Date();
+    }
     updateTimestamp("claude");
   } catch (error) {
-    const errorMsg = String(error);
+    const { kind, message } = toUsageError(error);
+    showClaudeLogin(kind === "not_configured" || kind === "auth_expired");
     const errorContainer = document.getElementById("claude-error");
     const dataContainer = document.getElementById("claude-data");
     const errorMessage = document.getElementById("claude-error-message");
 
     if (errorContainer && errorMessage) {
-      errorMessage.textContent = getClaudeErrorMessage(errorMsg);
+      errorMessage.textContent = message;
       errorContainer.style.display = "flex";
     }
     if (dataContainer) {
@@ -570,7 +886,7 @@ async function fetchClaudeData() {
     const tierEl = document.getElementById("claude-tier");
     if (tierEl) {
       tierEl.textContent = "Error";
-      tierEl.title = String(error);
+      tierEl.title = message;
     }
   }
 }
@@ -592,10 +908,21 @@ async function fetchCodexData(forceRefresh = false) {
     renderCodexUsageData(dataContainer, errorContainer, usageData);
     updateCodexTier(tierData.plan_name);
 
-    codexLastRefresh = new Date();
+    rateLimitedUntil.codex = usageData.rate_limited_until ?? null;
+    lastFetchError.codex = usageData.error ?? null;
+    if (usageData.stale_since) {
+      codexLastRefresh = new Date(usageData.stale_since);
+    } else if (!rateLimitedUntil.codex) {
+      codexLastRefresh = new Date();
+    }
     updateTimestamp("codex");
   } catch (error) {
-    showCodexError(String(error), errorContainer, dataContainer, errorMessage);
+    showCodexError(
+      toUsageError(error),
+      errorContainer,
+      dataContainer,
+      errorMessage
+    );
   }
 }
 
@@ -635,12 +962,12 @@ function appendCodexWindowGauge(
 }
 
 function showCodexError(
-  errorMsg: string,
+  error: UsageError,
   errorContainer: HTMLElement,
   dataContainer: HTMLElement,
   errorMessage: HTMLElement
 ): void {
-  if (isCodexAuthError(errorMsg)) {
+  if (error.kind === "not_configured" || error.kind === "auth_expired") {
     hasCodexAuth = false;
     updateCodexConnectionBadge(hasCodexAuth);
     dataContainer.style.display = "none";
@@ -649,21 +976,10 @@ function showCodexError(
     return;
   }
 
-  errorMessage.textContent = errorMsg;
+  errorMessage.textContent = error.message;
   errorContainer.style.display = "flex";
   dataContainer.style.display = "none";
-  updateCodexTier("Error", errorMsg);
-}
-
-function isCodexAuthError(errorMsg: string): boolean {
-  const lower = errorMsg.toLowerCase();
-  return (
-    lower.includes("not configured") ||
-    lower.includes("re-authenticate") ||
-    lower.includes("sign in") ||
-    lower.includes("expired or invalid") ||
-    lower.includes("contains no access token")
-  );
+  updateCodexTier("Error", error.message);
 }
 
 function updateCodexTier(text: string, title = ""): void {
@@ -701,6 +1017,56 @@ function createCodexCreditsSection(usageData: CodexUsageData): HTMLElement {
   return infoSection;
 }
 
+// Session percentage of each saved key and their average; nothing with only the default key.
+function createZaiAccountsSection(
+  accounts: ZaiAccountsUsage | undefined
+): HTMLElement | null {
+  const entries = Object.entries(accounts?.accounts ?? {});
+  if (entries.length < 2) {
+    return null;
+  }
+
+  const infoSection = document.createElement("div");
+  infoSection.className = "info-section";
+
+  const infoTitle = document.createElement("div");
+  infoTitle.className = "info-section-header";
+  const titleSpan = document.createElement("span");
+  titleSpan.className = "info-section-title";
+  titleSpan.textContent = "API Keys";
+  infoTitle.appendChild(titleSpan);
+  infoSection.appendChild(infoTitle);
+
+  const addRow = (label: string, value: string, muted = false) => {
+    const row = document.createElement("div");
+    row.className = "info-section-row";
+    const labelEl = document.createElement("span");
+    labelEl.className = "info-label";
+    labelEl.textContent = label;
+    const valueEl = document.createElement("span");
+    valueEl.className = muted ? "info-value muted" : "info-value";
+    valueEl.textContent = value;
+    row.append(labelEl, valueEl);
+    infoSection.appendChild(row);
+  };
+
+  for (const [label, account] of entries) {
+    const percentage = account.usage?.token_usage?.percentage;
+    if (typeof percentage === "number") {
+      addRow(label, `${percentage.toFixed(0)}%`);
+    } else {
+      addRow(label, account.error?.message ?? "No session data", true);
+    }
+  }
+
+  const aggregate = accounts?.aggregate_percentage;
+  if (typeof aggregate === "number") {
+    addRow("All keys (average)", `${aggregate.toFixed(0)}%`);
+  }
+
+  return infoSection;
+}
+
 async function fetchZaiData(forceRefresh = false) {
   const zaiView = document.getElementById("zai-view");
   const errorContainer = document.getElementById("zai-error");
@@ -713,8 +1079,8 @@ async function fetchZaiData(forceRefresh = false) {
 
   try {
     const command = forceRefresh ? "zai_refresh_all" : "zai_get_all";
-    const [usageData, tierData] =
-      await invoke<[ZaiUsageData, ZaiTierData]>(command);
+    const [usageData, tierData, accounts] =
+      await invoke<[ZaiUsageData, ZaiTierData, ZaiAccountsUsage]>(command);
 
     if (!usageData) {
       return;
@@ -745,17 +1111,28 @@ async function fetchZaiData(forceRefresh = false) {
       dataContainer.appendChild(mcpGauge);
     }
 
+    const accountsSection = createZaiAccountsSection(accounts);
+    if (accountsSection) {
+      dataContainer.appendChild(accountsSection);
+    }
+
     const tierEl = document.getElementById("zai-tier");
     if (tierEl) {
       tierEl.textContent = tierData.plan_name;
       tierEl.title = "";
     }
 
-    zaiLastRefresh = new Date();
+    rateLimitedUntil.zai = usageData.rate_limited_until ?? null;
+    lastFetchError.zai = usageData.error ?? null;
+    if (usageData.stale_since) {
+      zaiLastRefresh = new Date(usageData.stale_since);
+    } else if (!rateLimitedUntil.zai) {
+      zaiLastRefresh = new Date();
+    }
     updateTimestamp("zai");
   } catch (error) {
-    const errorMsg = String(error);
-    if (errorMsg.includes("not configured")) {
+    const { kind, message } = toUsageError(error);
+    if (kind === "not_configured") {
       dataContainer.style.display = "none";
       errorContainer.style.display = "none";
 
@@ -765,14 +1142,14 @@ async function fetchZaiData(forceRefresh = false) {
         tierEl.title = "";
       }
     } else {
-      errorMessage.textContent = errorMsg;
+      errorMessage.textContent = message;
       errorContainer.style.display = "flex";
       dataContainer.style.display = "none";
 
       const tierEl = document.getElementById("zai-tier");
       if (tierEl) {
         tierEl.textContent = "Error";
-        tierEl.title = String(error);
+        tierEl.title = message;
       }
     }
   }
@@ -847,27 +1224,35 @@ async function fetchAmpData(forceRefresh = false, skipCookieCheck = false) {
     const total = data.quota;
     const balanceRow = document.createElement("div");
     balanceRow.className = "info-row";
-    balanceRow.textContent = `$${remaining.toFixed(2)} / $${total.toFixed(2)} remaining`;
+    balanceRow.textContent = data.money
+      ? `${data.money.remaining.formatted} / ${data.money.quota.formatted} remaining`
+      : `$${remaining.toFixed(2)} / $${total.toFixed(2)} remaining`;
     infoSection.appendChild(balanceRow);
 
     dataContainer.appendChild(infoSection);
 
-    ampLastRefresh = new Date();
+    rateLimitedUntil.amp = data.rate_limited_until ?? null;
+    lastFetchError.amp = data.error ?? null;
+    if (data.stale_since) {
+      ampLastRefresh = new Date(data.stale_since);
+    } else if (!rateLimitedUntil.amp) {
+      ampLastRefresh = new Date();
+    }
     updateTimestamp("amp");
   } catch (error) {
-    const errorMsg = String(error);
-    if (errorMsg.includes("not configured")) {
+    const { kind, message } = toUsageError(error);
+    if (kind === "not_configured") {
       dataContainer.style.display = "none";
       errorContainer.style.display = "none";
     } else {
-      errorMessage.textContent = errorMsg;
+      errorMessage.textContent = message;
       errorContainer.style.display = "flex";
       dataContainer.style.display = "none";
 
       const tierEl = document.getElementById("amp-tier");
       if (tierEl) {
         tierEl.textContent = "Error";
-        tierEl.title = String(error);
+        tierEl.title = message;
       }
     }
   }
@@ -882,39 +1267,56 @@ async function refreshAmpUI(): Promise<void> {
   }
 }
 
+function lastRefreshFor(provider: Provider): Date | null {
+  if (provider === "claude") {
+    return claudeLastRefresh;
+  }
+  if (provider === "codex") {
+    return codexLastRefresh;
+  }
+  if (provider === "zai") {
+    return zaiLastRefresh;
+  }
+  return ampLastRefresh;
+}
+
 function updateTimestamp(provider: "claude" | "codex" | "zai" | "amp") {
   const el = document.getElementById(`${provider}-updated`);
   if (!el) {
     return;
   }
 
-  let lastRefresh: Date | null = null;
-  if (provider === "claude") {
-    lastRefresh = claudeLastRefresh;
-  } else if (provider === "codex") {
-    lastRefresh = codexLastRefresh;
-  } else if (provider === "zai") {
-    lastRefresh = zaiLastRefresh;
-  } else {
-    lastRefresh = ampLastRefresh;
-  }
-  if (!lastRefresh) {
-    el.textContent = "Updated just now";
+  const limitedUntil = rateLimitedUntil[provider];
+  if (limitedUntil && limitedUntil > Date.now()) {
+    const retryAt = new Date(limitedUntil).toLocaleTimeString([], {
+      hour: "2-digit",
+      minute: "2-digit",
+    });
+    el.textContent = `Rate limited until ${retryAt}`;
+    el.title = "";
     return;
   }
 
-  const now = new Date();
-  const diffMs = now.getTime() - lastRefresh.getTime();
-  const diffMin = Math.floor(diffMs / 60_000);
+  const lastRefresh = lastRefreshFor(provider);
+  let text = "Updated just now";
+  if (lastRefresh) {
+    const diffMs = Date.now() - lastRefresh.getTime();
+    const diffMin = Math.floor(diffMs / 60_000);
+    if (diffMin >= 60) {
+      text = `Updated ${Math.floor(diffMin / 60)}h ago`;
+    } else if (diffMin >= 1) {
+      text = `Updated ${diffMin}m ago`;
+    }
+  }
 
-  if (diffMin < 1) {
-    el.textContent = "Updated just now";
-  } else if (diffMin < 60) {
-    el.textContent = `Updated ${diffMin}m ago`;
-  } else {
-    const diffHours = Math.floor(diffMin / 60);
-    el.textContent = `Updated ${diffHours}h ago`;
+  const change = lastChange[provider];
+  if (change) {
+    text = `${text} · ${change}`;
   }
+
+  const fetchError = lastFetchError[provider];
+  el.textContent = fetchError ? `${text} · refresh failed` : text;
+  el.title = fetchError ?? "";
 }
 
 function updateAllTimestamps(): void {
@@ -933,31 +1335,295 @@ function startTimestampUpdater() {
   }, 30_000); // update every 30s
 }
 
-/// Shared refresh logic. forceRefresh=true bypasses cache; false uses cached data.
-async function doRefresh(forceRefresh: boolean): Promise<void> {
-  await Promise.allSettled([
-    fetchClaudeData(),
-    fetchCodexData(forceRefresh),
-    fetchZaiData(forceRefresh),
-    fetchAmpData(forceRefresh),
-  ]);
-}
+// Each provider polls on its own timer so e.g. Amp's HTML scrape can run less
+// often than Claude. Intervals come from the backend with floors applied.
+const providerPollers: Record<Provider, () => Promise<void>> = {
+  claude: () => fetchClaudeData(),
+  codex: () => fetchCodexData(false),
+  zai: () => fetchZaiData(false),
+  amp: () => fetchAmpData(false),
+};
 
 function startPolling() {
-  if (pollingTimer !== null) {
+  for (const provider of PROVIDERS) {
+    if (pollingTimers[provider] !== null || !isEnabled(provider)) {
+      continue;
+    }
+    pollingTimers[provider] = window.setInterval(async () => {
+      await providerPollers[provider]();
+    }, pollIntervals[provider]);
+  }
+}
+
+function clearPollingTimers(): void {
+  for (const provider of PROVIDERS) {
+    const timer = pollingTimers[provider];
+    if (timer !== null) {
+      clearInterval(timer);
+      pollingTimers[provider] = null;
+    }
+  }
+}
+
+function restartPolling(): void {
+  clearPollingTimers();
+  startPolling();
+}
+
+interface SessionState {
+  locked: boolean;
+  presenting: boolean;
+}
+
+// Polling pauses while Windows is locked; unlocking refreshes every provider
+// right away instead of waiting out the rest of each interval.
+async function watchSessionState(): Promise<void> {
+  await listen<SessionState>("session-state-changed", (event) => {
+    if (event.payload.locked) {
+      clearPollingTimers();
+      return;
+    }
+    if (PROVIDERS.some((p) => pollingTimers[p] !== null)) {
+      return;
+    }
+    for (const provider of PROVIDERS.filter(isEnabled)) {
+      providerPollers[provider]().catch(console.error);
+    }
+    startPolling();
+  });
+  const state = await invoke<SessionState>("get_session_state");
+  if (state.locked) {
+    clearPollingTimers();
+  }
+}
+
+interface AmpSessionStatus {
+  state: "ok" | "expiring" | "expired";
+  saved_at: number | null;
+  expires_at: number | null;
+  consecutive_auth_failures: number;
+  error: { code: string | null; message: string } | null;
+}
+
+function showAmpSessionWarning(status: AmpSessionStatus): void {
+  const container = document.getElementById("amp-session-warning");
+  const messageEl = document.getElementById("amp-session-warning-message");
+  if (!(container && messageEl)) {
     return;
   }
+  messageEl.textContent = status.error?.message ?? "";
+  container.style.display = status.error ? "flex" : "none";
+}
 
-  pollingTimer = window.setInterval(async () => {
-    await doRefresh(false);
-  }, POLL_INTERVAL);
+// Warns before the Amp cookie stops working (and once it has), so it can be
+// replaced before usage goes missing.
+async function watchAmpSession(): Promise<void> {
+  await listen<AmpSessionStatus>("amp-session-changed", (event) => {
+    showAmpSessionWarning(event.payload);
+  });
+  showAmpSessionWarning(
+    await invoke<AmpSessionStatus>("amp_get_session_status")
+  );
 }
 
-function stopPolling() {
-  if (pollingTimer !== null) {
-    clearInterval(pollingTimer);
-    pollingTimer = null;
+function showSettingsFileError(message: string | null): void {
+  const container = document.getElementById("settings-file-error");
+  const messageEl = document.getElementById("settings-file-error-message");
+  if (!(container && messageEl)) {
+    return;
+  }
+  messageEl.textContent = message ?? "";
+  container.style.display = message ? "flex" : "none";
+}
+
+async function applyPollIntervals(): Promise<void> {
+  const intervals = await invoke<PollIntervals>("get_poll_intervals");
+  let changed = false;
+  for (const provider of PROVIDERS) {
+    const next = intervals[provider] * 1000;
+    if (next !== pollIntervals[provider]) {
+      pollIntervals[provider] = next;
+      changed = true;
+    }
+  }
+  if (changed && PROVIDERS.some((p) => pollingTimers[p] !== null)) {
+    restartPolling();
+  }
+}
+
+function isEnabled(provider: Provider): boolean {
+  return !disabledProviders.has(provider);
+}
+
+interface ProviderSettings {
+  disabled_providers: Provider[];
+  claude_active_profile?: string | null;
+}
+
+// `claude_active_profile` last seen; `undefined` until settings first load.
+let activeClaudeProfile: string | null | undefined;
+
+// A profile switch (`claude_switch_profile` or an edit) changes whose usage the Claude tab shows.
+function applyClaudeProfile(settings: ProviderSettings): void {
+  const profile = settings.claude_active_profile ?? null;
+  const switched =
+    activeClaudeProfile !== undefined && profile !== activeClaudeProfile;
+  activeClaudeProfile = profile;
+  if (switched) {
+    claudeProfile = null;
+    if (isEnabled("claude")) {
+      fetchClaudeData().catch(console.error);
+    }
+  }
+}
+
+// Hides the tabs of disabled providers and stops or starts their timers.
+function applyDisabledProviders(settings: ProviderSettings): void {
+  disabledProviders = new Set(settings.disabled_providers);
+  for (const provider of PROVIDERS) {
+    const tab = document.getElementById(`tab-${provider}`);
+    if (tab) {
+      tab.style.display = isEnabled(provider) ? "" : "none";
+    }
+  }
+  const activeTab = localStorage.getItem("activeTab") as Provider | null;
+  if (activeTab && !isEnabled(activeTab)) {
+    const next = PROVIDERS.find(isEnabled);
+    if (next) {
+      switchTab(next);
+    }
+  }
+  if (PROVIDERS.some((p) => pollingTimers[p] !== null)) {
+    restartPolling();
+  }
+}
+
+// settings.json can be edited by hand; the backend watches it and tells us
+// when it changes or when an edit was rejected (previous values stay active).
+async function loadSettings(): Promise<void> {
+  try {
+    await applyPollIntervals();
+    const settings = await invoke<ProviderSettings>("get_settings");
+    applyDisabledProviders(settings);
+    applyClaudeProfile(settings);
+    showSettingsFileError(await invoke<string | null>("get_settings_error"));
+  } catch (error) {
+    console.error("Failed to load settings:", error);
+  }
+
+  await listen<ProviderSettings>("settings-changed", (event) => {
+    showSettingsFileError(null);
+    applyDisabledProviders(event.payload);
+    applyClaudeProfile(event.payload);
+    applyPollIntervals().catch(console.error);
+  });
+  await listen<string>("settings-error", (event) => {
+    showSettingsFileError(event.payload);
+  });
+  // A poll_schedules window started or ended.
+  await listen("poll-intervals-changed", () => {
+    applyPollIntervals().catch(console.error);
+  });
+}
+
+interface CredentialMigration {
+  provider: Provider;
+  from: string;
+  to: string;
+  reencoded: boolean;
+  error: string | null;
+}
+
+const PROVIDER_LABELS: Record<Provider, string> = {
+  claude: "Claude",
+  codex: "Codex",
+  zai: "Z.ai",
+  amp: "Amp",
+};
+
+// Credentials saved by older builds are moved at startup; say so, so a key
+// that was moved (or could not be) is never a silent surprise.
+async function showCredentialMigrations(): Promise<void> {
+  const migrations = await invoke<CredentialMigration[]>(
+    "get_credential_migrations"
+  );
+  const container = document.getElementById("credential-migration-notice");
+  const messageEl = document.getElementById("credential-migration-message");
+  if (!(container && messageEl) || migrations.length === 0) {
+    return;
+  }
+  messageEl.textContent = migrations
+    .map((m) => {
+      const name = PROVIDER_LABELS[m.provider];
+      if (m.error) {
+        return `${name}: could not migrate ${m.from} (${m.error})`;
+      }
+      return m.from === m.to
+        ? `${name}: re-encoded ${m.to} as UTF-8`
+        : `${name}: moved ${m.from} to ${m.to}`;
+    })
+    .join(". ");
+  container.style.display = "flex";
+}
+
+interface UsageDelta {
+  since: number;
+  windows: { window: string; change: number }[];
+  spend: { budget: string; spent: number }[];
+}
+
+interface UsageUpdate {
+  provider: Provider;
+  windows: { window: string; percent: number }[];
+  fetched_at: number;
+  delta?: UsageDelta;
+}
+
+function describeChange(update: UsageUpdate): string | null {
+  if (!update.delta) {
+    return null;
   }
+  let biggest = 0;
+  for (const { change } of update.delta.windows) {
+    if (Math.abs(change) > Math.abs(biggest)) {
+      biggest = change;
+    }
+  }
+  const rounded = Math.round(biggest);
+  if (rounded === 0) {
+    return null;
+  }
+  const minutes = Math.max(
+    1,
+    Math.round((update.fetched_at - update.delta.since) / 60_000)
+  );
+  const span = minutes >= 60 ? `${Math.round(minutes / 60)}h` : `${minutes}m`;
+  return `${rounded > 0 ? "+" : ""}${rounded}% in ${span}`;
+}
+
+// One push channel per provider. Fetches made by the backend itself (e.g.
+// refresh on show) arrive here; the data is already cached, so re-rendering
+// through the poller doesn't hit the network again.
+async function subscribeUsage(): Promise<void> {
+  for (const provider of PROVIDERS) {
+    const channel = new Channel<UsageUpdate>();
+    channel.onmessage = (update) => {
+      if (update.delta) {
+        lastChange[provider] = describeChange(update);
+        updateTimestamp(provider);
+      }
+      const lastRefresh = lastRefreshFor(provider);
+      if (lastRefresh && update.fetched_at <= lastRefresh.getTime()) {
+        return;
+      }
+      providerPollers[provider]().catch(console.error);
+    };
+    await invoke("subscribe_usage", { provider, onUpdate: channel });
+  }
+}
+
+function stopPolling() {
+  clearPollingTimers();
   if (timestampTimer !== null) {
     clearInterval(timestampTimer);
     timestampTimer = null;